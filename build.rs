@@ -0,0 +1,27 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    {
+        use tonic_build::manual::{Builder, Method, Service};
+
+        let method = |name: &str, route_name: &str, input_type: &str, output_type: &str| {
+            Method::builder()
+                .name(name)
+                .route_name(route_name)
+                .input_type(format!("crate::grpc::{}", input_type))
+                .output_type(format!("crate::grpc::{}", output_type))
+                .codec_path("tonic_prost::ProstCodec")
+                .build()
+        };
+        let estimator = Service::builder()
+            .name("Estimator")
+            .package("lqe")
+            .method(method("create_filter", "CreateFilter", "CreateFilterRequest", "CreateFilterResponse"))
+            .method(method("step", "Step", "StepRequest", "StateEstimate"))
+            .method(method("forecast", "Forecast", "ForecastRequest", "ForecastResponse"))
+            .method(method("delete_filter", "DeleteFilter", "DeleteFilterRequest", "DeleteFilterResponse"))
+            .build();
+        // The generated client relies on the 2021 prelude, and Rust clients can
+        // use the filters directly anyway.
+        Builder::new().build_client(false).compile(&[estimator]);
+    }
+}
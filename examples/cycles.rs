@@ -0,0 +1,101 @@
+//! Measures the execution time of `KalmanFilter::next_deterministic` for
+//! constant velocity models in two and three dimensions.
+//!
+//! Every case steps a copy of the same filter many times and prints the
+//! least, median and largest time of a step, in cycles of the time stamp
+//! counter on x86_64 and in nanoseconds elsewhere. The largest time includes
+//! interrupts and cache misses, so it only bounds the worst case from below.
+//!
+//! Run it in release mode on the machine to be measured:
+//!
+//! ```text
+//! cargo run --release --features nalgebra --example cycles
+//! ```
+
+use std::hint::black_box;
+
+use lqe::multivariate::KalmanFilter;
+use nalgebra::{SMatrix, SVector};
+
+const RUNS: usize = 100_000;
+
+#[cfg(target_arch = "x86_64")]
+const UNIT: &str = "cycles";
+
+#[cfg(not(target_arch = "x86_64"))]
+const UNIT: &str = "ns";
+
+#[cfg(target_arch = "x86_64")]
+fn now() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn now() -> u64 {
+    use std::time::Instant;
+    thread_local!(static START: Instant = Instant::now());
+    START.with(|start| start.elapsed().as_nanos() as u64)
+}
+
+/// `constant_velocity` tracks `M` positions, measured with unit variance,
+/// and their velocities.
+fn constant_velocity<const N: usize, const M: usize>() -> KalmanFilter<N, M> {
+    let mut transition = SMatrix::<f64, N, N>::identity();
+    let mut observation = SMatrix::<f64, M, N>::zeros();
+    for i in 0..M {
+        transition[(i, i + M)] = 1.0;
+        observation[(i, i)] = 1.0;
+    }
+    KalmanFilter {
+        state: SVector::zeros(),
+        covariance: SMatrix::identity(),
+        transition,
+        process_noise: SMatrix::identity() * 0.01,
+        observation,
+        measurement_noise: SMatrix::identity()
+    }
+}
+
+fn measure<const N: usize, const M: usize>(name: &str, filter: KalmanFilter<N, M>, measurement: SVector<f64, M>) {
+    let mut times = Vec::with_capacity(RUNS);
+    for _ in 0..RUNS {
+        let mut filter = black_box(filter);
+        let start = now();
+        let _ = black_box(filter.next_deterministic(black_box(&measurement)));
+        times.push(now() - start);
+    }
+    times.sort_unstable();
+    println!(
+        "{:<24} {:>8} {:>8} {:>8} {}",
+        name,
+        times[0],
+        times[RUNS / 2],
+        times[RUNS - 1],
+        UNIT
+    );
+}
+
+fn main() {
+    println!("{:<24} {:>8} {:>8} {:>8}", "case", "least", "median", "largest");
+
+    let filter = constant_velocity::<4, 2>();
+    measure("2D", filter, SVector::from([1.0, 2.0]));
+    let mut subnormal = filter;
+    subnormal.covariance *= 1e-310;
+    subnormal.process_noise *= 1e-310;
+    subnormal.measurement_noise *= 1e-310;
+    measure("2D subnormal", subnormal, SVector::from([1e-310, 2e-310]));
+    let mut singular = filter;
+    singular.covariance *= 0.0;
+    singular.process_noise *= 0.0;
+    singular.measurement_noise *= 0.0;
+    measure("2D singular", singular, SVector::from([1.0, 2.0]));
+
+    let filter = constant_velocity::<6, 3>();
+    measure("3D", filter, SVector::from([1.0, 2.0, 3.0]));
+    let mut subnormal = filter;
+    subnormal.covariance *= 1e-310;
+    subnormal.process_noise *= 1e-310;
+    subnormal.measurement_noise *= 1e-310;
+    measure("3D subnormal", subnormal, SVector::from([1e-310, 2e-310, 3e-310]));
+}
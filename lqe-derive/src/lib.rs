@@ -0,0 +1,124 @@
+//! Derive macros for `lqe`.
+//!
+//! Use them through the `derive` feature of `lqe` rather than directly.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Expr, Fields, Ident};
+
+/// `Variance` is where the variance of a measured field comes from.
+enum Variance {
+    Constant(Expr),
+    Field(Ident)
+}
+
+/// `Attributes` are the `#[measurement(...)]` attributes of a field.
+#[derive(Default)]
+struct Attributes {
+    skip: bool,
+    variance: Option<Variance>
+}
+
+/// `parse_attributes` reads the attributes of `field`.
+fn parse_attributes(field: &syn::Field) -> Result<Attributes, Error> {
+    let mut attributes = Attributes::default();
+    for attribute in field.attrs.iter().filter(|attribute| attribute.path().is_ident("measurement")) {
+        attribute.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                attributes.skip = true;
+            } else if meta.path.is_ident("variance") {
+                attributes.variance = Some(Variance::Constant(meta.value()?.parse()?));
+            } else if meta.path.is_ident("variance_field") {
+                attributes.variance = Some(Variance::Field(meta.value()?.parse()?));
+            } else {
+                return Err(meta.error("expected `variance`, `variance_field` or `skip`"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(attributes)
+}
+
+/// `expand` implements `MeasurementVector` for `input`.
+fn expand(input: DeriveInput) -> Result<TokenStream2, Error> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(Error::new(input.span(), "`Measurement` needs a struct with named fields"))
+        },
+        _ => return Err(Error::new(input.span(), "`Measurement` can only be derived for structs"))
+    };
+
+    let attributes = fields.iter().map(parse_attributes).collect::<Result<Vec<_>, _>>()?;
+    // Fields holding the variance of another are not measured themselves.
+    let sources: Vec<&Ident> = attributes
+        .iter()
+        .filter_map(|attributes| match &attributes.variance {
+            Some(Variance::Field(source)) => Some(source),
+            _ => None
+        })
+        .collect();
+    let mut components = Vec::new();
+    for (field, attributes) in fields.iter().zip(attributes.iter()) {
+        let name = field.ident.as_ref().unwrap();
+        if attributes.skip || sources.contains(&name) {
+            continue;
+        }
+        match &attributes.variance {
+            Some(variance) => components.push((name, variance)),
+            None => {
+                return Err(Error::new(
+                    field.span(),
+                    "measured fields need `#[measurement(variance = ...)]` or `#[measurement(variance_field = ...)]`"
+                ))
+            }
+        }
+    }
+
+    let dimensions = components.len();
+    let values = components.iter().map(|(name, _)| quote!(f64::from(self.#name)));
+    let variances = components.iter().map(|(_, variance)| match variance {
+        Variance::Constant(value) => quote!(f64::from(#value)),
+        Variance::Field(source) => quote!(f64::from(self.#source))
+    });
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::lqe::measurement::MeasurementVector for #name #type_generics #where_clause {
+            const DIMENSIONS: usize = #dimensions;
+
+            fn to_vector<__M: ::lqe::matrix::Matrix>(&self) -> __M {
+                __M::vector(&[#(#values),*])
+            }
+
+            fn noise<__M: ::lqe::matrix::Matrix>(&self) -> __M {
+                let variances: [f64; #dimensions] = [#(#variances),*];
+                let mut noise = __M::zeros(variances.len(), variances.len());
+                for (i, variance) in variances.iter().enumerate() {
+                    noise.set(i, i, *variance);
+                }
+                noise
+            }
+        }
+    })
+}
+
+/// `Measurement` implements `lqe::measurement::MeasurementVector` for a
+/// struct with named fields, measuring its fields in declaration order.
+///
+/// Every field takes one of the attributes:
+///
+/// - `#[measurement(variance = <expression>)]`: a constant variance.
+/// - `#[measurement(variance_field = <field>)]`: the variance is read from
+///   another field, which isn't measured itself.
+/// - `#[measurement(skip)]`: the field isn't measured, e.g. a timestamp.
+///
+/// Fields referenced with `variance_field` need no attribute.
+#[proc_macro_derive(Measurement, attributes(measurement))]
+pub fn derive_measurement(input: TokenStream) -> TokenStream {
+    expand(parse_macro_input!(input as DeriveInput))
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
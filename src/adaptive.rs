@@ -0,0 +1,396 @@
+//! Filters that estimate their own noise levels.
+//!
+//! The variances a filter is configured with are often guesses, and some
+//! change over time, e.g. a sensor that gets noisier as it warms up. The
+//! filters here track a randomly walking value like `TimedFilter`, one
+//! measurement per step, while adapting their noise to what the measurements
+//! show.
+//!
+//! `VariationalFilter` learns the measurement noise, `SageHusaFilter` both
+//! the measurement and the process noise. `StrongTrackingFilter` instead
+//! inflates its uncertainty when the measurements stop matching it, to
+//! recover quickly from abrupt changes.
+//!
+//! When the sensor can be held still for a while, `RollingVariance` measures
+//! its noise directly instead.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::LQE;
+//! use lqe::adaptive::VariationalFilter;
+//!
+//! let mut filter = VariationalFilter::new(LQE { measurement: 0.0, variance: 1.0 }, 0.01, 1.0);
+//! for z in &[4.0, -3.5, 3.8, -4.2, 4.1] {
+//!     filter.next(*z);
+//! }
+//! filter.measurement_variance() > 1.0;
+//! // => true
+//! ```
+
+use std::collections::VecDeque;
+
+use crate::step::StepInfo;
+use crate::LQE;
+
+/// `VariationalFilter` jointly estimates a value and the variance of the
+/// noise on its measurements, by variational Bayes.
+///
+/// The measurement variance has an inverse gamma distribution with `shape`
+/// and `scale`, which `forgetting` (in `(0, 1]`) spreads out each step so
+/// that the variance can drift; `1.0` treats it as constant. Each update
+/// alternates `iterations` times between refining the value given the
+/// variance and the variance given the value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct VariationalFilter {
+    pub estimate: LQE,
+    pub process_noise: f64,
+    pub shape: f64,
+    pub scale: f64,
+    pub forgetting: f64,
+    pub iterations: usize
+}
+
+impl VariationalFilter {
+    /// `new` creates a filter starting from `estimate` with the initial guess
+    /// `measurement_variance`, forgetting 1% of what it learned about the
+    /// variance per step and iterating 5 times per update.
+    pub fn new(estimate: LQE, process_noise: f64, measurement_variance: f64) -> Self {
+        VariationalFilter {
+            estimate,
+            process_noise,
+            shape: 1.0,
+            scale: measurement_variance,
+            forgetting: 0.99,
+            iterations: 5
+        }
+    }
+
+    /// `measurement_variance` returns the current estimate of the
+    /// measurement noise variance.
+    pub fn measurement_variance(&self) -> f64 {
+        self.scale / self.shape
+    }
+
+    /// `next` performs the entire predict - update cycle for `measurement`,
+    /// returning the refined estimate.
+    pub fn next(&mut self, measurement: f64) -> LQE {
+        let prediction = LQE {
+            measurement: self.estimate.measurement,
+            variance: self.estimate.variance + self.process_noise
+        };
+        let shape = self.forgetting * self.shape + 0.5;
+        let prior_scale = self.forgetting * self.scale;
+        let mut scale = prior_scale;
+        let mut estimate = prediction;
+        for _ in 0..self.iterations.max(1) {
+            estimate = prediction.correct(measurement, scale / shape).posterior;
+            scale = prior_scale + 0.5 * ((measurement - estimate.measurement).powi(2) + estimate.variance);
+        }
+        self.estimate = estimate;
+        self.shape = shape;
+        self.scale = scale;
+        estimate
+    }
+
+    /// `result` returns the current estimate as a tuple value.
+    pub fn result(&self) -> (f64, f64) {
+        self.estimate.result()
+    }
+}
+
+/// `SageHusaFilter` estimates a value together with both its process and
+/// measurement noise variances, by the Sage-Husa recursive noise estimator.
+///
+/// Each step weighs the noise implied by the latest innovation against the
+/// running estimates with `d = (1 - b) / (1 - bᵏ)` for the `forgetting`
+/// factor `b` (in `(0, 1)`) after `k` steps, so that early steps learn fast
+/// and later ones settle to an exponential average. An update that would
+/// make a variance negative is skipped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SageHusaFilter {
+    pub estimate: LQE,
+    pub process_noise: f64,
+    pub measurement_noise: f64,
+    pub forgetting: f64,
+    steps: i32
+}
+
+impl SageHusaFilter {
+    /// `new` creates a filter starting from `estimate` and the initial noise
+    /// guesses, with a forgetting factor of `0.98`.
+    pub fn new(estimate: LQE, process_noise: f64, measurement_noise: f64) -> Self {
+        SageHusaFilter {
+            estimate,
+            process_noise,
+            measurement_noise,
+            forgetting: 0.98,
+            steps: 0
+        }
+    }
+
+    /// `next` performs the entire predict - update cycle for `measurement`
+    /// and then adapts both noise variances, returning the details of the
+    /// step.
+    pub fn next(&mut self, measurement: f64) -> StepInfo {
+        self.steps = self.steps.saturating_add(1);
+        let weight = (1.0 - self.forgetting) / (1.0 - self.forgetting.powi(self.steps));
+        let prediction = LQE {
+            measurement: self.estimate.measurement,
+            variance: self.estimate.variance + self.process_noise
+        };
+        let innovation = measurement - prediction.measurement;
+        let measurement_noise =
+            (1.0 - weight) * self.measurement_noise + weight * (innovation.powi(2) - prediction.variance);
+        if measurement_noise > 0.0 {
+            self.measurement_noise = measurement_noise;
+        }
+        let step = prediction.correct(measurement, self.measurement_noise);
+        let process_noise = (1.0 - weight) * self.process_noise
+            + weight * ((step.gain * innovation).powi(2) + step.posterior.variance - self.estimate.variance);
+        if process_noise >= 0.0 {
+            self.process_noise = process_noise;
+        }
+        self.estimate = step.posterior;
+        step
+    }
+
+    /// `result` returns the current estimate as a tuple value.
+    pub fn result(&self) -> (f64, f64) {
+        self.estimate.result()
+    }
+}
+
+/// `StrongTrackingFilter` estimates a value that may change abruptly, by
+/// inflating the predicted variance with a fading factor whenever the
+/// innovations grow larger than the filter expects.
+///
+/// The innovation variance is tracked as an exponential average that keeps
+/// `retention` (in `[0, 1)`) of its history each step. `weakening` (at least
+/// `1.0`) scales the measurement noise it is compared against, making the
+/// filter less eager to fade.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct StrongTrackingFilter {
+    pub estimate: LQE,
+    pub process_noise: f64,
+    pub measurement_noise: f64,
+    pub retention: f64,
+    pub weakening: f64,
+    innovation_variance: Option<f64>,
+    fading_factor: f64
+}
+
+impl StrongTrackingFilter {
+    /// `new` creates a filter starting from `estimate`, with a retention of
+    /// `0.95` and no weakening.
+    pub fn new(estimate: LQE, process_noise: f64, measurement_noise: f64) -> Self {
+        StrongTrackingFilter {
+            estimate,
+            process_noise,
+            measurement_noise,
+            retention: 0.95,
+            weakening: 1.0,
+            innovation_variance: None,
+            fading_factor: 1.0
+        }
+    }
+
+    /// `fading_factor` returns the factor the variance was inflated by in the
+    /// last step; `1.0` means the filter behaved like a plain Kalman filter.
+    pub fn fading_factor(&self) -> f64 {
+        self.fading_factor
+    }
+
+    /// `next` performs the entire predict - update cycle for `measurement`,
+    /// returning the details of the step.
+    pub fn next(&mut self, measurement: f64) -> StepInfo {
+        let innovation = measurement - self.estimate.measurement;
+        let innovation_variance = match self.innovation_variance {
+            Some(variance) => (self.retention * variance + innovation.powi(2)) / (1.0 + self.retention),
+            None => innovation.powi(2)
+        };
+        let excess = innovation_variance - self.weakening * self.measurement_noise - self.process_noise;
+        self.fading_factor = if self.estimate.variance > 0.0 {
+            (excess / self.estimate.variance).max(1.0)
+        } else {
+            1.0
+        };
+        self.innovation_variance = Some(innovation_variance);
+        let prediction = LQE {
+            measurement: self.estimate.measurement,
+            variance: self.fading_factor * self.estimate.variance + self.process_noise
+        };
+        let step = prediction.correct(measurement, self.measurement_noise);
+        self.estimate = step.posterior;
+        step
+    }
+
+    /// `result` returns the current estimate as a tuple value.
+    pub fn result(&self) -> (f64, f64) {
+        self.estimate.result()
+    }
+}
+
+/// `RollingVariance` estimates the variance of a sensor's noise from the
+/// last `window` raw readings, e.g. taken during a calibration phase with the
+/// measured value held constant, to pass on as the measurement variance.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::LQE;
+/// use lqe::adaptive::RollingVariance;
+/// use lqe::timed::TimedFilter;
+///
+/// let mut calibration = RollingVariance::new(100);
+/// for reading in &[20.1, 19.8, 20.3, 19.9, 20.0] {
+///     calibration.push(*reading);
+/// }
+/// let variance = calibration.variance().unwrap();
+/// // => 0.037
+///
+/// let mut filter = TimedFilter::new(LQE { measurement: 20.0, variance }, 0.01);
+/// filter.next(0.0, 20.4, variance);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct RollingVariance {
+    window: usize,
+    readings: VecDeque<f64>
+}
+
+impl RollingVariance {
+    /// `new` creates an estimator over the last `window` readings.
+    ///
+    /// # Panics:
+    ///
+    /// Panics if `window` is less than 2.
+    pub fn new(window: usize) -> Self {
+        assert!(window >= 2, "window must hold at least two readings");
+        RollingVariance {
+            window,
+            readings: VecDeque::with_capacity(window)
+        }
+    }
+
+    /// `push` adds `reading`, dropping the oldest one if the window is full.
+    pub fn push(&mut self, reading: f64) {
+        if self.readings.len() == self.window {
+            self.readings.pop_front();
+        }
+        self.readings.push_back(reading);
+    }
+
+    /// `mean` returns the mean of the readings in the window, or `None` if
+    /// there are none.
+    pub fn mean(&self) -> Option<f64> {
+        if self.readings.is_empty() {
+            return None;
+        }
+        Some(self.readings.iter().sum::<f64>() / self.readings.len() as f64)
+    }
+
+    /// `variance` returns the sample variance of the readings in the window,
+    /// or `None` until there are at least two.
+    pub fn variance(&self) -> Option<f64> {
+        if self.readings.len() < 2 {
+            return None;
+        }
+        let mean = self.mean()?;
+        let squares = self.readings.iter().map(|reading| (reading - mean).powi(2)).sum::<f64>();
+        Some(squares / (self.readings.len() - 1) as f64)
+    }
+
+    /// `len` returns the number of readings in the window.
+    pub fn len(&self) -> usize {
+        self.readings.len()
+    }
+
+    /// `is_empty` returns whether no readings have been pushed yet.
+    pub fn is_empty(&self) -> bool {
+        self.readings.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particle::Random;
+
+    #[test]
+    fn learns_measurement_variance() {
+        let mut random = Random::new(11);
+        let mut filter = VariationalFilter::new(LQE { measurement: 0.0, variance: 1.0 }, 1e-4, 0.1);
+        filter.forgetting = 1.0;
+
+        for _ in 0..2000 {
+            filter.next(5.0 + 2.0 * random.normal());
+        }
+        assert!((filter.measurement_variance() - 4.0).abs() < 0.5);
+        assert!((filter.result().0 - 5.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn follows_changing_variance() {
+        let mut random = Random::new(5);
+        let mut filter = VariationalFilter::new(LQE { measurement: 0.0, variance: 1.0 }, 1e-4, 1.0);
+
+        for _ in 0..500 {
+            filter.next(0.1 * random.normal());
+        }
+        let quiet = filter.measurement_variance();
+        for _ in 0..500 {
+            filter.next(3.0 * random.normal());
+        }
+        assert!(quiet < 0.1);
+        assert!(filter.measurement_variance() > 4.0);
+    }
+
+    #[test]
+    fn estimates_both_noise_variances() {
+        let mut random = Random::new(3);
+        let mut filter = SageHusaFilter::new(LQE { measurement: 0.0, variance: 1.0 }, 0.5, 0.5);
+
+        for _ in 0..2000 {
+            filter.next(5.0 + 2.0 * random.normal());
+        }
+        assert!((filter.measurement_noise - 4.0).abs() < 1.5);
+        assert!(filter.process_noise < 0.05);
+        assert!((filter.result().0 - 5.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn recovers_from_step_change() {
+        let initial = LQE { measurement: 0.0, variance: 0.01 };
+        let mut tracking = StrongTrackingFilter::new(initial, 0.001, 0.1);
+        let mut plain = initial;
+
+        for _ in 0..20 {
+            tracking.next(0.0);
+            plain = LQE { variance: plain.variance + 0.001, ..plain }.correct(0.0, 0.1).posterior;
+        }
+        assert_eq!(tracking.fading_factor(), 1.0);
+        for _ in 0..5 {
+            tracking.next(10.0);
+            plain = LQE { variance: plain.variance + 0.001, ..plain }.correct(10.0, 0.1).posterior;
+        }
+        assert!(tracking.fading_factor() > 1.0);
+        assert!((tracking.result().0 - 10.0).abs() < 1.0);
+        assert!((plain.measurement - 10.0).abs() > 5.0);
+    }
+
+    #[test]
+    fn measures_noise_over_window() {
+        let mut calibration = RollingVariance::new(4);
+        assert_eq!(calibration.variance(), None);
+
+        for reading in &[100.0, 1.0, 3.0, 1.0, 3.0] {
+            calibration.push(*reading);
+        }
+        assert_eq!(calibration.len(), 4);
+        assert_eq!(calibration.mean(), Some(2.0));
+        assert_eq!(calibration.variance(), Some(4.0 / 3.0));
+    }
+}
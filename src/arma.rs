@@ -0,0 +1,153 @@
+//! ARMA models in state-space form.
+//!
+//! An ARMA(p, q) series follows
+//! `yₜ = φ₁yₜ₋₁ + … + φₚyₜ₋ₚ + εₜ + θ₁εₜ₋₁ + … + θ_qεₜ₋_q` for white noise `ε`.
+//! `Arma` converts its coefficients to the equivalent state-space model, so
+//! that the Kalman machinery of the crate can evaluate the likelihood of a
+//! series exactly and forecast it.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::arma::Arma;
+//!
+//! let model = Arma { ar: vec![0.5], ma: vec![0.3], variance: 1.0 };
+//! let series = [0.2, 1.1, 0.7, -0.4, 0.1];
+//! model.log_likelihood(&series).unwrap();
+//! model.forecast(&series, 2).unwrap()[1].measurement;
+//! // => 0.102
+//! ```
+
+use crate::error::Error;
+use crate::matrix::{DenseMatrix, Matrix};
+use crate::multivariate::DynamicKalmanFilter;
+use crate::LQE;
+
+/// `Arma` is a zero-mean ARMA model with autoregressive coefficients `ar`,
+/// moving average coefficients `ma` and innovation variance `variance`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Arma {
+    pub ar: Vec<f64>,
+    pub ma: Vec<f64>,
+    pub variance: f64
+}
+
+impl Arma {
+    /// `state_space` returns the model in Harvey's state-space form, with
+    /// `max(p, q + 1)` states of which the first is the series itself, and
+    /// no measurement noise.
+    ///
+    /// The filter starts from the stationary distribution of the state,
+    /// found by solving `P = F P Fᵀ + Q` by doubling, which assumes the
+    /// autoregressive part is stationary.
+    pub fn state_space(&self) -> DynamicKalmanFilter<DenseMatrix> {
+        let states = self.ar.len().max(self.ma.len() + 1);
+        let mut filter = DynamicKalmanFilter::<DenseMatrix>::new(states, 1);
+        let mut transition = DenseMatrix::zeros(states, states);
+        let mut loading = DenseMatrix::zeros(states, 1);
+        loading.set(0, 0, 1.0);
+        for i in 0..states {
+            transition.set(i, 0, self.ar.get(i).copied().unwrap_or(0.0));
+            if i + 1 < states {
+                transition.set(i, i + 1, 1.0);
+                loading.set(i + 1, 0, self.ma.get(i).copied().unwrap_or(0.0));
+            }
+        }
+        let process_noise = loading.mul(&loading.transpose()).scale(self.variance);
+
+        // Smith's doubling: P = Σ Fᵏ Q Fᵏᵀ, summing twice as many terms each
+        // iteration.
+        let mut covariance = process_noise.clone();
+        let mut power = transition.clone();
+        for _ in 0..64 {
+            let increment = power.mul(&covariance).mul(&power.transpose());
+            covariance = covariance.add(&increment);
+            power = power.mul(&power);
+            let largest = (0..states)
+                .flat_map(|i| (0..states).map(move |j| (i, j)))
+                .map(|(i, j)| increment.get(i, j).abs())
+                .fold(0.0, f64::max);
+            if largest < 1e-15 {
+                break;
+            }
+        }
+
+        filter.transition = transition;
+        filter.process_noise = process_noise;
+        filter.covariance = covariance;
+        filter.measurement_noise = DenseMatrix::zeros(1, 1);
+        filter
+    }
+
+    /// `log_likelihood` returns the exact Gaussian log-likelihood of
+    /// `series` under the model, e.g. to fit the coefficients by maximizing
+    /// it.
+    pub fn log_likelihood(&self, series: &[f64]) -> Result<f64, Error> {
+        let mut filter = self.state_space();
+        let mut log_likelihood = 0.0;
+        for (t, value) in series.iter().enumerate() {
+            if t > 0 {
+                filter.predict();
+            }
+            let measurement = DenseMatrix::vector(&[*value]);
+            log_likelihood += filter.log_likelihood(&measurement)?;
+            filter.update(&measurement)?;
+        }
+        Ok(log_likelihood)
+    }
+
+    /// `forecast` returns the distribution of the next `horizon` values after
+    /// `series`.
+    pub fn forecast(&self, series: &[f64], horizon: usize) -> Result<Vec<LQE>, Error> {
+        let mut filter = self.state_space();
+        for (t, value) in series.iter().enumerate() {
+            if t > 0 {
+                filter.predict();
+            }
+            filter.update(&DenseMatrix::vector(&[*value]))?;
+        }
+        let mut forecasts = Vec::with_capacity(horizon);
+        for _ in 0..horizon {
+            filter.predict();
+            forecasts.push(LQE {
+                measurement: filter.state.get(0, 0),
+                variance: filter.covariance.get(0, 0)
+            });
+        }
+        Ok(forecasts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn starts_from_stationary_distribution() {
+        let ar = Arma { ar: vec![0.5], ma: vec![], variance: 1.0 };
+        let ma = Arma { ar: vec![], ma: vec![0.5], variance: 2.0 };
+
+        assert!((ar.state_space().covariance.get(0, 0) - 4.0 / 3.0).abs() < 1e-12);
+        assert!((ma.state_space().covariance.get(0, 0) - 2.5).abs() < 1e-12);
+        assert_eq!(ma.state_space().dimensions(), (2, 1));
+    }
+
+    #[test]
+    fn evaluates_exact_likelihood() {
+        let model = Arma { ar: vec![0.5], ma: vec![], variance: 1.0 };
+
+        // y₁ ~ N(0, 4/3), then y₂ | y₁ ~ N(y₁ / 2, 1).
+        let density = |x: f64, variance: f64| -0.5 * ((2.0 * std::f64::consts::PI * variance).ln() + x * x / variance);
+        let expected = density(1.0, 4.0 / 3.0) + density(2.0 - 0.5, 1.0);
+        assert!((model.log_likelihood(&[1.0, 2.0]).unwrap() - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn forecasts_autoregression() {
+        let model = Arma { ar: vec![0.5], ma: vec![], variance: 1.0 };
+
+        let forecast = model.forecast(&[2.0], 2).unwrap();
+        assert!((forecast[0].measurement - 1.0).abs() < 1e-12 && (forecast[0].variance - 1.0).abs() < 1e-12);
+        assert!((forecast[1].measurement - 0.5).abs() < 1e-12 && (forecast[1].variance - 1.25).abs() < 1e-12);
+    }
+}
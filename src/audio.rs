@@ -0,0 +1,243 @@
+//! Envelope and pitch tracking of audio.
+//!
+//! Running a filter on every sample of a 48 kHz stream is wasteful when the
+//! quantities of interest, the loudness and the pitch of a note, change
+//! over tens of milliseconds. An `AudioTracker` instead cuts the stream into
+//! blocks, measures the RMS level and the fundamental frequency of each by
+//! autocorrelation, and filters those measurements at the block rate. The
+//! filters smooth away the jitter of block-wise analysis and, for the pitch,
+//! reject the octave jumps autocorrelation is prone to.
+//!
+//! # Example:
+//!
+//! ```
+//! use std::f32::consts::PI;
+//! use lqe::audio::AudioTracker;
+//!
+//! // Half a second of A3 at 48 kHz.
+//! let samples: Vec<f32> = (0..24_000)
+//!     .map(|n| 0.5 * (2.0 * PI * 220.0 * n as f32 / 48_000.0).sin())
+//!     .collect();
+//! let mut tracker = AudioTracker::new(48_000.0);
+//! let frames = tracker.process(&samples);
+//! frames.last().unwrap().pitch.unwrap().round();
+//! // => 220.0
+//! ```
+
+use std::collections::VecDeque;
+
+use crate::{step, LQE};
+
+/// `Frame` is the tracked level and pitch at the end of a block.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Frame {
+    /// The RMS level of the signal.
+    pub envelope: f64,
+    /// The fundamental frequency in hertz, or `None` if the block is
+    /// unvoiced, i.e. noise or silence.
+    pub pitch: Option<f64>
+}
+
+/// `AudioTracker` tracks the envelope and pitch of audio sampled at
+/// `sample_rate` hertz in blocks of `block_size` samples.
+///
+/// Pitches are searched between `min_frequency` and `max_frequency`. A block
+/// is voiced if its normalized autocorrelation at the period reaches
+/// `voicing_threshold`. `envelope_noise` and `pitch_noise` are the variances
+/// the level and the pitch gain per second, the latter in Hz², and
+/// `envelope_noise_ratio` the relative variance of a block level. Pitch
+/// measurements further than `gate` standard deviations from the prediction
+/// are rejected as octave errors.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AudioTracker {
+    pub sample_rate: f64,
+    pub block_size: usize,
+    pub min_frequency: f64,
+    pub max_frequency: f64,
+    pub voicing_threshold: f64,
+    pub envelope_noise: f64,
+    pub envelope_noise_ratio: f64,
+    pub pitch_noise: f64,
+    pub gate: f64,
+    envelope: Option<LQE>,
+    pitch: Option<LQE>,
+    history: VecDeque<f64>,
+    pending: usize
+}
+
+impl AudioTracker {
+    /// `new` creates a tracker for voice and most instruments, with blocks
+    /// of about 10 ms.
+    pub fn new(sample_rate: f64) -> Self {
+        AudioTracker {
+            sample_rate,
+            block_size: (sample_rate / 100.0).round() as usize,
+            min_frequency: 60.0,
+            max_frequency: 1500.0,
+            voicing_threshold: 0.6,
+            envelope_noise: 1.0,
+            envelope_noise_ratio: 0.01,
+            pitch_noise: 1e4,
+            gate: 3.0,
+            envelope: None,
+            pitch: None,
+            history: VecDeque::new(),
+            pending: 0
+        }
+    }
+
+    /// `window` returns the number of samples analysed for each block, long
+    /// enough to hold two periods of the lowest pitch.
+    fn window(&self) -> usize {
+        self.block_size.max((2.0 * self.sample_rate / self.min_frequency).ceil() as usize)
+    }
+
+    /// `process` feeds `samples` to the tracker and returns a frame for every
+    /// block completed by them. Samples left over are kept for the next
+    /// call.
+    pub fn process(&mut self, samples: &[f32]) -> Vec<Frame> {
+        let window = self.window();
+        let mut frames = Vec::with_capacity((self.pending + samples.len()) / self.block_size.max(1));
+        for sample in samples {
+            if self.history.len() == window {
+                self.history.pop_front();
+            }
+            self.history.push_back(f64::from(*sample));
+            self.pending += 1;
+            if self.pending == self.block_size {
+                self.pending = 0;
+                frames.push(self.analyse());
+            }
+        }
+        frames
+    }
+
+    /// `analyse` measures the latest block and updates the estimates.
+    fn analyse(&mut self) -> Frame {
+        let dt = self.block_size as f64 / self.sample_rate;
+        self.history.make_contiguous();
+        let signal = self.history.as_slices().0;
+        let block = &signal[signal.len() - self.block_size..];
+        let level = (block.iter().map(|x| x * x).sum::<f64>() / block.len() as f64).sqrt();
+        let measured = self.period(signal);
+        let level_noise = self.envelope_noise_ratio * level * level + f64::EPSILON;
+        let envelope = match self.envelope {
+            Some(envelope) => {
+                let prior = step::predict(envelope, 0.0, self.envelope_noise * dt);
+                step::update(prior, level, level_noise).posterior
+            }
+            None => LQE {
+                measurement: level,
+                variance: level_noise
+            }
+        };
+        self.envelope = Some(envelope);
+
+        self.pitch = match (self.pitch, measured) {
+            (_, None) => None,
+            (None, Some(pitch)) => Some(pitch),
+            (Some(estimate), Some(pitch)) => {
+                let prior = step::predict(estimate, 0.0, self.pitch_noise * dt);
+                let step = step::update(prior, pitch.measurement, pitch.variance);
+                if step.innovation.powi(2) > self.gate.powi(2) * step.innovation_variance {
+                    Some(prior)
+                } else {
+                    Some(step.posterior)
+                }
+            }
+        };
+        Frame {
+            envelope: envelope.measurement,
+            pitch: self.pitch.map(|pitch| pitch.measurement)
+        }
+    }
+
+    /// `period` measures the fundamental frequency of the analysis window
+    /// `signal` by normalized autocorrelation, with the variance of its
+    /// quantization to whole samples, or returns `None` if it is unvoiced.
+    fn period(&self, signal: &[f64]) -> Option<LQE> {
+        let shortest = (self.sample_rate / self.max_frequency).floor().max(1.0) as usize;
+        let longest = ((self.sample_rate / self.min_frequency).ceil() as usize).min(signal.len() / 2);
+        if shortest >= longest {
+            return None;
+        }
+        let correlation = |lag: usize| {
+            let (head, tail) = (&signal[..signal.len() - lag], &signal[lag..]);
+            let cross: f64 = head.iter().zip(tail).map(|(a, b)| a * b).sum();
+            let energy: f64 = head.iter().map(|a| a * a).sum::<f64>() * tail.iter().map(|b| b * b).sum::<f64>();
+            if energy > 0.0 {
+                cross / energy.sqrt()
+            } else {
+                0.0
+            }
+        };
+        let correlations: Vec<f64> = (shortest - 1..=longest + 1).map(correlation).collect();
+        let peak = correlations[1..correlations.len() - 1].iter().cloned().fold(f64::MIN, f64::max);
+        if peak < self.voicing_threshold {
+            return None;
+        }
+        // Multiples of the period correlate about as well as the period
+        // itself, so take the first local maximum close to the peak.
+        let index = (1..correlations.len() - 1).find(|&i| {
+            correlations[i] >= 0.9 * peak
+                && correlations[i] >= correlations[i - 1]
+                && correlations[i] >= correlations[i + 1]
+        })?;
+        // Refine the lag by a parabola through the neighbouring values.
+        let (before, at, after) = (correlations[index - 1], correlations[index], correlations[index + 1]);
+        let curvature = before - 2.0 * at + after;
+        let offset = if curvature < 0.0 { 0.5 * (before - after) / curvature } else { 0.0 };
+        let lag = (shortest - 1 + index) as f64 + offset;
+        let frequency = self.sample_rate / lag;
+        Some(LQE {
+            measurement: frequency,
+            variance: (frequency / lag).powi(2) / 12.0
+        })
+    }
+
+    /// `envelope` returns the estimated RMS level, or `None` before the
+    /// first block.
+    pub fn envelope(&self) -> Option<LQE> {
+        self.envelope
+    }
+
+    /// `pitch` returns the estimated fundamental frequency, or `None` while
+    /// unvoiced.
+    pub fn pitch(&self) -> Option<LQE> {
+        self.pitch
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particle::Random;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn tracks_note_through_noise() {
+        let sample_rate = 48_000.0;
+        let mut tracker = AudioTracker::new(sample_rate);
+        let mut random = Random::new(6);
+        // A harmonic-rich note at 150 Hz, then silence with a little noise.
+        let note: Vec<f32> = (0..24_000)
+            .map(|n| {
+                let t = n as f64 / sample_rate;
+                let tone = (2.0 * PI * 150.0 * t).sin() + 0.5 * (2.0 * PI * 300.0 * t).sin();
+                (0.4 * tone + 0.02 * random.normal()) as f32
+            })
+            .collect();
+        let frames = tracker.process(&note);
+        assert_eq!(frames.len(), 50);
+        let last = frames.last().unwrap();
+        assert!((last.pitch.unwrap() - 150.0).abs() < 0.5);
+        let rms = 0.4 * (0.5_f64 + 0.125).sqrt();
+        assert!((last.envelope - rms).abs() < 0.01);
+
+        let silence: Vec<f32> = (0..4_800).map(|_| (0.001 * random.normal()) as f32).collect();
+        let frames = tracker.process(&silence);
+        assert_eq!(frames.last().unwrap().pitch, None);
+        assert!(frames.last().unwrap().envelope < 0.01);
+    }
+}
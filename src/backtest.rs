@@ -0,0 +1,465 @@
+//! Backtesting against recorded ground truth.
+//!
+//! `backtest` runs an `Estimator` over recorded measurements and scores its
+//! estimates against the true values, e.g. from a reference sensor or a
+//! simulation, so that tunings can be compared by more than eye.
+//!
+//! When the truth only becomes available while the filter is running, e.g.
+//! from a survey-grade reference that reports late, a `ReferenceTracker`
+//! keeps the same statistics live.
+//!
+//! A `Comparison` runs several estimators over the same measurements in
+//! lockstep and reports their statistics side by side, e.g. the baselines
+//! against tunings of a Kalman filter.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::backtest;
+//! use lqe::baseline::MovingAverage;
+//!
+//! let truth = [1.0, 1.0, 1.0, 1.0, 1.0];
+//! let measurements = [1.2, 0.9, 1.1, 0.8, 1.0];
+//! let scores = backtest::backtest(&mut MovingAverage::new(3), &measurements, &truth);
+//! scores.rmse();
+//! // => 0.102
+//! ```
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::baseline::{BayesEstimator, Estimator};
+use crate::bayes::BayesFilter;
+use crate::diagnostics;
+use crate::matrix::Matrix;
+use crate::LQE;
+
+/// `ErrorStatistics` accumulates the errors of estimates against the true
+/// values they estimate.
+///
+/// An estimate covers the truth when the truth lies within the central
+/// interval of the estimate's normal distribution holding `confidence` of
+/// its probability. Estimates without a finite variance, e.g. of a moving
+/// average still warming up, always cover the truth and are left out of the
+/// log score.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ErrorStatistics {
+    count: usize,
+    error: f64,
+    absolute_error: f64,
+    squared_error: f64,
+    covered: usize,
+    log_score: f64,
+    scored: usize,
+    bound: f64
+}
+
+impl ErrorStatistics {
+    /// `new` creates empty statistics with `95%` confidence intervals.
+    pub fn new() -> Self {
+        Self::with_confidence(0.95)
+    }
+
+    /// `with_confidence` creates empty statistics with intervals of the
+    /// given `confidence`, in `(0, 1)`.
+    pub fn with_confidence(confidence: f64) -> Self {
+        ErrorStatistics {
+            count: 0,
+            error: 0.0,
+            absolute_error: 0.0,
+            squared_error: 0.0,
+            covered: 0,
+            log_score: 0.0,
+            scored: 0,
+            // The squared bound in standard deviations is chi-square with
+            // one degree of freedom.
+            bound: diagnostics::chi_square_quantile(confidence, 1.0)
+        }
+    }
+
+    /// `push` adds the error of `estimate` against `truth`.
+    pub fn push(&mut self, estimate: &LQE, truth: f64) {
+        let error = estimate.measurement - truth;
+        self.count += 1;
+        self.error += error;
+        self.absolute_error += error.abs();
+        self.squared_error += error * error;
+        if error * error <= self.bound * estimate.variance {
+            self.covered += 1;
+        }
+        if estimate.variance.is_finite() {
+            self.scored += 1;
+            self.log_score -= 0.5 * (error * error / estimate.variance + (2.0 * std::f64::consts::PI * estimate.variance).ln());
+        }
+    }
+
+    /// `count` returns the number of estimates scored.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// `rmse` returns the root mean squared error.
+    pub fn rmse(&self) -> f64 {
+        (self.squared_error / self.count as f64).sqrt()
+    }
+
+    /// `mae` returns the mean absolute error.
+    pub fn mae(&self) -> f64 {
+        self.absolute_error / self.count as f64
+    }
+
+    /// `bias` returns the mean error, positive when the estimates run high.
+    pub fn bias(&self) -> f64 {
+        self.error / self.count as f64
+    }
+
+    /// `coverage` returns the fraction of estimates whose confidence interval
+    /// covers the truth, which should be close to the confidence for well
+    /// calibrated variances.
+    pub fn coverage(&self) -> f64 {
+        self.covered as f64 / self.count as f64
+    }
+
+    /// `log_score` returns the mean log density of the truth under the
+    /// estimates, higher being better. It rewards accurate estimates and
+    /// honest variances alike.
+    pub fn log_score(&self) -> f64 {
+        self.log_score / self.scored as f64
+    }
+}
+
+impl Default for ErrorStatistics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `backtest` runs `model` over `measurements` and scores each estimate
+/// against the `truth` at the same step.
+///
+/// # Panics:
+///
+/// Panics if `measurements` and `truth` differ in length.
+pub fn backtest(model: &mut impl Estimator, measurements: &[f64], truth: &[f64]) -> ErrorStatistics {
+    assert_eq!(measurements.len(), truth.len(), "expected a true value per measurement");
+    let mut statistics = ErrorStatistics::new();
+    for (measurement, truth) in measurements.iter().zip(truth) {
+        let estimate = model.next(*measurement);
+        statistics.push(&estimate, *truth);
+    }
+    statistics
+}
+
+/// `ReferenceTracker` wraps a live `estimator` and scores its estimates
+/// against a reference channel that reports the true value of a past step.
+///
+/// The estimates of the last `capacity` steps are kept for the reference to
+/// catch up with; references to older steps are ignored.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::backtest::ReferenceTracker;
+/// use lqe::baseline::{Estimator, ExponentialMovingAverage};
+///
+/// let mut tracker = ReferenceTracker::new(ExponentialMovingAverage::new(0.5), 10);
+/// let step = tracker.step();
+/// tracker.next(1.2);
+/// tracker.next(0.9);
+/// // The reference for the first step arrives late.
+/// tracker.reference(step, 1.0);
+/// tracker.statistics().rmse();
+/// // => 0.2
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReferenceTracker<E> {
+    pub estimator: E,
+    capacity: usize,
+    estimates: VecDeque<LQE>,
+    step: usize,
+    statistics: ErrorStatistics
+}
+
+impl<E: Estimator> ReferenceTracker<E> {
+    /// `new` wraps `estimator`, keeping the estimates of the last `capacity`
+    /// steps.
+    pub fn new(estimator: E, capacity: usize) -> Self {
+        ReferenceTracker {
+            estimator,
+            capacity,
+            estimates: VecDeque::with_capacity(capacity),
+            step: 0,
+            statistics: ErrorStatistics::new()
+        }
+    }
+
+    /// `step` returns the index of the next step, counted from zero.
+    pub fn step(&self) -> usize {
+        self.step
+    }
+
+    /// `reference` scores the estimate of `step` against its `truth`,
+    /// returning whether that estimate was still kept.
+    pub fn reference(&mut self, step: usize, truth: f64) -> bool {
+        let oldest = self.step - self.estimates.len();
+        let estimates = &self.estimates;
+        match step.checked_sub(oldest).and_then(|index| estimates.get(index)) {
+            Some(estimate) => {
+                self.statistics.push(estimate, truth);
+                true
+            }
+            None => false
+        }
+    }
+
+    /// `statistics` returns the errors scored so far.
+    pub fn statistics(&self) -> &ErrorStatistics {
+        &self.statistics
+    }
+
+    /// `reset_statistics` forgets the errors scored so far, e.g. to report
+    /// them per interval.
+    pub fn reset_statistics(&mut self) {
+        self.statistics = ErrorStatistics::new();
+    }
+}
+
+impl<E: Estimator> Estimator for ReferenceTracker<E> {
+    fn next(&mut self, measurement: f64) -> LQE {
+        let estimate = self.estimator.next(measurement);
+        if self.estimates.len() == self.capacity {
+            self.estimates.pop_front();
+        }
+        if self.capacity > 0 {
+            self.estimates.push_back(estimate);
+        }
+        self.step += 1;
+        estimate
+    }
+}
+
+/// `Comparison` is a set of named estimators to be scored on the same
+/// measurements.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::backtest::Comparison;
+/// use lqe::baseline::{ExponentialMovingAverage, MovingAverage};
+///
+/// let truth = [1.0, 1.0, 1.0, 1.0, 1.0];
+/// let measurements = [1.2, 0.9, 1.1, 0.8, 1.0];
+/// let report = Comparison::new()
+///     .with("moving average", MovingAverage::new(3))
+///     .with("smoothing", ExponentialMovingAverage::new(0.3))
+///     .run(&measurements, &truth);
+/// println!("{}", report);
+/// // name                 rmse       mae      bias  coverage log score
+/// // moving average     0.1025    0.0833    0.0433      1.00      1.20
+/// // smoothing          0.1130    0.0885    0.0885      0.80      0.84
+/// ```
+#[derive(Default)]
+pub struct Comparison {
+    estimators: Vec<(String, Box<dyn Estimator>)>
+}
+
+impl Comparison {
+    /// `new` creates an empty comparison.
+    pub fn new() -> Self {
+        Comparison {
+            estimators: Vec::new()
+        }
+    }
+
+    /// `with` adds `estimator` under `name`.
+    pub fn with(mut self, name: impl Into<String>, estimator: impl Estimator + 'static) -> Self {
+        self.estimators.push((name.into(), Box::new(estimator)));
+        self
+    }
+
+    /// `with_filter` adds `filter`, a `BayesFilter` of a single scalar
+    /// measurement such as one tuning of a `DynamicKalmanFilter`, under
+    /// `name`. Its estimates are the values `observation` picks out of its
+    /// state, as for a `BayesEstimator`.
+    pub fn with_filter<T, F>(self, name: impl Into<String>, filter: F, observation: T) -> Self
+    where
+        T: Matrix + 'static,
+        F: BayesFilter<Measurement = T, Estimate = (T, T)> + 'static
+    {
+        self.with(name, BayesEstimator::new(filter, observation))
+    }
+
+    /// `run` feeds every measurement to each estimator in turn before moving
+    /// on to the next, scoring each estimate against the `truth` at the same
+    /// step.
+    ///
+    /// # Panics:
+    ///
+    /// Panics if `measurements` and `truth` differ in length.
+    pub fn run(mut self, measurements: &[f64], truth: &[f64]) -> Report {
+        assert_eq!(measurements.len(), truth.len(), "expected a true value per measurement");
+        let mut statistics = vec![ErrorStatistics::new(); self.estimators.len()];
+        for (measurement, truth) in measurements.iter().zip(truth) {
+            for ((_, estimator), statistics) in self.estimators.iter_mut().zip(&mut statistics) {
+                statistics.push(&estimator.next(*measurement), *truth);
+            }
+        }
+        Report {
+            rows: self.estimators.into_iter().map(|(name, _)| name).zip(statistics).collect()
+        }
+    }
+}
+
+/// `Report` holds the statistics of each estimator of a `Comparison`, in the
+/// order they were added, and displays them as a table.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    pub rows: Vec<(String, ErrorStatistics)>
+}
+
+impl Report {
+    /// `best` returns the name of the estimator with the lowest RMSE.
+    pub fn best(&self) -> Option<&str> {
+        self.rows
+            .iter()
+            .min_by(|(_, a), (_, b)| a.rmse().total_cmp(&b.rmse()))
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = self.rows.iter().map(|(name, _)| name.len()).fold(4, usize::max);
+        write!(
+            f,
+            "{:<width$}  {:>9} {:>9} {:>9} {:>9} {:>9}",
+            "name",
+            "rmse",
+            "mae",
+            "bias",
+            "coverage",
+            "log score",
+            width = width
+        )?;
+        for (name, statistics) in &self.rows {
+            write!(
+                f,
+                "\n{:<width$}  {:>9.4} {:>9.4} {:>9.4} {:>9.2} {:>9.2}",
+                name,
+                statistics.rmse(),
+                statistics.mae(),
+                statistics.bias(),
+                statistics.coverage(),
+                statistics.log_score(),
+                width = width
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adaptive::SageHusaFilter;
+    use crate::baseline::MovingAverage;
+    use crate::matrix::DenseMatrix;
+    use crate::multivariate::DynamicKalmanFilter;
+    use crate::particle::Random;
+
+    #[test]
+    fn scores_errors() {
+        let mut statistics = ErrorStatistics::new();
+
+        statistics.push(&LQE { measurement: 2.0, variance: 1.0 }, 1.0);
+        statistics.push(&LQE { measurement: -1.0, variance: 1.0 }, 2.0);
+        assert_eq!(statistics.count(), 2);
+        assert_eq!((statistics.bias(), statistics.mae()), (-1.0, 2.0));
+        assert!((statistics.rmse() - 5f64.sqrt()).abs() < 1e-12);
+        // Only the first error is within 1.96 standard deviations.
+        assert_eq!(statistics.coverage(), 0.5);
+        let log_score = -0.5 * (5.0 + (2.0 * std::f64::consts::PI).ln());
+        assert!((statistics.log_score() - log_score).abs() < 1e-12);
+        statistics.push(&LQE { measurement: 9.0, variance: f64::INFINITY }, 0.0);
+        assert!((statistics.log_score() - log_score).abs() < 1e-12);
+        assert!((statistics.coverage() - 2.0 / 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn ranks_filter_above_noisy_baseline() {
+        let mut random = Random::new(6);
+        let truth = vec![5.0; 300];
+        let measurements = truth.iter().map(|x| x + random.normal()).collect::<Vec<_>>();
+
+        let filter = backtest(
+            &mut SageHusaFilter::new(LQE { measurement: 0.0, variance: 100.0 }, 0.0001, 1.0),
+            &measurements,
+            &truth
+        );
+        let baseline = backtest(&mut MovingAverage::new(2), &measurements, &truth);
+        assert!(filter.rmse() < baseline.rmse());
+        assert!(filter.coverage() > 0.8);
+    }
+
+    #[test]
+    fn scores_delayed_references() {
+        let mut tracker = ReferenceTracker::new(MovingAverage::new(2), 2);
+
+        for z in &[1.0, 3.0, 5.0] {
+            tracker.next(*z);
+        }
+        // The estimates were 1, 2 and 4; the first is no longer kept.
+        assert!(!tracker.reference(0, 1.0));
+        assert!(tracker.reference(1, 1.0));
+        assert!(tracker.reference(2, 4.0));
+        assert!(!tracker.reference(3, 4.0));
+        assert_eq!(tracker.statistics().count(), 2);
+        assert_eq!(tracker.statistics().bias(), 0.5);
+
+        tracker.reset_statistics();
+        assert_eq!(tracker.statistics().count(), 0);
+    }
+
+    #[test]
+    fn compares_estimators_side_by_side() {
+        let mut random = Random::new(6);
+        let truth = vec![5.0; 200];
+        let measurements = truth.iter().map(|x| x + random.normal()).collect::<Vec<_>>();
+
+        let report = Comparison::new()
+            .with("noisy", MovingAverage::new(2))
+            .with("smooth", MovingAverage::new(20))
+            .run(&measurements, &truth);
+        assert_eq!(report.rows.len(), 2);
+        assert_eq!(report.best(), Some("smooth"));
+        assert_eq!(report.rows[0].1, backtest(&mut MovingAverage::new(2), &measurements, &truth));
+        let table = report.to_string();
+        assert_eq!(table.lines().count(), 3);
+        assert!(table.lines().nth(2).unwrap().starts_with("smooth"));
+    }
+
+    #[test]
+    fn compares_kalman_tunings() {
+        // A value moving at a constant rate, measured with unit variance.
+        let mut random = Random::new(7);
+        let truth = (0..200).map(|t| 0.1 * f64::from(t)).collect::<Vec<_>>();
+        let measurements = truth.iter().map(|x| x + random.normal()).collect::<Vec<_>>();
+        let tuning = |process_noise: f64| {
+            let mut filter = DynamicKalmanFilter::<DenseMatrix>::new(2, 1);
+            filter.transition = DenseMatrix::from_row_slices(&[&[1.0, 1.0], &[0.0, 1.0]]);
+            filter.process_noise = DenseMatrix::identity(2).scale(process_noise);
+            filter
+        };
+        let observation = DenseMatrix::from_row_slices(&[&[1.0, 0.0]]);
+
+        let report = Comparison::new()
+            .with_filter("jumpy", tuning(1.0), observation.clone())
+            .with_filter("steady", tuning(1e-6), observation.clone())
+            .with("average", MovingAverage::new(20))
+            .run(&measurements, &truth);
+        assert_eq!(report.best(), Some("steady"));
+        let mut steady = BayesEstimator::new(tuning(1e-6), observation);
+        assert_eq!(report.rows[1].1, backtest(&mut steady, &measurements, &truth));
+    }
+}
@@ -0,0 +1,419 @@
+//! Simple smoothers to compare the filters against.
+//!
+//! A Kalman filter earns its keep by beating the obvious alternatives. The
+//! smoothers here are those alternatives: a `MovingAverage`, an
+//! `ExponentialMovingAverage` and a causal `SavitzkyGolay` filter. Like the
+//! adaptive filters they implement `Estimator`, taking one measurement per
+//! step and returning an estimate with a variance, so they can be swapped
+//! for one another in the same loop.
+//!
+//! The Kalman filters they are measured against join in the same way: a
+//! `ScalarKalmanFilter` runs the scalar predict - update loop with fixed
+//! noise, and a `BayesEstimator` runs any `BayesFilter` of a single scalar
+//! measurement, e.g. a `DynamicKalmanFilter` with a richer state.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::adaptive::SageHusaFilter;
+//! use lqe::baseline::{Estimator, MovingAverage};
+//! use lqe::LQE;
+//!
+//! let mut estimators: Vec<Box<dyn Estimator>> = vec![
+//!     Box::new(MovingAverage::new(4)),
+//!     Box::new(SageHusaFilter::new(LQE { measurement: 0.0, variance: 1.0 }, 0.01, 1.0))
+//! ];
+//! for estimator in &mut estimators {
+//!     for z in &[1.0, 1.2, 0.9, 1.1] {
+//!         estimator.next(*z);
+//!     }
+//! }
+//! ```
+
+use std::collections::VecDeque;
+
+use crate::adaptive::{RollingVariance, SageHusaFilter, StrongTrackingFilter, VariationalFilter};
+use crate::bayes::BayesFilter;
+use crate::error::Error;
+use crate::matrix::{DenseMatrix, Matrix};
+use crate::{step, LQE};
+
+/// `Estimator` is anything that estimates a value from a stream of
+/// measurements, one per step.
+pub trait Estimator {
+    /// `next` folds in `measurement` and returns the estimate after it.
+    fn next(&mut self, measurement: f64) -> LQE;
+}
+
+impl Estimator for VariationalFilter {
+    fn next(&mut self, measurement: f64) -> LQE {
+        VariationalFilter::next(self, measurement)
+    }
+}
+
+impl Estimator for SageHusaFilter {
+    fn next(&mut self, measurement: f64) -> LQE {
+        SageHusaFilter::next(self, measurement).posterior
+    }
+}
+
+impl Estimator for StrongTrackingFilter {
+    fn next(&mut self, measurement: f64) -> LQE {
+        StrongTrackingFilter::next(self, measurement).posterior
+    }
+}
+
+/// `ScalarKalmanFilter` estimates a randomly walking value with the scalar
+/// Kalman filter, gaining `process_noise` per step and measured with
+/// `measurement_noise`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ScalarKalmanFilter {
+    pub estimate: LQE,
+    pub process_noise: f64,
+    pub measurement_noise: f64
+}
+
+impl ScalarKalmanFilter {
+    /// `new` creates a filter starting out from `estimate`.
+    pub const fn new(estimate: LQE, process_noise: f64, measurement_noise: f64) -> Self {
+        ScalarKalmanFilter {
+            estimate,
+            process_noise,
+            measurement_noise
+        }
+    }
+}
+
+impl Estimator for ScalarKalmanFilter {
+    fn next(&mut self, measurement: f64) -> LQE {
+        let prior = step::predict(self.estimate, 0.0, self.process_noise);
+        self.estimate = step::update(prior, measurement, self.measurement_noise).posterior;
+        self.estimate
+    }
+}
+
+/// `BayesEstimator` runs `filter`, a `BayesFilter` of a single scalar
+/// measurement, as an `Estimator`. The estimate is the measured value
+/// `H x` with variance `H P Hᵀ`, for the 1 x `N` `observation` `H`.
+///
+/// A measurement the filter rejects, e.g. for a singular innovation, leaves
+/// it at its prediction.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::baseline::{BayesEstimator, Estimator};
+/// use lqe::matrix::{DenseMatrix, Matrix};
+/// use lqe::multivariate::DynamicKalmanFilter;
+///
+/// // A position and velocity, measuring the position.
+/// let mut filter = DynamicKalmanFilter::<DenseMatrix>::new(2, 1);
+/// filter.transition = DenseMatrix::from_row_slices(&[&[1.0, 1.0], &[0.0, 1.0]]);
+/// let observation = filter.observation.clone();
+/// let mut estimator = BayesEstimator::new(filter, observation);
+/// estimator.next(2.0).measurement;
+/// // => 1.3333333333333333
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct BayesEstimator<F, T> {
+    pub filter: F,
+    pub observation: T
+}
+
+impl<F, T> BayesEstimator<F, T> {
+    /// `new` wraps `filter`, reporting its state through `observation`.
+    pub fn new(filter: F, observation: T) -> Self {
+        BayesEstimator { filter, observation }
+    }
+}
+
+impl<T: Matrix, F: BayesFilter<Measurement = T, Estimate = (T, T)>> Estimator for BayesEstimator<F, T> {
+    fn next(&mut self, measurement: f64) -> LQE {
+        // A rejected measurement leaves the filter at its prediction.
+        let _ = self.filter.step(&T::vector(&[measurement]));
+        let (state, covariance) = self.filter.estimate();
+        LQE {
+            measurement: self.observation.mul(&state).get(0, 0),
+            variance: self
+                .observation
+                .mul(&covariance)
+                .mul(&self.observation.transpose())
+                .get(0, 0)
+        }
+    }
+}
+
+/// `MovingAverage` estimates a value as the mean of the last `window`
+/// measurements, with the variance of that mean, `s² / n`, for the sample
+/// variance `s²` of the `n` measurements averaged.
+///
+/// The variance is infinite until there are two measurements.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MovingAverage {
+    readings: RollingVariance
+}
+
+impl MovingAverage {
+    /// `new` creates a moving average over `window` measurements.
+    ///
+    /// # Panics:
+    ///
+    /// Panics if `window` is less than 2.
+    pub fn new(window: usize) -> Self {
+        MovingAverage {
+            readings: RollingVariance::new(window)
+        }
+    }
+}
+
+impl Estimator for MovingAverage {
+    fn next(&mut self, measurement: f64) -> LQE {
+        self.readings.push(measurement);
+        LQE {
+            measurement: self.readings.mean().unwrap_or(measurement),
+            variance: self
+                .readings
+                .variance()
+                .map_or(f64::INFINITY, |variance| variance / self.readings.len() as f64)
+        }
+    }
+}
+
+/// `ExponentialMovingAverage` estimates a value by exponential smoothing
+/// with weight `alpha` (in `(0, 1]`) on the newest measurement.
+///
+/// The spread of the measurements around the average is tracked with the
+/// same weight, and the variance of the estimate is `α / (2 - α)` times that
+/// spread, as for independent measurements around a constant value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ExponentialMovingAverage {
+    pub alpha: f64,
+    average: Option<f64>,
+    spread: f64,
+    steps: usize
+}
+
+impl ExponentialMovingAverage {
+    /// `new` creates an average that starts at the first measurement.
+    pub const fn new(alpha: f64) -> Self {
+        ExponentialMovingAverage {
+            alpha,
+            average: None,
+            spread: 0.0,
+            steps: 0
+        }
+    }
+}
+
+impl Estimator for ExponentialMovingAverage {
+    fn next(&mut self, measurement: f64) -> LQE {
+        self.steps += 1;
+        let average = match self.average {
+            None => measurement,
+            Some(average) => {
+                let difference = measurement - average;
+                self.spread = (1.0 - self.alpha) * (self.spread + self.alpha * difference * difference);
+                average + self.alpha * difference
+            }
+        };
+        self.average = Some(average);
+        LQE {
+            measurement: average,
+            variance: if self.steps < 2 {
+                f64::INFINITY
+            } else {
+                self.alpha / (2.0 - self.alpha) * self.spread
+            }
+        }
+    }
+}
+
+/// `SavitzkyGolay` estimates a value by fitting a polynomial of `order` to
+/// the last `window` measurements by least squares and evaluating it at the
+/// newest one, which preserves peaks and slopes better than averaging.
+///
+/// The variance is that of the fitted value, from the scatter of the
+/// measurements around the fit. Until the window is full the estimate is the
+/// mean of the measurements so far.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SavitzkyGolay {
+    window: usize,
+    order: usize,
+    design: DenseMatrix,
+    // The least squares fit, (AᵀA)⁻¹ Aᵀ, and the variance of its constant
+    // coefficient per unit of scatter, the first element of (AᵀA)⁻¹.
+    projection: DenseMatrix,
+    gain: f64,
+    fit: Vec<f64>,
+    readings: VecDeque<f64>
+}
+
+impl SavitzkyGolay {
+    /// `new` creates a filter fitting a polynomial of `order` to `window`
+    /// measurements.
+    ///
+    /// Returns `Error::SingularCovariance` if the fit is too ill-conditioned
+    /// to compute, as happens for high orders.
+    ///
+    /// # Panics:
+    ///
+    /// Panics unless `window` exceeds `order + 1`, which leaves the residuals
+    /// to estimate the noise from.
+    pub fn new(window: usize, order: usize) -> Result<Self, Error> {
+        assert!(window > order + 1, "window must exceed order + 1");
+        let design = Self::design(window, order);
+        let normal = design.transpose().mul(&design);
+        let projection = normal
+            .cholesky_solve(&design.transpose())
+            .ok_or(Error::SingularCovariance)?;
+        let mut first = DenseMatrix::zeros(order + 1, 1);
+        first.set(0, 0, 1.0);
+        let gain = normal.cholesky_solve(&first).ok_or(Error::SingularCovariance)?.get(0, 0);
+        if !gain.is_finite() || gain <= 0.0 {
+            return Err(Error::SingularCovariance);
+        }
+        Ok(SavitzkyGolay {
+            window,
+            order,
+            design,
+            projection,
+            gain,
+            fit: vec![0.0; order + 1],
+            readings: VecDeque::with_capacity(window)
+        })
+    }
+
+    /// `design` returns the powers `tᵏ` of the times `t = (1 - window) /
+    /// window, .., 0` of the measurements relative to the newest, one row per
+    /// measurement. Scaling the times into `[-1, 0]` keeps the powers from
+    /// overflowing the fit, and leaves its value at `t = 0` as it is.
+    fn design(window: usize, order: usize) -> DenseMatrix {
+        let mut design = DenseMatrix::zeros(window, order + 1);
+        for i in 0..window {
+            let time = (i as f64 + 1.0 - window as f64) / window as f64;
+            for k in 0..=order {
+                design.set(i, k, time.powi(k as i32));
+            }
+        }
+        design
+    }
+}
+
+impl Estimator for SavitzkyGolay {
+    fn next(&mut self, measurement: f64) -> LQE {
+        if self.readings.len() == self.window {
+            self.readings.pop_front();
+        }
+        self.readings.push_back(measurement);
+        if self.readings.len() < self.window {
+            let count = self.readings.len() as f64;
+            return LQE {
+                measurement: self.readings.iter().sum::<f64>() / count,
+                variance: f64::INFINITY
+            };
+        }
+
+        let (projection, design, readings) = (&self.projection, &self.design, &self.readings);
+        for (k, coefficient) in self.fit.iter_mut().enumerate() {
+            *coefficient = readings.iter().enumerate().map(|(j, y)| projection.get(k, j) * y).sum();
+        }
+        let fit = &self.fit;
+        let scatter = readings
+            .iter()
+            .enumerate()
+            .map(|(i, y)| {
+                let fitted = fit.iter().enumerate().map(|(k, c)| design.get(i, k) * c).sum::<f64>();
+                (y - fitted).powi(2)
+            })
+            .sum::<f64>()
+            / (self.window - self.order - 1) as f64;
+        // The fitted value at t = 0 is the constant coefficient.
+        LQE {
+            measurement: fit[0],
+            variance: scatter * self.gain
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multivariate::DynamicKalmanFilter;
+
+    #[test]
+    fn averages_last_measurements() {
+        let mut average = MovingAverage::new(3);
+
+        assert_eq!(average.next(1.0).variance, f64::INFINITY);
+        average.next(2.0);
+        average.next(3.0);
+        let estimate = average.next(7.0);
+        // The mean of 2, 3 and 7 with sample variance 7, over 3.
+        assert_eq!(estimate.measurement, 4.0);
+        assert!((estimate.variance - 7.0 / 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn smooths_exponentially() {
+        let mut average = ExponentialMovingAverage::new(0.5);
+
+        assert_eq!(average.next(2.0).measurement, 2.0);
+        let estimate = average.next(4.0);
+        assert_eq!(estimate.measurement, 3.0);
+        // The spread is 0.5 · 0.5 · 2² = 1, scaled by 0.5 / 1.5.
+        assert!((estimate.variance - 1.0 / 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn follows_polynomials_exactly() {
+        let mut filter = SavitzkyGolay::new(5, 2).unwrap();
+        let mut moving = MovingAverage::new(5);
+
+        let mut estimate = filter.next(0.0);
+        let mut average = moving.next(0.0);
+        for t in 1..10 {
+            let z = 0.5 * (t * t) as f64 - t as f64;
+            estimate = filter.next(z);
+            average = moving.next(z);
+        }
+        assert!((estimate.measurement - 31.5).abs() < 1e-9);
+        assert!(estimate.variance.abs() < 1e-9);
+        assert!(average.measurement < 25.0);
+    }
+
+    #[test]
+    fn runs_kalman_filters() {
+        let initial = LQE {
+            measurement: 0.0,
+            variance: 1.0
+        };
+        let mut scalar = ScalarKalmanFilter::new(initial, 0.5, 2.0);
+        let mut model = DynamicKalmanFilter::<DenseMatrix>::new(1, 1);
+        model.process_noise = DenseMatrix::vector(&[0.5]);
+        model.measurement_noise = DenseMatrix::vector(&[2.0]);
+        let mut bayes = BayesEstimator::new(model, DenseMatrix::identity(1));
+
+        let mut expected = initial;
+        for z in &[1.0, 3.0, 2.0, 2.5] {
+            expected = step::update(step::predict(expected, 0.0, 0.5), *z, 2.0).posterior;
+            assert_eq!(scalar.next(*z), expected);
+            let estimate = bayes.next(*z);
+            assert!((estimate.measurement - expected.measurement).abs() < 1e-12);
+            assert!((estimate.variance - expected.variance).abs() < 1e-12);
+        }
+
+        // A rejected measurement leaves the prediction.
+        bayes.filter.measurement_noise = DenseMatrix::vector(&[f64::NAN]);
+        let estimate = bayes.next(0.0);
+        assert_eq!(estimate.measurement, expected.measurement);
+        assert!((estimate.variance - expected.variance - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn rejects_ill_conditioned_fits() {
+        assert_eq!(SavitzkyGolay::new(200, 30), Err(Error::SingularCovariance));
+        assert!(SavitzkyGolay::new(200, 4).is_ok());
+    }
+}
@@ -0,0 +1,493 @@
+//! Offline batch estimation over a whole trajectory.
+//!
+//! When a log has been recorded in full, every state can be estimated from
+//! every measurement at once rather than recursively. `estimate` finds the
+//! maximum a posteriori trajectory of a linear Gaussian model: it weighs the
+//! prior, each transition and each measurement as a quadratic factor and
+//! solves the resulting normal equations.
+//!
+//! Each state is only tied to its neighbours in time, so the information
+//! matrix of the normal equations is block tridiagonal. It is solved block by
+//! block in `O(K N³)` for `K` measurements, instead of `O((K N)³)`.
+//!
+//! `smooth` reaches the same trajectory by a forward filtering and a backward
+//! Rauch-Tung-Striebel pass, which also returns the covariance of each state
+//! and doesn't need the process noise to be invertible.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::batch;
+//! use lqe::matrix::{DenseMatrix, Matrix};
+//! use lqe::multivariate::DynamicKalmanFilter;
+//!
+//! let mut model = DynamicKalmanFilter::<DenseMatrix>::new(1, 1);
+//! model.process_noise = DenseMatrix::identity(1).scale(0.1);
+//! let measurements = [1.0, 1.2, 0.9].iter().map(|z| DenseMatrix::vector(&[*z])).collect::<Vec<_>>();
+//!
+//! let trajectory = batch::estimate(&model, &measurements).unwrap();
+//! trajectory.len();
+//! // => 3
+//! ```
+
+use crate::error::Error;
+use crate::matrix::Matrix;
+use crate::multivariate::DynamicKalmanFilter;
+
+/// `invert` returns the inverse of the symmetric positive definite `matrix`.
+fn invert<T: Matrix>(matrix: &T) -> Result<T, Error> {
+    matrix
+        .cholesky_solve(&T::identity(matrix.shape().0))
+        .ok_or(Error::SingularCovariance)
+}
+
+/// `NormalEquations` is the block tridiagonal system `Λ x = b` whose solution
+/// is the maximum a posteriori trajectory.
+///
+/// Block `k` of the unknowns is the state after `k` measurements, block 0 the
+/// prior. `below` is every block of `Λ` at `(k, k - 1)`.
+struct NormalEquations<T> {
+    diagonal: Vec<T>,
+    below: T,
+    vector: Vec<T>
+}
+
+impl<T: Matrix> NormalEquations<T> {
+    fn new(model: &DynamicKalmanFilter<T>, measurements: &[T]) -> Result<Self, Error> {
+        let transition = &model.transition;
+        let observation = &model.observation;
+        let prior_information = invert(&model.covariance)?;
+        let process_information = invert(&model.process_noise)?;
+        let measurement_information = invert(&model.measurement_noise)?;
+
+        let transition_information = transition.transpose().mul(&process_information).mul(transition);
+        let observation_information = observation.transpose().mul(&measurement_information).mul(observation);
+        let mut diagonal = vec![prior_information.add(&transition_information)];
+        let mut vector = vec![prior_information.mul(&model.state)];
+        for (k, measurement) in measurements.iter().enumerate() {
+            let mut block = process_information.add(&observation_information);
+            if k + 1 < measurements.len() {
+                block = block.add(&transition_information);
+            }
+            diagonal.push(block);
+            vector.push(observation.transpose().mul(&measurement_information).mul(measurement));
+        }
+        if measurements.is_empty() {
+            diagonal[0] = prior_information;
+        }
+        Ok(NormalEquations {
+            diagonal,
+            below: process_information.mul(transition).scale(-1.0),
+            vector
+        })
+    }
+
+    /// `solve` eliminates the sub-diagonal blocks by block Cholesky, then
+    /// back-substitutes.
+    fn solve(&self) -> Result<Vec<T>, Error> {
+        let below = &self.below;
+        let mut diagonal = self.diagonal.clone();
+        let mut vector = self.vector.clone();
+        for k in 1..diagonal.len() {
+            let factor = below.mul(&invert(&diagonal[k - 1])?);
+            diagonal[k] = diagonal[k].sub(&factor.mul(&below.transpose()));
+            vector[k] = vector[k].sub(&factor.mul(&vector[k - 1]));
+        }
+        let mut states = vec![T::zeros(0, 0); diagonal.len()];
+        for k in (0..diagonal.len()).rev() {
+            let mut rhs = vector[k].clone();
+            if k + 1 < diagonal.len() {
+                rhs = rhs.sub(&below.transpose().mul(&states[k + 1]));
+            }
+            states[k] = diagonal[k].cholesky_solve(&rhs).ok_or(Error::SingularCovariance)?;
+        }
+        Ok(states)
+    }
+
+    /// `residual` returns row `i` of `b - Λ x` for block `k`, leaving out the
+    /// diagonal element of `Λ`.
+    fn residual(&self, states: &[T], k: usize, i: usize) -> f64 {
+        let size = self.below.shape().0;
+        let mut residual = self.vector[k].get(i, 0);
+        for j in 0..size {
+            if j != i {
+                residual -= self.diagonal[k].get(i, j) * states[k].get(j, 0);
+            }
+            if k > 0 {
+                residual -= self.below.get(i, j) * states[k - 1].get(j, 0);
+            }
+            if k + 1 < states.len() {
+                residual -= self.below.get(j, i) * states[k + 1].get(j, 0);
+            }
+        }
+        residual
+    }
+}
+
+/// `estimate` returns the maximum a posteriori state after each of
+/// `measurements`, using the system model of `model` and its `state` and
+/// `covariance` as the prior before the first step.
+///
+/// The steps are the same as calling `next` on `model` for each measurement,
+/// so the last state equals the estimate the filter would end on. The earlier
+/// states are refined by the measurements that follow them too.
+///
+/// # Errors:
+///
+/// Returns `Error::SingularCovariance` if the prior, process or measurement
+/// noise covariance is not positive definite.
+pub fn estimate<T: Matrix>(model: &DynamicKalmanFilter<T>, measurements: &[T]) -> Result<Vec<T>, Error> {
+    let mut states = NormalEquations::new(model, measurements)?.solve()?;
+    states.remove(0);
+    Ok(states)
+}
+
+/// `estimate_within` is `estimate` with every state constrained to lie
+/// between `lower` and `upper` element-wise, e.g. to keep concentrations
+/// nonnegative. Use `f64::INFINITY` and `f64::NEG_INFINITY` for unbounded
+/// elements.
+///
+/// The constrained problem is solved by projected Gauss-Seidel iteration,
+/// starting from the unconstrained trajectory.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::batch;
+/// use lqe::matrix::{DenseMatrix, Matrix};
+/// use lqe::multivariate::DynamicKalmanFilter;
+///
+/// let mut model = DynamicKalmanFilter::<DenseMatrix>::new(1, 1);
+/// model.process_noise = DenseMatrix::identity(1);
+/// let measurements = [DenseMatrix::vector(&[-0.5]), DenseMatrix::vector(&[0.2])];
+/// let lower = DenseMatrix::vector(&[0.0]);
+/// let upper = DenseMatrix::vector(&[f64::INFINITY]);
+///
+/// let trajectory = batch::estimate_within(&model, &measurements, &lower, &upper).unwrap();
+/// trajectory[0].get(0, 0) >= 0.0;
+/// // => true
+/// ```
+pub fn estimate_within<T: Matrix>(
+    model: &DynamicKalmanFilter<T>,
+    measurements: &[T],
+    lower: &T,
+    upper: &T
+) -> Result<Vec<T>, Error> {
+    const SWEEPS: usize = 10_000;
+    const TOLERANCE: f64 = 1e-12;
+
+    let equations = NormalEquations::new(model, measurements)?;
+    let size = equations.below.shape().0;
+    let mut states = equations.solve()?;
+    for state in &mut states {
+        for i in 0..size {
+            state.set(i, 0, state.get(i, 0).max(lower.get(i, 0)).min(upper.get(i, 0)));
+        }
+    }
+    for _ in 0..SWEEPS {
+        let mut change: f64 = 0.0;
+        for k in 0..states.len() {
+            for i in 0..size {
+                let value = equations.residual(&states, k, i) / equations.diagonal[k].get(i, i);
+                let value = value.max(lower.get(i, 0)).min(upper.get(i, 0));
+                change = change.max((value - states[k].get(i, 0)).abs());
+                states[k].set(i, 0, value);
+            }
+        }
+        if change < TOLERANCE {
+            break;
+        }
+    }
+    states.remove(0);
+    Ok(states)
+}
+
+/// `smooth` returns the smoothed state and covariance after each of
+/// `measurements`, by running `model` forward and then a Rauch-Tung-Striebel
+/// pass backward.
+///
+/// # Errors:
+///
+/// Returns the error of the first failing update, or
+/// `Error::SingularCovariance` if a predicted covariance is not positive
+/// definite.
+pub fn smooth<T: Matrix>(model: &DynamicKalmanFilter<T>, measurements: &[T]) -> Result<Vec<(T, T)>, Error> {
+    smooth_gaps(model, measurements.iter().map(Some))
+}
+
+/// `smooth_missing` is `smooth` for a series with gaps: where a measurement
+/// is `None` the forward pass only predicts, and the smoother fills the gap
+/// in from both sides.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::batch;
+/// use lqe::matrix::{DenseMatrix, Matrix};
+/// use lqe::multivariate::DynamicKalmanFilter;
+///
+/// let mut model = DynamicKalmanFilter::<DenseMatrix>::new(1, 1);
+/// model.process_noise = DenseMatrix::identity(1);
+/// let series = [Some(DenseMatrix::vector(&[0.0])), None, Some(DenseMatrix::vector(&[4.0]))];
+/// let smoothed = batch::smooth_missing(&model, &series).unwrap();
+/// smoothed[1].0.get(0, 0);
+/// // => 1.82
+/// ```
+pub fn smooth_missing<T: Matrix>(model: &DynamicKalmanFilter<T>, measurements: &[Option<T>]) -> Result<Vec<(T, T)>, Error> {
+    smooth_gaps(model, measurements.iter().map(Option::as_ref))
+}
+
+fn smooth_gaps<'a, T: Matrix + 'a>(
+    model: &DynamicKalmanFilter<T>,
+    measurements: impl ExactSizeIterator<Item = Option<&'a T>>
+) -> Result<Vec<(T, T)>, Error> {
+    let mut filter = model.clone();
+    let mut predicted = Vec::with_capacity(measurements.len());
+    let mut smoothed = Vec::with_capacity(measurements.len());
+    for measurement in measurements {
+        filter.predict();
+        predicted.push(filter.result());
+        if let Some(measurement) = measurement {
+            filter.update(measurement)?;
+        }
+        smoothed.push(filter.result());
+    }
+    let transitions = vec![model.transition.clone(); smoothed.len()];
+    smooth_backward(&mut smoothed, &predicted, &transitions)?;
+    Ok(smoothed)
+}
+
+/// `smooth_backward` runs the Rauch-Tung-Striebel pass over the `filtered`
+/// estimates in place, given the `predicted` estimate of each step and the
+/// transition that led to it.
+fn smooth_backward<T: Matrix>(filtered: &mut [(T, T)], predicted: &[(T, T)], transitions: &[T]) -> Result<(), Error> {
+    for k in (0..filtered.len().saturating_sub(1)).rev() {
+        let (state, covariance) = &filtered[k];
+        let (next_state, next_covariance) = &filtered[k + 1];
+        let (predicted_state, predicted_covariance) = &predicted[k + 1];
+        // C = P Fᵀ P⁻⁻¹, computed as (P⁻⁻¹ F P)ᵀ since both are symmetric.
+        let gain = predicted_covariance
+            .cholesky_solve(&transitions[k + 1].mul(covariance))
+            .ok_or(Error::SingularCovariance)?
+            .transpose();
+        let state = state.add(&gain.mul(&next_state.sub(predicted_state)));
+        let covariance = covariance.add(&gain.mul(&next_covariance.sub(predicted_covariance)).mul(&gain.transpose()));
+        filtered[k] = (state, covariance);
+    }
+    Ok(())
+}
+
+/// `interpolate` returns the smoothed state and covariance at each of the
+/// `queries` times, given `measurements` taken at their own times, e.g. to
+/// resample a 7 Hz sensor onto a 10 Hz control loop.
+///
+/// `discretize` returns the transition and process noise of the model over
+/// a time step, which makes the model continuous in time. The estimate of
+/// `model` is the prior at the time of the earliest measurement or query.
+/// Times need not be sorted, and queries before the first or after the last
+/// measurement extrapolate.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::batch;
+/// use lqe::matrix::{DenseMatrix, Matrix};
+/// use lqe::multivariate::DynamicKalmanFilter;
+///
+/// // A random walk gaining a variance of 1.0 per second.
+/// let model = DynamicKalmanFilter::<DenseMatrix>::new(1, 1);
+/// let discretize = |dt: f64| (DenseMatrix::identity(1), DenseMatrix::identity(1).scale(dt));
+/// let measurements = [(0.0, DenseMatrix::vector(&[1.0])), (1.0, DenseMatrix::vector(&[3.0]))];
+///
+/// let estimates = batch::interpolate(&model, discretize, &measurements, &[0.5]).unwrap();
+/// estimates[0].0.get(0, 0);
+/// // => 1.5
+/// ```
+pub fn interpolate<T: Matrix>(
+    model: &DynamicKalmanFilter<T>,
+    discretize: impl Fn(f64) -> (T, T),
+    measurements: &[(f64, T)],
+    queries: &[f64]
+) -> Result<Vec<(T, T)>, Error> {
+    // Every measurement and query is a step, ordered by time, measurements
+    // first when they coincide.
+    let mut events = measurements
+        .iter()
+        .map(|(time, measurement)| (*time, Some(measurement), None))
+        .chain(queries.iter().enumerate().map(|(i, time)| (*time, None, Some(i))))
+        .collect::<Vec<_>>();
+    events.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.2.cmp(&b.2)));
+
+    let mut filter = model.clone();
+    let mut predicted = Vec::with_capacity(events.len());
+    let mut smoothed = Vec::with_capacity(events.len());
+    let mut transitions = Vec::with_capacity(events.len());
+    let mut last_time = events.first().map_or(0.0, |event| event.0);
+    for (time, measurement, _) in &events {
+        let (transition, process_noise) = discretize(time - last_time);
+        filter.transition = transition.clone();
+        filter.process_noise = process_noise;
+        filter.predict();
+        transitions.push(transition);
+        predicted.push(filter.result());
+        if let Some(measurement) = measurement {
+            filter.update(measurement)?;
+        }
+        smoothed.push(filter.result());
+        last_time = *time;
+    }
+    smooth_backward(&mut smoothed, &predicted, &transitions)?;
+
+    let mut estimates = vec![None; queries.len()];
+    for ((_, _, query), estimate) in events.iter().zip(smoothed) {
+        if let Some(i) = query {
+            estimates[*i] = Some(estimate);
+        }
+    }
+    Ok(estimates.into_iter().flatten().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::DenseMatrix;
+
+    fn constant_velocity() -> DynamicKalmanFilter<DenseMatrix> {
+        DynamicKalmanFilter {
+            state: DenseMatrix::vector(&[0.0, 1.0]),
+            covariance: DenseMatrix::identity(2),
+            transition: DenseMatrix::from_row_slices(&[&[1.0, 1.0], &[0.0, 1.0]]),
+            process_noise: DenseMatrix::identity(2).scale(0.1),
+            observation: DenseMatrix::from_row_slices(&[&[1.0, 0.0]]),
+            measurement_noise: DenseMatrix::identity(1)
+        }
+    }
+
+    #[test]
+    fn ends_on_filtered_estimate() {
+        let model = constant_velocity();
+        let measurements = [1.2, 1.9, 3.3, 3.8, 5.1]
+            .iter()
+            .map(|z| DenseMatrix::vector(&[*z]))
+            .collect::<Vec<_>>();
+
+        let trajectory = estimate(&model, &measurements).unwrap();
+        let mut filter = model.clone();
+        for measurement in &measurements {
+            filter.next(measurement).unwrap();
+        }
+        assert_eq!(trajectory.len(), measurements.len());
+        let difference = trajectory[4].sub(&filter.state);
+        assert!(difference.get(0, 0).abs() < 1e-9 && difference.get(1, 0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn smooths_earlier_states() {
+        let mut model = DynamicKalmanFilter::<DenseMatrix>::new(1, 1);
+        model.process_noise = DenseMatrix::identity(1);
+        let measurements = [DenseMatrix::vector(&[0.0]), DenseMatrix::vector(&[3.0])];
+
+        // With P = Q = R = 1 the first state is pulled towards the second
+        // measurement, which the filter alone would leave at 0.0.
+        let trajectory = estimate(&model, &measurements).unwrap();
+        assert!((trajectory[0].get(0, 0) - 0.75).abs() < 1e-12);
+        assert!((trajectory[1].get(0, 0) - 1.875).abs() < 1e-12);
+    }
+
+    #[test]
+    fn smooths_like_batch_estimate() {
+        let model = constant_velocity();
+        let measurements = [1.2, 1.9, 3.3, 3.8, 5.1]
+            .iter()
+            .map(|z| DenseMatrix::vector(&[*z]))
+            .collect::<Vec<_>>();
+
+        let smoothed = smooth(&model, &measurements).unwrap();
+        let trajectory = estimate(&model, &measurements).unwrap();
+        for ((state, _), expected) in smoothed.iter().zip(&trajectory) {
+            let difference = state.sub(expected);
+            assert!(difference.get(0, 0).abs() < 1e-9 && difference.get(1, 0).abs() < 1e-9);
+        }
+        let mut identity = DynamicKalmanFilter::<DenseMatrix>::new(1, 1);
+        identity.process_noise = DenseMatrix::identity(1);
+        let smoothed = smooth(&identity, &[DenseMatrix::vector(&[0.0]), DenseMatrix::vector(&[3.0])]).unwrap();
+        // The second state is filtered with variance 5/8, the first smoothed
+        // from 2/3 down to 1/2.
+        assert!((smoothed[0].1.get(0, 0) - 0.5).abs() < 1e-12);
+        assert!((smoothed[1].1.get(0, 0) - 0.625).abs() < 1e-12);
+    }
+
+    #[test]
+    fn fills_gaps() {
+        let model = constant_velocity();
+        let measurements = [Some(1.0), None, None, Some(4.0), Some(5.0)]
+            .iter()
+            .map(|z| z.map(|z| DenseMatrix::vector(&[z])))
+            .collect::<Vec<_>>();
+
+        let smoothed = smooth_missing(&model, &measurements).unwrap();
+        assert_eq!(smoothed.len(), 5);
+        let (position, variance) = (smoothed[1].0.get(0, 0), smoothed[1].1.get(0, 0));
+        assert!(position > 1.0 && position < 4.0);
+        assert!(variance > smoothed[0].1.get(0, 0) && variance > smoothed[3].1.get(0, 0));
+        let complete = measurements.iter().flatten().cloned().collect::<Vec<_>>();
+        assert_eq!(smooth(&model, &complete).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn constrains_states() {
+        let mut model = DynamicKalmanFilter::<DenseMatrix>::new(1, 1);
+        model.process_noise = DenseMatrix::identity(1);
+        let measurements = [DenseMatrix::vector(&[0.0]), DenseMatrix::vector(&[-3.0])];
+        let unbounded = DenseMatrix::vector(&[f64::INFINITY]);
+
+        let free = estimate_within(&model, &measurements, &unbounded.scale(-1.0), &unbounded).unwrap();
+        assert!((free[0].get(0, 0) + 0.75).abs() < 1e-9);
+        assert!((free[1].get(0, 0) + 1.875).abs() < 1e-9);
+
+        let nonnegative = estimate_within(&model, &measurements, &DenseMatrix::vector(&[0.0]), &unbounded).unwrap();
+        assert_eq!(nonnegative, vec![DenseMatrix::vector(&[0.0]), DenseMatrix::vector(&[0.0])]);
+    }
+
+    #[test]
+    fn rejects_singular_process_noise() {
+        let model = DynamicKalmanFilter::<DenseMatrix>::new(1, 1);
+
+        assert_eq!(
+            estimate(&model, &[DenseMatrix::vector(&[1.0])]),
+            Err(Error::SingularCovariance)
+        );
+    }
+
+    #[test]
+    fn interpolates_between_measurements() {
+        let model = constant_velocity();
+        let discretize = |dt: f64| {
+            let transition = DenseMatrix::from_row_slices(&[&[1.0, dt], &[0.0, 1.0]]);
+            (transition, DenseMatrix::identity(2).scale(0.1 * dt))
+        };
+        let measurements = [1.2, 1.9, 3.3, 3.8, 5.1]
+            .iter()
+            .enumerate()
+            .map(|(t, z)| (t as f64 + 1.0, DenseMatrix::vector(&[*z])))
+            .collect::<Vec<_>>();
+
+        // Queried at the measurement times, a unit time step reproduces the
+        // fixed-rate smoother.
+        let fixed_rate = measurements.iter().map(|(_, z)| z.clone()).collect::<Vec<_>>();
+        let mut queries = vec![5.0, 1.0, 2.0, 3.0, 4.0];
+        let mut unit = model.clone();
+        unit.predict();
+        let estimates = interpolate(&unit, discretize, &measurements, &queries).unwrap();
+        let smoothed = smooth(&model, &fixed_rate).unwrap();
+        for (time, (state, _)) in queries.iter().zip(&estimates) {
+            let difference = state.sub(&smoothed[*time as usize - 1].0);
+            assert!(difference.get(0, 0).abs() < 1e-9 && difference.get(1, 0).abs() < 1e-9);
+        }
+
+        queries = vec![2.5];
+        let estimates = interpolate(&unit, discretize, &measurements, &queries).unwrap();
+        let position = estimates[0].0.get(0, 0);
+        assert!(position > smoothed[1].0.get(0, 0) && position < smoothed[2].0.get(0, 0));
+    }
+}
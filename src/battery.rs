@@ -0,0 +1,205 @@
+//! State-of-charge estimation for batteries.
+//!
+//! A `BatteryEstimator` follows the state of charge and internal resistance
+//! of a battery from its terminal voltage and current, the classic use of an
+//! extended Kalman filter in battery management systems. The battery is
+//! modelled as an equivalent circuit: an open-circuit voltage that depends
+//! on the state of charge, in series with the internal resistance `R₀` and a
+//! resistor `R₁` parallel to a capacitor, for the slow polarization of the
+//! cell. The terminal voltage drawing a current `I` is
+//!
+//! ```text
+//! V = OCV(SoC) - V₁ - R₀ I
+//! ```
+//!
+//! with `V₁` the voltage across the `R₁` branch. Currents are positive when
+//! discharging.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::battery::{Battery, BatteryEstimator};
+//!
+//! // A 2.5 Ah cell, thought to be half full.
+//! let mut estimator = BatteryEstimator::new(Battery::lithium_ion(2.5), 0.5, 0.05);
+//! for _ in 0..10 {
+//!     estimator.next(1.0, 1.0, 3.95).unwrap();
+//! }
+//! estimator.state_of_charge() > 0.6;
+//! // => true
+//! ```
+
+use crate::error::Error;
+use crate::matrix::{DenseMatrix, Matrix};
+use crate::multivariate::DynamicKalmanFilter;
+
+/// `Battery` holds the parameters of the equivalent circuit of a battery:
+/// its `capacity` in ampere-hours, the `polarization_resistance` `R₁` in
+/// ohms, the `time_constant` `R₁ C₁` in seconds and the open-circuit voltage
+/// at increasing states of charge, interpolated linearly in between.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Battery {
+    pub capacity: f64,
+    pub polarization_resistance: f64,
+    pub time_constant: f64,
+    pub open_circuit_voltage: Vec<(f64, f64)>
+}
+
+impl Battery {
+    /// `lithium_ion` returns typical parameters of a lithium-ion cell with
+    /// an NMC cathode and the given `capacity`, for when the cell hasn't
+    /// been characterized.
+    pub fn lithium_ion(capacity: f64) -> Self {
+        Battery {
+            capacity,
+            polarization_resistance: 0.015,
+            time_constant: 30.0,
+            open_circuit_voltage: vec![
+                (0.0, 3.0),
+                (0.1, 3.45),
+                (0.2, 3.55),
+                (0.3, 3.62),
+                (0.4, 3.68),
+                (0.5, 3.74),
+                (0.6, 3.82),
+                (0.7, 3.91),
+                (0.8, 4.0),
+                (0.9, 4.08),
+                (1.0, 4.2)
+            ]
+        }
+    }
+
+    /// `open_circuit` returns the open-circuit voltage at `state_of_charge`
+    /// and its slope there. Beyond the table the outermost segments are
+    /// extended.
+    ///
+    /// # Panics:
+    ///
+    /// Panics if the table has fewer than two points.
+    pub fn open_circuit(&self, state_of_charge: f64) -> (f64, f64) {
+        let table = &self.open_circuit_voltage;
+        assert!(table.len() >= 2, "open-circuit voltage needs at least two points");
+        let index = table
+            .iter()
+            .skip(1)
+            .position(|(soc, _)| state_of_charge < *soc)
+            .unwrap_or(table.len() - 2);
+        let ((soc0, voltage0), (soc1, voltage1)) = (table[index], table[index + 1]);
+        let slope = (voltage1 - voltage0) / (soc1 - soc0);
+        (voltage0 + slope * (state_of_charge - soc0), slope)
+    }
+}
+
+/// `BatteryEstimator` estimates the state `[SoC, V₁, R₀]` of a `battery`.
+///
+/// `current_noise` is the variance of a current measurement and
+/// `voltage_noise` that of a voltage measurement, `resistance_drift` the
+/// variance the internal resistance gains per second, e.g. with the
+/// temperature.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatteryEstimator {
+    pub filter: DynamicKalmanFilter<DenseMatrix>,
+    pub battery: Battery,
+    pub current_noise: f64,
+    pub voltage_noise: f64,
+    pub resistance_drift: f64
+}
+
+impl BatteryEstimator {
+    /// `new` creates an estimator starting from a guess of the
+    /// `state_of_charge` and `resistance`, both uncertain enough for the
+    /// measurements to correct them.
+    pub fn new(battery: Battery, state_of_charge: f64, resistance: f64) -> Self {
+        let mut filter = DynamicKalmanFilter::new(3, 1);
+        filter.state = DenseMatrix::vector(&[state_of_charge, 0.0, resistance]);
+        filter.covariance = DenseMatrix::zeros(3, 3);
+        filter.covariance.set(0, 0, 0.04);
+        filter.covariance.set(1, 1, 1e-4);
+        filter.covariance.set(2, 2, resistance * resistance);
+        BatteryEstimator {
+            filter,
+            battery,
+            current_noise: 1e-4,
+            voltage_noise: 1e-4,
+            resistance_drift: 1e-10
+        }
+    }
+
+    /// `predict` moves the estimate `dt` seconds forward while drawing
+    /// `current`.
+    pub fn predict(&mut self, dt: f64, current: f64) {
+        let decay = (-dt / self.battery.time_constant).exp();
+        let input = DenseMatrix::vector(&[
+            -dt / (3600.0 * self.battery.capacity),
+            self.battery.polarization_resistance * (1.0 - decay),
+            0.0
+        ]);
+        self.filter.transition = DenseMatrix::identity(3);
+        self.filter.transition.set(1, 1, decay);
+        self.filter.process_noise = input.mul(&input.transpose()).scale(self.current_noise);
+        self.filter.process_noise.set(2, 2, self.resistance_drift * dt);
+        self.filter.predict();
+        self.filter.state = self.filter.state.add(&input.scale(current));
+    }
+
+    /// `update` refines the estimate with the terminal `voltage` measured
+    /// while drawing `current`, linearizing the model at the estimate.
+    pub fn update(&mut self, current: f64, voltage: f64) -> Result<(), Error> {
+        let state = &self.filter.state;
+        let (open_circuit, slope) = self.battery.open_circuit(state.get(0, 0));
+        let expected = open_circuit - state.get(1, 0) - state.get(2, 0) * current;
+        let observation = DenseMatrix::from_row_slices(&[&[slope, -1.0, -current]]);
+        self.filter.update_linearized(
+            &DenseMatrix::vector(&[voltage]),
+            &DenseMatrix::vector(&[expected]),
+            observation,
+            DenseMatrix::vector(&[self.voltage_noise])
+        )
+    }
+
+    /// `next` predicts `dt` seconds forward and updates with the `current`
+    /// and `voltage` measured at the end of them.
+    pub fn next(&mut self, dt: f64, current: f64, voltage: f64) -> Result<(), Error> {
+        self.predict(dt, current);
+        self.update(current, voltage)
+    }
+
+    /// `state_of_charge` returns the estimated state of charge, from 0 for
+    /// empty to 1 for full.
+    pub fn state_of_charge(&self) -> f64 {
+        self.filter.state.get(0, 0)
+    }
+
+    /// `resistance` returns the estimated internal resistance in ohms.
+    pub fn resistance(&self) -> f64 {
+        self.filter.state.get(2, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particle::Random;
+
+    #[test]
+    fn converges_from_wrong_guess() {
+        let battery = Battery::lithium_ion(2.5);
+        let mut estimator = BatteryEstimator::new(battery.clone(), 0.6, 0.02);
+        let mut random = Random::new(3);
+        let (mut state_of_charge, mut polarization, resistance) = (0.9, 0.0, 0.05);
+        let decay = (-1.0 / battery.time_constant).exp();
+        for step in 0..1800 {
+            // Pulses of load, so the resistance shows.
+            let current = if step % 20 < 10 { 0.5 } else { 5.0 };
+            state_of_charge -= current / (3600.0 * battery.capacity);
+            polarization = decay * polarization + battery.polarization_resistance * (1.0 - decay) * current;
+            let voltage = battery.open_circuit(state_of_charge).0 - polarization - resistance * current;
+            estimator
+                .next(1.0, current + 0.01 * random.normal(), voltage + 0.01 * random.normal())
+                .unwrap();
+        }
+        assert!((estimator.state_of_charge() - state_of_charge).abs() < 0.03);
+        assert!((estimator.resistance() - resistance).abs() < 0.01);
+    }
+}
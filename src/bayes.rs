@@ -0,0 +1,337 @@
+//! A common interface for recursive Bayesian filters.
+//!
+//! `BayesFilter` lets applications write their processing once and swap the
+//! estimator behind it through generics. It is implemented by every filter
+//! whose step splits into a prediction and an update with a single kind of
+//! measurement:
+//!
+//! - `DynamicKalmanFilter`, `KalmanFilter` (`nalgebra` feature),
+//!   `SparseKalmanFilter` and `ScaledFilter`, estimating their state and
+//!   covariance.
+//! - `GaussianSumFilter`, estimating the moments of its mixture.
+//! - `DelayedFilter`, for its current measurements.
+//! - `BoxTracker`, measuring and estimating bounding boxes.
+//! - `ModelledFilter`, a `RaoBlackwellizedFilter` with the functions that
+//!   draw and condition its particles, estimating the moments of the linear
+//!   part of the state.
+//!
+//! The multiple model filters mix their modes between prediction and update
+//! and only offer `next`, and a `TimedFilter` predicts to a timestamp, so
+//! they have their own interfaces.
+//!
+//! The trait is object safe, so an estimator can be chosen at runtime, e.g.
+//! from a configuration file, and handed around as a `BoxedFilter`. Boxed
+//! filters are filters themselves and work with generic code unchanged.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::bayes::BayesFilter;
+//! use lqe::matrix::{DenseMatrix, Matrix};
+//! use lqe::multivariate::DynamicKalmanFilter;
+//!
+//! fn track<F: BayesFilter<Measurement = DenseMatrix>>(filter: &mut F, positions: &[f64]) -> F::Estimate {
+//!     for position in positions {
+//!         filter.predict();
+//!         filter.update(&DenseMatrix::vector(&[*position])).unwrap();
+//!     }
+//!     filter.estimate()
+//! }
+//!
+//! let mut filter = DynamicKalmanFilter::<DenseMatrix>::new(1, 1);
+//! track(&mut filter, &[2.0]).0.get(0, 0);
+//! // => 1.0
+//! ```
+
+#[cfg(feature = "nalgebra")]
+use nalgebra::{SMatrix, SVector};
+
+use crate::error::Error;
+use crate::latency::DelayedFilter;
+use crate::matrix::Matrix;
+use crate::mixture::GaussianSumFilter;
+#[cfg(feature = "nalgebra")]
+use crate::multivariate::KalmanFilter;
+use crate::multivariate::DynamicKalmanFilter;
+use crate::particle::{ModelledFilter, Random};
+use crate::scaling::ScaledFilter;
+use crate::sparse::SparseKalmanFilter;
+use crate::tracking::{BoundingBox, BoxTracker};
+
+/// `BayesFilter` is a recursive estimator stepped by a prediction followed
+/// by an update with a `Measurement`, summarizing its belief as an
+/// `Estimate`.
+pub trait BayesFilter {
+    /// `Measurement` is what `update` folds in.
+    type Measurement;
+
+    /// `Estimate` is what `estimate` returns, e.g. a state and covariance.
+    type Estimate;
+
+    /// `predict` moves the belief one step forward.
+    fn predict(&mut self);
+
+    /// `update` refines the belief with `measurement`.
+    fn update(&mut self, measurement: &Self::Measurement) -> Result<(), Error>;
+
+    /// `estimate` returns the current belief.
+    fn estimate(&self) -> Self::Estimate;
+
+    /// `step` performs the entire predict - update cycle for a measurement.
+    fn step(&mut self, measurement: &Self::Measurement) -> Result<(), Error> {
+        self.predict();
+        self.update(measurement)
+    }
+}
+
+/// `BoxedFilter` is a filter with state and covariance estimates chosen at
+/// runtime.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::bayes::{BayesFilter, BoxedFilter};
+/// use lqe::matrix::{DenseMatrix, Matrix};
+/// use lqe::multivariate::DynamicKalmanFilter;
+/// use lqe::scaling::ScaledFilter;
+///
+/// let model = DynamicKalmanFilter::<DenseMatrix>::new(1, 1);
+/// let mut filter: BoxedFilter<DenseMatrix> = match "scaled" {
+///     "scaled" => Box::new(ScaledFilter::new(model, &[10.0])),
+///     _ => Box::new(model)
+/// };
+/// filter.step(&DenseMatrix::vector(&[2.0])).unwrap();
+/// filter.estimate().0.get(0, 0);
+/// // => 1.0
+/// ```
+pub type BoxedFilter<T> = Box<dyn BayesFilter<Measurement = T, Estimate = (T, T)>>;
+
+impl<F: BayesFilter + ?Sized> BayesFilter for Box<F> {
+    type Measurement = F::Measurement;
+    type Estimate = F::Estimate;
+
+    fn predict(&mut self) {
+        (**self).predict();
+    }
+
+    fn update(&mut self, measurement: &F::Measurement) -> Result<(), Error> {
+        (**self).update(measurement)
+    }
+
+    fn estimate(&self) -> F::Estimate {
+        (**self).estimate()
+    }
+
+    fn step(&mut self, measurement: &F::Measurement) -> Result<(), Error> {
+        (**self).step(measurement)
+    }
+}
+
+impl<T: Matrix> BayesFilter for DynamicKalmanFilter<T> {
+    type Measurement = T;
+    type Estimate = (T, T);
+
+    fn predict(&mut self) {
+        DynamicKalmanFilter::predict(self);
+    }
+
+    fn update(&mut self, measurement: &T) -> Result<(), Error> {
+        DynamicKalmanFilter::update(self, measurement)
+    }
+
+    fn estimate(&self) -> (T, T) {
+        self.result()
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+impl<const N: usize, const M: usize> BayesFilter for KalmanFilter<N, M> {
+    type Measurement = SVector<f64, M>;
+    type Estimate = (SVector<f64, N>, SMatrix<f64, N, N>);
+
+    fn predict(&mut self) {
+        KalmanFilter::predict(self);
+    }
+
+    fn update(&mut self, measurement: &SVector<f64, M>) -> Result<(), Error> {
+        KalmanFilter::update(self, measurement)
+    }
+
+    fn estimate(&self) -> (SVector<f64, N>, SMatrix<f64, N, N>) {
+        self.result()
+    }
+}
+
+impl<T: Matrix> BayesFilter for SparseKalmanFilter<T> {
+    type Measurement = T;
+    type Estimate = (T, T);
+
+    fn predict(&mut self) {
+        SparseKalmanFilter::predict(self);
+    }
+
+    fn update(&mut self, measurement: &T) -> Result<(), Error> {
+        SparseKalmanFilter::update(self, measurement)
+    }
+
+    fn estimate(&self) -> (T, T) {
+        self.result()
+    }
+}
+
+impl<T: Matrix> BayesFilter for ScaledFilter<T> {
+    type Measurement = T;
+    type Estimate = (T, T);
+
+    fn predict(&mut self) {
+        ScaledFilter::predict(self);
+    }
+
+    fn update(&mut self, measurement: &T) -> Result<(), Error> {
+        ScaledFilter::update(self, measurement)
+    }
+
+    fn estimate(&self) -> (T, T) {
+        self.result()
+    }
+}
+
+impl<T: Matrix> BayesFilter for GaussianSumFilter<T> {
+    type Measurement = T;
+    type Estimate = (T, T);
+
+    fn predict(&mut self) {
+        GaussianSumFilter::predict(self);
+    }
+
+    fn update(&mut self, measurement: &T) -> Result<(), Error> {
+        GaussianSumFilter::update(self, measurement)
+    }
+
+    fn estimate(&self) -> (T, T) {
+        self.result()
+    }
+}
+
+impl<T: Matrix> BayesFilter for DelayedFilter<T> {
+    type Measurement = T;
+    type Estimate = (T, T);
+
+    fn predict(&mut self) {
+        DelayedFilter::predict(self);
+    }
+
+    fn update(&mut self, measurement: &T) -> Result<(), Error> {
+        DelayedFilter::update(self, measurement)
+    }
+
+    fn estimate(&self) -> (T, T) {
+        self.result()
+    }
+}
+
+impl BayesFilter for BoxTracker {
+    type Measurement = BoundingBox;
+    type Estimate = BoundingBox;
+
+    fn predict(&mut self) {
+        BoxTracker::predict(self);
+    }
+
+    fn update(&mut self, bounding_box: &BoundingBox) -> Result<(), Error> {
+        BoxTracker::update(self, *bounding_box)
+    }
+
+    fn estimate(&self) -> BoundingBox {
+        self.bounding_box()
+    }
+}
+
+impl<S, T, P, C> BayesFilter for ModelledFilter<S, T, P, C>
+where
+    S: Clone,
+    T: Matrix,
+    P: FnMut(&S, &mut Random) -> S,
+    C: FnMut(&S, &mut DynamicKalmanFilter<T>)
+{
+    type Measurement = T;
+    type Estimate = (T, T);
+
+    fn predict(&mut self) {
+        self.filter.predict(&mut self.propagate, &mut self.condition);
+    }
+
+    fn update(&mut self, measurement: &T) -> Result<(), Error> {
+        self.filter.update(measurement)
+    }
+
+    fn estimate(&self) -> (T, T) {
+        self.filter.result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::DenseMatrix;
+    use crate::mixture::Component;
+    use crate::particle::{Particle, RaoBlackwellizedFilter};
+
+    fn run<F: BayesFilter<Measurement = DenseMatrix, Estimate = (DenseMatrix, DenseMatrix)>>(
+        mut filter: F
+    ) -> (DenseMatrix, DenseMatrix) {
+        for step in 0..10 {
+            filter.step(&DenseMatrix::vector(&[f64::from(step)])).unwrap();
+        }
+        filter.estimate()
+    }
+
+    #[test]
+    fn swaps_estimators_generically() {
+        let mut model = DynamicKalmanFilter::<DenseMatrix>::new(2, 1);
+        model.transition = DenseMatrix::from_row_slices(&[&[1.0, 1.0], &[0.0, 1.0]]);
+        model.process_noise = DenseMatrix::identity(2).scale(0.01);
+
+        let (state, covariance) = run(model.clone());
+        let mixture = GaussianSumFilter {
+            components: vec![Component {
+                weight: 1.0,
+                filter: model.clone()
+            }]
+        };
+        for (other_state, other_covariance) in [run(mixture), run(ScaledFilter::new(model, &[10.0, 1.0]))] {
+            assert!(other_state.sub(&state).get(0, 0).abs() < 1e-9);
+            assert!(other_covariance.sub(&covariance).get(1, 1).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn runs_boxed_filters() {
+        let model = DynamicKalmanFilter::<DenseMatrix>::new(2, 1);
+        let filters: Vec<BoxedFilter<DenseMatrix>> = vec![
+            Box::new(model.clone()),
+            Box::new(ScaledFilter::new(model.clone(), &[1.0, 1.0]))
+        ];
+        for filter in filters {
+            assert_eq!(run(filter), run(model.clone()));
+        }
+    }
+
+    #[test]
+    fn steps_particle_filters() {
+        let mut model = DynamicKalmanFilter::<DenseMatrix>::new(2, 1);
+        model.transition = DenseMatrix::from_row_slices(&[&[1.0, 1.0], &[0.0, 1.0]]);
+        let particle = |weight| Particle {
+            sample: 1.0,
+            weight,
+            filter: model.clone()
+        };
+        let particles = RaoBlackwellizedFilter::new(vec![particle(0.5), particle(0.5)], 3);
+
+        // Without sampling, the particles all follow the Kalman filter.
+        let (state, covariance) = run(ModelledFilter::new(particles, |gain: &f64, _| *gain, |_, _| ()));
+        let (expected_state, expected_covariance) = run(model.clone());
+        assert!(state.sub(&expected_state).get(0, 0).abs() < 1e-9);
+        assert!(covariance.sub(&expected_covariance).get(1, 1).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,92 @@
+//! `lqe-monitor` filters a stream of numbers read from standard input and
+//! shows the filter live in the terminal.
+//!
+//! Each line holds a measurement, optionally followed by its variance;
+//! lines that don't parse are skipped. Press `q` or `Esc` to quit.
+
+use std::io::{self, BufRead};
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use lqe::monitor::{self, Monitor};
+use lqe::{step, LQE};
+
+const USAGE: &str = "usage: lqe-monitor [--name NAME] [--process-noise Q] [--variance R] [--history N]";
+
+/// `Options` are the command line arguments.
+struct Options {
+    name: String,
+    process_noise: f64,
+    variance: f64,
+    history: usize
+}
+
+/// `parse` reads the options from `args`, or returns what is wrong with them.
+fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        name: "stdin".to_string(),
+        process_noise: 0.01,
+        variance: 1.0,
+        history: 200
+    };
+    while let Some(arg) = args.next() {
+        let value = args.next().ok_or_else(|| format!("missing value for {}", arg))?;
+        let invalid = || format!("invalid value for {}: {}", arg, value);
+        match arg.as_str() {
+            "--name" => options.name = value.clone(),
+            "--process-noise" => options.process_noise = value.parse().map_err(|_| invalid())?,
+            "--variance" => options.variance = value.parse().map_err(|_| invalid())?,
+            "--history" => options.history = value.parse().map_err(|_| invalid())?,
+            _ => return Err(format!("unknown argument {}", arg))
+        }
+    }
+    Ok(options)
+}
+
+fn main() {
+    let options = parse(std::env::args().skip(1)).unwrap_or_else(|error| {
+        eprintln!("{}\n{}", error, USAGE);
+        process::exit(2);
+    });
+    let shared = Arc::new(Mutex::new(Monitor::new(options.name.as_str(), options.history)));
+
+    let monitor = Arc::clone(&shared);
+    thread::spawn(move || {
+        let mut estimate: Option<LQE> = None;
+        for line in io::stdin().lock().lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break
+            };
+            let mut fields = line.split_whitespace().map(str::parse::<f64>);
+            let (value, variance) = match (fields.next(), fields.next()) {
+                (Some(Ok(value)), None) => (value, options.variance),
+                (Some(Ok(value)), Some(Ok(variance))) => (value, variance),
+                _ => continue
+            };
+            // Start from the first measurement rather than an arbitrary guess.
+            let prior = match estimate {
+                Some(estimate) => step::predict(estimate, 0.0, options.process_noise),
+                None => {
+                    let first = LQE {
+                        measurement: value,
+                        variance
+                    };
+                    estimate = Some(first);
+                    monitor.lock().unwrap().predict(first);
+                    continue;
+                }
+            };
+            let info = step::update(prior, value, variance);
+            estimate = Some(info.posterior);
+            monitor.lock().unwrap().observe(&info);
+        }
+    });
+
+    if let Err(error) = monitor::run(&shared, Duration::from_millis(100)) {
+        eprintln!("lqe-monitor: {}", error);
+        process::exit(1);
+    }
+}
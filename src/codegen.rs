@@ -0,0 +1,269 @@
+//! Code generation of specialized filters.
+//!
+//! The generic filters loop over matrices whose shape is only known at
+//! runtime. For a small filter with a fixed model, `generate` writes the
+//! source of a filter specialized to it instead: states and covariances are
+//! fixed-size arrays, every matrix product is unrolled, and the model is
+//! folded in as constants, dropping the terms multiplied by zero. Call it
+//! from a build script and `include!` the result:
+//!
+//! ```no_run
+//! // build.rs, with `lqe` in `[build-dependencies]`.
+//! use lqe::codegen;
+//! use lqe::matrix::{DenseMatrix, Matrix};
+//! use lqe::multivariate::DynamicKalmanFilter;
+//!
+//! let mut model = DynamicKalmanFilter::<DenseMatrix>::new(2, 1);
+//! model.transition = DenseMatrix::from_row_slices(&[&[1.0, 0.1], &[0.0, 1.0]]);
+//! model.process_noise = DenseMatrix::identity(2).scale(1e-3);
+//! let out_dir = std::env::var("OUT_DIR").unwrap();
+//! std::fs::write(format!("{}/tracker.rs", out_dir), codegen::generate("Tracker", &model)).unwrap();
+//! ```
+//!
+//! ```ignore
+//! // src/main.rs
+//! include!(concat!(env!("OUT_DIR"), "/tracker.rs"));
+//!
+//! let mut tracker = Tracker::new();
+//! tracker.next([2.0]).unwrap();
+//! ```
+//!
+//! The generated filter steps exactly as `DynamicKalmanFilter::predict` and
+//! `update`, using the standard rather than the Joseph form covariance
+//! update, and reports an `lqe::error::Error` if the innovation covariance
+//! isn't positive definite.
+
+use std::fmt::Write;
+
+use crate::matrix::Matrix;
+use crate::multivariate::DynamicKalmanFilter;
+
+/// `term` returns `coefficient * variable` folded for a constant
+/// coefficient, `None` if it is zero.
+fn term(coefficient: f64, variable: &str) -> Option<String> {
+    if coefficient == 0.0 {
+        None
+    } else if coefficient == 1.0 {
+        Some(variable.to_string())
+    } else if coefficient == -1.0 {
+        Some(format!("-{}", variable))
+    } else {
+        Some(format!("{:?} * {}", coefficient, variable))
+    }
+}
+
+/// `sum` adds up `terms`, plus a nonzero `constant`.
+fn sum(terms: impl IntoIterator<Item = String>, constant: f64) -> String {
+    let mut terms: Vec<String> = terms.into_iter().collect();
+    if constant != 0.0 || terms.is_empty() {
+        terms.push(format!("{:?}", constant));
+    }
+    terms.join(" + ")
+}
+
+/// `array` formats the `f64` elements of `matrix` as a Rust array, nested
+/// unless `matrix` is a column vector.
+fn array<T: Matrix>(matrix: &T) -> String {
+    let (rows, columns) = matrix.shape();
+    let row = |i: usize| {
+        let values: Vec<String> = (0..columns).map(|j| format!("{:?}", matrix.get(i, j))).collect();
+        values.join(", ")
+    };
+    let rows: Vec<String> = if columns == 1 {
+        (0..rows).map(row).collect()
+    } else {
+        (0..rows).map(|i| format!("[{}]", row(i))).collect()
+    };
+    format!("[{}]", rows.join(", "))
+}
+
+/// `generate` returns the source of a filter called `name` specialized to
+/// the model of `filter`, starting from its state and covariance.
+///
+/// The generated struct has `pub` fields `state: [f64; N]` and
+/// `covariance: [[f64; N]; N]`, a `const fn new()` and the methods
+/// `predict()`, `update(measurement: [f64; M])` and `next(measurement)`.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::codegen;
+/// use lqe::matrix::{DenseMatrix, Matrix};
+/// use lqe::multivariate::DynamicKalmanFilter;
+///
+/// let mut model = DynamicKalmanFilter::<DenseMatrix>::new(2, 1);
+/// model.transition = DenseMatrix::from_row_slices(&[&[1.0, 0.1], &[0.0, 1.0]]);
+/// codegen::generate("Tracker", &model).contains("let x0 = x[0] + 0.1 * x[1];");
+/// // => true
+/// ```
+///
+/// # Panics:
+///
+/// Panics if the model is invalid, see `DynamicKalmanFilter::validate`.
+pub fn generate<T: Matrix>(name: &str, filter: &DynamicKalmanFilter<T>) -> String {
+    filter.validate().expect("invalid model");
+    let (n, m) = filter.dimensions();
+    let (f, q) = (&filter.transition, &filter.process_noise);
+    let (h, r) = (&filter.observation, &filter.measurement_noise);
+    let mut code = String::new();
+    let mut line = |indent: usize, text: String| {
+        writeln!(code, "{:indent$}{}", "", text, indent = indent * 4).unwrap();
+    };
+
+    line(0, format!("/// `{}` is a Kalman filter generated by `lqe::codegen`.", name));
+    line(0, "#[derive(Clone, Copy, Debug, PartialEq)]".to_string());
+    line(0, format!("pub struct {} {{", name));
+    line(1, format!("pub state: [f64; {}],", n));
+    line(1, format!("pub covariance: [[f64; {}]; {}]", n, n));
+    line(0, "}".to_string());
+    line(0, String::new());
+    line(0, format!("impl {} {{", name));
+
+    line(1, "/// `new` creates the filter at its initial estimate.".to_string());
+    line(1, "pub const fn new() -> Self {".to_string());
+    line(2, format!("{} {{", name));
+    line(3, format!("state: {},", array(&filter.state)));
+    line(3, format!("covariance: {}", array(&filter.covariance)));
+    line(2, "}".to_string());
+    line(1, "}".to_string());
+    line(0, String::new());
+
+    // x = F x, P = F P Fᵀ + Q.
+    line(1, "/// `predict` moves the estimate one step forward.".to_string());
+    line(1, "#[allow(clippy::all, unused_parens, unused_variables)]".to_string());
+    line(1, "pub fn predict(&mut self) {".to_string());
+    line(2, "let x = self.state;".to_string());
+    line(2, "let p = self.covariance;".to_string());
+    for i in 0..n {
+        let terms = (0..n).filter_map(|k| term(f.get(i, k), &format!("x[{}]", k)));
+        line(2, format!("let x{} = {};", i, sum(terms, 0.0)));
+    }
+    for i in 0..n {
+        for j in 0..n {
+            let terms = (0..n).filter_map(|k| term(f.get(i, k), &format!("p[{}][{}]", k, j)));
+            line(2, format!("let fp{}_{} = {};", i, j, sum(terms, 0.0)));
+        }
+    }
+    for i in 0..n {
+        for j in i..n {
+            let terms = (0..n).filter_map(|k| term(f.get(j, k), &format!("fp{}_{}", i, k)));
+            line(2, format!("let p{}_{} = {};", i, j, sum(terms, q.get(i, j))));
+        }
+    }
+    let state: Vec<String> = (0..n).map(|i| format!("x{}", i)).collect();
+    line(2, format!("self.state = [{}];", state.join(", ")));
+    line(2, format!("self.covariance = {};", symmetric(n, "p")));
+    line(1, "}".to_string());
+    line(0, String::new());
+
+    // S = H P Hᵀ + R = L Lᵀ, x = x + (H P)ᵀ S⁻¹ y, P = P - (H P)ᵀ S⁻¹ H P.
+    line(1, "/// `update` refines the estimate with `measurement`.".to_string());
+    line(1, "#[allow(clippy::all, unused_parens, unused_variables)]".to_string());
+    line(1, format!("pub fn update(&mut self, measurement: [f64; {}]) -> Result<(), ::lqe::error::Error> {{", m));
+    line(2, "let x = self.state;".to_string());
+    line(2, "let p = self.covariance;".to_string());
+    for i in 0..m {
+        let terms = (0..n).filter_map(|k| term(-h.get(i, k), &format!("x[{}]", k)));
+        let terms = std::iter::once(format!("measurement[{}]", i)).chain(terms);
+        line(2, format!("let y{} = {};", i, sum(terms, 0.0)));
+    }
+    for i in 0..m {
+        for j in 0..n {
+            let terms = (0..n).filter_map(|k| term(h.get(i, k), &format!("p[{}][{}]", k, j)));
+            line(2, format!("let hp{}_{} = {};", i, j, sum(terms, 0.0)));
+        }
+    }
+    for i in 0..m {
+        for j in 0..=i {
+            let terms = (0..n).filter_map(|k| term(h.get(j, k), &format!("hp{}_{}", i, k)));
+            line(2, format!("let s{}_{} = {};", i, j, sum(terms, r.get(i, j))));
+        }
+    }
+    for j in 0..m {
+        let terms = (0..j).map(|k| format!("-l{}_{} * l{}_{}", j, k, j, k));
+        line(2, format!("let d{} = {};", j, sum(std::iter::once(format!("s{}_{}", j, j)).chain(terms), 0.0)));
+        line(2, format!("if !(d{} > 0.0) {{", j));
+        line(3, "return Err(::lqe::error::Error::SingularInnovation);".to_string());
+        line(2, "}".to_string());
+        line(2, format!("let l{}_{} = d{}.sqrt();", j, j, j));
+        for i in j + 1..m {
+            let terms = (0..j).map(|k| format!("-l{}_{} * l{}_{}", i, k, j, k));
+            let numerator = sum(std::iter::once(format!("s{}_{}", i, j)).chain(terms), 0.0);
+            line(2, format!("let l{}_{} = ({}) / l{}_{};", i, j, numerator, j, j));
+        }
+    }
+    // Solve S v = y and S g = H P by forward and back substitution.
+    for column in std::iter::once(None).chain((0..n).map(Some)) {
+        let (solution, rhs) = match column {
+            None => ("v".to_string(), (0..m).map(|i| format!("y{}", i)).collect::<Vec<_>>()),
+            Some(j) => (format!("g{}_", j), (0..m).map(|i| format!("hp{}_{}", i, j)).collect())
+        };
+        for (i, value) in rhs.into_iter().enumerate() {
+            let terms = (0..i).map(|k| format!("-l{}_{} * w{}{}", i, k, solution, k));
+            let numerator = sum(std::iter::once(value).chain(terms), 0.0);
+            line(2, format!("let w{}{} = ({}) / l{}_{};", solution, i, numerator, i, i));
+        }
+        for i in (0..m).rev() {
+            let terms = (i + 1..m).map(|k| format!("-l{}_{} * {}{}", k, i, solution, k));
+            let numerator = sum(std::iter::once(format!("w{}{}", solution, i)).chain(terms), 0.0);
+            line(2, format!("let {}{} = ({}) / l{}_{};", solution, i, numerator, i, i));
+        }
+    }
+    for i in 0..n {
+        let terms = (0..m).map(|k| format!("hp{}_{} * v{}", k, i, k));
+        line(2, format!("let x{} = {};", i, sum(std::iter::once(format!("x[{}]", i)).chain(terms), 0.0)));
+    }
+    for i in 0..n {
+        for j in i..n {
+            let terms = (0..m).map(|k| format!("-hp{}_{} * g{}_{}", k, i, j, k));
+            line(2, format!("let p{}_{} = {};", i, j, sum(std::iter::once(format!("p[{}][{}]", i, j)).chain(terms), 0.0)));
+        }
+    }
+    line(2, format!("self.state = [{}];", state.join(", ")));
+    line(2, format!("self.covariance = {};", symmetric(n, "p")));
+    line(2, "Ok(())".to_string());
+    line(1, "}".to_string());
+    line(0, String::new());
+
+    line(1, "/// `next` performs the entire predict - update cycle for a measurement.".to_string());
+    line(1, format!("pub fn next(&mut self, measurement: [f64; {}]) -> Result<(), ::lqe::error::Error> {{", m));
+    line(2, "self.predict();".to_string());
+    line(2, "self.update(measurement)".to_string());
+    line(1, "}".to_string());
+    line(0, "}".to_string());
+    code
+}
+
+/// `symmetric` formats the symmetric matrix whose upper triangle is held in
+/// the variables `<prefix><i>_<j>` as a nested array.
+fn symmetric(n: usize, prefix: &str) -> String {
+    let rows: Vec<String> = (0..n)
+        .map(|i| {
+            let row: Vec<String> = (0..n)
+                .map(|j| format!("{}{}_{}", prefix, i.min(j), i.max(j)))
+                .collect();
+            format!("[{}]", row.join(", "))
+        })
+        .collect();
+    format!("[{}]", rows.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::DenseMatrix;
+
+    #[test]
+    fn folds_model_into_code() {
+        let mut model = DynamicKalmanFilter::<DenseMatrix>::new(2, 1);
+        model.transition = DenseMatrix::from_row_slices(&[&[1.0, 0.5], &[0.0, 1.0]]);
+        model.process_noise = DenseMatrix::from_row_slices(&[&[0.0, 0.0], &[0.0, 0.25]]);
+        let code = generate("Tracker", &model);
+
+        assert!(code.contains("let x0 = x[0] + 0.5 * x[1];"));
+        assert!(code.contains("let x1 = x[1];"));
+        assert!(code.contains("let p1_1 = fp1_1 + 0.25;"));
+        assert!(code.contains("let y0 = measurement[0] + -x[0];"));
+        assert!(code.contains("state: [0.0, 0.0],"));
+    }
+}
@@ -0,0 +1,310 @@
+//! Filters described by configuration files.
+//!
+//! With the `config` feature enabled a `FilterConfig` describes a filter,
+//! its type, dimensions, model matrices and tuning, in TOML or YAML, so it
+//! can be retuned without recompiling. `type` is one of:
+//!
+//! - `kalman`: a `DynamicKalmanFilter`.
+//! - `scaled`: a `ScaledFilter`, with the additional `scale` factors.
+//!
+//! `states` and `measurements` give the dimensions. Every other field is
+//! optional and defaults to the one of `DynamicKalmanFilter::new`: `state`
+//! is a list of values, the matrices `covariance`, `transition`,
+//! `process_noise`, `observation` and `measurement_noise` a list of rows, a
+//! table with the `diagonal`, or a number multiplying the identity.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::bayes::BayesFilter;
+//! use lqe::config::FilterConfig;
+//! use lqe::matrix::{DenseMatrix, Matrix};
+//!
+//! let config = FilterConfig::from_toml(r#"
+//!     type = "kalman"
+//!     states = 2
+//!     measurements = 1
+//!     transition = [[1.0, 1.0], [0.0, 1.0]]
+//!     process_noise = { diagonal = [0.01, 0.01] }
+//!     measurement_noise = 4.0
+//! "#).unwrap();
+//! let mut filter = config.build::<DenseMatrix>().unwrap();
+//! filter.step(&DenseMatrix::vector(&[2.0])).unwrap();
+//! ```
+
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::bayes::BoxedFilter;
+use crate::error::Error;
+use crate::matrix::Matrix;
+use crate::multivariate::DynamicKalmanFilter;
+use crate::scaling::ScaledFilter;
+
+/// `ConfigError` describes why a filter couldn't be built from its
+/// configuration.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The file couldn't be read.
+    Io(io::Error),
+    /// The description isn't valid TOML or YAML, or misses or misspells a
+    /// field.
+    Parse(String),
+    /// The file extension is neither `toml`, `yaml` nor `yml`.
+    UnknownFormat,
+    /// The described model is invalid, e.g. its matrices don't have the
+    /// shapes of its dimensions.
+    Model(Error)
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(error) => write!(f, "failed to read filter configuration: {}", error),
+            ConfigError::Parse(message) => write!(f, "invalid filter configuration: {}", message),
+            ConfigError::UnknownFormat => write!(f, "filter configuration is neither TOML nor YAML"),
+            ConfigError::Model(error) => write!(f, "invalid filter model: {}", error)
+        }
+    }
+}
+
+impl error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ConfigError::Io(error) => Some(error),
+            ConfigError::Model(error) => Some(error),
+            _ => None
+        }
+    }
+}
+
+impl From<Error> for ConfigError {
+    fn from(error: Error) -> Self {
+        ConfigError::Model(error)
+    }
+}
+
+/// `MatrixConfig` is a matrix given by its rows, its diagonal, or a multiple
+/// of the identity.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum MatrixConfig {
+    Identity(f64),
+    Diagonal { diagonal: Vec<f64> },
+    Rows(Vec<Vec<f64>>)
+}
+
+impl MatrixConfig {
+    /// `to_matrix` builds the matrix, failing if it isn't `rows` x
+    /// `columns`. A multiple of the identity has ones wherever row and
+    /// column index match.
+    pub fn to_matrix<T: Matrix>(&self, rows: usize, columns: usize) -> Result<T, Error> {
+        let mut matrix = T::zeros(rows, columns);
+        match self {
+            MatrixConfig::Identity(value) => {
+                for i in 0..rows.min(columns) {
+                    matrix.set(i, i, *value);
+                }
+            }
+            MatrixConfig::Diagonal { diagonal } => {
+                if rows != columns || diagonal.len() != rows {
+                    return Err(Error::DimensionMismatch);
+                }
+                for (i, value) in diagonal.iter().enumerate() {
+                    matrix.set(i, i, *value);
+                }
+            }
+            MatrixConfig::Rows(values) => {
+                if values.len() != rows || values.iter().any(|row| row.len() != columns) {
+                    return Err(Error::DimensionMismatch);
+                }
+                for (i, row) in values.iter().enumerate() {
+                    for (j, value) in row.iter().enumerate() {
+                        matrix.set(i, j, *value);
+                    }
+                }
+            }
+        }
+        Ok(matrix)
+    }
+}
+
+/// `ModelConfig` is the model of a `DynamicKalmanFilter`, with the fields
+/// left out taken from `DynamicKalmanFilter::new`.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelConfig {
+    pub states: usize,
+    pub measurements: usize,
+    #[serde(default)]
+    pub state: Option<Vec<f64>>,
+    #[serde(default)]
+    pub covariance: Option<MatrixConfig>,
+    #[serde(default)]
+    pub transition: Option<MatrixConfig>,
+    #[serde(default)]
+    pub process_noise: Option<MatrixConfig>,
+    #[serde(default)]
+    pub observation: Option<MatrixConfig>,
+    #[serde(default)]
+    pub measurement_noise: Option<MatrixConfig>
+}
+
+impl ModelConfig {
+    /// `to_filter` builds the filter, failing if a matrix doesn't have the
+    /// shape the dimensions call for.
+    pub fn to_filter<T: Matrix>(&self) -> Result<DynamicKalmanFilter<T>, Error> {
+        fn field<T: Matrix>(matrix: &Option<MatrixConfig>, default: T) -> Result<T, Error> {
+            let (rows, columns) = default.shape();
+            matrix.as_ref().map_or(Ok(default), |matrix| matrix.to_matrix(rows, columns))
+        }
+        let mut filter = DynamicKalmanFilter::<T>::new(self.states, self.measurements);
+        if let Some(state) = &self.state {
+            if state.len() != self.states {
+                return Err(Error::DimensionMismatch);
+            }
+            filter.state = T::vector(state);
+        }
+        Ok(DynamicKalmanFilter {
+            covariance: field(&self.covariance, filter.covariance)?,
+            transition: field(&self.transition, filter.transition)?,
+            process_noise: field(&self.process_noise, filter.process_noise)?,
+            observation: field(&self.observation, filter.observation)?,
+            measurement_noise: field(&self.measurement_noise, filter.measurement_noise)?,
+            state: filter.state
+        })
+    }
+}
+
+/// `FilterConfig` describes a filter of any supported `type`.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FilterConfig {
+    Kalman(ModelConfig),
+    Scaled {
+        scale: Vec<f64>,
+        #[serde(flatten)]
+        model: ModelConfig
+    }
+}
+
+impl FilterConfig {
+    /// `from_toml` parses a TOML description.
+    pub fn from_toml(source: &str) -> Result<Self, ConfigError> {
+        toml::from_str(source).map_err(|error| ConfigError::Parse(error.to_string()))
+    }
+
+    /// `from_yaml` parses a YAML description.
+    pub fn from_yaml(source: &str) -> Result<Self, ConfigError> {
+        serde_yaml::from_str(source).map_err(|error| ConfigError::Parse(error.to_string()))
+    }
+
+    /// `load` reads the description at `path`, in TOML or YAML depending on
+    /// its extension.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let parse = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => Self::from_toml,
+            Some("yaml") | Some("yml") => Self::from_yaml,
+            _ => return Err(ConfigError::UnknownFormat)
+        };
+        parse(&fs::read_to_string(path).map_err(ConfigError::Io)?)
+    }
+
+    /// `model` returns the model of the filter.
+    pub fn model(&self) -> &ModelConfig {
+        match self {
+            FilterConfig::Kalman(model) | FilterConfig::Scaled { model, .. } => model
+        }
+    }
+
+    /// `build` creates the described filter over matrix backend `T`.
+    ///
+    /// A scaled filter needs one positive, finite scale factor per state, or
+    /// fails with `Error::DimensionMismatch`.
+    pub fn build<T: Matrix + 'static>(&self) -> Result<BoxedFilter<T>, ConfigError> {
+        let model = self.model().to_filter::<T>()?;
+        Ok(match self {
+            FilterConfig::Kalman(_) => Box::new(model),
+            FilterConfig::Scaled { scale, .. } => {
+                let valid = scale.iter().all(|factor| factor.is_finite() && *factor > 0.0);
+                if scale.len() != model.dimensions().0 || !valid {
+                    return Err(ConfigError::Model(Error::DimensionMismatch));
+                }
+                Box::new(ScaledFilter::new(model, scale))
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::DenseMatrix;
+
+    #[test]
+    fn parses_toml_and_yaml_alike() {
+        let toml = FilterConfig::from_toml(
+            r#"
+            type = "scaled"
+            states = 2
+            measurements = 1
+            scale = [10.0, 1.0]
+            state = [1.0, 0.5]
+            transition = [[1.0, 1.0], [0.0, 1.0]]
+            process_noise = { diagonal = [0.01, 0.01] }
+            measurement_noise = 4.0
+            "#
+        )
+        .unwrap();
+        let yaml = FilterConfig::from_yaml(
+            "
+            type: scaled
+            states: 2
+            measurements: 1
+            scale: [10.0, 1.0]
+            state: [1.0, 0.5]
+            transition: [[1.0, 1.0], [0.0, 1.0]]
+            process_noise: { diagonal: [0.01, 0.01] }
+            measurement_noise: 4.0
+            "
+        )
+        .unwrap();
+        assert_eq!(toml, yaml);
+
+        let mut expected = DynamicKalmanFilter::<DenseMatrix>::new(2, 1);
+        expected.state = DenseMatrix::vector(&[1.0, 0.5]);
+        expected.transition = DenseMatrix::from_row_slices(&[&[1.0, 1.0], &[0.0, 1.0]]);
+        expected.process_noise = DenseMatrix::identity(2).scale(0.01);
+        expected.measurement_noise = DenseMatrix::vector(&[4.0]);
+        assert_eq!(toml.model().to_filter::<DenseMatrix>().unwrap(), expected);
+        assert!(toml.build::<DenseMatrix>().is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_descriptions() {
+        let misshapen = FilterConfig::from_toml("type = \"kalman\"\nstates = 2\nmeasurements = 1\ntransition = [[1.0]]");
+        assert!(matches!(
+            misshapen.unwrap().build::<DenseMatrix>(),
+            Err(ConfigError::Model(Error::DimensionMismatch))
+        ));
+        for scale in &["[1.0]", "[1.0, 0.0]", "[1.0, -2.0]"] {
+            let scaled = FilterConfig::from_toml(&format!(
+                "type = \"scaled\"\nstates = 2\nmeasurements = 1\nscale = {}",
+                scale
+            ));
+            assert!(matches!(
+                scaled.unwrap().build::<DenseMatrix>(),
+                Err(ConfigError::Model(Error::DimensionMismatch))
+            ));
+        }
+        let misspelled = FilterConfig::from_toml("type = \"kalman\"\nstates = 2\nmeasurements = 1\nprocess_nosie = 1.0");
+        assert!(matches!(misspelled, Err(ConfigError::Parse(_))));
+        assert!(matches!(FilterConfig::load("filter.ini"), Err(ConfigError::UnknownFormat)));
+    }
+}
@@ -0,0 +1,171 @@
+//! Decimation of estimate streams.
+//!
+//! Filters run at the rate of their sensors, often hundreds of estimates a
+//! second, while databases and dashboards want one value every second or
+//! minute. A `Decimator` cuts a stream of timestamped estimates into fixed
+//! intervals and emits one estimate per interval, once the interval is
+//! over.
+//!
+//! The estimates of a filter are strongly correlated, each built on the
+//! previous one, so averaging them doesn't shrink the variance the way
+//! averaging independent measurements would. `Aggregation::Mean` reports
+//! the mean variance bound that holds whatever their correlation, rather
+//! than dividing by the count.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::LQE;
+//! use lqe::decimation::{Aggregation, Decimator};
+//!
+//! // 10 Hz estimates, decimated to one a second.
+//! let mut decimator = Decimator::new(1.0, Aggregation::Last);
+//! let mut emitted = Vec::new();
+//! for n in 0..25 {
+//!     let estimate = LQE { measurement: n as f64, variance: 1.0 };
+//!     emitted.extend(decimator.push(n as f64 / 10.0, estimate));
+//! }
+//! emitted.iter().map(|decimated| decimated.estimate.measurement).collect::<Vec<_>>();
+//! // => [9.0, 19.0]
+//! ```
+
+use crate::timed::Timestamp;
+use crate::LQE;
+
+/// `Aggregation` is how the estimates of an interval are combined.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Aggregation {
+    /// The last estimate of the interval, the best estimate of the value at
+    /// its end.
+    Last,
+    /// The mean of the estimates, an estimate of the mean value over the
+    /// interval, with the square of their mean standard deviation as its
+    /// variance.
+    Mean
+}
+
+/// `Decimated` is the estimate emitted for an interval: the `interval`-th
+/// since the first timestamp, the `timestamp` of its last estimate and the
+/// `count` of estimates aggregated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Decimated<T> {
+    pub interval: u64,
+    pub timestamp: T,
+    pub estimate: LQE,
+    pub count: usize
+}
+
+/// `Accumulator` holds the estimates of the current interval.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Accumulator<T> {
+    interval: u64,
+    timestamp: T,
+    last: LQE,
+    sum: f64,
+    deviations: f64,
+    count: usize
+}
+
+/// `Decimator` emits one estimate every `period` seconds, aggregated by
+/// `aggregation`.
+///
+/// Intervals are counted from the first timestamp. Intervals without any
+/// estimates emit nothing, so a consumer expecting one per interval can
+/// tell gaps by `Decimated::interval`. Timestamps running backwards count
+/// towards the current interval.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Decimator<T> {
+    pub period: f64,
+    pub aggregation: Aggregation,
+    start: Option<T>,
+    current: Option<Accumulator<T>>
+}
+
+impl<T: Timestamp> Decimator<T> {
+    /// `new` creates a decimator with intervals of `period` seconds.
+    pub fn new(period: f64, aggregation: Aggregation) -> Self {
+        Decimator {
+            period,
+            aggregation,
+            start: None,
+            current: None
+        }
+    }
+
+    /// `push` adds `estimate` made at `timestamp`, returning the aggregate
+    /// of the previous interval if `timestamp` is past its end.
+    pub fn push(&mut self, timestamp: T, estimate: LQE) -> Option<Decimated<T>> {
+        let start = *self.start.get_or_insert(timestamp);
+        let interval = (timestamp.seconds_since(&start) / self.period).floor() as u64;
+        let finished = match self.current {
+            Some(current) if interval > current.interval => self.flush(),
+            _ => None
+        };
+        let current = self.current.get_or_insert(Accumulator {
+            interval,
+            timestamp,
+            last: estimate,
+            sum: 0.0,
+            deviations: 0.0,
+            count: 0
+        });
+        current.timestamp = timestamp;
+        current.last = estimate;
+        current.sum += estimate.measurement;
+        current.deviations += estimate.variance.sqrt();
+        current.count += 1;
+        finished
+    }
+
+    /// `flush` returns the aggregate of the current interval before it is
+    /// over, e.g. at the end of a stream, and starts a new one.
+    pub fn flush(&mut self) -> Option<Decimated<T>> {
+        let current = self.current.take()?;
+        let estimate = match self.aggregation {
+            Aggregation::Last => current.last,
+            Aggregation::Mean => {
+                let count = current.count as f64;
+                LQE {
+                    measurement: current.sum / count,
+                    variance: (current.deviations / count).powi(2)
+                }
+            }
+        };
+        Some(Decimated {
+            interval: current.interval,
+            timestamp: current.timestamp,
+            estimate,
+            count: current.count
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aggregates_intervals() {
+        let mut decimator = Decimator::new(0.5, Aggregation::Mean);
+        let estimates = [(0.0, 1.0, 1.0), (0.2, 3.0, 9.0), (0.6, 5.0, 4.0), (2.1, 7.0, 4.0)];
+        let emitted: Vec<_> = estimates
+            .iter()
+            .filter_map(|(timestamp, measurement, variance)| {
+                let estimate = LQE {
+                    measurement: *measurement,
+                    variance: *variance
+                };
+                decimator.push(*timestamp, estimate)
+            })
+            .collect();
+        assert_eq!(emitted.len(), 2);
+        assert_eq!((emitted[0].interval, emitted[0].count), (0, 2));
+        // Not the 2.5 independent estimates would have.
+        assert_eq!(emitted[0].estimate.result(), (2.0, 4.0));
+        assert_eq!((emitted[1].interval, emitted[1].timestamp), (1, 0.6));
+        let last = decimator.flush().unwrap();
+        assert_eq!((last.interval, last.estimate.result()), (4, (7.0, 4.0)));
+        assert_eq!(decimator.flush(), None);
+    }
+}
@@ -0,0 +1,678 @@
+//! Checks that a filter is well tuned.
+//!
+//! When the model of a filter matches reality its innovations, the
+//! differences between the measurements and the predictions, are
+//! uncorrelated from one step to the next. Correlated innovations mean the
+//! filter misses some of the dynamics or weighs its measurements wrongly.
+//!
+//! The innovations should be normalized by their standard deviation first,
+//! e.g. `step.innovation / step.innovation_variance.sqrt()` of each recorded
+//! `StepInfo`.
+//!
+//! `ljung_box` tests a recorded sequence of innovations after the fact,
+//! while a `NisMonitor` watches a running filter and raises an alarm as soon
+//! as its recent innovations stop matching their expected variance. A
+//! `Cusum` detects an abrupt shift in their mean instead, e.g. when the
+//! underlying process jumps to a new level.
+//!
+//! A `ConditionMonitor` watches the numerical health of a multivariate filter
+//! instead: it raises an alarm when the condition number of the covariance
+//! grows so large that the next steps are about to lose their precision.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::diagnostics;
+//!
+//! // Innovations that keep the same sign for a while: the filter lags.
+//! let innovations = [1.0, 0.8, 1.2, 0.9, 1.1, -1.0, -0.9, -1.1, -0.8, -1.2];
+//! diagnostics::ljung_box(&innovations, 3, 0.05).white;
+//! // => false
+//! ```
+
+use std::collections::VecDeque;
+
+use crate::matrix::Matrix;
+use crate::step::StepInfo;
+
+/// `Alarm` is a boxed callback receiving the average NIS of a window that
+/// fell outside its bounds.
+pub type Alarm = Box<dyn FnMut(f64) + Send>;
+
+/// `Whiteness` is the outcome of a whiteness test.
+///
+/// `statistic` is chi-square distributed when the innovations are white.
+/// `p_value` is the probability of a statistic at least this large in that
+/// case, and `white` whether it is at least the significance of the test.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Whiteness {
+    pub statistic: f64,
+    pub p_value: f64,
+    pub white: bool
+}
+
+/// `autocorrelation` returns the sample autocorrelation of `series` at
+/// `lag`, around its mean.
+pub fn autocorrelation(series: &[f64], lag: usize) -> f64 {
+    let mean = series.iter().sum::<f64>() / series.len() as f64;
+    let variance = series.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>();
+    if variance == 0.0 {
+        return 0.0;
+    }
+    let covariance = series
+        .iter()
+        .zip(series.iter().skip(lag))
+        .map(|(x, y)| (x - mean) * (y - mean))
+        .sum::<f64>();
+    covariance / variance
+}
+
+/// `ljung_box` tests whether `innovations` are white, from their
+/// autocorrelations at lags `1..=lags`, at the given `significance`, e.g.
+/// `0.05`.
+///
+/// A common choice for `lags` is around `ln(n)` for `n` innovations.
+///
+/// # Panics:
+///
+/// Panics if `lags` is zero or not smaller than the number of innovations.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::diagnostics;
+/// let innovations = [0.3, -1.1, 0.8, 0.2, -0.5, 1.4, -0.9, 0.1, -0.2, 0.6];
+/// diagnostics::ljung_box(&innovations, 2, 0.05).white;
+/// // => true
+/// ```
+pub fn ljung_box(innovations: &[f64], lags: usize, significance: f64) -> Whiteness {
+    let n = innovations.len();
+    assert!(lags > 0 && lags < n, "lags must be in 1..{}", n);
+    let statistic = (1..=lags)
+        .map(|lag| autocorrelation(innovations, lag).powi(2) / (n - lag) as f64)
+        .sum::<f64>()
+        * (n * (n + 2)) as f64;
+    let p_value = 1.0 - chi_square_cdf(statistic, lags as f64);
+    Whiteness {
+        statistic,
+        p_value,
+        white: p_value >= significance
+    }
+}
+
+/// `nis` returns the normalized innovation squared `yᵀ S⁻¹ y` of an
+/// innovation `y` with covariance `S`, e.g. as returned by
+/// `DynamicKalmanFilter::innovation`, or `None` if `S` is not positive
+/// definite.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::diagnostics;
+/// use lqe::matrix::{DenseMatrix, Matrix};
+/// let covariance = DenseMatrix::from_row_slices(&[&[4.0, 0.0], &[0.0, 1.0]]);
+/// diagnostics::nis(&DenseMatrix::vector(&[2.0, 1.0]), &covariance);
+/// // => Some(2.0)
+/// ```
+pub fn nis<T: Matrix>(innovation: &T, covariance: &T) -> Option<f64> {
+    let weighted = covariance.cholesky_solve(innovation)?;
+    Some(innovation.transpose().mul(&weighted).get(0, 0))
+}
+
+/// `NisMonitor` checks that the normalized innovation squared (NIS) of a
+/// running filter stays consistent with its model.
+///
+/// For a well tuned filter the NIS summed over `window` steps is chi-square
+/// distributed with `window * dimensions` degrees of freedom, `dimensions`
+/// being the size of each measurement. Once the window is full, an average
+/// NIS outside the two-sided bounds at `confidence` marks the filter
+/// unhealthy and calls the alarm, if any: too high means the filter is
+/// overconfident or its model has drifted, too low that it overestimates
+/// its noise.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::diagnostics::NisMonitor;
+/// let mut monitor = NisMonitor::new(20, 1, 0.95);
+/// monitor.set_alarm(|nis| println!("average NIS {} out of bounds", nis));
+/// for _ in 0..20 {
+///     monitor.push(4.0);
+/// }
+/// monitor.healthy();
+/// // => false
+/// ```
+pub struct NisMonitor {
+    window: usize,
+    lower: f64,
+    upper: f64,
+    readings: VecDeque<f64>,
+    healthy: bool,
+    alarm: Option<Alarm>
+}
+
+impl NisMonitor {
+    /// `new` creates a monitor averaging the last `window` steps of a filter
+    /// with measurements of `dimensions` values.
+    ///
+    /// # Panics:
+    ///
+    /// Panics if `window` or `dimensions` is zero, or `confidence` is not in
+    /// `(0, 1)`.
+    pub fn new(window: usize, dimensions: usize, confidence: f64) -> Self {
+        assert!(window > 0 && dimensions > 0, "window and dimensions must be positive");
+        assert!(confidence > 0.0 && confidence < 1.0, "confidence must be in (0, 1)");
+        let dof = (window * dimensions) as f64;
+        let tail = (1.0 - confidence) / 2.0;
+        NisMonitor {
+            window,
+            lower: chi_square_quantile(tail, dof) / window as f64,
+            upper: chi_square_quantile(1.0 - tail, dof) / window as f64,
+            readings: VecDeque::with_capacity(window),
+            healthy: true,
+            alarm: None
+        }
+    }
+
+    /// `set_alarm` registers a callback invoked with the average NIS of every
+    /// subsequent full window outside the bounds, replacing any previous
+    /// alarm.
+    pub fn set_alarm(&mut self, alarm: impl FnMut(f64) + Send + 'static) {
+        self.alarm = Some(Box::new(alarm));
+    }
+
+    /// `push` records the NIS of the latest step and returns whether the
+    /// filter is healthy.
+    pub fn push(&mut self, nis: f64) -> bool {
+        if self.readings.len() == self.window {
+            self.readings.pop_front();
+        }
+        self.readings.push_back(nis);
+        if let Some(average) = self.average() {
+            self.healthy = average >= self.lower && average <= self.upper;
+            if !self.healthy {
+                if let Some(alarm) = self.alarm.as_mut() {
+                    alarm(average);
+                }
+            }
+        }
+        self.healthy
+    }
+
+    /// `observe` records the NIS of a scalar step, so that the monitor can be
+    /// fed from the observer of a `TimedFilter`.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use lqe::LQE;
+    /// use lqe::diagnostics::NisMonitor;
+    /// use lqe::step::StepInfo;
+    /// let mut monitor = NisMonitor::new(1, 1, 0.95);
+    /// let step = StepInfo {
+    ///     prediction: LQE { measurement: 7.0, variance: 2.0 },
+    ///     measurement: 10.0,
+    ///     innovation: 3.0,
+    ///     innovation_variance: 4.0,
+    ///     gain: 0.5,
+    ///     posterior: LQE { measurement: 8.5, variance: 1.0 }
+    /// };
+    /// monitor.observe(&step);
+    /// // => true
+    /// ```
+    pub fn observe(&mut self, step: &StepInfo) -> bool {
+        self.push(step.innovation * step.innovation / step.innovation_variance)
+    }
+
+    /// `average` returns the average NIS over the window, or `None` until the
+    /// window is full.
+    pub fn average(&self) -> Option<f64> {
+        if self.readings.len() < self.window {
+            return None;
+        }
+        Some(self.readings.iter().sum::<f64>() / self.window as f64)
+    }
+
+    /// `bounds` returns the lower and upper bound on the average NIS.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use lqe::diagnostics::NisMonitor;
+    /// let (lower, upper) = NisMonitor::new(20, 1, 0.95).bounds();
+    /// (lower, upper);
+    /// // => (0.48, 1.71)
+    /// ```
+    pub fn bounds(&self) -> (f64, f64) {
+        (self.lower, self.upper)
+    }
+
+    /// `healthy` returns whether the latest full window was within bounds.
+    pub fn healthy(&self) -> bool {
+        self.healthy
+    }
+}
+
+/// `condition_bound` returns a cheap lower bound on the condition number of
+/// a symmetric positive definite `covariance`, the squared ratio of the
+/// largest to the smallest diagonal element of its Cholesky factor, or
+/// `None` if it is not positive definite.
+///
+/// Each step of a filter loses about `log10` of the condition number of the
+/// digits of its covariance, out of the 16 of an `f64`.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::diagnostics;
+/// use lqe::matrix::{DenseMatrix, Matrix};
+/// let covariance = DenseMatrix::from_row_slices(&[&[4.0, 0.0], &[0.0, 0.01]]);
+/// diagnostics::condition_bound(&covariance);
+/// // => Some(400.0)
+/// ```
+pub fn condition_bound<T: Matrix>(covariance: &T) -> Option<f64> {
+    let factor = covariance.cholesky_factor()?;
+    let diagonal = (0..factor.shape().0).map(|i| factor.get(i, i));
+    let (smallest, largest) = diagonal.fold((f64::INFINITY, 0.0f64), |(smallest, largest), value| {
+        (smallest.min(value), largest.max(value))
+    });
+    Some((largest / smallest).powi(2))
+}
+
+/// `ConditionMonitor` checks that the covariance of a running filter stays
+/// well enough conditioned for its arithmetic, warning before it breaks
+/// down rather than after.
+///
+/// A covariance whose `condition_bound` exceeds `limit`, or which is no
+/// longer positive definite at all, marks the filter unhealthy and calls the
+/// alarm, if any, with the bound, infinite in the latter case. A `limit` of
+/// `1e12` leaves about four significant digits.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::diagnostics::ConditionMonitor;
+/// use lqe::matrix::{DenseMatrix, Matrix};
+/// let mut monitor = ConditionMonitor::new(1e12);
+/// monitor.set_alarm(|condition| println!("covariance condition {:e}", condition));
+/// monitor.push(&DenseMatrix::from_row_slices(&[&[1.0, 0.0], &[0.0, 1e-14]]));
+/// // => false
+/// ```
+pub struct ConditionMonitor {
+    limit: f64,
+    condition: Option<f64>,
+    alarm: Option<Alarm>
+}
+
+impl ConditionMonitor {
+    /// `new` creates a monitor flagging condition numbers above `limit`.
+    pub fn new(limit: f64) -> Self {
+        ConditionMonitor {
+            limit,
+            condition: None,
+            alarm: None
+        }
+    }
+
+    /// `set_alarm` registers a callback invoked with the condition bound of
+    /// every subsequent unhealthy covariance, replacing any previous alarm.
+    pub fn set_alarm(&mut self, alarm: impl FnMut(f64) + Send + 'static) {
+        self.alarm = Some(Box::new(alarm));
+    }
+
+    /// `push` checks the covariance of the latest step and returns whether
+    /// the filter is healthy.
+    pub fn push<T: Matrix>(&mut self, covariance: &T) -> bool {
+        let condition = condition_bound(covariance).unwrap_or(f64::INFINITY);
+        self.condition = Some(condition);
+        let healthy = self.healthy();
+        if !healthy {
+            if let Some(alarm) = self.alarm.as_mut() {
+                alarm(condition);
+            }
+        }
+        healthy
+    }
+
+    /// `condition` returns the condition bound of the latest covariance, or
+    /// `None` before the first.
+    pub fn condition(&self) -> Option<f64> {
+        self.condition
+    }
+
+    /// `healthy` returns whether the latest covariance was within the limit.
+    pub fn healthy(&self) -> bool {
+        self.condition.is_none_or(|condition| condition <= self.limit)
+    }
+}
+
+/// `Shift` is the direction of a detected change in mean.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Shift {
+    Up,
+    Down
+}
+
+/// `Change` is a change in mean detected by a `Cusum`.
+///
+/// `onset` is the step the change is estimated to have started at and
+/// `detected` the step it was detected at, both counted from zero since the
+/// detector was created or reset.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Change {
+    pub onset: usize,
+    pub detected: usize,
+    pub shift: Shift
+}
+
+/// `Cusum` detects abrupt shifts in the mean of normalized residuals with a
+/// two-sided cumulative sum test.
+///
+/// Each side accumulates the residuals beyond `drift`, typically half the
+/// smallest shift worth detecting in standard deviations, and reports a
+/// change once its sum exceeds `threshold`. Larger thresholds raise fewer
+/// false alarms but detect later. The sums restart after each detection.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::diagnostics::{Cusum, Shift};
+/// let mut cusum = Cusum::new(0.5, 4.0);
+/// let residuals = [0.2, -0.4, 0.1, 2.1, 1.8, 2.3, 1.9];
+/// let change = residuals.iter().find_map(|r| cusum.push(*r)).unwrap();
+/// (change.onset, change.detected, change.shift);
+/// // => (3, 5, Shift::Up)
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Cusum {
+    pub drift: f64,
+    pub threshold: f64,
+    upper: f64,
+    lower: f64,
+    upper_onset: usize,
+    lower_onset: usize,
+    steps: usize
+}
+
+impl Cusum {
+    /// `new` creates a detector with the given `drift` and `threshold`, in
+    /// standard deviations of the residuals.
+    pub fn new(drift: f64, threshold: f64) -> Self {
+        Cusum {
+            drift,
+            threshold,
+            upper: 0.0,
+            lower: 0.0,
+            upper_onset: 0,
+            lower_onset: 0,
+            steps: 0
+        }
+    }
+
+    /// `push` adds the normalized residual of the latest step and returns the
+    /// change it completes, if any.
+    pub fn push(&mut self, residual: f64) -> Option<Change> {
+        let step = self.steps;
+        self.steps += 1;
+        // A side that drops back to zero restarts, so a change it later
+        // detects began after this step.
+        if self.upper == 0.0 {
+            self.upper_onset = step;
+        }
+        if self.lower == 0.0 {
+            self.lower_onset = step;
+        }
+        self.upper = (self.upper + residual - self.drift).max(0.0);
+        self.lower = (self.lower - residual - self.drift).max(0.0);
+        let change = if self.upper > self.threshold {
+            Some((self.upper_onset, Shift::Up))
+        } else if self.lower > self.threshold {
+            Some((self.lower_onset, Shift::Down))
+        } else {
+            None
+        };
+        change.map(|(onset, shift)| {
+            self.upper = 0.0;
+            self.lower = 0.0;
+            Change {
+                onset,
+                detected: step,
+                shift
+            }
+        })
+    }
+
+    /// `observe` adds the innovation of a scalar step, normalized by its
+    /// standard deviation.
+    pub fn observe(&mut self, step: &StepInfo) -> Option<Change> {
+        self.push(step.innovation / step.innovation_variance.sqrt())
+    }
+
+    /// `reset` clears the sums and restarts counting steps from zero.
+    pub fn reset(&mut self) {
+        *self = Cusum::new(self.drift, self.threshold);
+    }
+}
+
+/// `chi_square_cdf` returns the probability of a chi-square variable with
+/// `dof` degrees of freedom being at most `x`.
+pub(crate) fn chi_square_cdf(x: f64, dof: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    regularized_gamma(dof / 2.0, x / 2.0)
+}
+
+/// `chi_square_quantile` returns the `x` at which `chi_square_cdf(x, dof)`
+/// reaches `p`, by bisection.
+pub(crate) fn chi_square_quantile(p: f64, dof: f64) -> f64 {
+    let mut high = dof + 10.0;
+    while chi_square_cdf(high, dof) < p {
+        high *= 2.0;
+    }
+    let mut low = 0.0;
+    for _ in 0..100 {
+        let middle = (low + high) / 2.0;
+        if chi_square_cdf(middle, dof) < p {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    (low + high) / 2.0
+}
+
+/// `regularized_gamma` returns the lower regularized incomplete gamma
+/// function `P(a, x)`, from its series below `a + 1` and its continued
+/// fraction above.
+fn regularized_gamma(a: f64, x: f64) -> f64 {
+    let prefactor = (a * x.ln() - x - ln_gamma(a)).exp();
+    if x < a + 1.0 {
+        let mut term = 1.0 / a;
+        let mut sum = term;
+        for n in 1..500 {
+            term *= x / (a + n as f64);
+            sum += term;
+            if term.abs() < sum.abs() * 1e-15 {
+                break;
+            }
+        }
+        sum * prefactor
+    } else {
+        // Lentz's method for the continued fraction of Q(a, x).
+        let tiny = 1e-300;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut fraction = d;
+        for n in 1..500 {
+            let an = -(n as f64) * (n as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < tiny {
+                d = tiny;
+            }
+            c = b + an / c;
+            if c.abs() < tiny {
+                c = tiny;
+            }
+            d = 1.0 / d;
+            let delta = d * c;
+            fraction *= delta;
+            if (delta - 1.0).abs() < 1e-15 {
+                break;
+            }
+        }
+        1.0 - prefactor * fraction
+    }
+}
+
+/// `ln_gamma` returns the natural logarithm of the gamma function for
+/// positive `x`, by the Lanczos approximation.
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7
+    ];
+    if x < 0.5 {
+        // Reflection keeps the approximation accurate near zero.
+        let pi = std::f64::consts::PI;
+        return (pi / (pi * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let t = x + 7.5;
+    let series = COEFFICIENTS[1..]
+        .iter()
+        .enumerate()
+        .fold(COEFFICIENTS[0], |sum, (i, c)| sum + c / (x + i as f64 + 1.0));
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + series.ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::DenseMatrix;
+    use crate::particle::Random;
+    use std::sync::mpsc;
+
+    #[test]
+    fn computes_chi_square_distribution() {
+        // Known quantiles: 3.841 at 95% with one degree of freedom, 18.307
+        // with ten.
+        assert!((chi_square_cdf(3.841_458_820_694_124, 1.0) - 0.95).abs() < 1e-9);
+        assert!((chi_square_cdf(18.307_038_053_275_146, 10.0) - 0.95).abs() < 1e-9);
+        assert!((chi_square_cdf(2.0, 2.0) - (1.0 - (-1.0f64).exp())).abs() < 1e-12);
+        assert_eq!(chi_square_cdf(0.0, 3.0), 0.0);
+        assert!((chi_square_quantile(0.95, 10.0) - 18.307_038_053_275_146).abs() < 1e-9);
+    }
+
+    #[test]
+    fn accepts_white_noise() {
+        let mut random = Random::new(7);
+        let innovations = (0..500).map(|_| random.normal()).collect::<Vec<_>>();
+
+        let whiteness = ljung_box(&innovations, 6, 0.05);
+        assert!(whiteness.white, "{:?}", whiteness);
+    }
+
+    #[test]
+    fn rejects_correlated_innovations() {
+        let mut random = Random::new(7);
+        let mut innovation = 0.0;
+        let innovations = (0..500)
+            .map(|_| {
+                innovation = 0.7 * innovation + random.normal();
+                innovation
+            })
+            .collect::<Vec<_>>();
+
+        let whiteness = ljung_box(&innovations, 6, 0.05);
+        assert!(!whiteness.white && whiteness.p_value < 1e-6);
+        assert!((autocorrelation(&innovations, 1) - 0.7).abs() < 0.1);
+    }
+
+    #[test]
+    fn monitors_consistent_filter() {
+        let mut random = Random::new(3);
+        let mut monitor = NisMonitor::new(50, 2, 0.99);
+
+        assert_eq!(monitor.average(), None);
+        for _ in 0..500 {
+            let (a, b) = (random.normal(), random.normal());
+            assert!(monitor.push(a * a + b * b));
+        }
+        let average = monitor.average().unwrap();
+        assert!((average - 2.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn raises_alarm_when_model_stops_matching() {
+        let (sender, receiver) = mpsc::channel();
+        let mut random = Random::new(3);
+        let mut monitor = NisMonitor::new(20, 1, 0.99);
+        monitor.set_alarm(move |nis| sender.send(nis).unwrap());
+
+        for _ in 0..40 {
+            monitor.push(random.normal().powi(2));
+        }
+        assert!(monitor.healthy());
+        // The sensor gets three times noisier than the model believes.
+        for _ in 0..20 {
+            monitor.push((3.0 * random.normal()).powi(2));
+        }
+        assert!(!monitor.healthy());
+        let alarms = receiver.try_iter().collect::<Vec<_>>();
+        assert!(!alarms.is_empty() && alarms.iter().all(|nis| *nis > monitor.bounds().1));
+    }
+
+    #[test]
+    fn computes_normalized_innovation_squared() {
+        let covariance = DenseMatrix::from_row_slices(&[&[2.0, 1.0], &[1.0, 2.0]]);
+
+        let nis = nis(&DenseMatrix::vector(&[1.0, 1.0]), &covariance).unwrap();
+        assert!((nis - 2.0 / 3.0).abs() < 1e-12);
+        assert_eq!(super::nis(&DenseMatrix::vector(&[1.0]), &DenseMatrix::zeros(1, 1)), None);
+    }
+
+    #[test]
+    fn monitors_covariance_condition() {
+        let (sender, receiver) = mpsc::channel();
+        let mut monitor = ConditionMonitor::new(1e6);
+        monitor.set_alarm(move |condition| sender.send(condition).unwrap());
+
+        assert!(monitor.healthy());
+        assert!(monitor.push(&DenseMatrix::from_row_slices(&[&[4.0, 2.0], &[2.0, 2.0]])));
+        assert_eq!(monitor.condition(), Some(4.0));
+        assert!(!monitor.push(&DenseMatrix::from_row_slices(&[&[1.0, 0.0], &[0.0, 1e-8]])));
+        assert!(!monitor.push(&DenseMatrix::from_row_slices(&[&[1.0, 2.0], &[2.0, 1.0]])));
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![1e8, f64::INFINITY]);
+    }
+
+    #[test]
+    fn detects_shift_in_mean() {
+        let mut random = Random::new(11);
+        let mut cusum = Cusum::new(0.5, 8.0);
+
+        for _ in 0..200 {
+            assert_eq!(cusum.push(random.normal()), None);
+        }
+        let change = (0..50).find_map(|_| cusum.push(random.normal() - 2.0)).unwrap();
+        assert_eq!(change.shift, Shift::Down);
+        assert!(change.onset >= 195 && change.onset <= 202, "{:?}", change);
+        assert!(change.detected < 210);
+
+        cusum.reset();
+        assert_eq!(cusum.push(9.0).map(|change| (change.onset, change.detected)), Some((0, 0)));
+    }
+}
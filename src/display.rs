@@ -0,0 +1,83 @@
+//! Formatting estimates for humans.
+//!
+//! `LQE` displays as `mean ± standard deviation`, rounded so the standard
+//! deviation keeps a fixed number of significant digits (3 unless a precision
+//! is given) and the mean is shown to the same decimal place.
+
+use std::fmt;
+
+use crate::LQE;
+
+const DEFAULT_SIGNIFICANT_DIGITS: usize = 3;
+
+impl fmt::Display for LQE {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let significant_digits = f.precision().unwrap_or(DEFAULT_SIGNIFICANT_DIGITS).max(1) as i32;
+        let deviation = self.variance.sqrt();
+        let magnitude = if deviation.is_normal() {
+            deviation.log10().floor() as i32
+        } else {
+            0
+        };
+        let decimals = significant_digits - 1 - magnitude;
+        if decimals >= 0 {
+            let decimals = decimals as usize;
+            write!(f, "{:.*} ± {:.*}", decimals, self.measurement, decimals, deviation)
+        } else {
+            let scale = 10f64.powi(-decimals);
+            let round = |value: f64| (value / scale).round() * scale;
+            write!(f, "{:.0} ± {:.0}", round(self.measurement), round(deviation))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn displays_with_uncertainty() {
+        let lqe = LQE {
+            measurement: 7.0,
+            variance: 2.0
+        };
+
+        assert_eq!(lqe.to_string(), "7.00 ± 1.41");
+    }
+
+    #[test]
+    fn uses_precision_as_significant_digits() {
+        let lqe = LQE {
+            measurement: 7.0,
+            variance: 2.0
+        };
+
+        assert_eq!(format!("{:.1}", lqe), "7 ± 1");
+        assert_eq!(format!("{:.4}", lqe), "7.000 ± 1.414");
+    }
+
+    #[test]
+    fn rounds_to_the_uncertainty() {
+        let small = LQE {
+            measurement: 0.123456,
+            variance: 0.0001
+        };
+        let large = LQE {
+            measurement: 123456.0,
+            variance: 1522756.0
+        };
+
+        assert_eq!(small.to_string(), "0.1235 ± 0.0100");
+        assert_eq!(large.to_string(), "123460 ± 1230");
+    }
+
+    #[test]
+    fn displays_exact_estimates() {
+        let lqe = LQE {
+            measurement: 7.0,
+            variance: 0.0
+        };
+
+        assert_eq!(lqe.to_string(), "7.00 ± 0.00");
+    }
+}
@@ -0,0 +1,128 @@
+//! Smoothing of slowly drifting environmental sensors.
+//!
+//! Temperature, humidity and pressure readings wander: the room warms up
+//! over the afternoon, the pressure falls ahead of a front. Smoothing them
+//! as a constant lags behind every such drift, smoothing them less lets the
+//! noise through. A `DriftingSensor` models the value together with the
+//! rate it drifts at, itself changing only slowly, so it follows the drift
+//! without lag and still averages the noise away. Both the cleaned reading
+//! and the drift are available, e.g. to alarm on a rising temperature
+//! before it crosses a threshold.
+//!
+//! Readings may come at irregular intervals; the noise levels are given per
+//! second.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::drift::DriftingSensor;
+//!
+//! // A thermometer read every minute, with 0.1 °C of noise.
+//! let mut sensor = DriftingSensor::new(20.0, 0.01);
+//! for reading in &[20.1, 20.1, 20.3, 20.4, 20.6, 20.7, 20.8, 21.1] {
+//!     sensor.next(60.0, *reading).unwrap();
+//! }
+//! // Warming by about 0.15 °C a minute.
+//! sensor.drift().measurement * 60.0 > 0.1;
+//! // => true
+//! ```
+
+use crate::error::Error;
+use crate::matrix::{DenseMatrix, Matrix};
+use crate::multivariate::DynamicKalmanFilter;
+use crate::LQE;
+
+/// `DriftingSensor` estimates the state `[value, drift]` of a sensor, the
+/// drift being the change of the value per second.
+///
+/// `value_noise` is the variance the value gains per second on top of the
+/// drift, `drift_noise` the variance the drift gains per second and
+/// `measurement_noise` the variance of a reading.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DriftingSensor {
+    pub filter: DynamicKalmanFilter<DenseMatrix>,
+    pub value_noise: f64,
+    pub drift_noise: f64,
+    pub measurement_noise: f64
+}
+
+impl DriftingSensor {
+    /// `new` creates a filter starting from `value`, as uncertain as a
+    /// reading, without drift, with noise levels suited to indoor
+    /// temperatures in °C.
+    pub fn new(value: f64, measurement_noise: f64) -> Self {
+        let mut filter = DynamicKalmanFilter::new(2, 1);
+        filter.state = DenseMatrix::vector(&[value, 0.0]);
+        filter.covariance = DenseMatrix::zeros(2, 2);
+        filter.covariance.set(0, 0, measurement_noise);
+        filter.covariance.set(1, 1, 1e-4);
+        filter.observation = DenseMatrix::from_row_slices(&[&[1.0, 0.0]]);
+        DriftingSensor {
+            filter,
+            value_noise: 1e-8,
+            drift_noise: 1e-12,
+            measurement_noise
+        }
+    }
+
+    /// `predict` moves the estimate `dt` seconds forward.
+    pub fn predict(&mut self, dt: f64) {
+        self.filter.transition = DenseMatrix::from_row_slices(&[&[1.0, dt], &[0.0, 1.0]]);
+        // The drift noise integrated over the step into the value as well.
+        let q = self.drift_noise;
+        self.filter.process_noise = DenseMatrix::from_row_slices(&[
+            &[self.value_noise * dt + q * dt.powi(3) / 3.0, q * dt * dt / 2.0],
+            &[q * dt * dt / 2.0, q * dt]
+        ]);
+        self.filter.predict();
+    }
+
+    /// `update` refines the estimate with `reading`.
+    pub fn update(&mut self, reading: f64) -> Result<(), Error> {
+        self.filter.measurement_noise = DenseMatrix::vector(&[self.measurement_noise]);
+        self.filter.update(&DenseMatrix::vector(&[reading]))
+    }
+
+    /// `next` predicts `dt` seconds forward and updates with `reading`.
+    pub fn next(&mut self, dt: f64, reading: f64) -> Result<(), Error> {
+        self.predict(dt);
+        self.update(reading)
+    }
+
+    /// `value` returns the cleaned reading with its variance.
+    pub fn value(&self) -> LQE {
+        LQE {
+            measurement: self.filter.state.get(0, 0),
+            variance: self.filter.covariance.get(0, 0)
+        }
+    }
+
+    /// `drift` returns the estimated drift per second with its variance.
+    pub fn drift(&self) -> LQE {
+        LQE {
+            measurement: self.filter.state.get(1, 0),
+            variance: self.filter.covariance.get(1, 1)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particle::Random;
+
+    #[test]
+    fn follows_drift_without_lag() {
+        // Warming by 1 °C an hour, read every 10 seconds.
+        let mut sensor = DriftingSensor::new(18.0, 0.01);
+        let mut random = Random::new(2);
+        let rate = 1.0 / 3600.0;
+        let mut truth = 18.0;
+        for _ in 0..1080 {
+            truth += rate * 10.0;
+            sensor.next(10.0, truth + 0.1 * random.normal()).unwrap();
+        }
+        assert!((sensor.value().measurement - truth).abs() < 0.05);
+        assert!((sensor.drift().measurement / rate - 1.0).abs() < 0.1);
+    }
+}
@@ -0,0 +1,38 @@
+//! Errors reported by the filters.
+
+use std::error;
+use std::fmt;
+
+/// `Error` describes why a filter step could not be completed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The innovation covariance `H P Hᵀ + R` is not positive definite, so the
+    /// measurement cannot be weighed against the prediction.
+    SingularInnovation,
+    /// A process, measurement or prior covariance that has to be inverted is
+    /// not positive definite.
+    SingularCovariance,
+    /// The measurement has zero likelihood under every hypothesis, so the
+    /// estimate can't be updated with it.
+    ImpossibleMeasurement,
+    /// The matrices of a model don't have the shapes its state and measurement
+    /// sizes call for.
+    DimensionMismatch,
+    /// The measurement comes from a sensor that isn't registered.
+    UnknownSensor
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::SingularInnovation => write!(f, "innovation covariance is not positive definite"),
+            Error::SingularCovariance => write!(f, "covariance is not positive definite"),
+            Error::ImpossibleMeasurement => write!(f, "measurement has zero likelihood"),
+            Error::DimensionMismatch => write!(f, "matrix dimensions do not match the model"),
+            Error::UnknownSensor => write!(f, "measurement from an unregistered sensor")
+        }
+    }
+}
+
+impl error::Error for Error {}
@@ -0,0 +1,345 @@
+//! Extended-precision arithmetic.
+//!
+//! With the `extended` feature, `ExtendedLQE` and the `ExtendedMatrix`
+//! backend compute with `TwoFloat` double-double numbers, which carry about
+//! 106 bits of mantissa instead of the 53 of `f64`. They are several times
+//! slower than their `f64` counterparts and meant for offline use: as a
+//! reference to measure the rounding error of the standard implementations
+//! against, and for ill-conditioned problems where `f64` loses too many
+//! digits.
+//!
+//! `ExtendedMatrix` keeps every intermediate matrix of a `DynamicKalmanFilter`
+//! in extended precision, and only rounds to `f64` where the filter reads
+//! single elements with `get`. In particular, the scalar gains of sequential
+//! updates, used when `measurement_noise` is diagonal, are rounded.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::LQE;
+//! use lqe::extended::ExtendedLQE;
+//!
+//! let lqe = LQE { measurement: 7.0, variance: 1.5 };
+//! ExtendedLQE::from(lqe).predict(0.0, 0.5).update(10.0, 2.0).to_lqe().result();
+//! // => (8.5, 1.0)
+//! ```
+
+pub use twofloat::TwoFloat;
+
+use crate::matrix::Matrix;
+use crate::LQE;
+
+/// `ExtendedLQE` is an `LQE` in extended precision.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ExtendedLQE {
+    pub measurement: TwoFloat,
+    pub variance: TwoFloat
+}
+
+impl ExtendedLQE {
+    /// `predict` is `step::predict` in extended precision: it moves the
+    /// estimate forward by `control` and widens it by `process_noise`.
+    pub fn predict(&self, control: impl Into<TwoFloat>, process_noise: impl Into<TwoFloat>) -> ExtendedLQE {
+        ExtendedLQE {
+            measurement: self.measurement + control.into(),
+            variance: self.variance + process_noise.into()
+        }
+    }
+
+    /// `update` is `step::update` in extended precision: it refines the
+    /// estimate with `measurement`, whose variance is `measurement_noise`,
+    /// and returns the posterior.
+    pub fn update(&self, measurement: impl Into<TwoFloat>, measurement_noise: impl Into<TwoFloat>) -> ExtendedLQE {
+        let gain = self.variance / (self.variance + measurement_noise.into());
+        ExtendedLQE {
+            measurement: self.measurement + gain * (measurement.into() - self.measurement),
+            variance: (TwoFloat::from(1.0) - gain) * self.variance
+        }
+    }
+
+    /// `next` performs the entire predict - update cycle of `LQE::next` in
+    /// extended precision, with the same formula and the same flaws.
+    #[deprecated(note = "use `predict` and `update`, which keep control and measurement apart")]
+    pub fn next(&self, measurement: impl Into<TwoFloat>, variance: impl Into<TwoFloat>) -> ExtendedLQE {
+        let (measurement, variance) = (measurement.into(), variance.into());
+        // Predict, then update the measurement with the prediction.
+        let predicted_measurement = self.measurement + measurement;
+        let predicted_variance = self.variance + variance;
+        let total = variance + predicted_variance;
+        ExtendedLQE {
+            measurement: (measurement * predicted_variance + predicted_measurement * variance) / total,
+            variance: variance * predicted_measurement / total
+        }
+    }
+
+    /// `to_lqe` rounds the estimate to `f64`.
+    pub fn to_lqe(&self) -> LQE {
+        LQE {
+            measurement: self.measurement.into(),
+            variance: self.variance.into()
+        }
+    }
+}
+
+impl From<LQE> for ExtendedLQE {
+    fn from(lqe: LQE) -> Self {
+        ExtendedLQE {
+            measurement: lqe.measurement.into(),
+            variance: lqe.variance.into()
+        }
+    }
+}
+
+/// `ExtendedMatrix` is a row-major `Matrix` backend of `TwoFloat` elements.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::extended::ExtendedMatrix;
+/// use lqe::matrix::Matrix;
+/// let third = ExtendedMatrix::vector(&[1.0]).scale(1.0 / 3.0);
+/// third.add(&third).add(&third).sub(&ExtendedMatrix::vector(&[1.0])).get(0, 0);
+/// // => 0.0
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExtendedMatrix {
+    rows: usize,
+    columns: usize,
+    values: Vec<TwoFloat>
+}
+
+impl ExtendedMatrix {
+    /// `from_matrix` copies any other `Matrix`.
+    pub fn from_matrix<T: Matrix>(matrix: &T) -> Self {
+        let (rows, columns) = matrix.shape();
+        let mut extended = ExtendedMatrix::zeros(rows, columns);
+        for i in 0..rows {
+            for j in 0..columns {
+                extended.set(i, j, matrix.get(i, j));
+            }
+        }
+        extended
+    }
+
+    /// `to_matrix` rounds the matrix into any other `Matrix`.
+    pub fn to_matrix<T: Matrix>(&self) -> T {
+        let mut matrix = T::zeros(self.rows, self.columns);
+        for i in 0..self.rows {
+            for j in 0..self.columns {
+                matrix.set(i, j, self.get(i, j));
+            }
+        }
+        matrix
+    }
+
+    /// `get_extended` returns the element at `row`, `column` without
+    /// rounding it.
+    pub fn get_extended(&self, row: usize, column: usize) -> TwoFloat {
+        self.values[self.index(row, column)]
+    }
+
+    fn index(&self, row: usize, column: usize) -> usize {
+        assert!(row < self.rows && column < self.columns, "matrix index out of bounds");
+        row * self.columns + column
+    }
+
+    /// `zip` combines the elements of two matrices of the same shape.
+    fn zip(&self, other: &Self, combine: impl Fn(TwoFloat, TwoFloat) -> TwoFloat) -> Self {
+        assert_eq!(self.shape(), other.shape(), "matrix shapes must match");
+        ExtendedMatrix {
+            rows: self.rows,
+            columns: self.columns,
+            values: self.values.iter().zip(&other.values).map(|(a, b)| combine(*a, *b)).collect()
+        }
+    }
+
+    /// `dot` returns the sum of the products of `count` pairs of elements.
+    fn dot(count: usize, mut product: impl FnMut(usize) -> TwoFloat) -> TwoFloat {
+        (0..count).fold(TwoFloat::from(0.0), |sum, k| sum + product(k))
+    }
+}
+
+impl Matrix for ExtendedMatrix {
+    fn zeros(rows: usize, columns: usize) -> Self {
+        ExtendedMatrix {
+            rows,
+            columns,
+            values: vec![TwoFloat::from(0.0); rows * columns]
+        }
+    }
+
+    fn shape(&self) -> (usize, usize) {
+        (self.rows, self.columns)
+    }
+
+    fn get(&self, row: usize, column: usize) -> f64 {
+        self.get_extended(row, column).into()
+    }
+
+    fn set(&mut self, row: usize, column: usize, value: f64) {
+        let index = self.index(row, column);
+        self.values[index] = value.into();
+    }
+
+    fn transpose(&self) -> Self {
+        let mut transposed = ExtendedMatrix::zeros(self.columns, self.rows);
+        for i in 0..self.rows {
+            for j in 0..self.columns {
+                transposed.values[j * self.rows + i] = self.get_extended(i, j);
+            }
+        }
+        transposed
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        self.zip(other, |a, b| a + b)
+    }
+
+    fn sub(&self, other: &Self) -> Self {
+        self.zip(other, |a, b| a - b)
+    }
+
+    fn scale(&self, factor: f64) -> Self {
+        ExtendedMatrix {
+            rows: self.rows,
+            columns: self.columns,
+            values: self.values.iter().map(|value| *value * factor).collect()
+        }
+    }
+
+    fn mul(&self, other: &Self) -> Self {
+        assert_eq!(self.columns, other.rows, "matrix shapes must be compatible");
+        let mut product = ExtendedMatrix::zeros(self.rows, other.columns);
+        for i in 0..self.rows {
+            for j in 0..other.columns {
+                product.values[i * other.columns + j] =
+                    ExtendedMatrix::dot(self.columns, |k| self.get_extended(i, k) * other.get_extended(k, j));
+            }
+        }
+        product
+    }
+
+    fn cholesky_solve(&self, rhs: &Self) -> Option<Self> {
+        let size = self.rows;
+        assert_eq!(size, rhs.rows, "matrix shapes must be compatible");
+        let factor = self.cholesky_factor()?;
+        // Forward substitution L y = rhs, then back substitution Lᵀ x = y.
+        let mut solution = ExtendedMatrix::zeros(size, rhs.columns);
+        for column in 0..rhs.columns {
+            for i in 0..size {
+                let sum = ExtendedMatrix::dot(i, |k| factor.get_extended(i, k) * solution.get_extended(k, column));
+                solution.values[i * rhs.columns + column] =
+                    (rhs.get_extended(i, column) - sum) / factor.get_extended(i, i);
+            }
+            for i in (0..size).rev() {
+                let sum = ExtendedMatrix::dot(size - i - 1, |k| {
+                    factor.get_extended(i + 1 + k, i) * solution.get_extended(i + 1 + k, column)
+                });
+                solution.values[i * rhs.columns + column] =
+                    (solution.get_extended(i, column) - sum) / factor.get_extended(i, i);
+            }
+        }
+        Some(solution)
+    }
+
+    fn cholesky_factor(&self) -> Option<Self> {
+        let size = self.rows;
+        assert_eq!(size, self.columns, "matrix must be square");
+        let mut factor = ExtendedMatrix::zeros(size, size);
+        for j in 0..size {
+            let diagonal = self.get_extended(j, j) - ExtendedMatrix::dot(j, |k| factor.get_extended(j, k).powi(2));
+            if !diagonal.is_valid() || diagonal <= 0.0 {
+                return None;
+            }
+            let diagonal = diagonal.sqrt();
+            factor.values[j * size + j] = diagonal;
+            for i in j + 1..size {
+                let sum = ExtendedMatrix::dot(j, |k| factor.get_extended(i, k) * factor.get_extended(j, k));
+                factor.values[i * size + j] = (self.get_extended(i, j) - sum) / diagonal;
+            }
+        }
+        Some(factor)
+    }
+
+    fn log_determinant(&self) -> Option<f64> {
+        let factor = self.cholesky_factor()?;
+        let sum = ExtendedMatrix::dot(factor.rows, |i| factor.get_extended(i, i).ln());
+        Some((sum * 2.0).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::DenseMatrix;
+    use crate::multivariate::DynamicKalmanFilter;
+    use crate::step;
+
+    #[test]
+    #[allow(deprecated)]
+    fn matches_standard_lqe() {
+        let lqe = LQE {
+            measurement: 3.0,
+            variance: 2.0
+        };
+
+        assert_eq!(ExtendedLQE::from(lqe).next(5.0, 3.0).to_lqe(), lqe.next(5.0, 3.0));
+        assert_eq!(ExtendedLQE::from(lqe).next(5.0, 3.0).next(7.0, 1.0).to_lqe().result(), (8.225, 2.625));
+    }
+
+    #[test]
+    fn matches_step_functions() {
+        let lqe = LQE {
+            measurement: 7.0,
+            variance: 1.5
+        };
+
+        let extended = ExtendedLQE::from(lqe).predict(0.0, 0.5).update(10.0, 2.0).to_lqe();
+        assert_eq!(extended, step::update(step::predict(lqe, 0.0, 0.5), 10.0, 2.0).posterior);
+        assert_eq!(extended.result(), (8.5, 1.0));
+    }
+
+    #[test]
+    fn solves_ill_conditioned_systems() {
+        // The Hilbert matrix of order 8 has a condition number of about 1e10.
+        let size = 8;
+        let mut hilbert = DenseMatrix::zeros(size, size);
+        for i in 0..size {
+            for j in 0..size {
+                hilbert.set(i, j, 1.0 / (i + j + 1) as f64);
+            }
+        }
+        // The right-hand side for a solution of ones, exact to about 1e-32.
+        let extended = ExtendedMatrix::from_matrix(&hilbert);
+        let rhs = extended.mul(&ExtendedMatrix::vector(&[1.0; 8]));
+        let error = |solution: &DenseMatrix| (0..size).map(|i| (solution.get(i, 0) - 1.0).abs()).fold(0.0, f64::max);
+
+        let standard = error(&hilbert.cholesky_solve(&rhs.to_matrix()).unwrap());
+        let extended = error(&extended.cholesky_solve(&rhs).unwrap().to_matrix());
+        assert!(extended < 1e-9);
+        assert!(extended * 1e3 < standard);
+    }
+
+    #[test]
+    fn runs_dynamic_filter() {
+        let mut standard: DynamicKalmanFilter<DenseMatrix> = DynamicKalmanFilter::new(2, 2);
+        standard.transition = DenseMatrix::from_row_slices(&[&[1.0, 1.0], &[0.0, 1.0]]);
+        standard.measurement_noise = DenseMatrix::from_row_slices(&[&[1.0, 0.5], &[0.5, 1.0]]);
+        let mut extended = DynamicKalmanFilter {
+            state: ExtendedMatrix::from_matrix(&standard.state),
+            covariance: ExtendedMatrix::from_matrix(&standard.covariance),
+            transition: ExtendedMatrix::from_matrix(&standard.transition),
+            process_noise: ExtendedMatrix::from_matrix(&standard.process_noise),
+            observation: ExtendedMatrix::from_matrix(&standard.observation),
+            measurement_noise: ExtendedMatrix::from_matrix(&standard.measurement_noise)
+        };
+
+        for step in 0..10 {
+            let measurement = [f64::from(step), 1.0];
+            standard.next(&DenseMatrix::vector(&measurement)).unwrap();
+            extended.next(&ExtendedMatrix::vector(&measurement)).unwrap();
+        }
+        let difference = standard.state.sub(&extended.state.to_matrix());
+        assert!(difference.get(0, 0).abs() < 1e-12 && difference.get(1, 0).abs() < 1e-12);
+    }
+}
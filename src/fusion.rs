@@ -0,0 +1,183 @@
+//! Track-to-track fusion.
+//!
+//! Two filters tracking the same target with different sensors end up with
+//! correlated errors: both predict through the same process noise. Fusing
+//! their tracks as if they were independent, e.g. by averaging them or
+//! weighing them by their covariances, double counts the common part and
+//! yields a covariance that is too small. `fuse` combines two tracks given
+//! their cross-covariance (Bar-Shalom and Campo, 1986), and a `TrackPair`
+//! runs two filters on a common model while keeping track of it.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::fusion::TrackPair;
+//! use lqe::matrix::{DenseMatrix, Matrix};
+//! use lqe::multivariate::DynamicKalmanFilter;
+//!
+//! let mut model = DynamicKalmanFilter::<DenseMatrix>::new(1, 1);
+//! model.process_noise = DenseMatrix::identity(1);
+//! let mut radar = model.clone();
+//! radar.measurement_noise = DenseMatrix::vector(&[4.0]);
+//! let mut tracks = TrackPair::new(model, radar);
+//! for _ in 0..20 {
+//!     tracks.predict();
+//!     tracks.update_first(&DenseMatrix::vector(&[1.0])).unwrap();
+//!     tracks.update_second(&DenseMatrix::vector(&[1.0])).unwrap();
+//! }
+//! // The fused variance is above what independent tracks would give.
+//! let (first, second) = (tracks.first.covariance.get(0, 0), tracks.second.covariance.get(0, 0));
+//! tracks.fused().unwrap().1.get(0, 0) > first * second / (first + second);
+//! // => true
+//! ```
+
+use crate::error::Error;
+use crate::matrix::Matrix;
+use crate::multivariate::DynamicKalmanFilter;
+
+/// `fuse` combines the tracks `first` and `second`, each a state estimate
+/// and its covariance, whose errors have the cross-covariance `cross`, i.e.
+/// `E[e₁ e₂ᵀ]`, returning the fused state and covariance.
+///
+/// It fails with `Error::SingularCovariance` if `P₁ + P₂ - P₁₂ - P₂₁` isn't
+/// positive definite, e.g. when both tracks carry exactly the same
+/// information.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::fusion;
+/// use lqe::matrix::{DenseMatrix, Matrix};
+/// let first = (DenseMatrix::vector(&[1.0]), DenseMatrix::vector(&[1.0]));
+/// let second = (DenseMatrix::vector(&[4.0]), DenseMatrix::vector(&[2.0]));
+/// let (state, covariance) = fusion::fuse(&first, &second, &DenseMatrix::zeros(1, 1)).unwrap();
+/// (state.get(0, 0), covariance.get(0, 0));
+/// // => (2.0, 0.6666666666666667)
+/// ```
+pub fn fuse<T: Matrix>(first: &(T, T), second: &(T, T), cross: &T) -> Result<(T, T), Error> {
+    let ((first_state, first_covariance), (second_state, second_covariance)) = (first, second);
+    // W = (P₁ - P₁₂) (P₁ + P₂ - P₁₂ - P₂₁)⁻¹
+    let difference = first_covariance
+        .add(second_covariance)
+        .sub(cross)
+        .sub(&cross.transpose())
+        .symmetrize();
+    let weight = difference
+        .cholesky_solve(&first_covariance.sub(cross).transpose())
+        .ok_or(Error::SingularCovariance)?
+        .transpose();
+    let state = first_state.add(&weight.mul(&second_state.sub(first_state)));
+    let covariance = first_covariance.sub(&weight.mul(&first_covariance.sub(&cross.transpose())));
+    Ok((state, covariance.symmetrize()))
+}
+
+/// `TrackPair` runs two filters of the same target with a common
+/// `transition` and `process_noise`, taken from `first`, but their own
+/// sensors, and keeps the `cross_covariance` of their errors.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrackPair<T> {
+    pub first: DynamicKalmanFilter<T>,
+    pub second: DynamicKalmanFilter<T>,
+    pub cross_covariance: T
+}
+
+/// `posterior_factor` returns `I - K H` for the gain `K` an update of
+/// `filter` applies.
+fn posterior_factor<T: Matrix>(filter: &DynamicKalmanFilter<T>) -> Result<T, Error> {
+    let observed = filter.observation.mul(&filter.covariance);
+    let innovation = observed
+        .mul(&filter.observation.transpose())
+        .add(&filter.measurement_noise);
+    let gain = innovation
+        .cholesky_solve(&observed)
+        .ok_or(Error::SingularInnovation)?
+        .transpose();
+    let states = filter.covariance.shape().0;
+    Ok(T::identity(states).sub(&gain.mul(&filter.observation)))
+}
+
+impl<T: Matrix> TrackPair<T> {
+    /// `new` pairs the filters, with independent initial errors.
+    pub fn new(first: DynamicKalmanFilter<T>, second: DynamicKalmanFilter<T>) -> Self {
+        let states = first.covariance.shape().0;
+        TrackPair {
+            first,
+            second,
+            cross_covariance: T::zeros(states, states)
+        }
+    }
+
+    /// `predict` moves both estimates one step forward, the common process
+    /// noise correlating their errors.
+    pub fn predict(&mut self) {
+        self.first.predict();
+        self.second.predict();
+        let transition = &self.first.transition;
+        self.cross_covariance = transition
+            .mul(&self.cross_covariance)
+            .mul(&transition.transpose())
+            .add(&self.first.process_noise);
+    }
+
+    /// `update_first` refines the first estimate with `measurement`.
+    pub fn update_first(&mut self, measurement: &T) -> Result<(), Error> {
+        let factor = posterior_factor(&self.first)?;
+        self.first.update(measurement)?;
+        self.cross_covariance = factor.mul(&self.cross_covariance);
+        Ok(())
+    }
+
+    /// `update_second` refines the second estimate with `measurement`.
+    pub fn update_second(&mut self, measurement: &T) -> Result<(), Error> {
+        let factor = posterior_factor(&self.second)?;
+        self.second.update(measurement)?;
+        self.cross_covariance = self.cross_covariance.mul(&factor.transpose());
+        Ok(())
+    }
+
+    /// `fused` returns the fused state and covariance of both tracks, see
+    /// `fuse`.
+    pub fn fused(&self) -> Result<(T, T), Error> {
+        fuse(&self.first.result(), &self.second.result(), &self.cross_covariance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::DenseMatrix;
+    use crate::particle::Random;
+
+    #[test]
+    fn tracks_cross_covariance() {
+        // Two position sensors on a random walk, many times over, to compare
+        // the predicted error statistics with the actual ones.
+        let mut model = DynamicKalmanFilter::<DenseMatrix>::new(1, 1);
+        model.process_noise = DenseMatrix::identity(1);
+        let mut second = model.clone();
+        second.measurement_noise = DenseMatrix::vector(&[4.0]);
+
+        let mut random = Random::new(7);
+        let runs = 4000;
+        let (mut cross, mut fused_error) = (0.0, 0.0);
+        let mut tracks = TrackPair::new(model.clone(), second.clone());
+        for _ in 0..runs {
+            tracks = TrackPair::new(model.clone(), second.clone());
+            let mut truth = random.normal();
+            for _ in 0..10 {
+                truth += random.normal();
+                tracks.predict();
+                tracks.update_first(&DenseMatrix::vector(&[truth + random.normal()])).unwrap();
+                tracks.update_second(&DenseMatrix::vector(&[truth + 2.0 * random.normal()])).unwrap();
+            }
+            let first_error = tracks.first.state.get(0, 0) - truth;
+            let second_error = tracks.second.state.get(0, 0) - truth;
+            cross += first_error * second_error / f64::from(runs);
+            fused_error += (tracks.fused().unwrap().0.get(0, 0) - truth).powi(2) / f64::from(runs);
+        }
+        let expected_cross = tracks.cross_covariance.get(0, 0);
+        assert!((cross / expected_cross - 1.0).abs() < 0.1);
+        let fused_variance = tracks.fused().unwrap().1.get(0, 0);
+        assert!((fused_error / fused_variance - 1.0).abs() < 0.1);
+    }
+}
@@ -0,0 +1,310 @@
+//! A gRPC estimation service.
+//!
+//! With the `grpc` feature enabled `EstimatorService` implements the
+//! `Estimator` service of `proto/lqe.proto` with `tonic`, so that services in
+//! any language can run Kalman filters over the network:
+//!
+//! - `CreateFilter` starts a session with a `FilterSnapshot` and returns its
+//!   id.
+//! - `Step` runs the predict - update cycle of a session's filter for a
+//!   measurement and returns the new estimate.
+//! - `Forecast` returns the estimates up to `MAX_FORECAST_STEPS` steps
+//!   ahead, leaving the filter as it is.
+//! - `DeleteFilter` ends a session.
+//!
+//! Each session has a lock of its own, so that steps of different sessions
+//! run concurrently. A step that fails leaves the session's filter as it
+//! was. Filter errors and longer forecasts are reported as `INVALID_ARGUMENT`,
+//! unknown sessions as `NOT_FOUND` and sessions beyond `max_sessions` as
+//! `RESOURCE_EXHAUSTED`.
+//!
+//! # Example:
+//!
+//! ```no_run
+//! use lqe::grpc::{EstimatorServer, EstimatorService};
+//!
+//! # async fn serve() -> Result<(), Box<dyn std::error::Error>> {
+//! tonic::transport::Server::builder()
+//!     .add_service(EstimatorServer::new(EstimatorService::new(1000)))
+//!     .serve("[::1]:50051".parse()?)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tonic::{Request, Response, Status};
+
+use crate::error::Error;
+use crate::matrix::{DenseMatrix, Matrix as _};
+use crate::multivariate::DynamicKalmanFilter;
+use crate::proto::{FilterSnapshot, Matrix};
+
+mod generated {
+    include!(concat!(env!("OUT_DIR"), "/lqe.Estimator.rs"));
+}
+
+pub use generated::estimator_server::{Estimator, EstimatorServer};
+
+/// `StateEstimate` is a filter's state estimate and its covariance.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct StateEstimate {
+    #[prost(message, optional, tag = "1")]
+    pub state: Option<Matrix>,
+    #[prost(message, optional, tag = "2")]
+    pub covariance: Option<Matrix>
+}
+
+impl From<&DynamicKalmanFilter<DenseMatrix>> for StateEstimate {
+    fn from(filter: &DynamicKalmanFilter<DenseMatrix>) -> Self {
+        StateEstimate {
+            state: Some(Matrix::from_matrix(&filter.state)),
+            covariance: Some(Matrix::from_matrix(&filter.covariance))
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CreateFilterRequest {
+    #[prost(message, optional, tag = "1")]
+    pub filter: Option<FilterSnapshot>
+}
+
+#[derive(Clone, Copy, PartialEq, prost::Message)]
+pub struct CreateFilterResponse {
+    #[prost(uint64, tag = "1")]
+    pub session: u64
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct StepRequest {
+    #[prost(uint64, tag = "1")]
+    pub session: u64,
+    #[prost(double, repeated, tag = "2")]
+    pub measurement: Vec<f64>
+}
+
+#[derive(Clone, Copy, PartialEq, prost::Message)]
+pub struct ForecastRequest {
+    #[prost(uint64, tag = "1")]
+    pub session: u64,
+    #[prost(uint32, tag = "2")]
+    pub steps: u32
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ForecastResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub estimates: Vec<StateEstimate>
+}
+
+#[derive(Clone, Copy, PartialEq, prost::Message)]
+pub struct DeleteFilterRequest {
+    #[prost(uint64, tag = "1")]
+    pub session: u64
+}
+
+#[derive(Clone, Copy, PartialEq, prost::Message)]
+pub struct DeleteFilterResponse {}
+
+type Session = Arc<Mutex<DynamicKalmanFilter<DenseMatrix>>>;
+
+/// `MAX_FORECAST_STEPS` is the most steps a single `Forecast` may ask for,
+/// bounding the work and memory a client can claim with one request.
+pub const MAX_FORECAST_STEPS: u32 = 10_000;
+
+/// `EstimatorService` keeps the filters of up to `max_sessions` sessions.
+#[derive(Debug, Default)]
+pub struct EstimatorService {
+    pub max_sessions: usize,
+    sessions: Mutex<Sessions>
+}
+
+#[derive(Debug, Default)]
+struct Sessions {
+    filters: HashMap<u64, Session>,
+    next_id: u64
+}
+
+impl EstimatorService {
+    /// `new` creates a service without sessions.
+    pub fn new(max_sessions: usize) -> Self {
+        EstimatorService {
+            max_sessions,
+            sessions: Mutex::default()
+        }
+    }
+
+    /// `sessions` returns the number of open sessions.
+    pub fn sessions(&self) -> usize {
+        self.sessions.lock().unwrap().filters.len()
+    }
+
+    /// `session` returns the filter of session `id`.
+    fn session(&self, id: u64) -> Result<Session, Status> {
+        let sessions = self.sessions.lock().unwrap();
+        sessions
+            .filters
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| Status::not_found(format!("no session {}", id)))
+    }
+}
+
+/// `status` reports a filter error to the client.
+fn status(error: Error) -> Status {
+    Status::invalid_argument(error.to_string())
+}
+
+#[tonic::async_trait]
+impl Estimator for EstimatorService {
+    async fn create_filter(
+        &self,
+        request: Request<CreateFilterRequest>
+    ) -> Result<Response<CreateFilterResponse>, Status> {
+        let snapshot = request
+            .into_inner()
+            .filter
+            .ok_or_else(|| Status::invalid_argument("missing filter"))?;
+        let filter = snapshot.to_filter().map_err(status)?;
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions.filters.len() >= self.max_sessions {
+            return Err(Status::resource_exhausted("too many sessions"));
+        }
+        let session = sessions.next_id;
+        sessions.next_id += 1;
+        sessions.filters.insert(session, Arc::new(Mutex::new(filter)));
+        Ok(Response::new(CreateFilterResponse { session }))
+    }
+
+    async fn step(&self, request: Request<StepRequest>) -> Result<Response<StateEstimate>, Status> {
+        let request = request.into_inner();
+        let session = self.session(request.session)?;
+        let mut filter = session.lock().unwrap();
+        if request.measurement.len() != filter.dimensions().1 {
+            return Err(status(Error::DimensionMismatch));
+        }
+        // Step a copy, so that a rejected measurement doesn't leave the
+        // session predicted a step ahead.
+        let mut stepped = filter.clone();
+        stepped.next(&DenseMatrix::vector(&request.measurement)).map_err(status)?;
+        *filter = stepped;
+        Ok(Response::new(StateEstimate::from(&*filter)))
+    }
+
+    async fn forecast(&self, request: Request<ForecastRequest>) -> Result<Response<ForecastResponse>, Status> {
+        let request = request.into_inner();
+        if request.steps > MAX_FORECAST_STEPS {
+            return Err(Status::invalid_argument(format!(
+                "forecast of {} steps exceeds the maximum of {}",
+                request.steps, MAX_FORECAST_STEPS
+            )));
+        }
+        let mut filter = self.session(request.session)?.lock().unwrap().clone();
+        let estimates = (0..request.steps)
+            .map(|_| {
+                filter.predict();
+                StateEstimate::from(&filter)
+            })
+            .collect();
+        Ok(Response::new(ForecastResponse { estimates }))
+    }
+
+    async fn delete_filter(
+        &self,
+        request: Request<DeleteFilterRequest>
+    ) -> Result<Response<DeleteFilterResponse>, Status> {
+        let session = request.into_inner().session;
+        match self.sessions.lock().unwrap().filters.remove(&session) {
+            Some(_) => Ok(Response::new(DeleteFilterResponse {})),
+            None => Err(Status::not_found(format!("no session {}", session)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::future::Future;
+    use std::task::{Context, Poll, Waker};
+    use tonic::Code;
+
+    /// `block_on` runs a future of the service, which never waits.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        match future.as_mut().poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("the service waited")
+        }
+    }
+
+    fn create(service: &EstimatorService) -> Result<u64, Status> {
+        let mut filter = DynamicKalmanFilter::<DenseMatrix>::new(2, 1);
+        filter.transition = DenseMatrix::from_row_slices(&[&[1.0, 1.0], &[0.0, 1.0]]);
+        let request = CreateFilterRequest {
+            filter: Some(FilterSnapshot::from(&filter))
+        };
+        Ok(block_on(service.create_filter(Request::new(request)))?.into_inner().session)
+    }
+
+    #[test]
+    fn steps_and_forecasts_sessions() {
+        let service = EstimatorService::new(2);
+        let first = create(&service).unwrap();
+        let second = create(&service).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(create(&service).unwrap_err().code(), Code::ResourceExhausted);
+
+        for measurement in 1..=10 {
+            let request = StepRequest {
+                session: first,
+                measurement: vec![measurement as f64]
+            };
+            block_on(service.step(Request::new(request))).unwrap();
+        }
+        let request = StepRequest {
+            session: first,
+            measurement: vec![1.0, 2.0]
+        };
+        assert_eq!(block_on(service.step(Request::new(request))).unwrap_err().code(), Code::InvalidArgument);
+
+        let request = ForecastRequest { session: first, steps: 3 };
+        let estimates = block_on(service.forecast(Request::new(request))).unwrap().into_inner().estimates;
+        assert_eq!(estimates.len(), 3);
+        let state: DenseMatrix = estimates[2].state.as_ref().unwrap().to_matrix().unwrap();
+        assert!((state.get(0, 0) - 13.0).abs() < 0.5);
+        // The forecast leaves the session's filter as it was.
+        let filter = service.session(first).unwrap();
+        assert!((filter.lock().unwrap().state.get(0, 0) - 10.0).abs() < 0.5);
+        let request = ForecastRequest {
+            session: first,
+            steps: MAX_FORECAST_STEPS + 1
+        };
+        assert_eq!(block_on(service.forecast(Request::new(request))).unwrap_err().code(), Code::InvalidArgument);
+
+        // So does a step that fails.
+        let before = {
+            let mut filter = filter.lock().unwrap();
+            filter.covariance = DenseMatrix::zeros(2, 2);
+            filter.measurement_noise = DenseMatrix::zeros(1, 1);
+            filter.clone()
+        };
+        let request = StepRequest {
+            session: first,
+            measurement: vec![11.0]
+        };
+        assert_eq!(block_on(service.step(Request::new(request))).unwrap_err().code(), Code::InvalidArgument);
+        assert_eq!(*filter.lock().unwrap(), before);
+
+        let request = DeleteFilterRequest { session: second };
+        block_on(service.delete_filter(Request::new(request))).unwrap();
+        assert_eq!(service.sessions(), 1);
+        let request = StepRequest {
+            session: second,
+            measurement: vec![1.0]
+        };
+        assert_eq!(block_on(service.step(Request::new(request))).unwrap_err().code(), Code::NotFound);
+    }
+}
@@ -0,0 +1,165 @@
+//! Heart rate smoothing for wearables.
+//!
+//! Optical (PPG) heart rate sensors report a rate every second or so, with a
+//! few beats per minute of noise, spikes where motion couples into the
+//! optical signal, and gaps where the sensor loses contact. A
+//! `HeartRateFilter` smooths the rate while:
+//!
+//! - letting it change no faster than a heart plausibly can, so the noise
+//!   allowed through is bounded by `max_change`;
+//! - rejecting readings outside the physiological range or too far from the
+//!   prediction to be anything but motion artifacts;
+//! - restarting from the next reading after a gap longer than `max_gap`,
+//!   rather than trusting a prediction that old.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::heart_rate::{HeartRateFilter, Status};
+//!
+//! let mut filter = HeartRateFilter::new();
+//! for reading in &[72.0, 74.0, 71.0, 73.0] {
+//!     filter.next(1.0, *reading);
+//! }
+//! // A motion artifact.
+//! filter.next(1.0, 140.0);
+//! // => Status::Rejected
+//! filter.rate().unwrap().measurement.round();
+//! // => 73.0
+//! ```
+
+use crate::{step, LQE};
+
+/// `Status` tells what became of a reading.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Status {
+    /// The reading refined the estimate.
+    Accepted,
+    /// The reading was implausible or an artifact and was ignored.
+    Rejected,
+    /// The reading started a new estimate, the first one or the first after
+    /// a gap.
+    Restarted
+}
+
+/// `HeartRateFilter` estimates a heart rate in beats per minute.
+///
+/// `max_change` is the fastest the rate plausibly changes, in beats per
+/// minute per second, and `measurement_noise` the variance of a reading.
+/// Readings outside `range` are rejected, as are those more than `gate`
+/// standard deviations from the prediction. After `max_gap` seconds without
+/// an accepted reading the filter restarts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HeartRateFilter {
+    pub max_change: f64,
+    pub measurement_noise: f64,
+    pub range: (f64, f64),
+    pub gate: f64,
+    pub max_gap: f64,
+    estimate: Option<LQE>,
+    since_accepted: f64
+}
+
+impl Default for HeartRateFilter {
+    fn default() -> Self {
+        HeartRateFilter {
+            max_change: 3.0,
+            measurement_noise: 16.0,
+            range: (30.0, 220.0),
+            gate: 3.0,
+            max_gap: 10.0,
+            estimate: None,
+            since_accepted: 0.0
+        }
+    }
+}
+
+impl HeartRateFilter {
+    /// `new` creates a filter without an estimate, with settings suited to
+    /// wrist-worn PPG sensors.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `predict` moves the estimate `dt` seconds forward, e.g. through a
+    /// gap without readings.
+    pub fn predict(&mut self, dt: f64) {
+        self.since_accepted += dt;
+        if let Some(estimate) = self.estimate {
+            // Bounding the slope bounds the change, three standard
+            // deviations of the prediction covering `max_change`.
+            let change = self.max_change * dt / 3.0;
+            self.estimate = Some(step::predict(estimate, 0.0, change * change));
+        }
+    }
+
+    /// `update` refines the estimate with `reading`, unless it is rejected.
+    pub fn update(&mut self, reading: f64) -> Status {
+        if !(self.range.0..=self.range.1).contains(&reading) {
+            return Status::Rejected;
+        }
+        let status = match self.estimate {
+            Some(estimate) if self.since_accepted <= self.max_gap => {
+                let step = step::update(estimate, reading, self.measurement_noise);
+                if step.innovation.powi(2) > self.gate.powi(2) * step.innovation_variance {
+                    return Status::Rejected;
+                }
+                self.estimate = Some(step.posterior);
+                Status::Accepted
+            }
+            _ => {
+                self.estimate = Some(LQE {
+                    measurement: reading,
+                    variance: self.measurement_noise
+                });
+                Status::Restarted
+            }
+        };
+        self.since_accepted = 0.0;
+        status
+    }
+
+    /// `next` predicts `dt` seconds forward and updates with `reading`.
+    pub fn next(&mut self, dt: f64, reading: f64) -> Status {
+        self.predict(dt);
+        self.update(reading)
+    }
+
+    /// `rate` returns the estimated rate and its variance, or `None` before
+    /// the first reading.
+    pub fn rate(&self) -> Option<LQE> {
+        self.estimate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particle::Random;
+
+    #[test]
+    fn rejects_artifacts_and_restarts_after_gaps() {
+        let mut filter = HeartRateFilter::new();
+        let mut random = Random::new(4);
+        assert_eq!(filter.next(1.0, 25.0), Status::Rejected);
+        assert_eq!(filter.rate(), None);
+        assert_eq!(filter.next(1.0, 70.0), Status::Restarted);
+
+        // Warming up from 70 to 130 over a minute, with motion artifacts.
+        for second in 0..60 {
+            let truth = 70.0 + f64::from(second);
+            let status = filter.next(1.0, truth + 4.0 * random.normal());
+            assert_eq!(status, Status::Accepted);
+            if second % 20 == 10 {
+                assert_eq!(filter.next(0.0, truth + 50.0), Status::Rejected);
+            }
+        }
+        assert!((filter.rate().unwrap().measurement - 129.0).abs() < 5.0);
+
+        // Losing contact for half a minute.
+        filter.predict(30.0);
+        assert_eq!(filter.update(100.0), Status::Restarted);
+        assert_eq!(filter.rate().unwrap().measurement, 100.0);
+    }
+}
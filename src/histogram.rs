@@ -0,0 +1,209 @@
+//! Grid-based Bayes filtering.
+//!
+//! `HistogramFilter` discretizes a bounded 1D or 2D state into cells and
+//! tracks the probability of each one. It makes no assumption about the shape
+//! of the distribution, so it handles arbitrary nonlinear models and
+//! multimodal posteriors, and at a fine enough resolution it is a ground truth
+//! to test the parametric filters against. The cost grows with the number of
+//! cells, which confines it to low-dimensional problems.
+//!
+//! Cells are addressed as `(row, column)`; a 1D grid has a single column.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::histogram::HistogramFilter;
+//!
+//! // A robot in one of five cells of a corridor, seeing the door at 1.
+//! let mut filter = HistogramFilter::new(5, 1);
+//! filter.update(|(row, _)| if row == 1 { 0.9 } else { 0.1 }).unwrap();
+//! // It then most likely moves forward by one cell.
+//! filter.convolve(&[&[0.0], &[0.1], &[0.9]]);
+//! filter.most_likely();
+//! // => (2, 0)
+//! ```
+
+use crate::error::Error;
+
+/// `HistogramFilter` is the probability of each cell of a `rows` x `columns`
+/// grid, stored row-major.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistogramFilter {
+    rows: usize,
+    columns: usize,
+    probabilities: Vec<f64>
+}
+
+impl HistogramFilter {
+    /// `new` creates a filter over a `rows` x `columns` grid with every cell
+    /// equally likely.
+    ///
+    /// # Panics:
+    ///
+    /// Panics if the grid has no cells.
+    pub fn new(rows: usize, columns: usize) -> Self {
+        let cells = rows * columns;
+        assert!(cells > 0, "grid must have cells");
+        HistogramFilter {
+            rows,
+            columns,
+            probabilities: vec![1.0 / cells as f64; cells]
+        }
+    }
+
+    /// `shape` returns the number of rows and columns of the grid.
+    pub fn shape(&self) -> (usize, usize) {
+        (self.rows, self.columns)
+    }
+
+    /// `probability` returns the probability of the cell `(row, column)`.
+    pub fn probability(&self, (row, column): (usize, usize)) -> f64 {
+        self.probabilities[row * self.columns + column]
+    }
+
+    /// `predict` moves the probability mass by `transition`, the probability
+    /// of reaching the first cell from the second.
+    ///
+    /// This costs `O(cells²)`; `convolve` is faster for motion that is the
+    /// same everywhere on the grid.
+    pub fn predict(&mut self, transition: impl Fn((usize, usize), (usize, usize)) -> f64) {
+        let cells = self.cells().collect::<Vec<_>>();
+        let predicted = cells
+            .iter()
+            .map(|&to| cells.iter().map(|&from| transition(to, from) * self.probability(from)).sum())
+            .collect();
+        self.probabilities = predicted;
+        self.normalize();
+    }
+
+    /// `convolve` moves the probability mass by `kernel`, whose centre
+    /// element is the probability of staying in the same cell and whose
+    /// others are those of moving by their offset from it. Mass that would
+    /// leave the grid stays in the edge cell it would cross.
+    ///
+    /// # Panics:
+    ///
+    /// Panics if `kernel` has an even number of rows or columns.
+    pub fn convolve(&mut self, kernel: &[&[f64]]) {
+        let kernel_rows = kernel.len();
+        let kernel_columns = kernel.first().map_or(0, |row| row.len());
+        assert!(kernel_rows % 2 == 1 && kernel_columns % 2 == 1, "kernel must have a centre element");
+        let (row_offset, column_offset) = ((kernel_rows / 2) as isize, (kernel_columns / 2) as isize);
+        let clamp = |value: isize, size: usize| value.max(0).min(size as isize - 1) as usize;
+        let mut predicted = vec![0.0; self.probabilities.len()];
+        for (row, column) in self.cells() {
+            let probability = self.probability((row, column));
+            for (i, kernel_row) in kernel.iter().enumerate() {
+                for (j, weight) in kernel_row.iter().enumerate() {
+                    let to_row = clamp(row as isize + i as isize - row_offset, self.rows);
+                    let to_column = clamp(column as isize + j as isize - column_offset, self.columns);
+                    predicted[to_row * self.columns + to_column] += weight * probability;
+                }
+            }
+        }
+        self.probabilities = predicted;
+        self.normalize();
+    }
+
+    /// `update` weighs each cell by `likelihood`, the probability of the
+    /// measurement given the state is in that cell.
+    ///
+    /// Returns `Error::ImpossibleMeasurement` if the measurement has zero
+    /// likelihood in every cell with any probability, leaving the filter
+    /// unchanged.
+    pub fn update(&mut self, likelihood: impl Fn((usize, usize)) -> f64) -> Result<(), Error> {
+        let posterior = self
+            .cells()
+            .map(|cell| likelihood(cell) * self.probability(cell))
+            .collect::<Vec<_>>();
+        let total = posterior.iter().sum::<f64>();
+        if total.is_nan() || total <= 0.0 {
+            return Err(Error::ImpossibleMeasurement);
+        }
+        self.probabilities = posterior;
+        self.normalize();
+        Ok(())
+    }
+
+    /// `mean` returns the expected row and column of the state.
+    pub fn mean(&self) -> (f64, f64) {
+        self.cells().fold((0.0, 0.0), |(rows, columns), (row, column)| {
+            let probability = self.probability((row, column));
+            (rows + probability * row as f64, columns + probability * column as f64)
+        })
+    }
+
+    /// `most_likely` returns the cell with the highest probability.
+    pub fn most_likely(&self) -> (usize, usize) {
+        self.cells()
+            .max_by(|&a, &b| self.probability(a).total_cmp(&self.probability(b)))
+            .unwrap_or((0, 0))
+    }
+
+    fn cells(&self) -> impl Iterator<Item = (usize, usize)> {
+        let columns = self.columns;
+        (0..self.rows * columns).map(move |index| (index / columns, index % columns))
+    }
+
+    fn normalize(&mut self) {
+        let total = self.probabilities.iter().sum::<f64>();
+        for probability in &mut self.probabilities {
+            *probability /= total;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LQE;
+
+    fn gaussian(x: f64, mean: f64, variance: f64) -> f64 {
+        (-(x - mean).powi(2) / (2.0 * variance)).exp()
+    }
+
+    #[test]
+    fn matches_scalar_filter() {
+        // Cells a tenth of a unit wide from -10 to 10, prior N(2, 4).
+        let position = |row: usize| row as f64 / 10.0 - 10.0;
+        let mut filter = HistogramFilter::new(201, 1);
+        filter.predict(|(to, _), _| gaussian(position(to), 2.0, 4.0));
+        filter.update(|(row, _)| gaussian(position(row), 3.0, 1.0)).unwrap();
+
+        let expected = LQE { measurement: 2.0, variance: 4.0 }.correct(3.0, 1.0).posterior;
+        let mean = position(0) + filter.mean().0 / 10.0;
+        assert!((mean - expected.measurement).abs() < 1e-6);
+    }
+
+    #[test]
+    fn convolves_with_clamped_edges() {
+        let mut filter = HistogramFilter::new(3, 3);
+        filter.update(|cell| if cell == (0, 2) { 1.0 } else { 0.0 }).unwrap();
+
+        filter.convolve(&[&[0.0, 0.0, 0.0], &[0.0, 0.5, 0.5], &[0.0, 0.0, 0.0]]);
+        assert_eq!(filter.probability((0, 2)), 1.0);
+        filter.convolve(&[&[0.0, 0.0, 0.0], &[0.0, 0.5, 0.0], &[0.0, 0.5, 0.0]]);
+        assert_eq!((filter.probability((0, 2)), filter.probability((1, 2))), (0.5, 0.5));
+    }
+
+    #[test]
+    fn localizes_in_corridor() {
+        let doors = [1, 3];
+        let mut filter = HistogramFilter::new(5, 1);
+
+        filter.update(|(row, _)| if doors.contains(&row) { 0.9 } else { 0.1 }).unwrap();
+        filter.convolve(&[&[0.0], &[0.0], &[1.0]]);
+        filter.update(|(row, _)| if doors.contains(&row) { 0.1 } else { 0.9 }).unwrap();
+        filter.convolve(&[&[0.0], &[0.0], &[1.0]]);
+        filter.update(|(row, _)| if doors.contains(&row) { 0.9 } else { 0.1 }).unwrap();
+        assert_eq!(filter.most_likely(), (3, 0));
+    }
+
+    #[test]
+    fn rejects_impossible_measurement() {
+        let mut filter = HistogramFilter::new(2, 2);
+
+        assert_eq!(filter.update(|_| 0.0), Err(Error::ImpossibleMeasurement));
+        assert_eq!(filter.probability((1, 1)), 0.25);
+    }
+}
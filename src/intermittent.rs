@@ -0,0 +1,224 @@
+//! Kalman filtering with intermittent observations.
+//!
+//! Over a lossy network each measurement arrives with some probability
+//! `arrival` and is lost otherwise. The filter itself only has to skip the
+//! update of a lost measurement, but how the loss rate affects accuracy is
+//! less obvious: the expected covariance follows the modified Riccati
+//! recursion
+//!
+//! `P = F P Fᵀ + Q - arrival F P Hᵀ (H P Hᵀ + R)⁻¹ H P Fᵀ`,
+//!
+//! and for an unstable system it stays bounded only above a critical arrival
+//! probability (Sinopoli et al., 2004). Below it, no amount of tuning keeps
+//! the estimate from drifting away between packets.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::intermittent;
+//! use lqe::matrix::{DenseMatrix, Matrix};
+//! use lqe::multivariate::DynamicKalmanFilter;
+//!
+//! // An unstable system, doubling every step.
+//! let mut model = DynamicKalmanFilter::<DenseMatrix>::new(1, 1);
+//! model.transition = DenseMatrix::vector(&[2.0]);
+//! model.process_noise = DenseMatrix::identity(1);
+//! intermittent::critical_arrival(&model);
+//! // => 0.75
+//! intermittent::expected_covariance(&model, 0.5).is_none();
+//! // => true
+//! ```
+
+use crate::error::Error;
+use crate::matrix::Matrix;
+use crate::multivariate::DynamicKalmanFilter;
+
+/// The number of steps the modified Riccati recursion is given to converge.
+const ITERATIONS: usize = 100_000;
+
+/// The trace beyond which the expected covariance is taken to diverge.
+const DIVERGENCE: f64 = 1e12;
+
+/// `expected_covariance` returns the steady-state expected prediction
+/// covariance of `model` when each measurement arrives with probability
+/// `arrival`, or `None` if the modified Riccati recursion diverges.
+///
+/// The recursion starts from the covariance of `model`.
+pub fn expected_covariance<T: Matrix>(model: &DynamicKalmanFilter<T>, arrival: f64) -> Option<T> {
+    let transition = &model.transition;
+    let observation = &model.observation;
+    let mut covariance = model.covariance.clone();
+    for _ in 0..ITERATIONS {
+        let innovation = observation
+            .mul(&covariance)
+            .mul(&observation.transpose())
+            .add(&model.measurement_noise);
+        let cross = observation.mul(&covariance).mul(&transition.transpose());
+        let correction = cross.transpose().mul(&innovation.cholesky_solve(&cross)?);
+        let next = transition
+            .mul(&covariance)
+            .mul(&transition.transpose())
+            .add(&model.process_noise)
+            .sub(&correction.scale(arrival))
+            .symmetrize();
+        let trace = (0..next.shape().0).map(|i| next.get(i, i)).sum::<f64>();
+        if trace.is_nan() || trace >= DIVERGENCE {
+            return None;
+        }
+        let (rows, columns) = next.shape();
+        let change = (0..rows)
+            .flat_map(|i| (0..columns).map(move |j| (i, j)))
+            .map(|(i, j)| (next.get(i, j) - covariance.get(i, j)).abs())
+            .fold(0.0, f64::max);
+        covariance = next;
+        if change <= 1e-10 * (1.0 + trace) {
+            return Some(covariance);
+        }
+    }
+    None
+}
+
+/// `critical_arrival` returns the smallest arrival probability, to within
+/// `0.001`, for which the expected covariance of `model` stays bounded.
+///
+/// It is zero for a stable system, and at least `1 - 1 / ρ²` for one whose
+/// transition has spectral radius `ρ > 1`, with equality when every state is
+/// measured.
+pub fn critical_arrival<T: Matrix>(model: &DynamicKalmanFilter<T>) -> f64 {
+    if expected_covariance(model, 0.0).is_some() {
+        return 0.0;
+    }
+    let (mut low, mut high) = (0.0, 1.0);
+    while high - low > 1e-3 {
+        let middle = (low + high) / 2.0;
+        if expected_covariance(model, middle).is_some() {
+            high = middle;
+        } else {
+            low = middle;
+        }
+    }
+    high
+}
+
+/// `IntermittentFilter` runs `filter` over a stream in which measurements may
+/// be lost, and keeps count of how many arrive.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IntermittentFilter<T> {
+    pub filter: DynamicKalmanFilter<T>,
+    arrived: usize,
+    steps: usize
+}
+
+impl<T: Matrix> IntermittentFilter<T> {
+    /// `new` wraps `filter`, with no steps counted yet.
+    pub fn new(filter: DynamicKalmanFilter<T>) -> Self {
+        IntermittentFilter {
+            filter,
+            arrived: 0,
+            steps: 0
+        }
+    }
+
+    /// `next` predicts the estimate one step forward and updates it with
+    /// `measurement`, if it arrived.
+    pub fn next(&mut self, measurement: Option<&T>) -> Result<(), Error> {
+        self.steps += 1;
+        self.filter.predict();
+        if let Some(measurement) = measurement {
+            self.arrived += 1;
+            self.filter.update(measurement)?;
+        }
+        Ok(())
+    }
+
+    /// `arrival_rate` returns the fraction of steps whose measurement
+    /// arrived, or `1.0` before the first step.
+    pub fn arrival_rate(&self) -> f64 {
+        if self.steps == 0 {
+            return 1.0;
+        }
+        self.arrived as f64 / self.steps as f64
+    }
+
+    /// `is_stable` returns whether the observed arrival rate is above the
+    /// critical arrival probability of the model, so that the expected
+    /// covariance stays bounded.
+    pub fn is_stable(&self) -> bool {
+        self.arrival_rate() > critical_arrival(&self.filter)
+    }
+
+    /// `result` returns the current state and covariance.
+    pub fn result(&self) -> (T, T) {
+        self.filter.result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::DenseMatrix;
+    use crate::particle::Random;
+
+    fn unstable(growth: f64) -> DynamicKalmanFilter<DenseMatrix> {
+        let mut model = DynamicKalmanFilter::new(1, 1);
+        model.transition = DenseMatrix::vector(&[growth]);
+        model.process_noise = DenseMatrix::identity(1);
+        model
+    }
+
+    #[test]
+    fn finds_critical_arrival_probability() {
+        // With every state measured the bound 1 - 1 / a² is tight.
+        assert!((critical_arrival(&unstable(2.0)) - 0.75).abs() < 2e-3);
+        assert!((critical_arrival(&unstable(1.25)) - 0.36).abs() < 2e-3);
+        assert_eq!(critical_arrival(&unstable(0.5)), 0.0);
+    }
+
+    #[test]
+    fn converges_with_partial_observation() {
+        // Only the first of two unstable states is measured.
+        let mut model = DynamicKalmanFilter::<DenseMatrix>::new(2, 1);
+        model.transition = DenseMatrix::from_row_slices(&[&[1.2, 1.0], &[0.0, 1.1]]);
+        model.process_noise = DenseMatrix::identity(2);
+        for arrival in &[0.6, 0.74, 0.76, 0.9] {
+            assert!(expected_covariance(&model, *arrival).is_some());
+        }
+        let critical = critical_arrival(&model);
+        // Above the bound 1 - 1 / ρ², which isn't tight here, and below
+        // the arrival probabilities that converged.
+        assert!(critical > 1.0 - 1.0 / 1.44 && critical < 0.6);
+        let mut filter = IntermittentFilter::new(model);
+        let measurement = DenseMatrix::vector(&[0.0]);
+        for step in 0..10 {
+            filter.next(Some(&measurement).filter(|_| step % 5 < 3)).unwrap();
+        }
+        assert!(filter.is_stable());
+    }
+
+    #[test]
+    fn converges_to_riccati_solution_without_loss() {
+        let mut model = DynamicKalmanFilter::<DenseMatrix>::new(1, 1);
+        model.process_noise = DenseMatrix::identity(1);
+
+        // p = p + 1 - p² / (p + 1) has the fixed point p = (1 + √5) / 2.
+        let covariance = expected_covariance(&model, 1.0).unwrap();
+        assert!((covariance.get(0, 0) - (1.0 + 5f64.sqrt()) / 2.0).abs() < 1e-8);
+        let lossy = expected_covariance(&model, 0.5).unwrap();
+        assert!(lossy.get(0, 0) > covariance.get(0, 0));
+    }
+
+    #[test]
+    fn skips_lost_measurements() {
+        let mut random = Random::new(8);
+        let mut filter = IntermittentFilter::new(unstable(1.1));
+
+        assert_eq!(filter.arrival_rate(), 1.0);
+        for _ in 0..1000 {
+            let measurement = DenseMatrix::vector(&[0.0]);
+            let arrived = random.uniform() < 0.8;
+            filter.next(Some(&measurement).filter(|_| arrived)).unwrap();
+        }
+        assert!((filter.arrival_rate() - 0.8).abs() < 0.05);
+        assert!(filter.is_stable());
+    }
+}
@@ -0,0 +1,254 @@
+//! Measurement latency compensation.
+//!
+//! GPS fixes and vision measurements typically arrive tens of milliseconds
+//! after the moment they describe. Folding them in as if they were current
+//! biases the estimate towards the past. A `DelayedFilter` keeps a short
+//! history of its recent steps instead, applies each late measurement at the
+//! step it was taken and re-propagates the estimate to the present.
+//!
+//! The latency is counted in steps of the filter, e.g. 100 ms at 50 Hz is
+//! 5 steps.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::latency::DelayedFilter;
+//! use lqe::matrix::{DenseMatrix, Matrix};
+//! use lqe::multivariate::DynamicKalmanFilter;
+//!
+//! let mut model = DynamicKalmanFilter::<DenseMatrix>::new(1, 1);
+//! model.process_noise = DenseMatrix::identity(1).scale(0.1);
+//! let mut filter = DelayedFilter::new(model, DenseMatrix::identity(1), DenseMatrix::identity(1), 2);
+//! for z in &[1.0, 1.2, 0.9] {
+//!     filter.predict();
+//!     filter.update(&DenseMatrix::vector(&[*z])).unwrap();
+//! }
+//! // A measurement taken two steps ago, at the first step.
+//! filter.update_delayed(&DenseMatrix::vector(&[1.1])).unwrap();
+//! filter.filter.state.get(0, 0);
+//! // => 0.88
+//! ```
+
+use std::collections::VecDeque;
+
+use crate::error::Error;
+use crate::matrix::Matrix;
+use crate::multivariate::DynamicKalmanFilter;
+
+/// `Source` tells which sensor a recorded measurement came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Source {
+    Current,
+    Delayed
+}
+
+/// `Step` is the prior of a recorded step, right after its prediction, and
+/// the measurements applied during it, in order.
+#[derive(Clone, Debug, PartialEq)]
+struct Step<T> {
+    state: T,
+    covariance: T,
+    measurements: Vec<(Source, T)>
+}
+
+/// `DelayedFilter` runs `filter` at the present while accepting measurements
+/// from a sensor that reports `latency` steps late.
+///
+/// Current measurements use the observation model of `filter`, delayed ones
+/// `observation` and `measurement_noise`. The last `latency + 1` steps are
+/// recorded, so a delayed measurement costs a replay of that many steps.
+/// The model is assumed not to change between steps.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DelayedFilter<T> {
+    pub filter: DynamicKalmanFilter<T>,
+    pub observation: T,
+    pub measurement_noise: T,
+    latency: usize,
+    history: VecDeque<Step<T>>
+}
+
+impl<T: Matrix> DelayedFilter<T> {
+    /// `new` wraps `filter` for a delayed sensor with the given observation
+    /// model that reports `latency` steps late.
+    pub fn new(filter: DynamicKalmanFilter<T>, observation: T, measurement_noise: T, latency: usize) -> Self {
+        DelayedFilter {
+            filter,
+            observation,
+            measurement_noise,
+            latency,
+            history: VecDeque::with_capacity(latency + 1)
+        }
+    }
+
+    /// `predict` starts the next step, dropping the oldest recorded step
+    /// once it is too old for any delayed measurement still to arrive.
+    pub fn predict(&mut self) {
+        self.filter.predict();
+        if self.history.len() > self.latency {
+            self.history.pop_front();
+        }
+        self.history.push_back(Step {
+            state: self.filter.state.clone(),
+            covariance: self.filter.covariance.clone(),
+            measurements: Vec::new()
+        });
+    }
+
+    /// `update` refines the estimate with a current `measurement`.
+    pub fn update(&mut self, measurement: &T) -> Result<(), Error> {
+        self.filter.update(measurement)?;
+        if let Some(step) = self.history.back_mut() {
+            step.measurements.push((Source::Current, measurement.clone()));
+        }
+        Ok(())
+    }
+
+    /// `update_delayed` applies a `measurement` taken `latency` steps ago at
+    /// that step and re-propagates the estimate to the present.
+    ///
+    /// Measurements taken before the first step are ignored. If the replay
+    /// fails, the filter and its history are left as they were.
+    pub fn update_delayed(&mut self, measurement: &T) -> Result<(), Error> {
+        if self.history.len() <= self.latency {
+            return Ok(());
+        }
+        let index = self.history.len() - 1 - self.latency;
+        let mut history = self.history.clone();
+        history[index].measurements.push((Source::Delayed, measurement.clone()));
+        self.filter = self.replay(&mut history, index)?;
+        self.history = history;
+        Ok(())
+    }
+
+    /// `replay` runs the steps of `history` again from `from` on, refreshing
+    /// the priors of the later ones, and returns the filter at the present.
+    fn replay(&self, history: &mut VecDeque<Step<T>>, from: usize) -> Result<DynamicKalmanFilter<T>, Error> {
+        let mut filter = self.filter.clone();
+        filter.state = history[from].state.clone();
+        filter.covariance = history[from].covariance.clone();
+        for (offset, step) in history.iter_mut().skip(from).enumerate() {
+            if offset > 0 {
+                filter.predict();
+                step.state = filter.state.clone();
+                step.covariance = filter.covariance.clone();
+            }
+            for (source, measurement) in &step.measurements {
+                match source {
+                    Source::Current => filter.update(measurement)?,
+                    Source::Delayed => {
+                        let mut delayed = filter.clone();
+                        delayed.observation = self.observation.clone();
+                        delayed.measurement_noise = self.measurement_noise.clone();
+                        delayed.update(measurement)?;
+                        filter.state = delayed.state;
+                        filter.covariance = delayed.covariance;
+                    }
+                }
+            }
+        }
+        Ok(filter)
+    }
+
+    /// `latency` returns how many steps late the delayed sensor reports.
+    pub fn latency(&self) -> usize {
+        self.latency
+    }
+
+    /// `result` returns the current state and covariance.
+    pub fn result(&self) -> (T, T) {
+        self.filter.result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::DenseMatrix;
+
+    fn constant_velocity() -> DynamicKalmanFilter<DenseMatrix> {
+        DynamicKalmanFilter {
+            state: DenseMatrix::vector(&[0.0, 1.0]),
+            covariance: DenseMatrix::identity(2),
+            transition: DenseMatrix::from_row_slices(&[&[1.0, 1.0], &[0.0, 1.0]]),
+            process_noise: DenseMatrix::identity(2).scale(0.1),
+            observation: DenseMatrix::from_row_slices(&[&[0.0, 1.0]]),
+            measurement_noise: DenseMatrix::identity(1).scale(0.5)
+        }
+    }
+
+    #[test]
+    fn matches_filter_with_timely_measurements() {
+        let speeds = [1.1, 0.9, 1.0, 1.2, 0.8, 1.0, 1.1, 0.9];
+        let positions = [1.0, 2.1, 2.9, 4.2, 5.0, 5.8, 7.1, 8.0];
+        let observation = DenseMatrix::from_row_slices(&[&[1.0, 0.0]]);
+        let noise = DenseMatrix::identity(1).scale(2.0);
+        let latency = 3;
+
+        let mut delayed = DelayedFilter::new(constant_velocity(), observation.clone(), noise.clone(), latency);
+        for (k, speed) in speeds.iter().enumerate() {
+            delayed.predict();
+            delayed.update(&DenseMatrix::vector(&[*speed])).unwrap();
+            if k >= latency {
+                delayed.update_delayed(&DenseMatrix::vector(&[positions[k - latency]])).unwrap();
+            }
+        }
+
+        // The same measurements, each position applied at the step it was
+        // taken.
+        let mut timely = constant_velocity();
+        for (k, speed) in speeds.iter().enumerate() {
+            timely.predict();
+            timely.update(&DenseMatrix::vector(&[*speed])).unwrap();
+            if k < speeds.len() - latency {
+                let mut position = timely.clone();
+                position.observation = observation.clone();
+                position.measurement_noise = noise.clone();
+                position.update(&DenseMatrix::vector(&[positions[k]])).unwrap();
+                timely.state = position.state;
+                timely.covariance = position.covariance;
+            }
+        }
+
+        let (state, covariance) = delayed.result();
+        for (i, j) in &[(0, 0), (1, 0)] {
+            assert!((state.get(*i, *j) - timely.state.get(*i, *j)).abs() < 1e-12);
+        }
+        for (i, j) in &[(0, 0), (0, 1), (1, 1)] {
+            assert!((covariance.get(*i, *j) - timely.covariance.get(*i, *j)).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn leaves_history_alone_on_failed_replay() {
+        let observation = DenseMatrix::from_row_slices(&[&[1.0, 0.0]]);
+        let mut filter = DelayedFilter::new(constant_velocity(), observation.clone(), DenseMatrix::identity(1), 1);
+        for speed in &[1.0, 1.1, 0.9] {
+            filter.predict();
+            filter.update(&DenseMatrix::vector(&[*speed])).unwrap();
+        }
+        let mut untouched = filter.clone();
+
+        // A sensor model that can't explain any measurement.
+        filter.observation = DenseMatrix::zeros(1, 2);
+        filter.measurement_noise = DenseMatrix::zeros(1, 1);
+        assert_eq!(filter.update_delayed(&DenseMatrix::vector(&[2.0])), Err(Error::SingularInnovation));
+        assert_eq!(filter.result(), untouched.result());
+
+        filter.observation = observation;
+        filter.measurement_noise = DenseMatrix::identity(1);
+        filter.update_delayed(&DenseMatrix::vector(&[2.0])).unwrap();
+        untouched.update_delayed(&DenseMatrix::vector(&[2.0])).unwrap();
+        assert_eq!(filter, untouched);
+    }
+
+    #[test]
+    fn ignores_measurements_before_first_step() {
+        let mut filter = DelayedFilter::new(constant_velocity(), DenseMatrix::from_row_slices(&[&[1.0, 0.0]]), DenseMatrix::identity(1), 2);
+
+        filter.predict();
+        let before = filter.result();
+        filter.update_delayed(&DenseMatrix::vector(&[10.0])).unwrap();
+        assert_eq!(filter.result(), before);
+        assert_eq!(filter.latency(), 2);
+    }
+}
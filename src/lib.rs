@@ -8,15 +8,135 @@
 //! # Example:
 //!
 //! ```
-//! use lqe::LQE;
+//! use lqe::{step, LQE};
 //! let lqe = LQE {
 //!   measurement: 7.0,
-//!   variance: 2.0
+//!   variance: 1.5
 //! };
 //!
-//! lqe.next(5.0, 3.0).next(7.0, 1.0).result();
-//! // => (8.225, 2.625)
+//! // Predict with no control input and a process noise of 0.5, then update
+//! // with a measurement of 10.0 with a variance of 2.0.
+//! let prior = step::predict(lqe, 0.0, 0.5);
+//! step::update(prior, 10.0, 2.0).posterior.result();
+//! // => (8.5, 1.0)
 //! ```
+//!
+//! # Features:
+//!
+//! - `chrono`, `time`: use their datetime types as measurement timestamps.
+//! - `config`: build filters from TOML or YAML descriptions of their model
+//!   and tuning.
+//! - `defmt`: implement `defmt::Format` for logging filter state on embedded
+//!   targets.
+//! - `derive`: `#[derive(Measurement)]`, turning the fields of a struct
+//!   into a measurement vector and its noise covariance.
+//! - `extended`: double-double precision `ExtendedLQE` and `ExtendedMatrix`
+//!   for validating the `f64` filters and for ill-conditioned problems.
+//! - `grpc`: a `tonic` gRPC service running filters for remote clients.
+//! - `nalgebra`: the fixed-size multivariate `KalmanFilter` over `nalgebra`
+//!   matrices, and `nalgebra::DMatrix` as a `Matrix` backend.
+//! - `ndarray`: `ndarray::Array2` as a `Matrix` backend.
+//! - `mavlink`: feed MAVLink telemetry to a tracker and emit its estimate
+//!   as MAVLink.
+//! - `metrics`: publish filter health through the `metrics` facade.
+//! - `mqtt`: smooth sensor streams from an MQTT broker and publish the
+//!   estimates back.
+//! - `plot`: an `egui` widget plotting the measurements, estimates and
+//!   confidence bands of a live filter.
+//! - `prost`: Protocol Buffers messages for measurements, estimates and
+//!   filter snapshots.
+//! - `redis`: filter measurement records from a Redis stream into another,
+//!   with checkpointed filter state.
+//! - `tracing`: emit a trace-level `tracing` event with the innovation, gain
+//!   and variance of every predict and update.
+//! - `tui`: a `ratatui` terminal dashboard of a running filter, and the
+//!   `lqe-monitor` binary filtering a stream read from standard input into
+//!   it.
+
+// Lets derived code name `::lqe` inside this crate too.
+extern crate self as lqe;
+
+#[macro_use]
+mod trace;
+
+pub mod adaptive;
+pub mod arma;
+pub mod audio;
+pub mod backtest;
+pub mod baseline;
+pub mod batch;
+pub mod battery;
+pub mod bayes;
+pub mod codegen;
+#[cfg(feature = "config")]
+pub mod config;
+pub mod decimation;
+pub mod diagnostics;
+mod display;
+pub mod drift;
+pub mod error;
+#[cfg(feature = "extended")]
+pub mod extended;
+pub mod fusion;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod heart_rate;
+pub mod histogram;
+pub mod intermittent;
+pub mod latency;
+pub mod lqg;
+pub mod lqr;
+pub mod matrix;
+#[cfg(feature = "mavlink")]
+pub mod mavlink;
+pub mod measurement;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod mixture;
+pub mod model;
+#[cfg(feature = "tui")]
+pub mod monitor;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+pub mod multiple_model;
+pub mod multivariate;
+pub mod nmea;
+pub mod observability;
+pub mod odometry;
+pub mod offset;
+mod ops;
+pub mod particle;
+pub mod pipeline;
+#[cfg(feature = "plot")]
+pub mod plot;
+pub mod pll;
+pub mod prelude;
+#[cfg(feature = "prost")]
+pub mod proto;
+pub mod protocol;
+pub mod queue;
+#[cfg(feature = "redis")]
+pub mod redis;
+pub mod redundancy;
+pub mod registry;
+pub mod regression;
+pub mod riccati;
+pub mod ros;
+pub mod rssi;
+pub mod scaling;
+pub mod shared;
+pub mod sparse;
+pub mod stability;
+pub mod step;
+pub mod structural;
+pub mod timed;
+pub mod tracking;
+pub mod trilateration;
+pub mod tuning;
+pub mod watchdog;
+pub mod window;
+
+use step::StepInfo;
 
 /// LQE is a data type representing a single measurement with a variance or
 /// confidence in that measurement.
@@ -34,12 +154,59 @@
 ///   variance: 2.0
 /// };
 /// ```
+///
+/// Estimates can be shifted and scaled like the quantity they describe, with
+/// the variance following along, e.g. to convert units:
+///
+/// ```
+/// use lqe::LQE;
+/// let metres = LQE { measurement: 7.0, variance: 2.0 };
+/// (metres * 100.0 + 5.0).result();
+/// // => (705.0, 20000.0)
+/// ```
+///
+/// Displaying an estimate shows its standard deviation, rounded to 3
+/// significant digits or to the requested precision:
+///
+/// ```
+/// use lqe::LQE;
+/// let lqe = LQE { measurement: 7.0, variance: 2.0 };
+/// format!("{}", lqe);
+/// // => "7.00 ± 1.41"
+/// format!("{:.2}", lqe);
+/// // => "7.0 ± 1.4"
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct LQE {
     pub measurement: f64,
     pub variance: f64
 }
 
 impl LQE {
+    /// `new` creates an estimate, in `const` contexts too, e.g. for a filter
+    /// kept in a `static` on an embedded target.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use std::sync::Mutex;
+    /// use lqe::{step, LQE};
+    ///
+    /// static ALTITUDE: Mutex<LQE> = Mutex::new(LQE::new(3.0, 2.0));
+    ///
+    /// let mut altitude = ALTITUDE.lock().unwrap();
+    /// *altitude = step::update(*altitude, 5.0, 2.0).posterior;
+    /// altitude.result();
+    /// // => (4.0, 1.0)
+    /// ```
+    pub const fn new(measurement: f64, variance: f64) -> Self {
+        LQE {
+            measurement,
+            variance
+        }
+    }
+
     /// `update` combines the past and current observation information to refine
     /// the state estimate.
     ///
@@ -55,12 +222,13 @@ impl LQE {
     /// ```
     pub fn update(&self, measurement: f64, variance: f64) -> (f64, f64) {
         // Calculate new measurement
-        let a = &self.variance + variance;
-        let c = (&self.measurement * variance) + (measurement * &self.variance);
-        let m = (1.0 / &a) * c;
+        let a = self.variance + variance;
+        let c = (self.measurement * variance) + (measurement * self.variance);
+        let m = (1.0 / a) * c;
         // Calculate new variance
-        let b = &self.variance * measurement;
+        let b = self.variance * measurement;
         let z = b / a;
+        step_event!(measurement, variance, mean = m, posterior_variance = z, "update");
         (m, z)
     }
 
@@ -78,26 +246,42 @@ impl LQE {
     /// // => (17.0, 4.0)
     /// ```
     pub fn predict(&self, measurement: f64, variance: f64) -> (f64, f64) {
-        let predicted_measurement = &self.measurement + measurement;
-        let predicted_variance = &self.variance + variance;
+        let predicted_measurement = self.measurement + measurement;
+        let predicted_variance = self.variance + variance;
+        step_event!(
+            control = measurement,
+            process_variance = variance,
+            mean = predicted_measurement,
+            variance = predicted_variance,
+            "predict"
+        );
         (predicted_measurement, predicted_variance)
     }
 
     /// `next` performs the entire predict - update cycle for a series of measurements.
     ///
+    /// It predicts with `measurement` as the control input and `variance` as
+    /// the process noise, then combines the prediction with the measurement,
+    /// so its result is not that of a Kalman filter step. Use `step::predict`
+    /// and `step::update` instead, which take the two apart: a value
+    /// drifting with process noise `q`, measured as `z` with variance `r`, is
+    /// `step::update(step::predict(lqe, 0.0, q), z, r).posterior`.
+    ///
     /// # Example:
     ///
     /// ```
+    /// # #![allow(deprecated)]
     /// use lqe::LQE;
     /// let lqe = LQE { measurement: 3.0, variance: 2.0 };
     /// lqe.next(5.0, 3.0).result();
     /// // => (6.125, 3.0)
     /// ```
+    #[deprecated(note = "use `step::predict` and `step::update`, which keep control and measurement apart")]
     pub fn next(&self, measurement: f64, variance: f64) -> LQE {
-        let prediction = &self.predict(measurement, variance);
+        let prediction = self.predict(measurement, variance);
         let mid_filter = LQE {
-            measurement: measurement,
-            variance: variance
+            measurement,
+            variance
         };
         let updated_result = mid_filter.update(prediction.0, prediction.1);
         LQE {
@@ -106,6 +290,34 @@ impl LQE {
         }
     }
 
+    /// `correct` folds a measurement into the estimate using the Kalman gain
+    /// `variance / (variance + measurement_variance)`.
+    pub(crate) fn correct(&self, measurement: f64, variance: f64) -> StepInfo {
+        let innovation = measurement - self.measurement;
+        let innovation_variance = self.variance + variance;
+        let gain = self.variance / innovation_variance;
+        let posterior = LQE {
+            measurement: self.measurement + gain * innovation,
+            variance: (1.0 - gain) * self.variance
+        };
+        step_event!(
+            measurement,
+            innovation,
+            gain,
+            mean = posterior.measurement,
+            variance = posterior.variance,
+            "correct"
+        );
+        StepInfo {
+            prediction: *self,
+            measurement,
+            innovation,
+            innovation_variance,
+            gain,
+            posterior
+        }
+    }
+
     /// `result` returns the current state of the LQE as a tuple value.
     ///
     /// # Example:
@@ -117,7 +329,7 @@ impl LQE {
     /// // => (7.0, 2.0)
     /// ```
     pub fn result (&self) -> (f64, f64) {
-        (*&self.measurement, *&self.variance)
+        (self.measurement, self.variance)
     }
 }
 
@@ -156,6 +368,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(deprecated)]
     fn runs_filter_correctly() {
         let lqe = LQE {
             measurement: 3.0,
@@ -165,4 +378,17 @@ mod tests {
       assert_eq!(lqe.next(5.0, 3.0).result(), (6.125, 3.0));
       assert_eq!(lqe.next(5.0, 3.0).next(7.0, 1.0).result(), (8.225, 2.625));
     }
+
+    #[test]
+    fn corrects_with_kalman_gain() {
+        let lqe = LQE {
+            measurement: 7.0,
+            variance: 2.0
+        };
+
+        let step = lqe.correct(10.0, 2.0);
+
+        assert_eq!(step.posterior.result(), (8.5, 1.0));
+        assert_eq!((step.innovation, step.innovation_variance, step.gain), (3.0, 4.0, 0.5));
+    }
 }
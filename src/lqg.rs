@@ -0,0 +1,116 @@
+//! Linear quadratic Gaussian control.
+//!
+//! An `LqgController` closes the loop around a system `x = F x + B u + w`
+//! measured as `z = H x + v`: a `DynamicKalmanFilter` estimates the state
+//! from the measurements and a `lqr::Regulator` computes the input from the
+//! estimate. Every `control` call takes the latest measurement and returns
+//! the input to apply until the next one.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::lqg::LqgController;
+//! use lqe::matrix::{DenseMatrix, Matrix};
+//! use lqe::multivariate::DynamicKalmanFilter;
+//!
+//! // An unstable system, growing by 20% a step, measured with noise.
+//! let mut model = DynamicKalmanFilter::<DenseMatrix>::new(1, 1);
+//! model.transition = DenseMatrix::vector(&[1.2]);
+//! model.process_noise = DenseMatrix::vector(&[0.01]);
+//! let input = DenseMatrix::vector(&[1.0]);
+//! let cost = DenseMatrix::identity(1);
+//! let mut controller = LqgController::new(model, input, &cost, &cost).unwrap();
+//! let u = controller.control(&DenseMatrix::vector(&[5.0])).unwrap();
+//! u.get(0, 0) < 0.0;
+//! // => true
+//! ```
+
+use crate::error::Error;
+use crate::lqr::Regulator;
+use crate::matrix::Matrix;
+use crate::multivariate::DynamicKalmanFilter;
+
+/// `LqgController` steers the system modelled by `filter`, driven through
+/// `input` `B`, with `regulator`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LqgController<T> {
+    pub filter: DynamicKalmanFilter<T>,
+    pub regulator: Regulator<T>,
+    pub input: T
+}
+
+impl<T: Matrix> LqgController<T> {
+    /// `new` designs the regulator for the model of `filter` and `input`
+    /// with state cost `Q` and input cost `R`, see `Regulator::new`, or
+    /// returns `None` if there is no stabilizing one.
+    ///
+    /// The filter starts from its current estimate, taken to be the
+    /// prediction for the first measurement.
+    pub fn new(filter: DynamicKalmanFilter<T>, input: T, state_cost: &T, input_cost: &T) -> Option<Self> {
+        let regulator = Regulator::new(&filter.transition, &input, state_cost, input_cost)?;
+        Some(LqgController {
+            filter,
+            regulator,
+            input
+        })
+    }
+
+    /// `control` refines the estimate with `measurement` and returns the
+    /// input `u` to apply, predicting the state it leads to for the next
+    /// call.
+    ///
+    /// If the measurement can't be applied the estimate is left unchanged
+    /// and the error returned; `coast` then keeps the loop going.
+    pub fn control(&mut self, measurement: &T) -> Result<T, Error> {
+        self.filter.update(measurement)?;
+        Ok(self.coast())
+    }
+
+    /// `coast` returns the input for a step without a measurement, e.g. a
+    /// lost one, steering by the prediction alone.
+    pub fn coast(&mut self) -> T {
+        let control = self.regulator.control(&self.filter.state);
+        self.filter.predict();
+        self.filter.state = self.filter.state.add(&self.input.mul(&control));
+        control
+    }
+
+    /// `estimate` returns the predicted state for the next measurement and
+    /// its covariance.
+    pub fn estimate(&self) -> (T, T) {
+        self.filter.result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::DenseMatrix;
+
+    #[test]
+    fn brings_cart_to_rest() {
+        // Position and velocity of a cart pushed by a force, with the
+        // position measured.
+        let transition = DenseMatrix::from_row_slices(&[&[1.0, 0.1], &[0.0, 1.0]]);
+        let input = DenseMatrix::vector(&[0.005, 0.1]);
+        let mut model = DynamicKalmanFilter::new(2, 1);
+        model.transition = transition.clone();
+        model.process_noise = DenseMatrix::identity(2).scale(1e-4);
+        model.measurement_noise = DenseMatrix::vector(&[0.01]);
+        let cost = DenseMatrix::identity(2);
+        let mut controller = LqgController::new(model, input.clone(), &cost, &DenseMatrix::identity(1)).unwrap();
+
+        let mut state = DenseMatrix::vector(&[1.0, 0.0]);
+        for step in 0..300 {
+            let control = if step % 3 == 0 {
+                controller.coast()
+            } else {
+                controller.control(&DenseMatrix::vector(&[state.get(0, 0)])).unwrap()
+            };
+            state = transition.mul(&state).add(&input.mul(&control));
+        }
+        assert!(state.get(0, 0).abs() < 1e-2);
+        assert!(state.get(1, 0).abs() < 1e-2);
+        assert!(controller.estimate().0.sub(&state).get(0, 0).abs() < 1e-2);
+    }
+}
@@ -0,0 +1,85 @@
+//! Linear quadratic regulation.
+//!
+//! The regulator is the other half of a linear quadratic Gaussian (LQG)
+//! controller: the estimator finds the state, the regulator the input that
+//! drives it to zero at the least cost. By the separation principle both
+//! can be designed on their own. A `Regulator` holds the optimal state
+//! feedback gain `K`; `lqg::LqgController` couples it to a
+//! `DynamicKalmanFilter`.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::lqr::Regulator;
+//! use lqe::matrix::{DenseMatrix, Matrix};
+//!
+//! // An unstable system, growing by 20% a step.
+//! let transition = DenseMatrix::vector(&[1.2]);
+//! let input = DenseMatrix::vector(&[1.0]);
+//! let regulator = Regulator::new(&transition, &input, &DenseMatrix::identity(1), &DenseMatrix::identity(1)).unwrap();
+//! regulator.control(&DenseMatrix::vector(&[5.0])).get(0, 0) < 0.0;
+//! // => true
+//! ```
+
+use crate::matrix::Matrix;
+use crate::riccati;
+
+/// `Regulator` is the state feedback `u = -K x` with `gain` `K`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Regulator<T> {
+    pub gain: T
+}
+
+impl<T: Matrix> Regulator<T> {
+    /// `new` designs the regulator of `x = A x + B u` for `transition` `A`
+    /// and `input` `B`, minimizing the sum of `xᵀ Q x + uᵀ R u` over all
+    /// steps with state cost `Q` and input cost `R`, or returns `None` if
+    /// there is no stabilizing regulator, see `riccati::solve_dare`.
+    pub fn new(transition: &T, input: &T, state_cost: &T, input_cost: &T) -> Option<Self> {
+        Some(Regulator {
+            gain: riccati::lqr_gain(transition, input, state_cost, input_cost)?
+        })
+    }
+
+    /// `continuous` designs the regulator of `dx/dt = A x + B u`, minimizing
+    /// the integral of `xᵀ Q x + uᵀ R u`, see `riccati::solve_care`.
+    pub fn continuous(transition: &T, input: &T, state_cost: &T, input_cost: &T) -> Option<Self> {
+        Some(Regulator {
+            gain: riccati::continuous_lqr_gain(transition, input, state_cost, input_cost)?
+        })
+    }
+
+    /// `control` returns the input `-K state`.
+    pub fn control(&self, state: &T) -> T {
+        self.gain.mul(state).scale(-1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::DenseMatrix;
+
+    #[test]
+    fn matches_continuous_regulator_for_short_steps() {
+        // A double integrator and its discretization over a short step.
+        let dt = 1e-3;
+        let continuous = Regulator::continuous(
+            &DenseMatrix::from_row_slices(&[&[0.0, 1.0], &[0.0, 0.0]]),
+            &DenseMatrix::vector(&[0.0, 1.0]),
+            &DenseMatrix::identity(2),
+            &DenseMatrix::identity(1)
+        )
+        .unwrap();
+        let discrete = Regulator::new(
+            &DenseMatrix::from_row_slices(&[&[1.0, dt], &[0.0, 1.0]]),
+            &DenseMatrix::vector(&[dt * dt / 2.0, dt]),
+            &DenseMatrix::identity(2).scale(dt),
+            &DenseMatrix::identity(1).scale(dt)
+        )
+        .unwrap();
+        for j in 0..2 {
+            assert!((discrete.gain.get(0, j) - continuous.gain.get(0, j)).abs() < 1e-2);
+        }
+    }
+}
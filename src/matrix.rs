@@ -0,0 +1,544 @@
+//! Dense matrix abstraction.
+//!
+//! The multivariate filters are written once against the small `Matrix`
+//! trait, and every backend only has to provide storage. Vectors are
+//! represented as single-column matrices.
+//!
+//! `Matrix` is implemented for the dependency-free `DenseMatrix`, for
+//! `nalgebra::DMatrix<f64>` with the `nalgebra` feature, for
+//! `ndarray::Array2<f64>` with the `ndarray` feature and for the double-double
+//! `extended::ExtendedMatrix` with the `extended` feature. The provided
+//! methods are plain loops over `get` and `set`; backends override them where
+//! they have a faster native implementation.
+//!
+//! There is no CMSIS-DSP backend for Cortex-M yet. The crate depends on
+//! `std`, which those targets lack, so such a backend could neither be built
+//! nor tested for them; it is deferred until the filters build without
+//! `std`.
+
+use std::ops::{Index, IndexMut};
+
+/// `Matrix` is a dense, heap-allocated `f64` matrix whose shape is known at
+/// runtime.
+///
+/// # Panics:
+///
+/// Arithmetic on matrices of incompatible shapes panics.
+pub trait Matrix: Clone {
+    /// `zeros` creates a `rows` x `columns` matrix of zeros.
+    fn zeros(rows: usize, columns: usize) -> Self;
+
+    /// `shape` returns the number of rows and columns.
+    fn shape(&self) -> (usize, usize);
+
+    /// `get` returns the element at `row`, `column`.
+    fn get(&self, row: usize, column: usize) -> f64;
+
+    /// `set` replaces the element at `row`, `column`.
+    fn set(&mut self, row: usize, column: usize, value: f64);
+
+    /// `identity` creates a `size` x `size` identity matrix.
+    fn identity(size: usize) -> Self {
+        let mut identity = Self::zeros(size, size);
+        for i in 0..size {
+            identity.set(i, i, 1.0);
+        }
+        identity
+    }
+
+    /// `from_row_slices` creates a matrix from a slice of equally long rows.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use lqe::matrix::{DenseMatrix, Matrix};
+    /// let matrix = DenseMatrix::from_row_slices(&[&[1.0, 2.0], &[3.0, 4.0]]);
+    /// matrix[(1, 0)];
+    /// // => 3.0
+    /// ```
+    fn from_row_slices(rows: &[&[f64]]) -> Self {
+        let columns = rows.first().map_or(0, |row| row.len());
+        let mut matrix = Self::zeros(rows.len(), columns);
+        for (i, row) in rows.iter().enumerate() {
+            assert_eq!(row.len(), columns, "rows must have equal length");
+            for (j, value) in row.iter().enumerate() {
+                matrix.set(i, j, *value);
+            }
+        }
+        matrix
+    }
+
+    /// `vector` creates a single-column matrix, i.e. a vector.
+    fn vector(values: &[f64]) -> Self {
+        let mut matrix = Self::zeros(values.len(), 1);
+        for (i, value) in values.iter().enumerate() {
+            matrix.set(i, 0, *value);
+        }
+        matrix
+    }
+
+    /// `row` returns row `row` as a single-row matrix.
+    fn row(&self, row: usize) -> Self {
+        let columns = self.shape().1;
+        let mut copy = Self::zeros(1, columns);
+        for j in 0..columns {
+            copy.set(0, j, self.get(row, j));
+        }
+        copy
+    }
+
+    /// `is_diagonal` returns whether every element off the diagonal is zero.
+    fn is_diagonal(&self) -> bool {
+        let (rows, columns) = self.shape();
+        (0..rows).all(|i| (0..columns).all(|j| i == j || self.get(i, j) == 0.0))
+    }
+
+    /// `transpose` returns the transposed matrix.
+    fn transpose(&self) -> Self {
+        let (rows, columns) = self.shape();
+        let mut transposed = Self::zeros(columns, rows);
+        for i in 0..rows {
+            for j in 0..columns {
+                transposed.set(j, i, self.get(i, j));
+            }
+        }
+        transposed
+    }
+
+    /// `add` returns the element-wise sum `self + other`.
+    fn add(&self, other: &Self) -> Self {
+        assert_eq!(self.shape(), other.shape(), "matrix shapes must match");
+        let (rows, columns) = self.shape();
+        let mut sum = self.clone();
+        for i in 0..rows {
+            for j in 0..columns {
+                sum.set(i, j, self.get(i, j) + other.get(i, j));
+            }
+        }
+        sum
+    }
+
+    /// `sub` returns the element-wise difference `self - other`.
+    fn sub(&self, other: &Self) -> Self {
+        self.add(&other.scale(-1.0))
+    }
+
+    /// `scale` multiplies every element by `factor`.
+    fn scale(&self, factor: f64) -> Self {
+        let (rows, columns) = self.shape();
+        let mut scaled = self.clone();
+        for i in 0..rows {
+            for j in 0..columns {
+                scaled.set(i, j, self.get(i, j) * factor);
+            }
+        }
+        scaled
+    }
+
+    /// `mul` returns the matrix product `self * other`.
+    fn mul(&self, other: &Self) -> Self {
+        let (rows, inner) = self.shape();
+        let (other_rows, columns) = other.shape();
+        assert_eq!(inner, other_rows, "matrix shapes must be compatible");
+        let mut product = Self::zeros(rows, columns);
+        for i in 0..rows {
+            for j in 0..columns {
+                let value = (0..inner).map(|k| self.get(i, k) * other.get(k, j)).sum();
+                product.set(i, j, value);
+            }
+        }
+        product
+    }
+
+    /// `symmetrize` returns `(self + selfᵀ) / 2`, removing the asymmetry
+    /// rounding errors leave in a covariance. A symmetric matrix is returned
+    /// unchanged.
+    fn symmetrize(&self) -> Self {
+        self.add(&self.transpose()).scale(0.5)
+    }
+
+    /// `symmetric_eigen` returns the eigenvalues of a symmetric matrix and a
+    /// matrix whose columns are the corresponding eigenvectors, by cyclic
+    /// Jacobi rotations.
+    ///
+    /// # Panics:
+    ///
+    /// Panics if the matrix is not square.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use lqe::matrix::{DenseMatrix, Matrix};
+    /// let matrix = DenseMatrix::from_row_slices(&[&[2.0, 1.0], &[1.0, 2.0]]);
+    /// matrix.symmetric_eigen().0;
+    /// // => [1.0, 3.0]
+    /// ```
+    fn symmetric_eigen(&self) -> (Vec<f64>, Self) {
+        let (size, columns) = self.shape();
+        assert_eq!(size, columns, "matrix must be square");
+        let mut values: Vec<f64> = (0..size * size).map(|k| self.get(k / size, k % size)).collect();
+        let mut vectors = vec![0.0; size * size];
+        for i in 0..size {
+            vectors[i * size + i] = 1.0;
+        }
+        for _ in 0..64 {
+            let norm = values.iter().map(|value| value * value).sum::<f64>();
+            let off_diagonal = (0..size * size)
+                .filter(|k| k / size != k % size)
+                .map(|k| values[k] * values[k])
+                .sum::<f64>();
+            if off_diagonal <= norm * f64::EPSILON * f64::EPSILON {
+                break;
+            }
+            for p in 0..size {
+                for q in p + 1..size {
+                    let coupling = values[p * size + q];
+                    if coupling == 0.0 {
+                        continue;
+                    }
+                    // The rotation by atan(t) that zeroes the coupling.
+                    let theta = (values[q * size + q] - values[p * size + p]) / (2.0 * coupling);
+                    let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                    let c = 1.0 / (t * t + 1.0).sqrt();
+                    let s = t * c;
+                    for k in 0..size {
+                        let (kp, kq) = (values[k * size + p], values[k * size + q]);
+                        values[k * size + p] = c * kp - s * kq;
+                        values[k * size + q] = s * kp + c * kq;
+                    }
+                    for k in 0..size {
+                        let (pk, qk) = (values[p * size + k], values[q * size + k]);
+                        values[p * size + k] = c * pk - s * qk;
+                        values[q * size + k] = s * pk + c * qk;
+                        let (kp, kq) = (vectors[k * size + p], vectors[k * size + q]);
+                        vectors[k * size + p] = c * kp - s * kq;
+                        vectors[k * size + q] = s * kp + c * kq;
+                    }
+                }
+            }
+        }
+        let mut eigenvectors = Self::zeros(size, size);
+        for (k, value) in vectors.iter().enumerate() {
+            eigenvectors.set(k / size, k % size, *value);
+        }
+        ((0..size).map(|i| values[i * size + i]).collect(), eigenvectors)
+    }
+
+    /// `cholesky_solve` solves `self * x = rhs` for a symmetric positive
+    /// definite `self`, returning `None` if it is not positive definite.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use lqe::matrix::{DenseMatrix, Matrix};
+    /// let matrix = DenseMatrix::from_row_slices(&[&[4.0, 2.0], &[2.0, 3.0]]);
+    /// matrix.cholesky_solve(&DenseMatrix::vector(&[2.0, 1.0]));
+    /// // => Some([0.5, 0.0])
+    /// ```
+    fn cholesky_solve(&self, rhs: &Self) -> Option<Self> {
+        let size = self.shape().0;
+        let (rhs_rows, rhs_columns) = rhs.shape();
+        assert_eq!(size, rhs_rows, "matrix shapes must be compatible");
+        let factor = self.cholesky_factor()?;
+        // Forward substitution L y = rhs, then back substitution Lᵀ x = y.
+        let mut solution = Self::zeros(size, rhs_columns);
+        for column in 0..rhs_columns {
+            for i in 0..size {
+                let value = rhs.get(i, column) - (0..i).map(|k| factor.get(i, k) * solution.get(k, column)).sum::<f64>();
+                solution.set(i, column, value / factor.get(i, i));
+            }
+            for i in (0..size).rev() {
+                let value = solution.get(i, column)
+                    - (i + 1..size).map(|k| factor.get(k, i) * solution.get(k, column)).sum::<f64>();
+                solution.set(i, column, value / factor.get(i, i));
+            }
+        }
+        Some(solution)
+    }
+
+    /// `cholesky_factor` returns the lower triangular `L` with `self = L Lᵀ`,
+    /// or `None` if the matrix is not positive definite.
+    ///
+    /// # Panics:
+    ///
+    /// Panics if the matrix is not square.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use lqe::matrix::{DenseMatrix, Matrix};
+    /// let matrix = DenseMatrix::from_row_slices(&[&[4.0, 2.0], &[2.0, 2.0]]);
+    /// matrix.cholesky_factor();
+    /// // => Some([[2.0, 0.0], [1.0, 1.0]])
+    /// ```
+    fn cholesky_factor(&self) -> Option<Self> {
+        let (size, columns) = self.shape();
+        assert_eq!(size, columns, "matrix must be square");
+        let mut factor = Self::zeros(size, size);
+        for j in 0..size {
+            let diagonal = self.get(j, j) - (0..j).map(|k| factor.get(j, k).powi(2)).sum::<f64>();
+            if diagonal.is_nan() || diagonal <= 0.0 {
+                return None;
+            }
+            let diagonal = diagonal.sqrt();
+            factor.set(j, j, diagonal);
+            for i in j + 1..size {
+                let value = self.get(i, j) - (0..j).map(|k| factor.get(i, k) * factor.get(j, k)).sum::<f64>();
+                factor.set(i, j, value / diagonal);
+            }
+        }
+        Some(factor)
+    }
+
+    /// `log_determinant` returns the natural logarithm of the determinant of
+    /// a positive definite matrix, or `None` if it is not positive definite.
+    fn log_determinant(&self) -> Option<f64> {
+        let factor = self.cholesky_factor()?;
+        Some(2.0 * (0..factor.shape().0).map(|i| factor.get(i, i).ln()).sum::<f64>())
+    }
+}
+
+/// `DenseMatrix` is the built-in row-major `Matrix` backend, for when no
+/// linear algebra crate is in use.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::matrix::{DenseMatrix, Matrix};
+/// let matrix = DenseMatrix::from_row_slices(&[&[1.0, 2.0], &[3.0, 4.0]]);
+/// matrix[(1, 0)];
+/// // => 3.0
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct DenseMatrix {
+    rows: usize,
+    columns: usize,
+    values: Vec<f64>
+}
+
+impl Matrix for DenseMatrix {
+    fn zeros(rows: usize, columns: usize) -> Self {
+        DenseMatrix {
+            rows,
+            columns,
+            values: vec![0.0; rows * columns]
+        }
+    }
+
+    fn shape(&self) -> (usize, usize) {
+        (self.rows, self.columns)
+    }
+
+    fn get(&self, row: usize, column: usize) -> f64 {
+        self[(row, column)]
+    }
+
+    fn set(&mut self, row: usize, column: usize, value: f64) {
+        self[(row, column)] = value;
+    }
+}
+
+impl Index<(usize, usize)> for DenseMatrix {
+    type Output = f64;
+
+    fn index(&self, (row, column): (usize, usize)) -> &f64 {
+        assert!(row < self.rows && column < self.columns, "matrix index out of bounds");
+        &self.values[row * self.columns + column]
+    }
+}
+
+impl IndexMut<(usize, usize)> for DenseMatrix {
+    fn index_mut(&mut self, (row, column): (usize, usize)) -> &mut f64 {
+        assert!(row < self.rows && column < self.columns, "matrix index out of bounds");
+        &mut self.values[row * self.columns + column]
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+impl Matrix for nalgebra::DMatrix<f64> {
+    fn zeros(rows: usize, columns: usize) -> Self {
+        nalgebra::DMatrix::zeros(rows, columns)
+    }
+
+    fn shape(&self) -> (usize, usize) {
+        self.shape()
+    }
+
+    fn get(&self, row: usize, column: usize) -> f64 {
+        self[(row, column)]
+    }
+
+    fn set(&mut self, row: usize, column: usize, value: f64) {
+        self[(row, column)] = value;
+    }
+
+    fn identity(size: usize) -> Self {
+        nalgebra::DMatrix::identity(size, size)
+    }
+
+    fn transpose(&self) -> Self {
+        self.transpose()
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        self + other
+    }
+
+    fn sub(&self, other: &Self) -> Self {
+        self - other
+    }
+
+    fn scale(&self, factor: f64) -> Self {
+        self * factor
+    }
+
+    fn mul(&self, other: &Self) -> Self {
+        self * other
+    }
+
+    fn cholesky_solve(&self, rhs: &Self) -> Option<Self> {
+        self.clone().cholesky().map(|cholesky| cholesky.solve(rhs))
+    }
+
+    fn cholesky_factor(&self) -> Option<Self> {
+        self.clone().cholesky().map(|cholesky| cholesky.l())
+    }
+}
+
+#[cfg(feature = "ndarray")]
+impl Matrix for ndarray::Array2<f64> {
+    fn zeros(rows: usize, columns: usize) -> Self {
+        ndarray::Array2::zeros((rows, columns))
+    }
+
+    fn shape(&self) -> (usize, usize) {
+        self.dim()
+    }
+
+    fn get(&self, row: usize, column: usize) -> f64 {
+        self[[row, column]]
+    }
+
+    fn set(&mut self, row: usize, column: usize, value: f64) {
+        self[[row, column]] = value;
+    }
+
+    fn identity(size: usize) -> Self {
+        ndarray::Array2::eye(size)
+    }
+
+    fn transpose(&self) -> Self {
+        self.t().to_owned()
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        self + other
+    }
+
+    fn sub(&self, other: &Self) -> Self {
+        self - other
+    }
+
+    fn scale(&self, factor: f64) -> Self {
+        self * factor
+    }
+
+    fn mul(&self, other: &Self) -> Self {
+        self.dot(other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multiplies_matrices() {
+        let a = DenseMatrix::from_row_slices(&[&[1.0, 2.0], &[3.0, 4.0]]);
+        let b = DenseMatrix::vector(&[1.0, 1.0]);
+
+        assert_eq!(a.mul(&b), DenseMatrix::vector(&[3.0, 7.0]));
+        assert_eq!(a.mul(&DenseMatrix::identity(2)), a);
+    }
+
+    #[test]
+    fn combines_element_wise() {
+        let a = DenseMatrix::from_row_slices(&[&[1.0, 2.0], &[3.0, 4.0]]);
+
+        assert_eq!(a.add(&a), a.scale(2.0));
+        assert_eq!(a.sub(&a), DenseMatrix::zeros(2, 2));
+        assert_eq!(a.transpose(), DenseMatrix::from_row_slices(&[&[1.0, 3.0], &[2.0, 4.0]]));
+        assert_eq!(a.row(1), DenseMatrix::from_row_slices(&[&[3.0, 4.0]]));
+    }
+
+    #[test]
+    fn detects_diagonal_matrices() {
+        assert!(DenseMatrix::identity(3).scale(2.0).is_diagonal());
+        assert!(!DenseMatrix::from_row_slices(&[&[1.0, 0.5], &[0.5, 1.0]]).is_diagonal());
+    }
+
+    #[test]
+    fn solves_positive_definite_systems() {
+        let a = DenseMatrix::from_row_slices(&[&[4.0, 2.0], &[2.0, 3.0]]);
+        let solution = a.cholesky_solve(&DenseMatrix::vector(&[2.0, 1.0])).unwrap();
+
+        assert!((solution.get(0, 0) - 0.5).abs() < 1e-12);
+        assert!(solution.get(1, 0).abs() < 1e-12);
+        assert_eq!(DenseMatrix::from_row_slices(&[&[1.0, 2.0], &[2.0, 1.0]]).cholesky_solve(&DenseMatrix::zeros(2, 1)), None);
+    }
+
+    #[test]
+    fn factors_positive_definite_matrices() {
+        let a = DenseMatrix::from_row_slices(&[&[4.0, 2.0], &[2.0, 2.0]]);
+
+        assert_eq!(a.cholesky_factor(), Some(DenseMatrix::from_row_slices(&[&[2.0, 0.0], &[1.0, 1.0]])));
+        assert!((a.log_determinant().unwrap() - 4.0f64.ln()).abs() < 1e-12);
+        assert_eq!(DenseMatrix::identity(2).scale(-1.0).log_determinant(), None);
+    }
+
+    #[test]
+    fn decomposes_symmetric_matrices() {
+        let a = DenseMatrix::from_row_slices(&[&[4.0, 1.0, 0.5], &[1.0, 3.0, 0.2], &[0.5, 0.2, 1.0]]);
+        let (values, vectors) = a.symmetric_eigen();
+        let mut diagonal = DenseMatrix::zeros(3, 3);
+        for (i, value) in values.iter().enumerate() {
+            diagonal.set(i, i, *value);
+        }
+
+        let reconstructed = vectors.mul(&diagonal).mul(&vectors.transpose());
+        assert!((0..9).all(|k| (reconstructed.get(k / 3, k % 3) - a.get(k / 3, k % 3)).abs() < 1e-12));
+        assert!((values.iter().sum::<f64>() - 8.0).abs() < 1e-12);
+        assert_eq!(a.symmetrize(), a);
+        assert_eq!(
+            DenseMatrix::from_row_slices(&[&[1.0, 2.0], &[4.0, 1.0]]).symmetrize(),
+            DenseMatrix::from_row_slices(&[&[1.0, 3.0], &[3.0, 1.0]])
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "matrix index out of bounds")]
+    fn checks_bounds() {
+        DenseMatrix::zeros(2, 3).get(0, 3);
+    }
+
+    #[cfg(feature = "nalgebra")]
+    #[test]
+    fn matches_nalgebra_backend() {
+        let a = nalgebra::DMatrix::from_row_slices(&[&[4.0, 2.0], &[2.0, 3.0]]);
+        let b = nalgebra::DMatrix::vector(&[2.0, 1.0]);
+
+        assert_eq!(Matrix::mul(&a, &b), nalgebra::DMatrix::vector(&[10.0, 7.0]));
+        assert!((a.cholesky_solve(&b).unwrap() - nalgebra::DMatrix::vector(&[0.5, 0.0])).norm() < 1e-12);
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn matches_ndarray_backend() {
+        let a = ndarray::Array2::from_row_slices(&[&[4.0, 2.0], &[2.0, 3.0]]);
+        let b = ndarray::Array2::vector(&[2.0, 1.0]);
+
+        assert_eq!(Matrix::mul(&a, &b), ndarray::Array2::vector(&[10.0, 7.0]));
+        let solution = a.cholesky_solve(&b).unwrap();
+        assert!((solution[[0, 0]] - 0.5).abs() < 1e-12 && solution[[1, 0]].abs() < 1e-12);
+    }
+}
@@ -0,0 +1,318 @@
+//! A MAVLink telemetry adapter.
+//!
+//! With the `mavlink` feature enabled a `MavlinkAdapter` consumes the
+//! messages of the common MAVLink dialect that describe where a vehicle is
+//! and how it moves, feeds them to a `Tracker3D`, and emits the estimate
+//! back as MAVLink:
+//!
+//! - `GLOBAL_POSITION_INT` updates the position and velocity.
+//! - `GPS_RAW_INT` updates the position, if the receiver has a 3D fix.
+//! - `ATTITUDE` records the orientation of the vehicle.
+//! - `SCALED_IMU` updates the acceleration, rotated into the local frame by
+//!   the latest attitude, if the tracker models `Motion::ConstantAcceleration`.
+//!
+//! Positions are converted to metres north, east and down (NED) of an origin,
+//! the first fix unless set with `with_origin`, as in `LOCAL_POSITION_NED`.
+//! The tracker is predicted forward by the `time_boot_ms` of each message, so
+//! messages should come from a single vehicle.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::mavlink::MavlinkAdapter;
+//! use lqe::tracking::{Motion, Tracker3D};
+//! use mavlink::dialects::common::{GLOBAL_POSITION_INT_DATA, MavMessage};
+//!
+//! let mut adapter = MavlinkAdapter::new(Tracker3D::new((0.0, 0.0, 0.0), Motion::ConstantVelocity, 4.0));
+//! for (time_boot_ms, lat) in &[(0, 473_977_420), (1000, 473_977_510)] {
+//!     let fix = GLOBAL_POSITION_INT_DATA { time_boot_ms: *time_boot_ms, lat: *lat, lon: 85_455_940, alt: 488_000, vx: 100, ..Default::default() };
+//!     adapter.handle(&MavMessage::GLOBAL_POSITION_INT(fix)).unwrap();
+//! }
+//! match adapter.local_position() {
+//!     MavMessage::LOCAL_POSITION_NED(position) => (position.x, position.vx),
+//!     _ => unreachable!()
+//! };
+//! // => (0.99, 0.99)
+//! ```
+
+use ::mavlink::dialects::common::{
+    GpsFixType, MavEstimatorType, MavMessage, LOCAL_POSITION_NED_COV_DATA, LOCAL_POSITION_NED_DATA,
+    SCALED_IMU_DATA
+};
+
+use crate::error::Error;
+use crate::matrix::Matrix;
+use crate::tracking::{Motion, Tracker3D};
+
+/// The mean radius of the earth in metres.
+const EARTH_RADIUS: f64 = 6_371_000.0;
+
+/// The standard gravity in m/s², the unit of `SCALED_IMU` accelerations
+/// being a thousandth of it.
+const GRAVITY: f64 = 9.806_65;
+
+/// `MavlinkAdapter` feeds MAVLink telemetry to `tracker`.
+///
+/// Accelerations are measured with the `acceleration_noise` of `tracker`, in
+/// (m/s²)².
+#[derive(Clone, Debug, PartialEq)]
+pub struct MavlinkAdapter {
+    pub tracker: Tracker3D,
+    attitude: Option<(f64, f64, f64)>,
+    origin: Option<(f64, f64, f64)>,
+    time_boot_ms: Option<u32>
+}
+
+impl MavlinkAdapter {
+    /// `new` creates an adapter whose origin is the first position fix.
+    pub fn new(tracker: Tracker3D) -> Self {
+        MavlinkAdapter {
+            tracker,
+            attitude: None,
+            origin: None,
+            time_boot_ms: None
+        }
+    }
+
+    /// `with_origin` sets the origin of the local frame to a latitude and
+    /// longitude in degrees and an altitude in metres above mean sea level.
+    pub fn with_origin(mut self, latitude: f64, longitude: f64, altitude: f64) -> Self {
+        self.origin = Some((latitude, longitude, altitude));
+        self
+    }
+
+    /// `handle` folds `message` into the estimate, returning whether it was
+    /// used. Messages of other types are ignored.
+    pub fn handle(&mut self, message: &MavMessage) -> Result<bool, Error> {
+        match message {
+            MavMessage::GLOBAL_POSITION_INT(fix) => {
+                self.advance(fix.time_boot_ms);
+                let position = self.local(fix.lat, fix.lon, fix.alt);
+                let velocity = (fix.vx as f64 / 100.0, fix.vy as f64 / 100.0, fix.vz as f64 / 100.0);
+                self.tracker.update_position_velocity(position, velocity)?;
+                Ok(true)
+            }
+            MavMessage::GPS_RAW_INT(fix) => {
+                if (fix.fix_type as u32) < GpsFixType::GPS_FIX_TYPE_3D_FIX as u32 {
+                    return Ok(false);
+                }
+                // The time of a raw fix may be since the epoch rather than
+                // boot, so it is taken to be the latest time seen.
+                let position = self.local(fix.lat, fix.lon, fix.alt);
+                self.tracker.update_position(position)?;
+                Ok(true)
+            }
+            MavMessage::ATTITUDE(attitude) => {
+                self.attitude = Some((attitude.roll as f64, attitude.pitch as f64, attitude.yaw as f64));
+                Ok(true)
+            }
+            MavMessage::SCALED_IMU(imu) => self.update_acceleration(imu),
+            _ => Ok(false)
+        }
+    }
+
+    /// `update_acceleration` refines the acceleration with the specific force
+    /// measured by `imu`, which needs an attitude to rotate it into the local
+    /// frame.
+    fn update_acceleration(&mut self, imu: &SCALED_IMU_DATA) -> Result<bool, Error> {
+        let attitude = match (self.tracker.motion, self.attitude) {
+            (Motion::ConstantAcceleration, Some(attitude)) => attitude,
+            _ => return Ok(false)
+        };
+        self.advance(imu.time_boot_ms);
+        let force = [imu.xacc, imu.yacc, imu.zacc].map(|a| a as f64 / 1000.0 * GRAVITY);
+        let rotation = rotation(attitude);
+        let mut acceleration = [0.0; 3];
+        for (i, axis) in acceleration.iter_mut().enumerate() {
+            *axis = (0..3).map(|j| rotation[i][j] * force[j]).sum::<f64>();
+        }
+        // At rest the accelerometer measures the reaction to gravity, -g
+        // along the down axis.
+        acceleration[2] += GRAVITY;
+        self.tracker.update_acceleration(&acceleration)?;
+        Ok(true)
+    }
+
+    /// `advance` predicts the tracker forward to `time_boot_ms`, if it is
+    /// later than the last message.
+    fn advance(&mut self, time_boot_ms: u32) {
+        if let Some(last) = self.time_boot_ms {
+            if time_boot_ms <= last {
+                return;
+            }
+            self.tracker.predict((time_boot_ms - last) as f64 / 1000.0);
+        }
+        self.time_boot_ms = Some(time_boot_ms);
+    }
+
+    /// `local` converts a MAVLink latitude and longitude in 1e-7 degrees
+    /// and altitude in millimetres to metres NED of the origin, setting the
+    /// origin first if there is none.
+    fn local(&mut self, lat: i32, lon: i32, alt: i32) -> (f64, f64, f64) {
+        let (latitude, longitude, altitude) = (lat as f64 * 1e-7, lon as f64 * 1e-7, alt as f64 / 1000.0);
+        let (origin_latitude, origin_longitude, origin_altitude) =
+            *self.origin.get_or_insert((latitude, longitude, altitude));
+        (
+            (latitude - origin_latitude).to_radians() * EARTH_RADIUS,
+            (longitude - origin_longitude).to_radians() * EARTH_RADIUS * origin_latitude.to_radians().cos(),
+            origin_altitude - altitude
+        )
+    }
+
+    /// `attitude` returns the latest roll, pitch and yaw in radians.
+    pub fn attitude(&self) -> Option<(f64, f64, f64)> {
+        self.attitude
+    }
+
+    /// `origin` returns the latitude, longitude and altitude of the origin
+    /// of the local frame, once known.
+    pub fn origin(&self) -> Option<(f64, f64, f64)> {
+        self.origin
+    }
+
+    /// `local_position` returns the estimated position and velocity as a
+    /// `LOCAL_POSITION_NED` message.
+    pub fn local_position(&self) -> MavMessage {
+        let (x, y, z) = self.tracker.position();
+        let (vx, vy, vz) = self.tracker.velocity();
+        MavMessage::LOCAL_POSITION_NED(LOCAL_POSITION_NED_DATA {
+            time_boot_ms: self.time_boot_ms.unwrap_or(0),
+            x: x as f32,
+            y: y as f32,
+            z: z as f32,
+            vx: vx as f32,
+            vy: vy as f32,
+            vz: vz as f32
+        })
+    }
+
+    /// `local_position_cov` returns the estimated position, velocity and
+    /// acceleration with their covariance as a `LOCAL_POSITION_NED_COV`
+    /// message. Without a modelled acceleration, it is zero and its rows of
+    /// the covariance are `NaN`.
+    pub fn local_position_cov(&self) -> MavMessage {
+        let (x, y, z) = self.tracker.position();
+        let (vx, vy, vz) = self.tracker.velocity();
+        let (ax, ay, az) = self.tracker.acceleration().unwrap_or((0.0, 0.0, 0.0));
+        let covariance = &self.tracker.filter.covariance;
+        let size = covariance.shape().0;
+        // The upper triangle of the 9x9 covariance, row by row.
+        let mut upper = [0.0; 45];
+        let mut index = 0;
+        for i in 0..9 {
+            for j in i..9 {
+                upper[index] = if j < size { covariance.get(i, j) as f32 } else { f32::NAN };
+                index += 1;
+            }
+        }
+        MavMessage::LOCAL_POSITION_NED_COV(LOCAL_POSITION_NED_COV_DATA {
+            time_usec: self.time_boot_ms.unwrap_or(0) as u64 * 1000,
+            x: x as f32,
+            y: y as f32,
+            z: z as f32,
+            vx: vx as f32,
+            vy: vy as f32,
+            vz: vz as f32,
+            ax: ax as f32,
+            ay: ay as f32,
+            az: az as f32,
+            covariance: upper,
+            estimator_type: MavEstimatorType::MAV_ESTIMATOR_TYPE_GPS_INS
+        })
+    }
+}
+
+/// `rotation` returns the matrix rotating a vector from the body frame of a
+/// vehicle with the given roll, pitch and yaw into the local NED frame.
+fn rotation((roll, pitch, yaw): (f64, f64, f64)) -> [[f64; 3]; 3] {
+    let (sr, cr) = roll.sin_cos();
+    let (sp, cp) = pitch.sin_cos();
+    let (sy, cy) = yaw.sin_cos();
+    [
+        [cp * cy, sr * sp * cy - cr * sy, cr * sp * cy + sr * sy],
+        [cp * sy, sr * sp * sy + cr * cy, cr * sp * sy - sr * cy],
+        [-sp, sr * cp, cr * cp]
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::mavlink::dialects::common::{ATTITUDE_DATA, GLOBAL_POSITION_INT_DATA, GPS_RAW_INT_DATA};
+
+    #[test]
+    fn tracks_global_positions_in_local_frame() {
+        let mut adapter = MavlinkAdapter::new(Tracker3D::new((0.0, 0.0, 0.0), Motion::ConstantVelocity, 1.0))
+            .with_origin(47.0, 8.0, 500.0);
+
+        let fix = GPS_RAW_INT_DATA {
+            lat: 470_000_090,
+            lon: 80_000_000,
+            alt: 490_000,
+            fix_type: GpsFixType::GPS_FIX_TYPE_2D_FIX,
+            ..Default::default()
+        };
+        assert!(!adapter.handle(&MavMessage::GPS_RAW_INT(fix.clone())).unwrap());
+        let fix = GPS_RAW_INT_DATA {
+            fix_type: GpsFixType::GPS_FIX_TYPE_3D_FIX,
+            ..fix
+        };
+        for _ in 0..200 {
+            assert!(adapter.handle(&MavMessage::GPS_RAW_INT(fix.clone())).unwrap());
+        }
+        // 9e-6 degrees of latitude is about a metre north, and 10 m lower
+        // is 10 m down.
+        let (north, east, down) = adapter.tracker.position();
+        assert!((north - 1.0).abs() < 0.01);
+        assert!(east.abs() < 1e-6);
+        assert!((down - 10.0).abs() < 0.1);
+        assert!(!adapter.handle(&MavMessage::HEARTBEAT(Default::default())).unwrap());
+    }
+
+    #[test]
+    fn rotates_accelerations_by_attitude() {
+        let mut adapter = MavlinkAdapter::new(Tracker3D::new((0.0, 0.0, 0.0), Motion::ConstantAcceleration, 1.0));
+        adapter.tracker.acceleration_noise = 0.1;
+        let fix = GLOBAL_POSITION_INT_DATA {
+            lat: 470_000_000,
+            lon: 80_000_000,
+            ..Default::default()
+        };
+        adapter.handle(&MavMessage::GLOBAL_POSITION_INT(fix)).unwrap();
+
+        // Without an attitude the body frame can't be rotated.
+        let imu = SCALED_IMU_DATA {
+            time_boot_ms: 10,
+            xacc: 1000,
+            ..Default::default()
+        };
+        assert!(!adapter.handle(&MavMessage::SCALED_IMU(imu.clone())).unwrap());
+        // Pitched up by 90 degrees, the nose points up and the specific
+        // force along the nose of 2 g is an upward acceleration of 1 g.
+        let attitude = ATTITUDE_DATA {
+            pitch: std::f32::consts::FRAC_PI_2,
+            ..Default::default()
+        };
+        adapter.handle(&MavMessage::ATTITUDE(attitude)).unwrap();
+        for step in 1..20 {
+            let imu = SCALED_IMU_DATA {
+                time_boot_ms: 10 * step,
+                xacc: 2000,
+                ..Default::default()
+            };
+            assert!(adapter.handle(&MavMessage::SCALED_IMU(imu)).unwrap());
+        }
+        let (north, east, down) = adapter.tracker.acceleration().unwrap();
+        assert!(north.abs() < 1e-3 && east.abs() < 1e-3);
+        assert!((down + GRAVITY).abs() < 0.1);
+
+        match adapter.local_position_cov() {
+            MavMessage::LOCAL_POSITION_NED_COV(estimate) => {
+                assert_eq!(estimate.time_usec, 190_000);
+                assert!(estimate.covariance.iter().all(|element| element.is_finite()));
+                assert!((estimate.az as f64 + GRAVITY).abs() < 0.1);
+            }
+            _ => unreachable!()
+        }
+    }
+}
@@ -0,0 +1,235 @@
+//! Timestamped measurements.
+//!
+//! `Measurement` is the common input format for everything that consumes more
+//! than a bare `(value, variance)` pair: logs, time-aware filters and
+//! multi-sensor fusion.
+//!
+//! Timestamps can be anything implementing `Timestamp`. With the `chrono`
+//! feature enabled that includes `chrono::DateTime` and `chrono::NaiveDateTime`,
+//! and with the `time` feature `time::OffsetDateTime` and
+//! `time::PrimitiveDateTime`.
+//!
+//! Readings of several quantities at once, e.g. from a sensor driver, are
+//! fed to the multivariate filters through `MeasurementVector`. With the
+//! `derive` feature enabled `#[derive(Measurement)]` implements it for a
+//! struct, with the variance of every measured field in an attribute:
+//!
+//! ```
+//! # #[cfg(feature = "derive")] {
+//! use lqe::matrix::{DenseMatrix, Matrix};
+//! use lqe::measurement::{Measurement, MeasurementVector};
+//! use lqe::multivariate::DynamicKalmanFilter;
+//!
+//! #[derive(Measurement)]
+//! struct GpsFix {
+//!     #[measurement(skip)]
+//!     timestamp: f64,
+//!     #[measurement(variance_field = horizontal_variance)]
+//!     east: f64,
+//!     #[measurement(variance_field = horizontal_variance)]
+//!     north: f64,
+//!     horizontal_variance: f64,
+//!     #[measurement(variance = 9.0)]
+//!     altitude: f32
+//! }
+//!
+//! let fix = GpsFix { timestamp: 0.0, east: 1.0, north: 2.0, horizontal_variance: 4.0, altitude: 3.0 };
+//! let mut filter = DynamicKalmanFilter::<DenseMatrix>::new(3, GpsFix::DIMENSIONS);
+//! filter.measurement_noise = fix.noise();
+//! filter.update(&fix.to_vector()).unwrap();
+//! filter.measurement_noise.get(2, 2);
+//! // => 9.0
+//! # }
+//! ```
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::LQE;
+//! use lqe::measurement::{Measurement, SensorId};
+//! use lqe::timed::TimedFilter;
+//!
+//! let mut filter = TimedFilter::new(LQE { measurement: 7.0, variance: 2.0 }, 0.5);
+//! filter.apply(&Measurement {
+//!   timestamp: 0.0,
+//!   value: 10.0,
+//!   variance: 2.0,
+//!   sensor_id: SensorId(1)
+//! }).result();
+//! // => (8.5, 1.0)
+//! ```
+
+use std::time::Instant;
+
+#[cfg(feature = "derive")]
+pub use lqe_derive::Measurement;
+
+use crate::matrix::Matrix;
+#[cfg(any(feature = "chrono", feature = "time"))]
+use crate::timed::Timestamp;
+use crate::LQE;
+
+/// `SensorId` identifies the sensor a measurement came from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SensorId(pub u32);
+
+/// `Measurement` is a single reading `value` with its `variance`, taken by
+/// `sensor_id` at `timestamp`.
+///
+/// # Example:
+///
+/// ```
+/// use std::time::Instant;
+/// use lqe::measurement::{Measurement, SensorId};
+/// let measurement = Measurement {
+///   timestamp: Instant::now(),
+///   value: 10.0,
+///   variance: 2.0,
+///   sensor_id: SensorId(1)
+/// };
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Measurement<T = Instant> {
+    pub timestamp: T,
+    pub value: f64,
+    pub variance: f64,
+    pub sensor_id: SensorId
+}
+
+impl<T> Measurement<T> {
+    /// `estimate` returns the measurement on its own as an `LQE`.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use lqe::measurement::{Measurement, SensorId};
+    /// let measurement = Measurement { timestamp: 0.0, value: 10.0, variance: 2.0, sensor_id: SensorId(1) };
+    /// measurement.estimate().result();
+    /// // => (10.0, 2.0)
+    /// ```
+    pub fn estimate(&self) -> LQE {
+        LQE {
+            measurement: self.value,
+            variance: self.variance
+        }
+    }
+}
+
+/// `MeasurementVector` is a reading of `DIMENSIONS` quantities with
+/// independent noise, as a measurement vector for the multivariate filters.
+pub trait MeasurementVector {
+    /// `DIMENSIONS` is the number of measured quantities.
+    const DIMENSIONS: usize;
+
+    /// `to_vector` returns the readings as a `DIMENSIONS` x 1 matrix.
+    fn to_vector<T: Matrix>(&self) -> T;
+
+    /// `noise` returns the diagonal covariance of the readings.
+    fn noise<T: Matrix>(&self) -> T;
+}
+
+#[cfg(feature = "chrono")]
+impl<Tz: chrono::TimeZone> Timestamp for chrono::DateTime<Tz>
+where
+    chrono::DateTime<Tz>: Copy
+{
+    fn seconds_since(&self, earlier: &Self) -> f64 {
+        self.signed_duration_since(*earlier)
+            .to_std()
+            .map_or(0.0, |elapsed| elapsed.as_secs_f64())
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl Timestamp for chrono::NaiveDateTime {
+    fn seconds_since(&self, earlier: &Self) -> f64 {
+        self.signed_duration_since(*earlier)
+            .to_std()
+            .map_or(0.0, |elapsed| elapsed.as_secs_f64())
+    }
+}
+
+#[cfg(feature = "time")]
+impl Timestamp for time::OffsetDateTime {
+    fn seconds_since(&self, earlier: &Self) -> f64 {
+        (*self - *earlier).as_seconds_f64().max(0.0)
+    }
+}
+
+#[cfg(feature = "time")]
+impl Timestamp for time::PrimitiveDateTime {
+    fn seconds_since(&self, earlier: &Self) -> f64 {
+        (*self - *earlier).as_seconds_f64().max(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn returns_estimate() {
+        let measurement = Measurement {
+            timestamp: 0.0,
+            value: 10.0,
+            variance: 2.0,
+            sensor_id: SensorId(1)
+        };
+
+        assert_eq!(measurement.estimate(), LQE { measurement: 10.0, variance: 2.0 });
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn measures_chrono_timestamps() {
+        use chrono::{TimeZone, Utc};
+
+        let earlier = Utc.timestamp_opt(1_000, 0).unwrap();
+        let later = Utc.timestamp_opt(1_002, 500_000_000).unwrap();
+
+        assert_eq!(later.seconds_since(&earlier), 2.5);
+        assert_eq!(earlier.seconds_since(&later), 0.0);
+        assert_eq!(later.naive_utc().seconds_since(&earlier.naive_utc()), 2.5);
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn measures_time_timestamps() {
+        use time::{Duration, OffsetDateTime};
+
+        let earlier = OffsetDateTime::UNIX_EPOCH;
+        let later = earlier + Duration::milliseconds(2_500);
+
+        assert_eq!(later.seconds_since(&earlier), 2.5);
+        assert_eq!(earlier.seconds_since(&later), 0.0);
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn derives_measurement_vector() {
+        use crate::matrix::DenseMatrix;
+
+        #[derive(Measurement)]
+        struct Imu<T> {
+            #[measurement(variance = 0.5)]
+            rate: f64,
+            #[measurement(variance_field = noise)]
+            acceleration: f32,
+            noise: f64,
+            #[measurement(skip)]
+            _timestamp: T
+        }
+
+        let imu = Imu {
+            rate: 1.0,
+            acceleration: 2.0,
+            noise: 4.0,
+            _timestamp: ()
+        };
+        assert_eq!(Imu::<()>::DIMENSIONS, 2);
+        assert_eq!(imu.to_vector::<DenseMatrix>(), DenseMatrix::vector(&[1.0, 2.0]));
+        assert_eq!(imu.noise::<DenseMatrix>(), DenseMatrix::from_row_slices(&[&[0.5, 0.0], &[0.0, 4.0]]));
+    }
+}
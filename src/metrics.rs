@@ -0,0 +1,74 @@
+//! Filter health metrics.
+//!
+//! With the `metrics` feature enabled a `Metrics` handle publishes the health
+//! of a running filter through the [`metrics`](https://docs.rs/metrics) facade,
+//! labelled with `filter = <name>`:
+//!
+//! - `lqe_innovation_variance`: the expected variance of the latest innovation.
+//! - `lqe_nis`: the latest normalized innovation squared, which should average
+//!   around 1 for a well tuned filter.
+//! - `lqe_covariance_trace`: the variance of the latest estimate.
+//! - `lqe_covariance_condition`: a lower bound on the condition number of the
+//!   covariance of a multivariate filter, recorded with `record_condition`.
+//! - `lqe_resets_total`: how often the filter has been reset.
+//!
+//! Handles are registered when the `Metrics` is created, so install the
+//! recorder first.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::LQE;
+//! use lqe::metrics::Metrics;
+//! use lqe::timed::TimedFilter;
+//!
+//! let mut filter = TimedFilter::new(LQE { measurement: 7.0, variance: 2.0 }, 0.5);
+//! filter.set_metrics(Metrics::new("altitude"));
+//! filter.next(0.0, 10.0, 2.0);
+//! ```
+
+use ::metrics::{counter, gauge, Counter, Gauge, SharedString};
+
+use crate::step::StepInfo;
+
+/// `Metrics` holds the registered metric handles of a single filter.
+pub struct Metrics {
+    innovation_variance: Gauge,
+    nis: Gauge,
+    covariance_trace: Gauge,
+    covariance_condition: Gauge,
+    resets: Counter
+}
+
+impl Metrics {
+    /// `new` registers the metrics of the filter called `filter`.
+    pub fn new(filter: impl Into<SharedString>) -> Self {
+        let filter = filter.into();
+        Metrics {
+            innovation_variance: gauge!("lqe_innovation_variance", "filter" => filter.clone()),
+            nis: gauge!("lqe_nis", "filter" => filter.clone()),
+            covariance_trace: gauge!("lqe_covariance_trace", "filter" => filter.clone()),
+            covariance_condition: gauge!("lqe_covariance_condition", "filter" => filter.clone()),
+            resets: counter!("lqe_resets_total", "filter" => filter)
+        }
+    }
+
+    /// `record` publishes the health of the filter after `step`.
+    pub fn record(&self, step: &StepInfo) {
+        self.innovation_variance.set(step.innovation_variance);
+        self.nis.set(step.innovation * step.innovation / step.innovation_variance);
+        self.covariance_trace.set(step.posterior.variance);
+    }
+
+    /// `record_condition` publishes the condition bound of the covariance of
+    /// a multivariate filter, e.g. from `DynamicKalmanFilter::condition`,
+    /// infinite if it is no longer positive definite.
+    pub fn record_condition(&self, condition: Option<f64>) {
+        self.covariance_condition.set(condition.unwrap_or(f64::INFINITY));
+    }
+
+    /// `record_reset` counts a reset of the filter.
+    pub fn record_reset(&self) {
+        self.resets.increment(1);
+    }
+}
@@ -0,0 +1,257 @@
+//! Gaussian sum filtering for multimodal posteriors.
+//!
+//! A single Gaussian can't describe an estimate that is ambiguous between
+//! several hypotheses, e.g. a bearing-only track that may be on either side of
+//! the sensor; the filter settles on the mean of the modes, which may be the
+//! least likely value of all. `GaussianSumFilter` instead propagates a weighted
+//! mixture of Gaussian components, each one a `DynamicKalmanFilter`, and
+//! reweighs them by how well they predict each measurement. `prune` and
+//! `merge` keep the number of components bounded.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::matrix::{DenseMatrix, Matrix};
+//! use lqe::mixture::{Component, GaussianSumFilter};
+//! use lqe::multivariate::DynamicKalmanFilter;
+//!
+//! let at = |position: f64| {
+//!     let mut filter = DynamicKalmanFilter::<DenseMatrix>::new(1, 1);
+//!     filter.state = DenseMatrix::vector(&[position]);
+//!     Component { weight: 0.5, filter }
+//! };
+//! let mut filter = GaussianSumFilter { components: vec![at(-1.0), at(1.0)] };
+//!
+//! filter.next(&DenseMatrix::vector(&[1.0])).unwrap();
+//! filter.components[1].weight;
+//! // => 0.731
+//! ```
+
+use crate::error::Error;
+use crate::matrix::Matrix;
+use crate::multivariate::DynamicKalmanFilter;
+
+/// `Component` is one Gaussian of a mixture, with its prior probability
+/// `weight`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Component<T> {
+    pub weight: f64,
+    pub filter: DynamicKalmanFilter<T>
+}
+
+/// `moments` returns the mean and covariance of the mixture of `components`,
+/// whose weights need not sum to one.
+pub(crate) fn moments<'a, T: Matrix + 'a>(components: impl Iterator<Item = &'a Component<T>> + Clone) -> (T, T) {
+    let total = components.clone().map(|component| component.weight).sum::<f64>();
+    let mut components = components.peekable();
+    let size = components.peek().map_or(0, |component| component.filter.state.shape().0);
+    let mut mean = T::zeros(size, 1);
+    for component in components.clone() {
+        mean = mean.add(&component.filter.state.scale(component.weight / total));
+    }
+    let mut covariance = T::zeros(size, size);
+    for component in components {
+        let spread = component.filter.state.sub(&mean);
+        let contribution = component.filter.covariance.add(&spread.mul(&spread.transpose()));
+        covariance = covariance.add(&contribution.scale(component.weight / total));
+    }
+    (mean, covariance)
+}
+
+/// `GaussianSumFilter` is an estimate made of the weighted Gaussian
+/// `components`, whose weights sum to one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GaussianSumFilter<T> {
+    pub components: Vec<Component<T>>
+}
+
+impl<T: Matrix> GaussianSumFilter<T> {
+    /// `predict` moves every component one step forward.
+    pub fn predict(&mut self) {
+        for component in &mut self.components {
+            component.filter.predict();
+        }
+    }
+
+    /// `update` refines every component with `measurement` and reweighs them
+    /// by its likelihood under each.
+    ///
+    /// Returns `Error::ImpossibleMeasurement` if the measurement has zero
+    /// likelihood under every component with any weight. On any error the
+    /// filter is left unchanged.
+    pub fn update(&mut self, measurement: &T) -> Result<(), Error> {
+        let mut log_weights = Vec::with_capacity(self.components.len());
+        let mut updated = Vec::with_capacity(self.components.len());
+        for component in &self.components {
+            log_weights.push(component.weight.ln() + component.filter.log_likelihood(measurement)?);
+            let mut filter = component.filter.clone();
+            filter.update(measurement)?;
+            updated.push(filter);
+        }
+        // Normalize in log space so that unlikely measurements don't
+        // underflow every weight to zero.
+        let largest = log_weights.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        if !largest.is_finite() {
+            return Err(Error::ImpossibleMeasurement);
+        }
+        let total = log_weights.iter().map(|weight| (weight - largest).exp()).sum::<f64>();
+        for ((component, filter), log_weight) in self.components.iter_mut().zip(updated).zip(log_weights) {
+            component.filter = filter;
+            component.weight = (log_weight - largest).exp() / total;
+        }
+        Ok(())
+    }
+
+    /// `next` performs the entire predict - update cycle for a measurement.
+    pub fn next(&mut self, measurement: &T) -> Result<(), Error> {
+        self.predict();
+        self.update(measurement)
+    }
+
+    /// `prune` removes the components weighing less than `threshold` and
+    /// renormalizes the rest, always keeping the most likely one.
+    pub fn prune(&mut self, threshold: f64) {
+        let largest = self
+            .components
+            .iter()
+            .map(|component| component.weight)
+            .fold(f64::NEG_INFINITY, f64::max);
+        self.components
+            .retain(|component| component.weight >= threshold.min(largest));
+        self.normalize();
+    }
+
+    /// `merge` replaces each group of components whose means lie within the
+    /// squared Mahalanobis `distance` of the heaviest among them by a single
+    /// component with the same mean and covariance.
+    ///
+    /// A leader with a singular covariance fails with
+    /// `Error::SingularCovariance`, leaving the components as they were.
+    pub fn merge(&mut self, distance: f64) -> Result<(), Error> {
+        let mut remaining = self.components.clone();
+        let mut merged = Vec::new();
+        while !remaining.is_empty() {
+            let heaviest = (0..remaining.len())
+                .max_by(|&i, &j| remaining[i].weight.total_cmp(&remaining[j].weight))
+                .unwrap_or(0);
+            let leader = remaining[heaviest].filter.clone();
+            let mut group = Vec::new();
+            let mut rest = Vec::new();
+            for component in remaining {
+                let spread = component.filter.state.sub(&leader.state);
+                let solved = leader.covariance.cholesky_solve(&spread).ok_or(Error::SingularCovariance)?;
+                if spread.transpose().mul(&solved).get(0, 0) <= distance {
+                    group.push(component);
+                } else {
+                    rest.push(component);
+                }
+            }
+            let (state, covariance) = moments(group.iter());
+            merged.push(Component {
+                weight: group.iter().map(|component| component.weight).sum(),
+                filter: DynamicKalmanFilter {
+                    state,
+                    covariance,
+                    ..leader
+                }
+            });
+            remaining = rest;
+        }
+        self.components = merged;
+        Ok(())
+    }
+
+    /// `result` returns the mean and covariance of the whole mixture.
+    pub fn result(&self) -> (T, T) {
+        moments(self.components.iter())
+    }
+
+    fn normalize(&mut self) {
+        let total = self.components.iter().map(|component| component.weight).sum::<f64>();
+        for component in &mut self.components {
+            component.weight /= total;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::DenseMatrix;
+
+    fn component(weight: f64, position: f64) -> Component<DenseMatrix> {
+        let mut filter = DynamicKalmanFilter::new(1, 1);
+        filter.state = DenseMatrix::vector(&[position]);
+        Component { weight, filter }
+    }
+
+    #[test]
+    fn reweighs_components() {
+        let mut filter = GaussianSumFilter {
+            components: vec![component(0.5, -1.0), component(0.5, 1.0)]
+        };
+
+        filter.update(&DenseMatrix::vector(&[1.0])).unwrap();
+        // The likelihoods are N(1; -1, 2) and N(1; 1, 2), in the ratio e⁻¹ : 1.
+        assert!((filter.components[1].weight - 1.0 / (1.0 + (-1.0f64).exp())).abs() < 1e-12);
+        assert!((filter.components[0].weight + filter.components[1].weight - 1.0).abs() < 1e-12);
+        assert_eq!(filter.components[1].filter.state, DenseMatrix::vector(&[1.0]));
+    }
+
+    #[test]
+    fn rejects_measurements_without_weight() {
+        let mut filter = GaussianSumFilter {
+            components: vec![component(0.0, -1.0), component(0.0, 1.0)]
+        };
+        let before = filter.clone();
+
+        assert_eq!(filter.update(&DenseMatrix::vector(&[1.0])), Err(Error::ImpossibleMeasurement));
+        assert_eq!(filter, before);
+
+        // A component that can't be updated leaves the others as they were.
+        filter.components[0].weight = 0.5;
+        filter.components[1].weight = 0.5;
+        filter.components[1].filter.covariance = DenseMatrix::zeros(1, 1);
+        filter.components[1].filter.measurement_noise = DenseMatrix::zeros(1, 1);
+        let before = filter.clone();
+        assert!(filter.update(&DenseMatrix::vector(&[1.0])).is_err());
+        assert_eq!(filter, before);
+    }
+
+    #[test]
+    fn keeps_modes_apart() {
+        let filter = GaussianSumFilter {
+            components: vec![component(0.5, -1.0), component(0.5, 1.0)]
+        };
+
+        let (mean, covariance) = filter.result();
+        assert_eq!(mean, DenseMatrix::vector(&[0.0]));
+        assert_eq!(covariance, DenseMatrix::from_row_slices(&[&[2.0]]));
+    }
+
+    #[test]
+    fn prunes_and_merges_components() {
+        let mut filter = GaussianSumFilter {
+            components: vec![component(0.6, 0.0), component(0.3, 0.2), component(0.1, 5.0)]
+        };
+
+        filter.prune(0.2);
+        assert_eq!(filter.components.len(), 2);
+        assert!((filter.components[0].weight - 2.0 / 3.0).abs() < 1e-12);
+
+        filter.merge(1.0).unwrap();
+        assert_eq!(filter.components.len(), 1);
+        let merged = &filter.components[0];
+        assert!((merged.weight - 1.0).abs() < 1e-12);
+        assert!((merged.filter.state.get(0, 0) - 0.2 / 3.0).abs() < 1e-12);
+
+        // A singular leader leaves the mixture alone.
+        let mut filter = GaussianSumFilter {
+            components: vec![component(0.6, 0.0), component(0.4, 0.2)]
+        };
+        filter.components[0].filter.covariance = DenseMatrix::zeros(1, 1);
+        let before = filter.clone();
+        assert_eq!(filter.merge(1.0), Err(Error::SingularCovariance));
+        assert_eq!(filter, before);
+    }
+}
@@ -0,0 +1,160 @@
+//! System models.
+//!
+//! A `SystemModel` gives the transition (`F`), observation (`H`), process
+//! noise (`Q`) and measurement noise (`R`) matrices of a linear system for a
+//! time step `dt`. `state_space!` defines one without spelling out nested
+//! arrays: matrices are written row by row, with rows separated by `;`, in
+//! terms of the time step and the parameters of the model.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::matrix::{DenseMatrix, Matrix};
+//! use lqe::model::SystemModel;
+//! use lqe::state_space;
+//!
+//! state_space! {
+//!     /// `ConstantVelocity` tracks a position and its rate, measuring the
+//!     /// position with variance `r`.
+//!     pub struct ConstantVelocity(dt; q, r) {
+//!         F = [1.0, dt;
+//!              0.0, 1.0];
+//!         H = [1.0, 0.0];
+//!         Q = [q * dt.powi(3) / 3.0, q * dt.powi(2) / 2.0;
+//!              q * dt.powi(2) / 2.0, q * dt];
+//!         R = [r];
+//!     }
+//! }
+//!
+//! let model = ConstantVelocity { q: 0.1, r: 4.0 };
+//! let mut filter = model.filter::<DenseMatrix>(0.5);
+//! filter.transition.get(0, 1);
+//! // => 0.5
+//! filter.next(&DenseMatrix::vector(&[2.0])).unwrap();
+//! ```
+
+use crate::matrix::Matrix;
+use crate::multivariate::DynamicKalmanFilter;
+
+/// `SystemModel` is a linear system sampled with time steps `dt`.
+pub trait SystemModel {
+    /// `transition` returns `F`, moving the state forward by `dt`.
+    fn transition<T: Matrix>(&self, dt: f64) -> T;
+
+    /// `observation` returns `H`, mapping the state to a measurement.
+    fn observation<T: Matrix>(&self, dt: f64) -> T;
+
+    /// `process_noise` returns `Q`, the covariance of the noise added over
+    /// `dt`.
+    fn process_noise<T: Matrix>(&self, dt: f64) -> T;
+
+    /// `measurement_noise` returns `R`, the covariance of a measurement.
+    fn measurement_noise<T: Matrix>(&self, dt: f64) -> T;
+
+    /// `apply` replaces the model of `filter` with the one for `dt`, keeping
+    /// its estimate, e.g. before predicting over an irregular time step.
+    fn apply<T: Matrix>(&self, filter: &mut DynamicKalmanFilter<T>, dt: f64) {
+        filter.transition = self.transition(dt);
+        filter.observation = self.observation(dt);
+        filter.process_noise = self.process_noise(dt);
+        filter.measurement_noise = self.measurement_noise(dt);
+    }
+
+    /// `filter` creates a filter for time steps `dt` starting at a zero state
+    /// with identity covariance.
+    fn filter<T: Matrix>(&self, dt: f64) -> DynamicKalmanFilter<T> {
+        let transition: T = self.transition(dt);
+        let states = transition.shape().0;
+        DynamicKalmanFilter {
+            state: T::zeros(states, 1),
+            covariance: T::identity(states),
+            transition,
+            process_noise: self.process_noise(dt),
+            observation: self.observation(dt),
+            measurement_noise: self.measurement_noise(dt)
+        }
+    }
+}
+
+/// `state_space!` defines a struct with an `f64` field for every parameter
+/// and implements `SystemModel` for it. Inside the matrices the time step is
+/// bound to the first name in parentheses and every parameter to its value.
+///
+/// See the [module documentation](crate::model) for an example.
+///
+/// # Panics:
+///
+/// The generated methods panic if the rows of a matrix differ in length.
+#[macro_export]
+macro_rules! state_space {
+    (@matrix [$($($value:expr),+);+]) => {
+        T::from_row_slices(&[$(&[$(f64::from($value)),+]),+])
+    };
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident($dt:ident $(; $($param:ident),+)?) {
+            F = $transition:tt;
+            H = $observation:tt;
+            Q = $process_noise:tt;
+            R = $measurement_noise:tt;
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, Debug, Default, PartialEq)]
+        $vis struct $name {
+            $($(pub $param: f64),+)?
+        }
+
+        #[allow(unused_variables)]
+        impl $crate::model::SystemModel for $name {
+            fn transition<T: $crate::matrix::Matrix>(&self, $dt: f64) -> T {
+                $($(let $param = self.$param;)+)?
+                $crate::state_space!(@matrix $transition)
+            }
+
+            fn observation<T: $crate::matrix::Matrix>(&self, $dt: f64) -> T {
+                $($(let $param = self.$param;)+)?
+                $crate::state_space!(@matrix $observation)
+            }
+
+            fn process_noise<T: $crate::matrix::Matrix>(&self, $dt: f64) -> T {
+                $($(let $param = self.$param;)+)?
+                $crate::state_space!(@matrix $process_noise)
+            }
+
+            fn measurement_noise<T: $crate::matrix::Matrix>(&self, $dt: f64) -> T {
+                $($(let $param = self.$param;)+)?
+                $crate::state_space!(@matrix $measurement_noise)
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::DenseMatrix;
+
+    state_space! {
+        struct RandomWalk(dt; q) {
+            F = [1.0];
+            H = [1.0; 2.0];
+            Q = [q * dt];
+            R = [1.0, 0.0;
+                 0.0, 4.0];
+        }
+    }
+
+    #[test]
+    fn defines_model() {
+        let model = RandomWalk { q: 0.5 };
+        let mut filter = DynamicKalmanFilter::<DenseMatrix>::new(1, 2);
+        filter.observation = DenseMatrix::vector(&[1.0, 2.0]);
+        filter.process_noise = DenseMatrix::vector(&[1.0]);
+        filter.measurement_noise = DenseMatrix::from_row_slices(&[&[1.0, 0.0], &[0.0, 4.0]]);
+        assert_eq!(model.filter::<DenseMatrix>(2.0), filter);
+
+        model.apply(&mut filter, 4.0);
+        assert_eq!(filter.process_noise.get(0, 0), 2.0);
+    }
+}
@@ -0,0 +1,275 @@
+//! Terminal dashboard of a running filter.
+//!
+//! With the `tui` feature, a `Monitor` follows the steps of a scalar filter
+//! and draws them as a `ratatui` widget: the current estimate and its
+//! variance, a chart of the latest innovations normalized by their standard
+//! deviation, and health flags from the `diagnostics` checks. `run` takes
+//! over the terminal and redraws a monitor shared with the thread running
+//! the filter until `q` or `Esc` is pressed.
+//!
+//! The `lqe-monitor` binary filters numbers read from standard input this
+//! way, one measurement per line, optionally followed by its variance:
+//!
+//! ```text
+//! sensor | lqe-monitor --process-noise 0.01 --variance 0.25
+//! ```
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::{step, LQE};
+//! use lqe::monitor::Monitor;
+//!
+//! let mut monitor = Monitor::new("altitude", 100);
+//! let mut estimate = LQE { measurement: 0.0, variance: 10.0 };
+//! for z in &[1.0, 1.2, 0.9] {
+//!     let info = step::update(step::predict(estimate, 0.0, 0.1), *z, 0.5);
+//!     estimate = info.posterior;
+//!     monitor.observe(&info);
+//! }
+//! // Inside `Terminal::draw`: `frame.render_widget(&monitor, frame.area());`
+//! monitor.health().finite;
+//! // => true
+//! ```
+
+use std::collections::VecDeque;
+use std::io;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use ratatui::buffer::Buffer;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Style};
+use ratatui::symbols::Marker;
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Axis, Block, Borders, Chart, Dataset, GraphType, Paragraph, Widget};
+
+use crate::diagnostics::{Change, Cusum, NisMonitor, Shift};
+use crate::step::StepInfo;
+use crate::LQE;
+
+/// `Health` is the state of the checks run on a monitored filter.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Health {
+    /// Whether the average NIS of the latest window is within its bounds,
+    /// i.e. the innovations match their predicted variance.
+    pub consistent: bool,
+    /// The latest shift in the mean of the innovations, if any.
+    pub change: Option<Change>,
+    /// Whether the estimate and its variance are finite, the variance
+    /// non-negative.
+    pub finite: bool
+}
+
+/// `Monitor` keeps the latest estimate and the last `capacity` normalized
+/// innovations of a filter named `name`, checking their NIS over windows of
+/// 20 steps and their mean with a `Cusum`.
+pub struct Monitor {
+    pub name: String,
+    capacity: usize,
+    estimate: Option<LQE>,
+    innovations: VecDeque<f64>,
+    steps: u64,
+    nis: NisMonitor,
+    cusum: Cusum,
+    change: Option<Change>
+}
+
+impl Monitor {
+    /// `new` creates a monitor without any steps yet.
+    pub fn new(name: impl Into<String>, capacity: usize) -> Self {
+        Monitor {
+            name: name.into(),
+            capacity,
+            estimate: None,
+            innovations: VecDeque::with_capacity(capacity),
+            steps: 0,
+            nis: NisMonitor::new(20, 1, 0.95),
+            cusum: Cusum::new(0.5, 5.0),
+            change: None
+        }
+    }
+
+    /// `observe` records a step, e.g. from an observer registered with
+    /// `TimedFilter::set_observer`.
+    pub fn observe(&mut self, step: &StepInfo) {
+        self.estimate = Some(step.posterior);
+        if self.capacity > 0 {
+            if self.innovations.len() == self.capacity {
+                self.innovations.pop_front();
+            }
+            self.innovations.push_back(step.innovation / step.innovation_variance.sqrt());
+        }
+        self.nis.observe(step);
+        if let Some(change) = self.cusum.observe(step) {
+            self.change = Some(change);
+        }
+        self.steps += 1;
+    }
+
+    /// `predict` records an `estimate` predicted without a measurement.
+    pub fn predict(&mut self, estimate: LQE) {
+        self.estimate = Some(estimate);
+    }
+
+    /// `estimate` returns the latest estimate, or `None` before the first.
+    pub fn estimate(&self) -> Option<LQE> {
+        self.estimate
+    }
+
+    /// `steps` returns the number of steps observed.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// `health` returns the state of the checks.
+    pub fn health(&self) -> Health {
+        let finite = self.estimate.is_none_or(|estimate| {
+            estimate.measurement.is_finite() && estimate.variance.is_finite() && estimate.variance >= 0.0
+        });
+        Health {
+            consistent: self.nis.healthy(),
+            change: self.change,
+            finite
+        }
+    }
+}
+
+/// `flag` returns a span reading `label`, green when `ok` and red otherwise.
+fn flag(label: String, ok: bool) -> Span<'static> {
+    let color = if ok { Color::Green } else { Color::Red };
+    Span::styled(format!(" {} ", label), Style::default().fg(Color::Black).bg(color))
+}
+
+impl Widget for &Monitor {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let [summary, chart, health] =
+            Layout::vertical([Constraint::Length(3), Constraint::Min(6), Constraint::Length(3)]).areas(area);
+
+        let text = match self.estimate {
+            Some(estimate) => format!(
+                "estimate {:.4}   variance {:.4}   σ {:.4}   steps {}",
+                estimate.measurement,
+                estimate.variance,
+                estimate.variance.sqrt(),
+                self.steps
+            ),
+            None => "waiting for the first step".to_string()
+        };
+        Paragraph::new(text)
+            .block(Block::default().borders(Borders::ALL).title(self.name.as_str()))
+            .render(summary, buf);
+
+        // Normalized innovations are mostly within ±3 when the filter is
+        // consistent, so fix the axis just beyond and let outliers clip.
+        let first = self.steps - self.innovations.len() as u64;
+        let points: Vec<(f64, f64)> = self
+            .innovations
+            .iter()
+            .enumerate()
+            .map(|(i, innovation)| ((first + i as u64) as f64, innovation.clamp(-4.0, 4.0)))
+            .collect();
+        let end = (first + self.capacity.max(1) as u64) as f64;
+        let dataset = Dataset::default()
+            .marker(Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(Color::Cyan))
+            .data(&points);
+        Chart::new(vec![dataset])
+            .block(Block::default().borders(Borders::ALL).title("normalized innovations"))
+            .x_axis(Axis::default().bounds([first as f64, end]))
+            .y_axis(Axis::default().bounds([-4.0, 4.0]).labels(["-4σ", "0", "4σ"]))
+            .render(chart, buf);
+
+        let state = self.health();
+        let (lower, upper) = self.nis.bounds();
+        let nis = match self.nis.average() {
+            Some(average) => format!("NIS {:.2} in [{:.2}, {:.2}]", average, lower, upper),
+            None => "NIS pending".to_string()
+        };
+        let shift = match state.change {
+            Some(Change {
+                shift: Shift::Up,
+                detected,
+                ..
+            }) => format!("shifted up at step {}", detected),
+            Some(Change {
+                shift: Shift::Down,
+                detected,
+                ..
+            }) => format!("shifted down at step {}", detected),
+            None => "no shift".to_string()
+        };
+        let flags = Line::from(vec![
+            flag(nis, state.consistent),
+            Span::raw(" "),
+            flag(shift, state.change.is_none()),
+            Span::raw(" "),
+            flag(if state.finite { "finite".to_string() } else { "diverged".to_string() }, state.finite)
+        ]);
+        Paragraph::new(flags)
+            .block(Block::default().borders(Borders::ALL).title("health"))
+            .render(health, buf);
+    }
+}
+
+/// `run` draws `monitor` full screen every `refresh` until `q` or `Esc` is
+/// pressed, restoring the terminal when it returns.
+pub fn run(monitor: &Mutex<Monitor>, refresh: Duration) -> io::Result<()> {
+    let mut terminal = ratatui::try_init()?;
+    let result = (|| loop {
+        terminal.draw(|frame| frame.render_widget(&*monitor.lock().unwrap(), frame.area()))?;
+        if event::poll(refresh)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) {
+                    return Ok(());
+                }
+            }
+        }
+    })();
+    ratatui::restore();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particle::Random;
+    use crate::step;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    #[test]
+    fn flags_shift_and_renders() {
+        let mut monitor = Monitor::new("level", 50);
+        let mut random = Random::new(9);
+        // An initial estimate as uncertain as it claims.
+        let mut estimate = LQE {
+            measurement: random.normal(),
+            variance: 1.0
+        };
+        for n in 0..100 {
+            // The level jumps by ten standard deviations after 60 steps.
+            let level = if n < 60 { 0.0 } else { 10.0 };
+            let info = step::update(step::predict(estimate, 0.0, 1e-4), level + random.normal(), 1.0);
+            estimate = info.posterior;
+            monitor.observe(&info);
+            if n == 59 {
+                assert!(monitor.health().change.is_none());
+                assert!(monitor.health().consistent);
+            }
+        }
+        let health = monitor.health();
+        assert_eq!(health.change.map(|change| change.shift), Some(Shift::Up));
+        assert!(!health.consistent);
+        assert!(health.finite);
+        assert_eq!((monitor.steps(), monitor.innovations.len()), (100, 50));
+
+        let mut terminal = Terminal::new(TestBackend::new(80, 20)).unwrap();
+        terminal.draw(|frame| frame.render_widget(&monitor, frame.area())).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect();
+        assert!(screen.contains("level"));
+        assert!(screen.contains("shifted up"));
+    }
+}
@@ -0,0 +1,200 @@
+//! MQTT ingestion of sensor streams.
+//!
+//! With the `mqtt` feature enabled an `MqttSmoother` subscribes to sensor
+//! topics through a `rumqttc` client, maps every message published to them
+//! to a `Measurement` with a closure, folds it into a `TimedFilter` and
+//! publishes the estimate to an output topic as
+//! `{"measurement":<mean>,"variance":<variance>}`. Measurements from several
+//! topics are fused into the one estimate; run a smoother per topic to smooth
+//! them separately.
+//!
+//! # Example:
+//!
+//! ```no_run
+//! use std::time::Instant;
+//! use lqe::LQE;
+//! use lqe::measurement::{Measurement, SensorId};
+//! use lqe::mqtt::MqttSmoother;
+//! use lqe::timed::TimedFilter;
+//! use rumqttc::{Client, MqttOptions};
+//!
+//! let (client, mut connection) = Client::new(MqttOptions::new("lqe", "localhost", 1883), 10);
+//! let filter = TimedFilter::new(LQE { measurement: 20.0, variance: 100.0 }, 0.01);
+//! let mut smoother = MqttSmoother::new(filter, "greenhouse/temperature/estimate", |_topic: &str, payload: &[u8]| {
+//!     let value = std::str::from_utf8(payload).ok()?.trim().parse().ok()?;
+//!     Some(Measurement { timestamp: Instant::now(), value, variance: 0.25, sensor_id: SensorId(1) })
+//! });
+//! smoother.run(&client, &mut connection, &["greenhouse/temperature"]).unwrap();
+//! ```
+
+use std::error;
+use std::fmt;
+
+use rumqttc::{Client, ClientError, Connection, ConnectionError, Event, Packet, QoS};
+
+use crate::measurement::Measurement;
+use crate::timed::{TimedFilter, Timestamp};
+use crate::LQE;
+
+/// `MqttError` describes why an `MqttSmoother` stopped.
+#[derive(Debug)]
+pub enum MqttError {
+    /// A request could not be passed to the client's event loop.
+    Client(ClientError),
+    /// The connection to the broker failed.
+    Connection(Box<ConnectionError>)
+}
+
+impl fmt::Display for MqttError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MqttError::Client(error) => write!(f, "mqtt client: {}", error),
+            MqttError::Connection(error) => write!(f, "mqtt connection: {}", error)
+        }
+    }
+}
+
+impl error::Error for MqttError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            MqttError::Client(error) => Some(error),
+            MqttError::Connection(error) => Some(error.as_ref())
+        }
+    }
+}
+
+impl From<ClientError> for MqttError {
+    fn from(error: ClientError) -> Self {
+        MqttError::Client(error)
+    }
+}
+
+impl From<ConnectionError> for MqttError {
+    fn from(error: ConnectionError) -> Self {
+        MqttError::Connection(Box::new(error))
+    }
+}
+
+/// `MqttSmoother` folds the messages `map` turns into measurements into
+/// `filter` and publishes the estimates to `output_topic` with `qos`.
+pub struct MqttSmoother<T, F> {
+    pub filter: TimedFilter<T>,
+    pub output_topic: String,
+    pub qos: QoS,
+    map: F
+}
+
+impl<T, F> MqttSmoother<T, F>
+where
+    T: Timestamp,
+    F: FnMut(&str, &[u8]) -> Option<Measurement<T>>
+{
+    /// `new` creates a smoother publishing at most once. `map` receives the
+    /// topic and payload of every message and returns `None` for those that
+    /// aren't measurements.
+    pub fn new(filter: TimedFilter<T>, output_topic: impl Into<String>, map: F) -> Self {
+        MqttSmoother {
+            filter,
+            output_topic: output_topic.into(),
+            qos: QoS::AtMostOnce,
+            map
+        }
+    }
+
+    /// `handle` folds a message published to `topic` into the estimate,
+    /// returning the new estimate if it was a measurement.
+    pub fn handle(&mut self, topic: &str, payload: &[u8]) -> Option<LQE> {
+        let measurement = (self.map)(topic, payload)?;
+        Some(self.filter.apply(&measurement))
+    }
+
+    /// `run` subscribes to `topics` and publishes an estimate for every
+    /// measurement received until the connection fails. The client
+    /// reconnects if `connection` is polled after an error, so `run` can be
+    /// called again to resume.
+    pub fn run(&mut self, client: &Client, connection: &mut Connection, topics: &[&str]) -> Result<(), MqttError> {
+        for topic in topics {
+            client.subscribe(*topic, self.qos)?;
+        }
+        for event in connection.iter() {
+            if let Event::Incoming(Packet::Publish(publish)) = event? {
+                if let Some(estimate) = self.handle(&publish.topic, &publish.payload) {
+                    client.publish(self.output_topic.as_str(), self.qos, false, payload(&estimate))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `payload` formats `estimate` as the JSON object an `MqttSmoother`
+/// publishes. JSON has no infinities or NaN, so non-finite values, e.g. of a
+/// diverged filter, are published as `null`.
+pub fn payload(estimate: &LQE) -> String {
+    format!(
+        "{{\"measurement\":{},\"variance\":{}}}",
+        json_number(estimate.measurement),
+        json_number(estimate.variance)
+    )
+}
+
+fn json_number(value: f64) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::measurement::SensorId;
+
+    #[test]
+    fn smooths_mapped_messages() {
+        let filter = TimedFilter::new(
+            LQE {
+                measurement: 7.0,
+                variance: 2.0
+            },
+            0.5
+        );
+        let mut smoother = MqttSmoother::new(filter, "estimate", |topic: &str, payload: &[u8]| {
+            let sensor_id = match topic {
+                "sensors/a" => SensorId(1),
+                "sensors/b" => SensorId(2),
+                _ => return None
+            };
+            let mut fields = std::str::from_utf8(payload).ok()?.split(',');
+            let timestamp: f64 = fields.next()?.parse().ok()?;
+            let value = fields.next()?.parse().ok()?;
+            Some(Measurement {
+                timestamp,
+                value,
+                variance: 2.0,
+                sensor_id
+            })
+        });
+
+        let estimate = smoother.handle("sensors/a", b"0.0,10.0").unwrap();
+        assert_eq!(estimate.result(), (8.5, 1.0));
+        assert_eq!(payload(&estimate), "{\"measurement\":8.5,\"variance\":1}");
+        assert_eq!(smoother.handle("sensors/a", b"garbage"), None);
+        assert_eq!(smoother.handle("other", b"1.0,10.0"), None);
+        // Both sensors feed the same estimate, which has now drifted for 2
+        // seconds.
+        let estimate = smoother.handle("sensors/b", b"2.0,8.5").unwrap();
+        assert_eq!(estimate.result(), (8.5, 1.0));
+    }
+
+    #[test]
+    fn publishes_non_finite_values_as_null() {
+        let estimate = LQE {
+            measurement: f64::NAN,
+            variance: f64::INFINITY
+        };
+
+        assert_eq!(payload(&estimate), "{\"measurement\":null,\"variance\":null}");
+    }
+}
@@ -0,0 +1,402 @@
+//! Multiple-model estimation.
+//!
+//! When it isn't known which of several models describes a system, e.g.
+//! whether a target is cruising or manoeuvring, or which noise level a sensor
+//! runs at, a `FilterBank` runs one filter per candidate model side by side
+//! and weighs each by how well it has predicted the measurements so far
+//! (Magill's filter bank). The weights are the posterior probabilities of the
+//! models, so the bank identifies the model online.
+//!
+//! When the system switches between the models instead, with Markov
+//! `transitions` between them, `Imm` (interacting multiple model) and `Gpb2`
+//! (second-order generalized pseudo-Bayesian) track both the estimate and
+//! the probability of each mode. `Imm` mixes the mode estimates before
+//! filtering and runs one filter per mode; `Gpb2` filters every pair of
+//! previous and current modes and merges afterwards, which costs a filter
+//! per pair but approximates the exact mixture more closely.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::matrix::{DenseMatrix, Matrix};
+//! use lqe::multiple_model::FilterBank;
+//! use lqe::multivariate::DynamicKalmanFilter;
+//!
+//! // Is the sensor noise variance 0.1 or 10?
+//! let model = |noise: f64| {
+//!     let mut filter = DynamicKalmanFilter::<DenseMatrix>::new(1, 1);
+//!     filter.measurement_noise = DenseMatrix::identity(1).scale(noise);
+//!     filter
+//! };
+//! let mut bank = FilterBank::new(vec![model(0.1), model(10.0)]);
+//! for z in &[4.0, -3.0, 5.0, -4.5] {
+//!     bank.next(&DenseMatrix::vector(&[*z])).unwrap();
+//! }
+//! bank.most_likely();
+//! // => 1
+//! ```
+
+use crate::error::Error;
+use crate::matrix::Matrix;
+use crate::mixture::{self, Component, GaussianSumFilter};
+use crate::multivariate::DynamicKalmanFilter;
+
+/// `FilterBank` runs a filter per candidate model, weighted by the posterior
+/// probability of the model.
+///
+/// The models never exchange estimates, so the bank assumes the true model
+/// stays the same throughout; a model that has ruled itself out stays ruled
+/// out.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FilterBank<T> {
+    pub models: GaussianSumFilter<T>
+}
+
+impl<T: Matrix> FilterBank<T> {
+    /// `new` creates a bank of `models` that are equally likely to begin
+    /// with.
+    pub fn new(models: Vec<DynamicKalmanFilter<T>>) -> Self {
+        let weight = 1.0 / models.len() as f64;
+        FilterBank {
+            models: GaussianSumFilter {
+                components: models.into_iter().map(|filter| Component { weight, filter }).collect()
+            }
+        }
+    }
+
+    /// `next` performs the entire predict - update cycle of every model and
+    /// reweighs them by the likelihood of `measurement` under each.
+    pub fn next(&mut self, measurement: &T) -> Result<(), Error> {
+        self.models.next(measurement)
+    }
+
+    /// `probabilities` returns the posterior probability of each model, in
+    /// the order they were given.
+    pub fn probabilities(&self) -> Vec<f64> {
+        self.models.components.iter().map(|component| component.weight).collect()
+    }
+
+    /// `most_likely` returns the index of the most probable model.
+    pub fn most_likely(&self) -> usize {
+        let components = &self.models.components;
+        (0..components.len())
+            .max_by(|&i, &j| components[i].weight.total_cmp(&components[j].weight))
+            .unwrap_or(0)
+    }
+
+    /// `result` returns the mean and covariance of the estimate, averaged
+    /// over the models by their probabilities.
+    pub fn result(&self) -> (T, T) {
+        mixture::moments(self.models.components.iter())
+    }
+}
+
+/// `Imm` tracks a system switching between the models of its `modes` by
+/// the interacting multiple model algorithm.
+///
+/// `transitions[i][j]` is the probability of moving from mode `i` to mode `j`
+/// in one step, so each row sums to one. The weight of each mode is its
+/// posterior probability and its filter the estimate conditioned on it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Imm<T> {
+    pub modes: Vec<Component<T>>,
+    pub transitions: Vec<Vec<f64>>
+}
+
+impl<T: Matrix> Imm<T> {
+    /// `new` switches between `models` with `transitions`, starting out with
+    /// every mode equally likely.
+    ///
+    /// # Panics:
+    ///
+    /// Panics unless `transitions` is square with a row per model.
+    pub fn new(models: Vec<DynamicKalmanFilter<T>>, transitions: Vec<Vec<f64>>) -> Self {
+        Imm {
+            modes: equally_likely(models, &transitions),
+            transitions
+        }
+    }
+
+    /// `next` performs one step for `measurement`: each mode starts from the
+    /// mix of the previous mode estimates it may have switched from, runs
+    /// its predict - update cycle and is reweighed by the likelihood of the
+    /// measurement.
+    pub fn next(&mut self, measurement: &T) -> Result<(), Error> {
+        let mut log_weights = Vec::with_capacity(self.modes.len());
+        let mut filters = Vec::with_capacity(self.modes.len());
+        for (j, mode) in self.modes.iter().enumerate() {
+            let mixing = self
+                .modes
+                .iter()
+                .zip(&self.transitions)
+                .map(|(from, row)| (from.weight * row[j]).ln())
+                .collect::<Vec<_>>();
+            let (state, covariance, log_predicted) = merge(&self.modes, &mixing);
+            let mut filter = DynamicKalmanFilter {
+                state,
+                covariance,
+                ..mode.filter.clone()
+            };
+            filter.predict();
+            log_weights.push(log_predicted + filter.log_likelihood(measurement)?);
+            filter.update(measurement)?;
+            filters.push(filter);
+        }
+        for ((mode, filter), weight) in self.modes.iter_mut().zip(filters).zip(normalize(&log_weights)) {
+            mode.filter = filter;
+            mode.weight = weight;
+        }
+        Ok(())
+    }
+
+    /// `probabilities` returns the posterior probability of each mode.
+    pub fn probabilities(&self) -> Vec<f64> {
+        self.modes.iter().map(|mode| mode.weight).collect()
+    }
+
+    /// `result` returns the mean and covariance of the estimate over all
+    /// modes.
+    pub fn result(&self) -> (T, T) {
+        mixture::moments(self.modes.iter())
+    }
+}
+
+/// `Gpb2` tracks a system switching between the models of its `modes` by
+/// the second-order generalized pseudo-Bayesian algorithm.
+///
+/// `modes` and `transitions` are as for `Imm`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gpb2<T> {
+    pub modes: Vec<Component<T>>,
+    pub transitions: Vec<Vec<f64>>
+}
+
+impl<T: Matrix> Gpb2<T> {
+    /// `new` switches between `models` with `transitions`, starting out with
+    /// every mode equally likely.
+    ///
+    /// # Panics:
+    ///
+    /// Panics unless `transitions` is square with a row per model.
+    pub fn new(models: Vec<DynamicKalmanFilter<T>>, transitions: Vec<Vec<f64>>) -> Self {
+        Gpb2 {
+            modes: equally_likely(models, &transitions),
+            transitions
+        }
+    }
+
+    /// `next` performs one step for `measurement`: every previous mode
+    /// estimate runs the predict - update cycle of every mode, and the
+    /// results ending in the same mode are merged, weighed by the
+    /// probability of the switch and the likelihood of the measurement.
+    pub fn next(&mut self, measurement: &T) -> Result<(), Error> {
+        let mut merged = Vec::with_capacity(self.modes.len());
+        let mut log_weights = Vec::with_capacity(self.modes.len());
+        for (j, mode) in self.modes.iter().enumerate() {
+            let mut branches = Vec::with_capacity(self.modes.len());
+            let mut branch_weights = Vec::with_capacity(self.modes.len());
+            for (from, row) in self.modes.iter().zip(&self.transitions) {
+                let mut filter = DynamicKalmanFilter {
+                    state: from.filter.state.clone(),
+                    covariance: from.filter.covariance.clone(),
+                    ..mode.filter.clone()
+                };
+                filter.predict();
+                branch_weights.push((from.weight * row[j]).ln() + filter.log_likelihood(measurement)?);
+                filter.update(measurement)?;
+                branches.push(Component { weight: 0.0, filter });
+            }
+            let (state, covariance, log_weight) = merge(&branches, &branch_weights);
+            merged.push(DynamicKalmanFilter {
+                state,
+                covariance,
+                ..mode.filter.clone()
+            });
+            log_weights.push(log_weight);
+        }
+        for ((mode, filter), weight) in self.modes.iter_mut().zip(merged).zip(normalize(&log_weights)) {
+            mode.filter = filter;
+            mode.weight = weight;
+        }
+        Ok(())
+    }
+
+    /// `probabilities` returns the posterior probability of each mode.
+    pub fn probabilities(&self) -> Vec<f64> {
+        self.modes.iter().map(|mode| mode.weight).collect()
+    }
+
+    /// `result` returns the mean and covariance of the estimate over all
+    /// modes.
+    pub fn result(&self) -> (T, T) {
+        mixture::moments(self.modes.iter())
+    }
+}
+
+/// `equally_likely` makes a mode of each of `models` with the same
+/// probability, checking that `transitions` fits them.
+fn equally_likely<T>(models: Vec<DynamicKalmanFilter<T>>, transitions: &[Vec<f64>]) -> Vec<Component<T>> {
+    assert!(
+        transitions.len() == models.len() && transitions.iter().all(|row| row.len() == models.len()),
+        "transitions must be a {0}x{0} matrix",
+        models.len()
+    );
+    let weight = 1.0 / models.len() as f64;
+    models.into_iter().map(|filter| Component { weight, filter }).collect()
+}
+
+/// `merge` returns the mean and covariance of the mixture of the estimates
+/// of `components` with the unnormalized `log_weights` in place of their
+/// own weights, and the logarithm of the total weight.
+///
+/// Mixing and merging in log space keeps modes whose every estimate has a
+/// vanishing weight from dividing zero by zero; they merge with equal
+/// weights instead. A mode no estimate can reach at all, with every weight
+/// zero, has a total weight of zero.
+fn merge<T: Matrix>(components: &[Component<T>], log_weights: &[f64]) -> (T, T, f64) {
+    let largest = log_weights.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let weights = normalize(log_weights);
+    let weighted = components
+        .iter()
+        .zip(weights)
+        .map(|(component, weight)| Component {
+            weight,
+            filter: component.filter.clone()
+        })
+        .collect::<Vec<_>>();
+    let (state, covariance) = mixture::moments(weighted.iter());
+    if largest == f64::NEG_INFINITY {
+        return (state, covariance, f64::NEG_INFINITY);
+    }
+    let total = log_weights.iter().map(|weight| relative(*weight, largest)).sum::<f64>();
+    (state, covariance, largest + total.ln())
+}
+
+/// `normalize` turns unnormalized `log_weights` into weights summing to one,
+/// or equal weights if they are all zero. NaN weights count as zero.
+fn normalize(log_weights: &[f64]) -> Vec<f64> {
+    let largest = log_weights.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    if largest == f64::NEG_INFINITY {
+        return vec![1.0 / log_weights.len() as f64; log_weights.len()];
+    }
+    let total = log_weights.iter().map(|weight| relative(*weight, largest)).sum::<f64>();
+    log_weights.iter().map(|weight| relative(*weight, largest) / total).collect()
+}
+
+/// `relative` returns the weight of `log_weight` relative to the finite
+/// `largest`, zero if it is NaN.
+fn relative(log_weight: f64, largest: f64) -> f64 {
+    if log_weight.is_nan() {
+        0.0
+    } else {
+        (log_weight - largest).exp()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::DenseMatrix;
+    use crate::particle::Random;
+
+    fn velocity_model(velocity: f64) -> DynamicKalmanFilter<DenseMatrix> {
+        // A position drifting at a constant, known velocity.
+        DynamicKalmanFilter {
+            state: DenseMatrix::vector(&[0.0, velocity]),
+            covariance: DenseMatrix::from_row_slices(&[&[0.01, 0.0], &[0.0, 0.0]]),
+            transition: DenseMatrix::from_row_slices(&[&[1.0, 1.0], &[0.0, 1.0]]),
+            process_noise: DenseMatrix::from_row_slices(&[&[0.01, 0.0], &[0.0, 0.0]]),
+            observation: DenseMatrix::from_row_slices(&[&[1.0, 0.0]]),
+            measurement_noise: DenseMatrix::identity(1).scale(0.25)
+        }
+    }
+
+    #[test]
+    fn identifies_generating_model() {
+        let mut random = Random::new(4);
+        let mut bank = FilterBank::new(vec![velocity_model(0.0), velocity_model(0.5), velocity_model(1.0)]);
+
+        for t in 1..=30 {
+            let position = 0.5 * t as f64 + 0.5 * random.normal();
+            bank.next(&DenseMatrix::vector(&[position])).unwrap();
+        }
+        let probabilities = bank.probabilities();
+        assert_eq!(bank.most_likely(), 1);
+        assert!(probabilities[1] > 0.99);
+        assert!((probabilities.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+        assert!((bank.result().0.get(1, 0) - 0.5).abs() < 0.01);
+    }
+
+    fn random_walk(noise: f64) -> DynamicKalmanFilter<DenseMatrix> {
+        let mut filter = DynamicKalmanFilter::new(1, 1);
+        filter.process_noise = DenseMatrix::identity(1).scale(noise);
+        filter.measurement_noise = DenseMatrix::identity(1).scale(0.1);
+        filter
+    }
+
+    fn switching() -> Vec<Vec<f64>> {
+        vec![vec![0.95, 0.05], vec![0.05, 0.95]]
+    }
+
+    #[test]
+    fn reduces_to_kalman_filter_with_one_mode() {
+        let mut imm = Imm::new(vec![random_walk(0.5)], vec![vec![1.0]]);
+        let mut gpb2 = Gpb2::new(vec![random_walk(0.5)], vec![vec![1.0]]);
+        let mut expected = random_walk(0.5);
+
+        for z in &[1.0, 1.5, 0.8] {
+            let measurement = DenseMatrix::vector(&[*z]);
+            imm.next(&measurement).unwrap();
+            gpb2.next(&measurement).unwrap();
+            expected.next(&measurement).unwrap();
+        }
+        assert!((imm.result().0.get(0, 0) - expected.state.get(0, 0)).abs() < 1e-12);
+        assert!((gpb2.result().1.get(0, 0) - expected.covariance.get(0, 0)).abs() < 1e-12);
+        assert_eq!(imm.probabilities(), [1.0]);
+    }
+
+    #[test]
+    fn detects_mode_switch() {
+        let mut random = Random::new(2);
+        let mut imm = Imm::new(vec![random_walk(0.001), random_walk(4.0)], switching());
+        let mut gpb2 = Gpb2::new(vec![random_walk(0.001), random_walk(4.0)], switching());
+
+        let mut truth = 0.0;
+        for t in 0..60 {
+            // Quiet, then jumping around from step 40 on.
+            if t >= 40 {
+                truth += 2.0 * random.normal();
+            }
+            let measurement = DenseMatrix::vector(&[truth + 0.3 * random.normal()]);
+            imm.next(&measurement).unwrap();
+            gpb2.next(&measurement).unwrap();
+            if t == 39 {
+                assert!(imm.probabilities()[0] > 0.9 && gpb2.probabilities()[0] > 0.9);
+            }
+        }
+        assert!(imm.probabilities()[1] > 0.9 && gpb2.probabilities()[1] > 0.9);
+        assert!((imm.result().0.get(0, 0) - truth).abs() < 1.0);
+        assert!((gpb2.result().0.get(0, 0) - truth).abs() < 1.0);
+    }
+
+    #[test]
+    fn keeps_unreachable_mode_dead() {
+        // Once left, the quiet mode can't be reached again.
+        let transitions = vec![vec![0.95, 0.05], vec![0.0, 1.0]];
+        let mut random = Random::new(3);
+        let mut imm = Imm::new(vec![random_walk(0.001), random_walk(4.0)], transitions.clone());
+        let mut gpb2 = Gpb2::new(vec![random_walk(0.001), random_walk(4.0)], transitions);
+
+        let mut truth = 0.0;
+        for _ in 0..40 {
+            truth += 20.0 * random.normal();
+            let measurement = DenseMatrix::vector(&[truth + 0.3 * random.normal()]);
+            imm.next(&measurement).unwrap();
+            gpb2.next(&measurement).unwrap();
+        }
+        assert_eq!(imm.probabilities(), [0.0, 1.0]);
+        assert_eq!(gpb2.probabilities(), [0.0, 1.0]);
+        assert!((imm.result().0.get(0, 0) - truth).abs() < 1.0);
+        assert!((gpb2.result().0.get(0, 0) - truth).abs() < 1.0);
+    }
+}
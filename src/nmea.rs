@@ -0,0 +1,356 @@
+//! NMEA 0183 GPS sentence ingestion.
+//!
+//! `Sentence::parse` reads the two sentences nearly every receiver emits:
+//!
+//! - `GGA` carries the position, altitude and horizontal dilution of
+//!   precision (HDOP) of a fix.
+//! - `RMC` carries the position, speed over ground and course of a fix.
+//!
+//! Latitudes and longitudes come out in signed decimal degrees rather than
+//! the `ddmm.mmmm` and hemisphere fields of the sentence, speeds in m/s
+//! rather than knots and times in seconds since midnight UTC.
+//!
+//! `NmeaAdapter` feeds them to a `Tracker3D` in metres north, east and down
+//! (NED) of an origin, the first fix unless set with `with_origin`. A `GGA`
+//! updates the position with a variance of `(HDOP × UERE)²`, where the user
+//! equivalent range error (UERE) is the standard deviation of the range to a
+//! single satellite. An `RMC` updates the horizontal velocity; its position
+//! is left to the `GGA` of the same fix, which also knows the altitude and
+//! accuracy.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::nmea::Sentence;
+//!
+//! match Sentence::parse("$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47") {
+//!     Some(Sentence::Gga(fix)) => (fix.latitude, fix.longitude, fix.variance(5.0)),
+//!     _ => unreachable!()
+//! };
+//! // => (48.1173, 11.5167, 20.25)
+//! ```
+
+use crate::error::Error;
+use crate::tracking::Tracker3D;
+
+/// The mean radius of the earth in metres.
+const EARTH_RADIUS: f64 = 6_371_000.0;
+
+/// Metres per second in a knot.
+const KNOT: f64 = 1852.0 / 3600.0;
+
+/// Seconds in a day, after which the time of a sentence wraps around.
+const DAY: f64 = 86_400.0;
+
+/// `Gga` is a fix from a `GGA` sentence.
+///
+/// `altitude` is in metres above mean sea level and `hdop` is the horizontal
+/// dilution of precision.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Gga {
+    pub time: f64,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: f64,
+    pub satellites: u32,
+    pub hdop: f64
+}
+
+impl Gga {
+    /// `variance` returns the variance in m² of the position in each axis,
+    /// given the user equivalent range error `uere` in metres.
+    pub fn variance(&self, uere: f64) -> f64 {
+        (self.hdop * uere).powi(2)
+    }
+}
+
+/// `Rmc` is a fix from an `RMC` sentence.
+///
+/// `speed` is the speed over ground in m/s and `course` the direction of
+/// travel in degrees clockwise from true north, which receivers leave out
+/// when standing still.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Rmc {
+    pub time: f64,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub speed: f64,
+    pub course: Option<f64>
+}
+
+impl Rmc {
+    /// `velocity` returns the velocity north and east in m/s, if the
+    /// direction of travel is known.
+    pub fn velocity(&self) -> Option<(f64, f64)> {
+        match self.course {
+            Some(course) => {
+                let (sin, cos) = course.to_radians().sin_cos();
+                Some((self.speed * cos, self.speed * sin))
+            }
+            None if self.speed == 0.0 => Some((0.0, 0.0)),
+            None => None
+        }
+    }
+}
+
+/// `Sentence` is a parsed NMEA sentence carrying a valid fix.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Sentence {
+    Gga(Gga),
+    Rmc(Rmc)
+}
+
+impl Sentence {
+    /// `parse` reads a `GGA` or `RMC` sentence from any talker, e.g.
+    /// `$GPGGA` or `$GNRMC`. It returns `None` for other sentences, malformed
+    /// ones, ones whose checksum doesn't match and ones without a fix.
+    pub fn parse(line: &str) -> Option<Sentence> {
+        let body = line.trim().strip_prefix('$')?;
+        let body = match body.find('*') {
+            Some(star) => {
+                let checksum = u8::from_str_radix(&body[star + 1..], 16).ok()?;
+                let body = &body[..star];
+                if body.bytes().fold(0, |sum, byte| sum ^ byte) != checksum {
+                    return None;
+                }
+                body
+            }
+            None => body
+        };
+        let fields: Vec<&str> = body.split(',').collect();
+        let kind = fields[0].get(2..)?;
+        match kind {
+            "GGA" if fields.len() >= 10 => {
+                if fields[6].parse::<u32>().ok()? == 0 {
+                    return None;
+                }
+                Some(Sentence::Gga(Gga {
+                    time: time(fields[1])?,
+                    latitude: coordinate(fields[2], fields[3], 'N', 'S')?,
+                    longitude: coordinate(fields[4], fields[5], 'E', 'W')?,
+                    altitude: fields[9].parse().ok()?,
+                    satellites: fields[7].parse().unwrap_or(0),
+                    hdop: fields[8].parse().ok()?
+                }))
+            }
+            "RMC" if fields.len() >= 9 => {
+                if fields[2] != "A" {
+                    return None;
+                }
+                Some(Sentence::Rmc(Rmc {
+                    time: time(fields[1])?,
+                    latitude: coordinate(fields[3], fields[4], 'N', 'S')?,
+                    longitude: coordinate(fields[5], fields[6], 'E', 'W')?,
+                    speed: fields[7].parse::<f64>().ok()? * KNOT,
+                    course: fields[8].parse().ok()
+                }))
+            }
+            _ => None
+        }
+    }
+}
+
+/// `time` converts an `hhmmss.ss` field to seconds since midnight.
+fn time(field: &str) -> Option<f64> {
+    let hours: f64 = field.get(0..2)?.parse().ok()?;
+    let minutes: f64 = field.get(2..4)?.parse().ok()?;
+    let seconds: f64 = field.get(4..)?.parse().ok()?;
+    Some(hours * 3600.0 + minutes * 60.0 + seconds)
+}
+
+/// `coordinate` converts a `dddmm.mmmm` field and its hemisphere to signed
+/// decimal degrees, negative in the `negative` hemisphere.
+fn coordinate(field: &str, hemisphere: &str, positive: char, negative: char) -> Option<f64> {
+    let value: f64 = field.parse().ok()?;
+    let degrees = (value / 100.0).trunc() + (value % 100.0) / 60.0;
+    match hemisphere.chars().next()? {
+        c if c == positive => Some(degrees),
+        c if c == negative => Some(-degrees),
+        _ => None
+    }
+}
+
+/// `NmeaAdapter` feeds NMEA sentences to `tracker`.
+///
+/// `uere` is the user equivalent range error in metres. A speed over ground
+/// is measured with the `velocity_noise` of `tracker`, in (m/s)².
+#[derive(Clone, Debug, PartialEq)]
+pub struct NmeaAdapter {
+    pub tracker: Tracker3D,
+    pub uere: f64,
+    origin: Option<(f64, f64, f64)>,
+    time: Option<f64>
+}
+
+impl NmeaAdapter {
+    /// `new` creates an adapter whose origin is the first `GGA` fix.
+    pub fn new(tracker: Tracker3D) -> Self {
+        NmeaAdapter {
+            tracker,
+            uere: 5.0,
+            origin: None,
+            time: None
+        }
+    }
+
+    /// `with_origin` sets the origin of the local frame to a latitude and
+    /// longitude in degrees and an altitude in metres above mean sea level.
+    pub fn with_origin(mut self, latitude: f64, longitude: f64, altitude: f64) -> Self {
+        self.origin = Some((latitude, longitude, altitude));
+        self
+    }
+
+    /// `handle` parses `line` and folds it into the estimate, returning
+    /// whether it was used. Sentences `Sentence::parse` rejects are ignored,
+    /// as are `RMC` fixes before the first `GGA` sets the origin.
+    pub fn handle(&mut self, line: &str) -> Result<bool, Error> {
+        match Sentence::parse(line) {
+            Some(sentence) => self.apply(&sentence),
+            None => Ok(false)
+        }
+    }
+
+    /// `apply` folds a parsed `sentence` into the estimate, returning whether
+    /// it was used.
+    pub fn apply(&mut self, sentence: &Sentence) -> Result<bool, Error> {
+        match sentence {
+            Sentence::Gga(fix) => {
+                self.advance(fix.time);
+                let position = self.local(fix.latitude, fix.longitude, fix.altitude);
+                self.tracker.position_noise = fix.variance(self.uere);
+                self.tracker.update_position(position)?;
+                Ok(true)
+            }
+            Sentence::Rmc(fix) => {
+                let velocity = match (self.origin, fix.velocity()) {
+                    (Some(_), Some(velocity)) => velocity,
+                    _ => return Ok(false)
+                };
+                self.advance(fix.time);
+                self.tracker.update_velocity(&[velocity.0, velocity.1])?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// `advance` predicts the tracker forward to `time`, if it is later than
+    /// the last fix. Times wrap around at midnight, so a fix up to half a
+    /// day before the last one is taken to be late rather than a day ahead.
+    fn advance(&mut self, time: f64) {
+        if let Some(last) = self.time {
+            let dt = (time - last).rem_euclid(DAY);
+            if dt == 0.0 || dt > DAY / 2.0 {
+                return;
+            }
+            self.tracker.predict(dt);
+        }
+        self.time = Some(time);
+    }
+
+    /// `local` converts a latitude and longitude in degrees and altitude in
+    /// metres to metres NED of the origin, setting the origin first if there
+    /// is none.
+    fn local(&mut self, latitude: f64, longitude: f64, altitude: f64) -> (f64, f64, f64) {
+        let (origin_latitude, origin_longitude, origin_altitude) =
+            *self.origin.get_or_insert((latitude, longitude, altitude));
+        (
+            (latitude - origin_latitude).to_radians() * EARTH_RADIUS,
+            (longitude - origin_longitude).to_radians() * EARTH_RADIUS * origin_latitude.to_radians().cos(),
+            origin_altitude - altitude
+        )
+    }
+
+    /// `origin` returns the latitude, longitude and altitude of the origin
+    /// of the local frame, once known.
+    pub fn origin(&self) -> Option<(f64, f64, f64)> {
+        self.origin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::Matrix;
+    use crate::tracking::Motion;
+
+    #[test]
+    fn parses_gga_and_rmc() {
+        let gga = match Sentence::parse("$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47") {
+            Some(Sentence::Gga(gga)) => gga,
+            other => panic!("{:?}", other)
+        };
+        assert_eq!(gga.time, 12.0 * 3600.0 + 35.0 * 60.0 + 19.0);
+        assert!((gga.latitude - 48.1173).abs() < 1e-9);
+        assert!((gga.longitude - 11.516_666_666).abs() < 1e-6);
+        assert_eq!((gga.altitude, gga.satellites, gga.hdop), (545.4, 8, 0.9));
+
+        let rmc = match Sentence::parse("$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*6A") {
+            Some(Sentence::Rmc(rmc)) => rmc,
+            other => panic!("{:?}", other)
+        };
+        assert!((rmc.speed - 22.4 * KNOT).abs() < 1e-9);
+        let (north, east) = rmc.velocity().unwrap();
+        assert!((north.hypot(east) - rmc.speed).abs() < 1e-9);
+        assert!(east > 9.0 * north);
+
+        // A corrupted checksum, no fix and a void fix.
+        assert_eq!(Sentence::parse("$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*48"), None);
+        assert_eq!(Sentence::parse("$GPGGA,123519,,,,,0,00,,,M,,M,,*6B"), None);
+        assert_eq!(Sentence::parse("$GPRMC,123519,V,,,,,,,230394,,*33"), None);
+        assert_eq!(Sentence::parse("$GPGSA,A,3,,,,,,,,,,,,,2.5,1.3,2.1"), None);
+        assert_eq!(
+            coordinate("3345.000", "S", 'N', 'S').zip(coordinate("07030.000", "W", 'E', 'W')),
+            Some((-33.75, -70.5))
+        );
+    }
+
+    #[test]
+    fn tracks_fixes_in_local_frame() {
+        let mut adapter = NmeaAdapter::new(Tracker3D::new((0.0, 0.0, 0.0), Motion::ConstantVelocity, 1e6))
+            .with_origin(48.1173, 11.5, 545.4);
+        adapter.tracker.velocity_noise = 0.1;
+
+        // Without a fix to tie it to the local frame, the velocity is dropped.
+        let mut unanchored = NmeaAdapter::new(adapter.tracker.clone());
+        assert!(!unanchored
+            .handle("$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*6A")
+            .unwrap());
+
+        for _ in 0..100 {
+            assert!(adapter
+                .handle("$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47")
+                .unwrap());
+        }
+        assert!((adapter.tracker.position_noise - 20.25).abs() < 1e-9);
+        let (north, east, down) = adapter.tracker.position();
+        assert!(north.abs() < 0.1 && down.abs() < 0.1);
+        // A sixtieth of a degree of longitude at 48° north is about 1240 m.
+        assert!((east - 1240.0).abs() < 5.0);
+
+        assert!(adapter
+            .handle("$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*6A")
+            .unwrap());
+        let (north, east, _) = adapter.tracker.velocity();
+        assert!((east - 22.4 * KNOT).abs() < 1.0);
+        assert!(north.abs() < 2.0);
+        assert!(!adapter.handle("not a sentence").unwrap());
+    }
+
+    #[test]
+    fn wraps_times_at_midnight() {
+        let mut adapter = NmeaAdapter::new(Tracker3D::new((0.0, 0.0, 0.0), Motion::ConstantVelocity, 1.0));
+        adapter.advance(DAY - 1.0);
+        let before = adapter.tracker.filter.covariance.get(0, 0);
+        adapter.advance(1.0);
+        assert_eq!(adapter.time, Some(1.0));
+        // Two seconds, not a day, have passed.
+        let after = adapter.tracker.filter.covariance.get(0, 0);
+        assert!(after > before && after < before + 1000.0);
+        // A late fix is not a day ahead.
+        adapter.advance(0.5);
+        assert_eq!(adapter.time, Some(1.0));
+    }
+}
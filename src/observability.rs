@@ -0,0 +1,168 @@
+//! Observability and controllability of linear models.
+//!
+//! A filter can only estimate the states its measurements tell it about. A
+//! state that never shows up in the measurements, directly or through the
+//! dynamics, is unobservable: its estimate isn't corrected and its variance
+//! grows, or stays put, however long the filter runs. The model `F`, `H` is
+//! observable if the observability matrix `[H; H F; ...; H Fⁿ⁻¹]` has full
+//! rank `n`, the number of states.
+//!
+//! Dually, the controllability matrix `[B, F B, ..., Fⁿ⁻¹ B]` has full rank
+//! if the input `B` can drive every state. With the process noise `Q` as the
+//! input, a rank deficit means some states are never disturbed: their
+//! variance converges to zero and the filter stops listening to the
+//! measurements about them.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::matrix::{DenseMatrix, Matrix};
+//! use lqe::observability;
+//!
+//! // Position and velocity, but only the velocity is measured.
+//! let transition = DenseMatrix::from_row_slices(&[&[1.0, 1.0], &[0.0, 1.0]]);
+//! let observation = DenseMatrix::from_row_slices(&[&[0.0, 1.0]]);
+//! let matrix = observability::observability_matrix(&transition, &observation);
+//! observability::rank(&matrix, 1e-9);
+//! // => 1
+//! observability::unobservable_directions(&transition, &observation, 1e-9);
+//! // => [[[1.0], [0.0]]]
+//! ```
+
+use crate::matrix::Matrix;
+
+/// `observability_matrix` stacks `H`, `H F`, ..., `H Fⁿ⁻¹` for the
+/// `transition` `F` and `observation` `H` of an `n` state model.
+pub fn observability_matrix<T: Matrix>(transition: &T, observation: &T) -> T {
+    let (states, _) = transition.shape();
+    let (measurements, _) = observation.shape();
+    let mut matrix = T::zeros(states * measurements, states);
+    let mut block = observation.clone();
+    for power in 0..states {
+        for i in 0..measurements {
+            for j in 0..states {
+                matrix.set(power * measurements + i, j, block.get(i, j));
+            }
+        }
+        block = block.mul(transition);
+    }
+    matrix
+}
+
+/// `controllability_matrix` places `B`, `F B`, ..., `Fⁿ⁻¹ B` side by side
+/// for the `transition` `F` and `input` `B` of an `n` state model.
+pub fn controllability_matrix<T: Matrix>(transition: &T, input: &T) -> T {
+    let (states, inputs) = input.shape();
+    let mut matrix = T::zeros(states, states * inputs);
+    let mut block = input.clone();
+    for power in 0..states {
+        for i in 0..states {
+            for j in 0..inputs {
+                matrix.set(i, power * inputs + j, block.get(i, j));
+            }
+        }
+        block = transition.mul(&block);
+    }
+    matrix
+}
+
+/// `rank` returns the numerical rank of `matrix` by Gaussian elimination
+/// with complete pivoting, taking pivots up to `tolerance` times its largest
+/// element for zero.
+pub fn rank<T: Matrix>(matrix: &T, tolerance: f64) -> usize {
+    let (rows, columns) = matrix.shape();
+    let mut elements: Vec<Vec<f64>> = (0..rows)
+        .map(|i| (0..columns).map(|j| matrix.get(i, j)).collect())
+        .collect();
+    let largest = elements.iter().flatten().fold(0.0_f64, |largest, value| largest.max(value.abs()));
+    let threshold = tolerance * largest;
+
+    let mut rank = 0;
+    while rank < rows.min(columns) {
+        let mut pivot = (rank, rank);
+        for (i, row) in elements.iter().enumerate().skip(rank) {
+            for (j, value) in row.iter().enumerate().skip(rank) {
+                if value.abs() > elements[pivot.0][pivot.1].abs() {
+                    pivot = (i, j);
+                }
+            }
+        }
+        if elements[pivot.0][pivot.1].abs() <= threshold {
+            break;
+        }
+        elements.swap(rank, pivot.0);
+        for row in elements.iter_mut() {
+            row.swap(rank, pivot.1);
+        }
+        let (done, rest) = elements.split_at_mut(rank + 1);
+        let pivot_row = &done[rank];
+        for row in rest {
+            let factor = row[rank] / pivot_row[rank];
+            for (value, pivot_value) in row.iter_mut().zip(pivot_row).skip(rank) {
+                *value -= factor * pivot_value;
+            }
+        }
+        rank += 1;
+    }
+    rank
+}
+
+/// `unobservable_directions` returns an orthonormal basis of the states the
+/// model can't observe, as column vectors, empty if it is observable.
+///
+/// They are the eigenvectors of `Oᵀ O`, with `O` the observability matrix,
+/// whose eigenvalues are at most `tolerance` times the largest.
+pub fn unobservable_directions<T: Matrix>(transition: &T, observation: &T, tolerance: f64) -> Vec<T> {
+    let matrix = observability_matrix(transition, observation);
+    let (eigenvalues, eigenvectors) = matrix.transpose().mul(&matrix).symmetric_eigen();
+    let largest = eigenvalues.iter().fold(0.0_f64, |largest, value| largest.max(value.abs()));
+    let states = eigenvalues.len();
+    eigenvalues
+        .iter()
+        .enumerate()
+        .filter(|(_, eigenvalue)| eigenvalue.abs() <= tolerance * largest)
+        .map(|(j, _)| {
+            let mut direction = T::zeros(states, 1);
+            for i in 0..states {
+                direction.set(i, 0, eigenvectors.get(i, j));
+            }
+            direction
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::DenseMatrix;
+
+    #[test]
+    fn finds_unobservable_states() {
+        // Two independent random walks and a velocity driving the first.
+        let transition = DenseMatrix::from_row_slices(&[&[1.0, 0.0, 1.0], &[0.0, 1.0, 0.0], &[0.0, 0.0, 1.0]]);
+        let observation = DenseMatrix::from_row_slices(&[&[1.0, 0.0, 0.0]]);
+        let matrix = observability_matrix(&transition, &observation);
+        assert_eq!(matrix.shape(), (3, 3));
+        assert_eq!(rank(&matrix, 1e-9), 2);
+
+        let directions = unobservable_directions(&transition, &observation, 1e-9);
+        assert_eq!(directions.len(), 1);
+        assert!((directions[0].get(1, 0).abs() - 1.0).abs() < 1e-9);
+
+        let observation = DenseMatrix::from_row_slices(&[&[1.0, 0.0, 0.0], &[0.0, 1.0, 0.0]]);
+        assert_eq!(rank(&observability_matrix(&transition, &observation), 1e-9), 3);
+        assert!(unobservable_directions(&transition, &observation, 1e-9).is_empty());
+    }
+
+    #[test]
+    fn checks_controllability() {
+        let transition = DenseMatrix::from_row_slices(&[&[1.0, 1.0], &[0.0, 1.0]]);
+        let acceleration = DenseMatrix::vector(&[0.0, 1.0]);
+        let matrix = controllability_matrix(&transition, &acceleration);
+        assert_eq!(matrix, DenseMatrix::from_row_slices(&[&[0.0, 1.0], &[1.0, 1.0]]));
+        assert_eq!(rank(&matrix, 1e-9), 2);
+
+        let position = DenseMatrix::vector(&[1.0, 0.0]);
+        assert_eq!(rank(&controllability_matrix(&transition, &position), 1e-9), 1);
+    }
+}
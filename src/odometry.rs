@@ -0,0 +1,184 @@
+//! Pose estimation for differential-drive robots.
+//!
+//! A `DifferentialDrive` follows the position and heading of a robot with
+//! two driven wheels from their encoders and a yaw gyroscope. The encoders
+//! tell how far the robot moved; the gyroscope how far it turned, which the
+//! encoders only know as well as the wheels grip the floor. The turn the
+//! encoders report is used instead to learn the bias of the gyroscope, the
+//! main source of heading drift, whenever the two agree.
+//!
+//! When they don't, a wheel is slipping: its travel is then trusted much
+//! less and the gyroscope bias left alone until the wheels grip again.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::odometry::DifferentialDrive;
+//!
+//! // Wheels 30 cm apart, driving straight at 0.5 m/s.
+//! let mut robot = DifferentialDrive::new(0.3);
+//! for _ in 0..10 {
+//!     robot.next(0.1, 0.05, 0.05, 0.0).unwrap();
+//! }
+//! robot.pose();
+//! // => (0.5, 0.0, 0.0)
+//! ```
+
+use crate::error::Error;
+use crate::matrix::{DenseMatrix, Matrix};
+use crate::multivariate::DynamicKalmanFilter;
+
+/// `DifferentialDrive` estimates the state `[x, y, heading, bias]` of a
+/// robot with wheels `track_width` meters apart, `bias` being that of the
+/// gyroscope in radians per second.
+///
+/// `wheel_noise` is the variance a wheel travel measurement gains per meter,
+/// `gyro_noise` the variance of a yaw rate measurement and `bias_drift` the
+/// variance the gyroscope bias gains per second. The wheels are taken to be
+/// slipping when the turns of the encoders and the gyroscope differ by more
+/// than `slip_gate` standard deviations, and their variance is then scaled
+/// by `slip_factor`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DifferentialDrive {
+    pub filter: DynamicKalmanFilter<DenseMatrix>,
+    pub track_width: f64,
+    pub wheel_noise: f64,
+    pub gyro_noise: f64,
+    pub bias_drift: f64,
+    pub slip_gate: f64,
+    pub slip_factor: f64,
+    slipping: bool
+}
+
+impl DifferentialDrive {
+    /// `new` creates an estimator at the origin, heading along the x axis,
+    /// with typical noise levels of hobby robot hardware.
+    pub fn new(track_width: f64) -> Self {
+        let mut filter = DynamicKalmanFilter::new(4, 1);
+        filter.covariance = DenseMatrix::zeros(4, 4);
+        filter.covariance.set(3, 3, 1e-4);
+        filter.observation = DenseMatrix::from_row_slices(&[&[0.0, 0.0, 0.0, 1.0]]);
+        DifferentialDrive {
+            filter,
+            track_width,
+            wheel_noise: 1e-4,
+            gyro_noise: 1e-4,
+            bias_drift: 1e-8,
+            slip_gate: 3.0,
+            slip_factor: 100.0,
+            slipping: false
+        }
+    }
+
+    /// `next` moves the estimate `dt` seconds forward, the `left` and
+    /// `right` wheels having travelled the given distances in meters and
+    /// the gyroscope reading `yaw_rate` in radians per second,
+    /// counterclockwise.
+    pub fn next(&mut self, dt: f64, left: f64, right: f64, yaw_rate: f64) -> Result<(), Error> {
+        let bias = self.filter.state.get(3, 0);
+        let wheel_turn = (right - left) / self.track_width;
+        let wheel_variance = self.wheel_noise * (left.abs() + right.abs());
+        let turn_variance = wheel_variance / (self.track_width * self.track_width) + self.gyro_noise * dt * dt;
+        // The gyroscope and encoders disagree by the bias on top of the noise.
+        let disagreement = yaw_rate * dt - wheel_turn;
+        let expected = bias * dt;
+        let variance = turn_variance + dt * dt * self.filter.covariance.get(3, 3);
+        self.slipping = (disagreement - expected).abs() > self.slip_gate * variance.sqrt();
+
+        let travel_variance = wheel_variance / 4.0 * if self.slipping { self.slip_factor } else { 1.0 };
+        self.predict(dt, (left + right) / 2.0, yaw_rate, travel_variance);
+        if self.slipping {
+            return Ok(());
+        }
+        self.filter.observation.set(0, 3, dt);
+        self.filter.measurement_noise = DenseMatrix::vector(&[turn_variance]);
+        self.filter.update(&DenseMatrix::vector(&[disagreement]))
+    }
+
+    /// `predict` moves the pose `travel` meters along the heading halfway
+    /// through the turn the gyroscope reports, linearizing the motion at
+    /// the estimate.
+    fn predict(&mut self, dt: f64, travel: f64, yaw_rate: f64, travel_variance: f64) {
+        let state = &self.filter.state;
+        let turn = (yaw_rate - state.get(3, 0)) * dt;
+        let heading = state.get(2, 0) + turn / 2.0;
+        let (sin, cos) = heading.sin_cos();
+        let moved = DenseMatrix::vector(&[
+            state.get(0, 0) + travel * cos,
+            state.get(1, 0) + travel * sin,
+            state.get(2, 0) + turn,
+            state.get(3, 0)
+        ]);
+
+        let mut transition = DenseMatrix::identity(4);
+        transition.set(0, 2, -travel * sin);
+        transition.set(1, 2, travel * cos);
+        transition.set(0, 3, travel * sin * dt / 2.0);
+        transition.set(1, 3, -travel * cos * dt / 2.0);
+        transition.set(2, 3, -dt);
+        let along = DenseMatrix::vector(&[cos, sin, 0.0, 0.0]);
+        let turning = DenseMatrix::vector(&[-travel * sin / 2.0, travel * cos / 2.0, 1.0, 0.0]);
+        let mut process_noise = along
+            .mul(&along.transpose())
+            .scale(travel_variance)
+            .add(&turning.mul(&turning.transpose()).scale(self.gyro_noise * dt * dt));
+        process_noise.set(3, 3, self.bias_drift * dt);
+
+        self.filter.transition = transition;
+        self.filter.process_noise = process_noise;
+        self.filter.predict();
+        self.filter.state = moved;
+    }
+
+    /// `pose` returns the estimated position and heading.
+    pub fn pose(&self) -> (f64, f64, f64) {
+        let state = &self.filter.state;
+        (state.get(0, 0), state.get(1, 0), state.get(2, 0))
+    }
+
+    /// `gyro_bias` returns the estimated bias of the gyroscope.
+    pub fn gyro_bias(&self) -> f64 {
+        self.filter.state.get(3, 0)
+    }
+
+    /// `is_slipping` tells whether the wheels slipped during the last step.
+    pub fn is_slipping(&self) -> bool {
+        self.slipping
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particle::Random;
+
+    #[test]
+    fn learns_gyro_bias_and_flags_slip() {
+        // Driving a circle of 1 m radius at 0.5 m/s with a biased gyroscope.
+        let (width, dt, bias) = (0.3, 0.05, 0.02);
+        let mut robot = DifferentialDrive::new(width);
+        robot.wheel_noise = 1e-6;
+        robot.gyro_noise = 2.5e-5;
+        let mut random = Random::new(11);
+        let (speed, rate) = (0.5, 0.5);
+        let mut heading: f64 = 0.0;
+        let mut position = (0.0, 0.0);
+        for step in 0..2000 {
+            let mid = heading + rate * dt / 2.0;
+            position.0 += speed * dt * mid.cos();
+            position.1 += speed * dt * mid.sin();
+            heading += rate * dt;
+            let mut left = (speed - rate * width / 2.0) * dt + 1e-4 * random.normal();
+            let right = (speed + rate * width / 2.0) * dt + 1e-4 * random.normal();
+            if step == 1500 {
+                left += 0.05;
+            }
+            robot.next(dt, left, right, rate + bias + 0.005 * random.normal()).unwrap();
+            assert_eq!(robot.is_slipping(), step == 1500);
+        }
+        assert!((robot.gyro_bias() - bias).abs() < 1e-3);
+        let (x, y, estimated_heading) = robot.pose();
+        assert!((estimated_heading - heading).abs() < 0.01);
+        assert!((x - position.0).hypot(y - position.1) < 0.05);
+    }
+}
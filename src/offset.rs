@@ -0,0 +1,143 @@
+//! Removal of DC offsets.
+//!
+//! ADC readings often sit on an offset that drifts with temperature and
+//! supply voltage. The usual fix, a first-order high-pass filter, starts
+//! from an offset of zero and takes many time constants to settle, during
+//! which the output is off by the whole offset. An `OffsetRemover` estimates
+//! the offset as a slow random walk hidden under the signal instead: from
+//! the first sample it averages over everything seen so far, with a gain
+//! that falls until it reaches that of the high-pass filter it replaces.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::offset::OffsetRemover;
+//!
+//! // A 1 kHz tone on an offset of 1.65 V, sampled at 48 kHz, with the
+//! // offset tracked as if by a 1 Hz high-pass filter.
+//! let mut remover = OffsetRemover::from_cutoff(1.0, 48_000.0, 0.5);
+//! let mut signal: Vec<f64> = (0..4_800)
+//!     .map(|n| 1.65 + (2.0 * std::f64::consts::PI * n as f64 / 48.0).sin())
+//!     .collect();
+//! remover.process_block(&mut signal);
+//! (remover.offset().measurement * 100.0).round() / 100.0;
+//! // => 1.65
+//! ```
+
+use crate::{step, LQE};
+
+/// `OffsetRemover` estimates the offset of a signal whose samples vary by
+/// `signal_variance` around it, the offset gaining `offset_noise` of
+/// variance per sample.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OffsetRemover {
+    pub offset_noise: f64,
+    pub signal_variance: f64,
+    estimate: Option<LQE>
+}
+
+impl OffsetRemover {
+    /// `new` creates a remover without an offset estimate yet.
+    pub fn new(offset_noise: f64, signal_variance: f64) -> Self {
+        OffsetRemover {
+            offset_noise,
+            signal_variance,
+            estimate: None
+        }
+    }
+
+    /// `from_cutoff` creates a remover that settles to the gain of a
+    /// first-order high-pass filter with corner frequency `cutoff` at
+    /// `sample_rate`, both in hertz, for a signal of `signal_variance`
+    /// around the offset.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use lqe::offset::OffsetRemover;
+    /// let mut remover = OffsetRemover::from_cutoff(10.0, 1000.0, 1.0);
+    /// for _ in 0..1000 {
+    ///     remover.process(0.0);
+    /// }
+    /// (remover.gain() * 1000.0).round();
+    /// // => 61.0
+    /// ```
+    pub fn from_cutoff(cutoff: f64, sample_rate: f64, signal_variance: f64) -> Self {
+        let gain = 1.0 - (-2.0 * std::f64::consts::PI * cutoff / sample_rate).exp();
+        // The steady state of the random walk has this gain when
+        // `q = K² r / (1 - K)`.
+        OffsetRemover::new(gain * gain * signal_variance / (1.0 - gain), signal_variance)
+    }
+
+    /// `process` refines the offset estimate with `sample` and returns the
+    /// sample with the offset removed.
+    pub fn process(&mut self, sample: f64) -> f64 {
+        let estimate = match self.estimate {
+            Some(estimate) => {
+                let prior = step::predict(estimate, 0.0, self.offset_noise);
+                step::update(prior, sample, self.signal_variance).posterior
+            }
+            None => LQE {
+                measurement: sample,
+                variance: self.signal_variance
+            }
+        };
+        self.estimate = Some(estimate);
+        sample - estimate.measurement
+    }
+
+    /// `process_block` removes the offset from `samples` in place.
+    pub fn process_block(&mut self, samples: &mut [f64]) {
+        for sample in samples {
+            *sample = self.process(*sample);
+        }
+    }
+
+    /// `offset` returns the estimated offset, zero before the first sample.
+    pub fn offset(&self) -> LQE {
+        self.estimate.unwrap_or(LQE {
+            measurement: 0.0,
+            variance: f64::INFINITY
+        })
+    }
+
+    /// `gain` returns the weight the next sample will get in the offset
+    /// estimate.
+    pub fn gain(&self) -> f64 {
+        match self.estimate {
+            Some(estimate) => {
+                let prior = estimate.variance + self.offset_noise;
+                prior / (prior + self.signal_variance)
+            }
+            None => 1.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particle::Random;
+
+    #[test]
+    fn settles_faster_than_high_pass() {
+        let (cutoff, sample_rate) = (0.5, 1000.0);
+        let mut remover = OffsetRemover::from_cutoff(cutoff, sample_rate, 1.0);
+        let alpha = (-2.0 * std::f64::consts::PI * cutoff / sample_rate).exp();
+        let mut high_pass = 0.0;
+        let mut random = Random::new(12);
+        for n in 0..10_000 {
+            // An offset drifting from 2.0 to 2.5 under unit noise.
+            let offset = 2.0 + 0.5 * n as f64 / 10_000.0;
+            let sample = offset + random.normal();
+            remover.process(sample);
+            high_pass = alpha * high_pass + (1.0 - alpha) * sample;
+            if n == 100 {
+                assert!((remover.offset().measurement - offset).abs() < 0.3);
+                assert!((high_pass - offset).abs() > 0.5);
+            }
+        }
+        assert!((remover.offset().measurement - 2.5).abs() < 0.2);
+        assert!((remover.gain() / (1.0 - alpha) - 1.0).abs() < 1e-6);
+    }
+}
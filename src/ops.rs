@@ -0,0 +1,133 @@
+//! Arithmetic on estimates.
+//!
+//! An `LQE` is a normal distribution, so shifting and scaling it are well
+//! defined: adding a known offset moves the mean and leaves the variance alone,
+//! multiplying by a scalar `k` scales the mean by `k` and the variance by `k²`,
+//! and adding or subtracting two independent estimates adds their variances.
+
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use crate::LQE;
+
+impl Add<f64> for LQE {
+    type Output = LQE;
+
+    fn add(self, offset: f64) -> LQE {
+        LQE {
+            measurement: self.measurement + offset,
+            variance: self.variance
+        }
+    }
+}
+
+impl Sub<f64> for LQE {
+    type Output = LQE;
+
+    fn sub(self, offset: f64) -> LQE {
+        self + -offset
+    }
+}
+
+impl Add for LQE {
+    type Output = LQE;
+
+    fn add(self, other: LQE) -> LQE {
+        LQE {
+            measurement: self.measurement + other.measurement,
+            variance: self.variance + other.variance
+        }
+    }
+}
+
+impl Sub for LQE {
+    type Output = LQE;
+
+    fn sub(self, other: LQE) -> LQE {
+        self + -other
+    }
+}
+
+impl Mul<f64> for LQE {
+    type Output = LQE;
+
+    fn mul(self, factor: f64) -> LQE {
+        LQE {
+            measurement: self.measurement * factor,
+            variance: self.variance * factor * factor
+        }
+    }
+}
+
+impl Mul<LQE> for f64 {
+    type Output = LQE;
+
+    fn mul(self, lqe: LQE) -> LQE {
+        lqe * self
+    }
+}
+
+impl Div<f64> for LQE {
+    type Output = LQE;
+
+    fn div(self, divisor: f64) -> LQE {
+        LQE {
+            measurement: self.measurement / divisor,
+            variance: self.variance / (divisor * divisor)
+        }
+    }
+}
+
+impl Neg for LQE {
+    type Output = LQE;
+
+    fn neg(self) -> LQE {
+        LQE {
+            measurement: -self.measurement,
+            variance: self.variance
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shifts_by_offset() {
+        let lqe = LQE {
+            measurement: 7.0,
+            variance: 2.0
+        };
+
+        assert_eq!((lqe + 3.0).result(), (10.0, 2.0));
+        assert_eq!((lqe - 3.0).result(), (4.0, 2.0));
+    }
+
+    #[test]
+    fn combines_independent_estimates() {
+        let a = LQE {
+            measurement: 7.0,
+            variance: 2.0
+        };
+        let b = LQE {
+            measurement: 3.0,
+            variance: 1.0
+        };
+
+        assert_eq!((a + b).result(), (10.0, 3.0));
+        assert_eq!((a - b).result(), (4.0, 3.0));
+    }
+
+    #[test]
+    fn scales_by_factor() {
+        let lqe = LQE {
+            measurement: 7.0,
+            variance: 2.0
+        };
+
+        assert_eq!((lqe * 3.0).result(), (21.0, 18.0));
+        assert_eq!((3.0 * lqe).result(), (21.0, 18.0));
+        assert_eq!((lqe / 2.0).result(), (3.5, 0.5));
+        assert_eq!((-lqe).result(), (-7.0, 2.0));
+    }
+}
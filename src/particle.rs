@@ -0,0 +1,489 @@
+//! Rao-Blackwellized particle filtering.
+//!
+//! Many models are only linear once part of the state is known: the map of a
+//! SLAM problem given the robot's path, or a signal given the regime it is
+//! in. `RaoBlackwellizedFilter` samples that part with particles and solves
+//! the conditionally linear remainder exactly with one `DynamicKalmanFilter`
+//! per particle, which needs far fewer particles than sampling everything.
+//!
+//! The particles are resampled once too few of them carry most of the
+//! weight, by one of the `Resampling` schemes. A `ModelledFilter` keeps the
+//! functions that draw and condition the particles with the filter, so that
+//! it can be stepped through `BayesFilter`.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::matrix::{DenseMatrix, Matrix};
+//! use lqe::multivariate::DynamicKalmanFilter;
+//! use lqe::particle::{Particle, RaoBlackwellizedFilter};
+//!
+//! // Is the sensor gain 1 or 2?
+//! let particle = |gain: f64| Particle {
+//!     sample: gain,
+//!     weight: 0.5,
+//!     filter: DynamicKalmanFilter::<DenseMatrix>::new(1, 1)
+//! };
+//! let mut filter = RaoBlackwellizedFilter::new(vec![particle(1.0), particle(2.0)], 7);
+//!
+//! filter.next(
+//!     &DenseMatrix::vector(&[2.0]),
+//!     |gain, _| *gain,
+//!     |gain, model| model.observation = DenseMatrix::vector(&[*gain])
+//! ).unwrap();
+//! ```
+
+use crate::error::Error;
+use crate::matrix::Matrix;
+use crate::multivariate::DynamicKalmanFilter;
+
+/// `Random` is a small, seedable pseudorandom number generator (SplitMix64)
+/// for drawing particles reproducibly.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Random {
+    state: u64
+}
+
+impl Random {
+    /// `new` creates a generator from `seed`; equal seeds give equal draws.
+    pub fn new(seed: u64) -> Self {
+        Random { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// `uniform` draws a number uniformly from `[0, 1)`.
+    pub fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// `normal` draws a number from the standard normal distribution.
+    pub fn normal(&mut self) -> f64 {
+        // Box-Muller, with 1 - u in (0, 1] to keep the logarithm finite.
+        let radius = (-2.0 * (1.0 - self.uniform()).ln()).sqrt();
+        radius * (2.0 * std::f64::consts::PI * self.uniform()).cos()
+    }
+}
+
+/// `Resampling` is a scheme for drawing an equally weighted set of
+/// particles in proportion to their weights.
+///
+/// All of them are unbiased. `Systematic` and `Stratified` add the least
+/// noise and are the usual choice, `Residual` keeps the integer part of each
+/// particle's expected count deterministically, and `Multinomial` draws
+/// every particle independently, which is the simplest but noisiest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Resampling {
+    /// One uniform offset shared by `n` evenly spaced positions.
+    Systematic,
+    /// One uniform draw within each of `n` equal strata.
+    Stratified,
+    /// `⌊n wᵢ⌋` copies of each particle, the rest drawn multinomially from
+    /// the remaining weights.
+    Residual,
+    /// `n` independent draws.
+    Multinomial
+}
+
+impl Resampling {
+    /// `indices` returns, for each particle of the resampled set, the index
+    /// of the particle it copies, given `weights` that sum to one.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use lqe::particle::{Random, Resampling};
+    /// Resampling::Residual.indices(&[0.5, 0.25, 0.25, 0.0], &mut Random::new(3));
+    /// // => [0, 0, 1, 2]
+    /// ```
+    pub fn indices(&self, weights: &[f64], random: &mut Random) -> Vec<usize> {
+        let count = weights.len();
+        match self {
+            Resampling::Systematic => {
+                let offset = random.uniform();
+                let positions = (0..count).map(|i| (i as f64 + offset) / count as f64).collect::<Vec<_>>();
+                sweep(weights, &positions)
+            }
+            Resampling::Stratified => {
+                let positions = (0..count)
+                    .map(|i| (i as f64 + random.uniform()) / count as f64)
+                    .collect::<Vec<_>>();
+                sweep(weights, &positions)
+            }
+            Resampling::Multinomial => {
+                let mut positions = (0..count).map(|_| random.uniform()).collect::<Vec<_>>();
+                positions.sort_by(f64::total_cmp);
+                sweep(weights, &positions)
+            }
+            Resampling::Residual => {
+                let mut indices = Vec::with_capacity(count);
+                let mut residuals = Vec::with_capacity(count);
+                for (i, weight) in weights.iter().enumerate() {
+                    let expected = weight * count as f64;
+                    let copies = (expected.floor() as usize).min(count - indices.len());
+                    indices.extend(std::iter::repeat_n(i, copies));
+                    residuals.push(expected - copies as f64);
+                }
+                let remaining = count - indices.len();
+                let total = residuals.iter().sum::<f64>();
+                if remaining > 0 && total > 0.0 {
+                    let residuals = residuals.iter().map(|residual| residual / total).collect::<Vec<_>>();
+                    let mut positions = (0..remaining).map(|_| random.uniform()).collect::<Vec<_>>();
+                    positions.sort_by(f64::total_cmp);
+                    indices.extend(sweep(&residuals, &positions));
+                }
+                indices
+            }
+        }
+    }
+}
+
+/// `sweep` returns the index of the particle whose share of the cumulative
+/// weight covers each of the ascending `positions` in `[0, 1)`.
+fn sweep(weights: &[f64], positions: &[f64]) -> Vec<usize> {
+    let mut indices = Vec::with_capacity(positions.len());
+    let mut index = 0;
+    let mut cumulative = weights.first().copied().unwrap_or(0.0);
+    for position in positions {
+        while cumulative < *position && index + 1 < weights.len() {
+            index += 1;
+            cumulative += weights[index];
+        }
+        indices.push(index);
+    }
+    indices
+}
+
+/// `Particle` is one hypothesis `sample` of the sampled part of the state,
+/// with its probability `weight` and the estimate `filter` of the linear part
+/// conditioned on it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Particle<S, T> {
+    pub sample: S,
+    pub weight: f64,
+    pub filter: DynamicKalmanFilter<T>
+}
+
+/// `RaoBlackwellizedFilter` is an estimate made of weighted `particles`,
+/// whose weights sum to one.
+///
+/// The particles are resampled with the `resampling` scheme whenever the
+/// effective sample size drops below `threshold` times their number, so
+/// `1.0` resamples every step and `0.0` never.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RaoBlackwellizedFilter<S, T> {
+    pub particles: Vec<Particle<S, T>>,
+    pub resampling: Resampling,
+    pub threshold: f64,
+    random: Random
+}
+
+impl<S: Clone, T: Matrix> RaoBlackwellizedFilter<S, T> {
+    /// `new` creates a filter from `particles`, drawing from a `Random`
+    /// seeded with `seed`, that resamples systematically once the effective
+    /// sample size drops below half the number of particles.
+    pub fn new(particles: Vec<Particle<S, T>>, seed: u64) -> Self {
+        RaoBlackwellizedFilter {
+            particles,
+            resampling: Resampling::Systematic,
+            threshold: 0.5,
+            random: Random::new(seed)
+        }
+    }
+
+    /// `next` performs one step of the filter for `measurement`: `predict`
+    /// with `propagate` and `condition`, then `update`.
+    ///
+    /// Returns `Error::ImpossibleMeasurement` if the measurement has zero
+    /// likelihood under every particle with any weight. On any error the
+    /// filter is left unchanged.
+    pub fn next(
+        &mut self,
+        measurement: &T,
+        propagate: impl FnMut(&S, &mut Random) -> S,
+        condition: impl FnMut(&S, &mut DynamicKalmanFilter<T>)
+    ) -> Result<(), Error> {
+        // Step a copy of the filter, and of its generator, so that an error
+        // leaves it as it was.
+        let mut filter = self.clone();
+        filter.predict(propagate, condition);
+        filter.update(measurement)?;
+        *self = filter;
+        Ok(())
+    }
+
+    /// `predict` moves the particles one step forward.
+    ///
+    /// `propagate` draws the next sample of each particle from its current
+    /// one, and `condition` sets up the linear model given the new sample.
+    /// Each particle's filter then predicts.
+    pub fn predict(
+        &mut self,
+        mut propagate: impl FnMut(&S, &mut Random) -> S,
+        mut condition: impl FnMut(&S, &mut DynamicKalmanFilter<T>)
+    ) {
+        for particle in &mut self.particles {
+            particle.sample = propagate(&particle.sample, &mut self.random);
+            condition(&particle.sample, &mut particle.filter);
+            particle.filter.predict();
+        }
+    }
+
+    /// `update` reweighs each particle by the likelihood of `measurement`
+    /// and updates its filter with it. The particles are then resampled if
+    /// the effective sample size dropped below the threshold.
+    ///
+    /// Returns `Error::ImpossibleMeasurement` if the measurement has zero
+    /// likelihood under every particle with any weight. On any error the
+    /// particles are left unchanged.
+    pub fn update(&mut self, measurement: &T) -> Result<(), Error> {
+        let mut particles = self.particles.clone();
+        let mut log_weights = Vec::with_capacity(particles.len());
+        for particle in &mut particles {
+            log_weights.push(particle.weight.ln() + particle.filter.log_likelihood(measurement)?);
+            particle.filter.update(measurement)?;
+        }
+        let largest = log_weights.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        if !largest.is_finite() {
+            return Err(Error::ImpossibleMeasurement);
+        }
+        let total = log_weights.iter().map(|weight| (weight - largest).exp()).sum::<f64>();
+        for (particle, log_weight) in particles.iter_mut().zip(log_weights) {
+            particle.weight = (log_weight - largest).exp() / total;
+        }
+        self.particles = particles;
+        if self.effective_sample_size() < self.threshold * self.particles.len() as f64 {
+            self.resample();
+        }
+        Ok(())
+    }
+
+    /// `effective_sample_size` returns `1 / Σ wᵢ²`, the number of equally
+    /// weighted particles that would carry as much information.
+    pub fn effective_sample_size(&self) -> f64 {
+        1.0 / self.particles.iter().map(|particle| particle.weight.powi(2)).sum::<f64>()
+    }
+
+    /// `resample` draws a new, equally weighted set of particles in
+    /// proportion to their weights, with the `resampling` scheme.
+    pub fn resample(&mut self) {
+        let weights = self.particles.iter().map(|particle| particle.weight).collect::<Vec<_>>();
+        let indices = self.resampling.indices(&weights, &mut self.random);
+        let weight = 1.0 / indices.len() as f64;
+        self.particles = indices
+            .into_iter()
+            .map(|index| Particle {
+                weight,
+                ..self.particles[index].clone()
+            })
+            .collect();
+    }
+
+    /// `result` returns the weighted mean and covariance of the linear part
+    /// of the state.
+    pub fn result(&self) -> (T, T) {
+        let size = self.particles.first().map_or(0, |particle| particle.filter.state.shape().0);
+        let mut mean = T::zeros(size, 1);
+        for particle in &self.particles {
+            mean = mean.add(&particle.filter.state.scale(particle.weight));
+        }
+        let mut covariance = T::zeros(size, size);
+        for particle in &self.particles {
+            let spread = particle.filter.state.sub(&mean);
+            let contribution = particle.filter.covariance.add(&spread.mul(&spread.transpose()));
+            covariance = covariance.add(&contribution.scale(particle.weight));
+        }
+        (mean, covariance)
+    }
+}
+
+/// `ModelledFilter` is a `RaoBlackwellizedFilter` together with the
+/// `propagate` and `condition` functions of its model, so that it can be
+/// stepped like any other `BayesFilter`.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::bayes::BayesFilter;
+/// use lqe::matrix::{DenseMatrix, Matrix};
+/// use lqe::multivariate::DynamicKalmanFilter;
+/// use lqe::particle::{ModelledFilter, Particle, RaoBlackwellizedFilter};
+///
+/// let particle = Particle {
+///     sample: 1.0,
+///     weight: 1.0,
+///     filter: DynamicKalmanFilter::<DenseMatrix>::new(1, 1)
+/// };
+/// let particles = RaoBlackwellizedFilter::new(vec![particle], 7);
+/// let mut filter = ModelledFilter::new(particles, |gain: &f64, _| *gain, |_, _| ());
+/// filter.step(&DenseMatrix::vector(&[2.0])).unwrap();
+/// filter.estimate().0.get(0, 0);
+/// // => 1.0
+/// ```
+#[derive(Clone)]
+pub struct ModelledFilter<S, T, P, C> {
+    pub filter: RaoBlackwellizedFilter<S, T>,
+    pub propagate: P,
+    pub condition: C
+}
+
+impl<S, T, P, C> ModelledFilter<S, T, P, C>
+where
+    S: Clone,
+    T: Matrix,
+    P: FnMut(&S, &mut Random) -> S,
+    C: FnMut(&S, &mut DynamicKalmanFilter<T>)
+{
+    /// `new` couples `filter` with the `propagate` and `condition` functions
+    /// of `RaoBlackwellizedFilter::predict`.
+    pub fn new(filter: RaoBlackwellizedFilter<S, T>, propagate: P, condition: C) -> Self {
+        ModelledFilter {
+            filter,
+            propagate,
+            condition
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::DenseMatrix;
+
+    fn particle(gain: f64, weight: f64) -> Particle<f64, DenseMatrix> {
+        let mut filter = DynamicKalmanFilter::new(1, 1);
+        filter.state = DenseMatrix::vector(&[1.0]);
+        filter.covariance = DenseMatrix::identity(1).scale(0.01);
+        filter.measurement_noise = DenseMatrix::identity(1).scale(0.1);
+        Particle {
+            sample: gain,
+            weight,
+            filter
+        }
+    }
+
+    #[test]
+    fn draws_reproducibly() {
+        let mut random = Random::new(42);
+        let draws = (0..1000).map(|_| random.uniform()).collect::<Vec<_>>();
+
+        assert!(draws.iter().all(|draw| (0.0..1.0).contains(draw)));
+        assert_eq!(Random::new(42).uniform(), draws[0]);
+        let mean = draws.iter().sum::<f64>() / 1000.0;
+        assert!((mean - 0.5).abs() < 0.05);
+    }
+
+    #[test]
+    fn identifies_sampled_parameter() {
+        let particles = (0..10).map(|i| particle(if i < 6 { 1.0 } else { 2.0 }, 0.1)).collect();
+        let mut filter = RaoBlackwellizedFilter::new(particles, 7);
+
+        for _ in 0..3 {
+            filter
+                .next(&DenseMatrix::vector(&[2.0]), |gain, _| *gain, |gain, model| {
+                    model.observation = DenseMatrix::vector(&[*gain])
+                })
+                .unwrap();
+        }
+        assert!(filter.particles.iter().all(|particle| particle.sample == 2.0));
+        assert!((filter.result().0.get(0, 0) - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn matches_kalman_filter_without_sampling() {
+        let mut filter = RaoBlackwellizedFilter::new(vec![particle(1.0, 0.5), particle(1.0, 0.5)], 1);
+        let mut expected = particle(1.0, 1.0).filter;
+
+        filter.next(&DenseMatrix::vector(&[1.5]), |gain, _| *gain, |_, _| ()).unwrap();
+        expected.next(&DenseMatrix::vector(&[1.5])).unwrap();
+        assert_eq!(filter.result().0, expected.state);
+        assert_eq!(filter.effective_sample_size(), 2.0);
+    }
+
+    #[test]
+    fn leaves_particles_alone_on_error() {
+        let mut filter = RaoBlackwellizedFilter::new(vec![particle(1.0, 0.0), particle(2.0, 0.0)], 5);
+        let before = filter.clone();
+
+        let result = filter.next(&DenseMatrix::vector(&[1.5]), |gain, random| gain + random.normal(), |_, _| ());
+        assert_eq!(result, Err(Error::ImpossibleMeasurement));
+        assert_eq!(filter, before);
+
+        // The second particle can't explain any measurement.
+        filter.particles[0].weight = 0.5;
+        filter.particles[1].weight = 0.5;
+        let before = filter.clone();
+        let result = filter.next(&DenseMatrix::vector(&[1.5]), |gain, random| gain + random.normal(), |gain, model| {
+            if *gain > 1.5 {
+                model.covariance = DenseMatrix::zeros(1, 1);
+                model.process_noise = DenseMatrix::zeros(1, 1);
+                model.measurement_noise = DenseMatrix::zeros(1, 1);
+            }
+        });
+        assert!(result.is_err());
+        assert_eq!(filter, before);
+    }
+
+    #[test]
+    fn resamples_in_proportion_to_weight() {
+        let mut filter = RaoBlackwellizedFilter::new(
+            vec![particle(1.0, 0.75), particle(2.0, 0.25), particle(3.0, 0.0), particle(4.0, 0.0)],
+            3
+        );
+
+        filter.resample();
+        let samples = filter.particles.iter().map(|particle| particle.sample).collect::<Vec<_>>();
+        assert_eq!(samples, vec![1.0, 1.0, 1.0, 2.0]);
+        assert!(filter.particles.iter().all(|particle| particle.weight == 0.25));
+    }
+
+    #[test]
+    fn resamples_with_every_scheme() {
+        let weights = [0.5, 0.3, 0.2, 0.0];
+        let schemes = [Resampling::Systematic, Resampling::Stratified, Resampling::Residual, Resampling::Multinomial];
+
+        for scheme in &schemes {
+            let mut random = Random::new(9);
+            let mut counts = [0usize; 4];
+            for _ in 0..500 {
+                for index in scheme.indices(&weights, &mut random) {
+                    counts[index] += 1;
+                }
+            }
+            assert_eq!(counts[3], 0, "{:?}", scheme);
+            for (count, weight) in counts.iter().zip(&weights) {
+                assert!((*count as f64 / 2000.0 - weight).abs() < 0.03, "{:?}: {:?}", scheme, counts);
+            }
+        }
+        // Residual resampling keeps the whole copies deterministically.
+        let indices = Resampling::Residual.indices(&weights, &mut Random::new(1));
+        assert_eq!(indices[..2], [0, 0]);
+    }
+
+    #[test]
+    fn resamples_below_threshold() {
+        let particles = (0..10).map(|i| particle(if i < 6 { 1.0 } else { 2.0 }, 0.1)).collect();
+        let mut filter = RaoBlackwellizedFilter::new(particles, 7);
+        filter.threshold = 0.0;
+        filter.resampling = Resampling::Stratified;
+
+        filter
+            .next(&DenseMatrix::vector(&[2.0]), |gain, _| *gain, |gain, model| {
+                model.observation = DenseMatrix::vector(&[*gain])
+            })
+            .unwrap();
+        assert!(filter.particles.iter().any(|particle| particle.sample == 1.0));
+        filter.threshold = 1.0;
+        filter
+            .next(&DenseMatrix::vector(&[2.0]), |gain, _| *gain, |_, _| ())
+            .unwrap();
+        assert!(filter.particles.iter().all(|particle| particle.weight == 0.1));
+    }
+}
@@ -0,0 +1,266 @@
+//! Lock-free single-producer single-consumer filtering pipelines.
+//!
+//! `pipeline` connects three threads through two bounded ring buffers: the
+//! `Producer` pushes measurements, a `Stage` folds them into an `Estimator`,
+//! and the `Consumer` pops the estimates. Each ring has exactly one writer
+//! and one reader, so they need no locks, only an atomic index each, and
+//! nothing is allocated after the pipeline is set up. That suits audio and
+//! other DSP-rate data, where a blocked thread drops samples.
+//!
+//! `ring` creates a single ring buffer for other uses.
+//!
+//! # Example:
+//!
+//! ```
+//! use std::thread;
+//! use lqe::baseline::ExponentialMovingAverage;
+//! use lqe::pipeline::pipeline;
+//!
+//! let (mut measurements, mut stage, mut estimates) = pipeline(ExponentialMovingAverage::new(0.5), 64);
+//! let filtering = thread::spawn(move || {
+//!     let mut filtered = 0;
+//!     while filtered < 2 {
+//!         filtered += stage.process();
+//!     }
+//! });
+//! measurements.push(1.0).unwrap();
+//! measurements.push(3.0).unwrap();
+//! filtering.join().unwrap();
+//! estimates.pop();
+//! estimates.pop().map(|estimate| estimate.measurement);
+//! // => Some(2.0)
+//! ```
+
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::baseline::Estimator;
+use crate::LQE;
+
+/// `Ring` is the storage shared by a `Producer` and a `Consumer`, holding up
+/// to `capacity` values. `head` and `tail` count the values popped and
+/// pushed so far.
+///
+/// There is a power of two of `slots`, so that the counters map to the same
+/// slots when they wrap around.
+struct Ring<T> {
+    capacity: usize,
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    head: AtomicUsize,
+    tail: AtomicUsize
+}
+
+// Safety: a slot is only written by the producer while it is free and only
+// read by the consumer while it is filled, as published through the indices.
+unsafe impl<T: Send> Sync for Ring<T> {}
+
+/// `Producer` is the writing end of a ring buffer.
+pub struct Producer<T> {
+    ring: Arc<Ring<T>>,
+    tail: usize
+}
+
+/// `Consumer` is the reading end of a ring buffer.
+pub struct Consumer<T> {
+    ring: Arc<Ring<T>>,
+    head: usize
+}
+
+/// `ring` creates a ring buffer holding up to `capacity` values.
+///
+/// # Panics:
+///
+/// Panics if `capacity` is zero or larger than the largest power of two.
+pub fn ring<T: Copy>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    assert!(capacity > 0, "ring capacity must be positive");
+    let slots = capacity.checked_next_power_of_two().expect("ring capacity is too large");
+    let ring = Arc::new(Ring {
+        capacity,
+        slots: (0..slots).map(|_| UnsafeCell::new(MaybeUninit::uninit())).collect(),
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0)
+    });
+    (
+        Producer {
+            ring: Arc::clone(&ring),
+            tail: 0
+        },
+        Consumer { ring, head: 0 }
+    )
+}
+
+impl<T> Ring<T> {
+    /// `slot` returns the slot of the value counted `index`.
+    fn slot(&self, index: usize) -> *mut MaybeUninit<T> {
+        self.slots[index & (self.slots.len() - 1)].get()
+    }
+}
+
+impl<T: Copy> Producer<T> {
+    /// `push` appends `value`, or returns it if the ring is full.
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(value);
+        }
+        // Safety: the slot is free, and only this producer writes slots.
+        unsafe { (*self.ring.slot(self.tail)).write(value) };
+        self.tail = self.tail.wrapping_add(1);
+        self.ring.tail.store(self.tail, Ordering::Release);
+        Ok(())
+    }
+
+    /// `is_full` returns whether a push would fail.
+    pub fn is_full(&self) -> bool {
+        self.tail.wrapping_sub(self.ring.head.load(Ordering::Acquire)) == self.ring.capacity
+    }
+}
+
+impl<T: Copy> Consumer<T> {
+    /// `pop` removes the oldest value, if there is one.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.head == self.ring.tail.load(Ordering::Acquire) {
+            return None;
+        }
+        // Safety: the slot was filled by a push published through `tail`.
+        let value = unsafe { (*self.ring.slot(self.head)).assume_init() };
+        self.head = self.head.wrapping_add(1);
+        self.ring.head.store(self.head, Ordering::Release);
+        Some(value)
+    }
+
+    /// `len` returns the number of values waiting.
+    pub fn len(&self) -> usize {
+        self.ring.tail.load(Ordering::Acquire).wrapping_sub(self.head)
+    }
+
+    /// `is_empty` returns whether a pop would return `None`.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// `Stage` folds the measurements of one ring buffer into `estimator` and
+/// pushes the estimates to another.
+pub struct Stage<E> {
+    pub estimator: E,
+    measurements: Consumer<f64>,
+    estimates: Producer<LQE>
+}
+
+impl<E: Estimator> Stage<E> {
+    /// `process` filters the waiting measurements for as long as there is
+    /// room for their estimates, returning how many it filtered.
+    pub fn process(&mut self) -> usize {
+        let mut filtered = 0;
+        while !self.estimates.is_full() {
+            let measurement = match self.measurements.pop() {
+                Some(measurement) => measurement,
+                None => break
+            };
+            // There is room, and only this stage pushes estimates.
+            let _ = self.estimates.push(self.estimator.next(measurement));
+            filtered += 1;
+        }
+        filtered
+    }
+}
+
+/// `pipeline` creates a pipeline filtering measurements with `estimator`
+/// through ring buffers of `capacity` measurements and estimates.
+///
+/// # Panics:
+///
+/// Panics if `capacity` is zero or larger than the largest power of two.
+pub fn pipeline<E: Estimator>(estimator: E, capacity: usize) -> (Producer<f64>, Stage<E>, Consumer<LQE>) {
+    let (producer, measurements) = ring(capacity);
+    let (estimates, consumer) = ring(capacity);
+    let stage = Stage {
+        estimator,
+        measurements,
+        estimates
+    };
+    (producer, stage, consumer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::baseline::ExponentialMovingAverage;
+    use std::thread;
+
+    #[test]
+    fn wraps_around_bounded_ring() {
+        let (mut producer, mut consumer) = ring(2);
+        assert_eq!(consumer.pop(), None::<u32>);
+        for round in 0..5 {
+            producer.push(2 * round).unwrap();
+            producer.push(2 * round + 1).unwrap();
+            assert!(producer.is_full());
+            assert_eq!(producer.push(99), Err(99));
+            assert_eq!(consumer.len(), 2);
+            assert_eq!(consumer.pop(), Some(2 * round));
+            assert_eq!(consumer.pop(), Some(2 * round + 1));
+            assert!(consumer.is_empty());
+        }
+    }
+
+    #[test]
+    fn wraps_counters_around() {
+        let (mut producer, mut consumer) = ring(3);
+        let start = usize::MAX - 4;
+        producer.tail = start;
+        producer.ring.tail.store(start, Ordering::Release);
+        consumer.head = start;
+        consumer.ring.head.store(start, Ordering::Release);
+        // A slot left unread across the wrap would be overwritten.
+        producer.push(0).unwrap();
+        for value in 1..20 {
+            producer.push(value).unwrap();
+            producer.push(value + 100).unwrap();
+            assert!(producer.is_full());
+            assert_eq!(consumer.pop(), Some(value - 1));
+            assert_eq!(consumer.pop(), Some(value));
+            assert_eq!(consumer.pop(), Some(value + 100));
+            producer.push(value).unwrap();
+        }
+        assert_eq!(consumer.pop(), Some(19));
+        assert!(consumer.is_empty());
+    }
+
+    #[test]
+    fn filters_across_threads() {
+        let count: u32 = 100_000;
+        let (mut producer, mut stage, mut consumer) = pipeline(ExponentialMovingAverage::new(0.1), 16);
+        let producing = thread::spawn(move || {
+            for i in 0..count {
+                let mut measurement = f64::from(i % 100);
+                while let Err(rejected) = producer.push(measurement) {
+                    measurement = rejected;
+                    thread::yield_now();
+                }
+            }
+        });
+        let filtering = thread::spawn(move || {
+            let mut filtered = 0;
+            while filtered < count as usize {
+                filtered += stage.process();
+                thread::yield_now();
+            }
+        });
+
+        let mut reference = ExponentialMovingAverage::new(0.1);
+        for i in 0..count {
+            let estimate = loop {
+                match consumer.pop() {
+                    Some(estimate) => break estimate,
+                    None => thread::yield_now()
+                }
+            };
+            assert_eq!(estimate, reference.next(f64::from(i % 100)));
+        }
+        producing.join().unwrap();
+        filtering.join().unwrap();
+    }
+}
@@ -0,0 +1,165 @@
+//! Frequency and phase tracking of sinusoids.
+//!
+//! A phase-locked loop follows the phase and frequency of a carrier through
+//! noise; its loop filter is, in the end, a steady-state Kalman filter with
+//! hand-picked gains. A `PhaseTracker` runs the Kalman filter itself on the
+//! state `[phase, frequency]`, so its bandwidth follows from the noise
+//! levels, it locks faster from an uncertain start, and it reports how well
+//! it is locked. It takes phase measurements, e.g. from a phase detector, or
+//! raw I/Q samples, linearizing at the estimate.
+//!
+//! Phases are in radians, frequencies in hertz.
+//!
+//! # Example:
+//!
+//! ```
+//! use std::f64::consts::PI;
+//! use lqe::pll::PhaseTracker;
+//!
+//! // A 1 kHz tone sampled at 8 kHz, starting from a guess of 990 Hz.
+//! let mut tracker = PhaseTracker::new(990.0);
+//! tracker.measurement_noise = 0.01;
+//! for n in 0..400 {
+//!     let phase = 2.0 * PI * 1000.0 * n as f64 / 8000.0;
+//!     tracker.predict(1.0 / 8000.0);
+//!     tracker.update_iq(phase.cos(), phase.sin()).unwrap();
+//! }
+//! tracker.frequency().round();
+//! // => 1000.0
+//! ```
+
+use std::f64::consts::PI;
+
+use crate::error::Error;
+use crate::matrix::{DenseMatrix, Matrix};
+use crate::multivariate::DynamicKalmanFilter;
+
+/// `wrap` returns `angle` wrapped into `[-π, π)`.
+fn wrap(angle: f64) -> f64 {
+    (angle + PI).rem_euclid(2.0 * PI) - PI
+}
+
+/// `PhaseTracker` estimates the state `[phase, frequency]` of a sinusoid of
+/// known `amplitude`, the frequency in radians per second internally.
+///
+/// `phase_noise` is the variance the phase gains per second from jitter,
+/// `frequency_noise` the variance the frequency gains per second, in
+/// (rad/s)², and `measurement_noise` the variance of a phase measurement,
+/// or of each of I and Q.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PhaseTracker {
+    pub filter: DynamicKalmanFilter<DenseMatrix>,
+    pub amplitude: f64,
+    pub phase_noise: f64,
+    pub frequency_noise: f64,
+    pub measurement_noise: f64
+}
+
+impl PhaseTracker {
+    /// `new` creates a tracker around a guess of the `frequency` in hertz,
+    /// uncertain by about 1% of it, with an unknown phase.
+    pub fn new(frequency: f64) -> Self {
+        let mut filter = DynamicKalmanFilter::new(2, 1);
+        filter.state = DenseMatrix::vector(&[0.0, 2.0 * PI * frequency]);
+        filter.covariance = DenseMatrix::zeros(2, 2);
+        filter.covariance.set(0, 0, PI * PI);
+        filter.covariance.set(1, 1, (2.0 * PI * frequency / 100.0).powi(2));
+        PhaseTracker {
+            filter,
+            amplitude: 1.0,
+            phase_noise: 1e-6,
+            frequency_noise: 1.0,
+            measurement_noise: 0.1
+        }
+    }
+
+    /// `predict` moves the estimate `dt` seconds forward.
+    pub fn predict(&mut self, dt: f64) {
+        self.filter.transition = DenseMatrix::from_row_slices(&[&[1.0, dt], &[0.0, 1.0]]);
+        // The frequency noise integrated over the step into the phase as well.
+        let q = self.frequency_noise;
+        self.filter.process_noise = DenseMatrix::from_row_slices(&[
+            &[self.phase_noise * dt + q * dt.powi(3) / 3.0, q * dt * dt / 2.0],
+            &[q * dt * dt / 2.0, q * dt]
+        ]);
+        self.filter.predict();
+    }
+
+    /// `update_phase` refines the estimate with a measured `phase`, taken
+    /// modulo a full turn.
+    pub fn update_phase(&mut self, phase: f64) -> Result<(), Error> {
+        let predicted = self.filter.state.get(0, 0);
+        self.filter.observation = DenseMatrix::from_row_slices(&[&[1.0, 0.0]]);
+        self.filter.measurement_noise = DenseMatrix::vector(&[self.measurement_noise]);
+        // Measure the phase relative to the prediction, across the wrap.
+        self.filter.update(&DenseMatrix::vector(&[predicted + wrap(phase - predicted)]))?;
+        self.wrap_phase();
+        Ok(())
+    }
+
+    /// `update_iq` refines the estimate with an I/Q sample, `amplitude`
+    /// times the cosine and sine of the phase plus noise.
+    pub fn update_iq(&mut self, i: f64, q: f64) -> Result<(), Error> {
+        let (sin, cos) = self.filter.state.get(0, 0).sin_cos();
+        let amplitude = self.amplitude;
+        let observation = DenseMatrix::from_row_slices(&[&[-amplitude * sin, 0.0], &[amplitude * cos, 0.0]]);
+        self.filter.update_linearized(
+            &DenseMatrix::vector(&[i, q]),
+            &DenseMatrix::vector(&[amplitude * cos, amplitude * sin]),
+            observation,
+            DenseMatrix::identity(2).scale(self.measurement_noise)
+        )?;
+        self.wrap_phase();
+        Ok(())
+    }
+
+    /// `wrap_phase` keeps the phase estimate within a turn.
+    fn wrap_phase(&mut self) {
+        let phase = wrap(self.filter.state.get(0, 0));
+        self.filter.state.set(0, 0, phase);
+    }
+
+    /// `phase` returns the estimated phase in `[-π, π)`.
+    pub fn phase(&self) -> f64 {
+        self.filter.state.get(0, 0)
+    }
+
+    /// `frequency` returns the estimated frequency in hertz.
+    pub fn frequency(&self) -> f64 {
+        self.filter.state.get(1, 0) / (2.0 * PI)
+    }
+
+    /// `frequency_deviation` returns the standard deviation of the frequency
+    /// estimate in hertz, small once the tracker is locked.
+    pub fn frequency_deviation(&self) -> f64 {
+        self.filter.covariance.get(1, 1).sqrt() / (2.0 * PI)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particle::Random;
+
+    #[test]
+    fn tracks_frequency_step() {
+        // A noisy phase detector at 1 kHz, the carrier jumping from 50 Hz
+        // to 51 Hz halfway through.
+        let dt = 1e-3;
+        let mut tracker = PhaseTracker::new(49.5);
+        let mut random = Random::new(8);
+        let mut phase = 1.0;
+        for n in 0..4000 {
+            let frequency = if n < 2000 { 50.0 } else { 51.0 };
+            phase += 2.0 * PI * frequency * dt;
+            tracker.predict(dt);
+            tracker.update_phase(wrap(phase + 0.3 * random.normal())).unwrap();
+            if n == 1999 {
+                assert!((tracker.frequency() - 50.0).abs() < 0.1);
+            }
+        }
+        assert!((tracker.frequency() - 51.0).abs() < 0.1);
+        assert!(wrap(tracker.phase() - phase).abs() < 0.1);
+        assert!(tracker.frequency_deviation() < 0.1);
+    }
+}
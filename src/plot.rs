@@ -0,0 +1,175 @@
+//! Live plots of filters in egui.
+//!
+//! With the `plot` feature, a `FilterPlot` records the measurements and
+//! estimates of a running filter and draws them as an `egui_plot` widget:
+//! the measurements as points, the estimate as a line and its confidence
+//! band around it. It keeps a fixed number of the latest steps, so it can be
+//! fed for as long as the GUI runs.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::{step, LQE};
+//! use lqe::plot::FilterPlot;
+//!
+//! let mut plot = FilterPlot::new("altitude", 500);
+//! let mut estimate = LQE { measurement: 0.0, variance: 10.0 };
+//! for (t, z) in [1.0, 1.2, 0.9].iter().enumerate() {
+//!     let info = step::update(step::predict(estimate, 0.0, 0.1), *z, 0.5);
+//!     estimate = info.posterior;
+//!     plot.push_step(t as f64, &info);
+//! }
+//! // Inside a UI callback: `ui.add(&plot);`
+//! plot.len();
+//! // => 3
+//! ```
+
+use std::collections::VecDeque;
+
+use egui::{Color32, Response, Ui, Widget};
+use egui_plot::{FilledArea, Legend, Line, Plot, PlotPoints, Points};
+
+use crate::step::StepInfo;
+use crate::LQE;
+
+/// `Sample` is a recorded step: its `time`, the measurement if there was
+/// one, and the estimate after it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Sample {
+    time: f64,
+    measurement: Option<f64>,
+    estimate: LQE
+}
+
+/// `FilterPlot` plots the latest `capacity` steps of a filter.
+///
+/// The confidence band spans `sigmas` standard deviations to either side of
+/// the estimate. `height` is the height of the widget in points, or `None`
+/// to fill the available space.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FilterPlot {
+    pub name: String,
+    pub sigmas: f64,
+    pub height: Option<f32>,
+    capacity: usize,
+    samples: VecDeque<Sample>
+}
+
+impl FilterPlot {
+    /// `new` creates an empty plot called `name`, also identifying it in the
+    /// GUI, with a band of two standard deviations.
+    pub fn new(name: impl Into<String>, capacity: usize) -> Self {
+        FilterPlot {
+            name: name.into(),
+            sigmas: 2.0,
+            height: Some(200.0),
+            capacity,
+            samples: VecDeque::with_capacity(capacity)
+        }
+    }
+
+    /// `push` records the `estimate` at `time`, after `measurement` if
+    /// there was one, dropping the oldest step when full.
+    pub fn push(&mut self, time: f64, measurement: Option<f64>, estimate: LQE) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(Sample {
+            time,
+            measurement,
+            estimate
+        });
+    }
+
+    /// `push_step` records the measurement and posterior of `info` at
+    /// `time`, e.g. from an observer registered with
+    /// `TimedFilter::set_observer`.
+    pub fn push_step(&mut self, time: f64, info: &StepInfo) {
+        self.push(time, Some(info.measurement), info.posterior);
+    }
+
+    /// `clear` forgets all recorded steps.
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// `len` returns the number of recorded steps.
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// `is_empty` tells whether no steps are recorded.
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+}
+
+impl Widget for &FilterPlot {
+    fn ui(self, ui: &mut Ui) -> Response {
+        let times: Vec<f64> = self.samples.iter().map(|sample| sample.time).collect();
+        let (lower, upper): (Vec<f64>, Vec<f64>) = self
+            .samples
+            .iter()
+            .map(|sample| {
+                let spread = self.sigmas * sample.estimate.variance.sqrt();
+                (sample.estimate.measurement - spread, sample.estimate.measurement + spread)
+            })
+            .unzip();
+        let estimates: PlotPoints = self
+            .samples
+            .iter()
+            .map(|sample| [sample.time, sample.estimate.measurement])
+            .collect();
+        let measurements: PlotPoints = self
+            .samples
+            .iter()
+            .filter_map(|sample| sample.measurement.map(|measurement| [sample.time, measurement]))
+            .collect();
+
+        let color = Color32::from_rgb(66, 133, 244);
+        let mut plot = Plot::new(&self.name).legend(Legend::default());
+        if let Some(height) = self.height {
+            plot = plot.height(height);
+        }
+        plot.show(ui, |plot| {
+            plot.add(
+                FilledArea::new(format!("±{}σ", self.sigmas), &times, &lower, &upper)
+                    .fill_color(color.gamma_multiply(0.25))
+            );
+            plot.line(Line::new("estimate", estimates).color(color));
+            plot.points(Points::new("measurement", measurements).radius(2.0));
+        })
+        .response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_latest_steps() {
+        let mut plot = FilterPlot::new("test", 2);
+        for t in 0..3 {
+            let estimate = LQE {
+                measurement: f64::from(t),
+                variance: 1.0
+            };
+            plot.push(f64::from(t), if t == 1 { None } else { Some(0.0) }, estimate);
+        }
+        assert_eq!(plot.len(), 2);
+        assert_eq!(plot.samples[0].time, 1.0);
+
+        // Draw a frame without a window.
+        let context = egui::Context::default();
+        let mut drawn = false;
+        let mut output = context.run_ui(egui::RawInput::default(), |ui| {
+            drawn = ui.add(&plot).rect.width() > 0.0;
+        });
+        output.textures_delta.clear();
+        assert!(drawn);
+    }
+}
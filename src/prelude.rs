@@ -0,0 +1,31 @@
+//! The most commonly used types and traits, for glob importing.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::prelude::*;
+//!
+//! let mut filter = DynamicKalmanFilter::<DenseMatrix>::new(1, 1);
+//! filter.next(&DenseMatrix::vector(&[2.0])).unwrap();
+//! filter.state.get(0, 0);
+//! // => 1.0
+//! ```
+
+pub use crate::adaptive::{RollingVariance, SageHusaFilter, StrongTrackingFilter, VariationalFilter};
+pub use crate::backtest::{backtest, Comparison, ErrorStatistics, ReferenceTracker};
+pub use crate::baseline::{Estimator, ExponentialMovingAverage, MovingAverage, SavitzkyGolay};
+pub use crate::bayes::BayesFilter;
+pub use crate::diagnostics::{Cusum, NisMonitor};
+pub use crate::error::Error;
+pub use crate::matrix::{DenseMatrix, Matrix};
+pub use crate::measurement::{Measurement, SensorId};
+pub use crate::mixture::GaussianSumFilter;
+pub use crate::model::SystemModel;
+pub use crate::multiple_model::{FilterBank, Gpb2, Imm};
+#[cfg(feature = "nalgebra")]
+pub use crate::multivariate::KalmanFilter;
+pub use crate::multivariate::DynamicKalmanFilter;
+pub use crate::step::StepInfo;
+pub use crate::structural::StructuralModel;
+pub use crate::timed::{Ticks, TimedFilter, Timestamp};
+pub use crate::LQE;
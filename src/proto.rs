@@ -0,0 +1,238 @@
+//! Protocol Buffers messages for measurements, estimates and filters.
+//!
+//! With the `prost` feature enabled this module provides the messages of
+//! `proto/lqe.proto` as `prost` types, written out by hand so that building
+//! the crate doesn't need `protoc`, and conversions between them and the
+//! types of this crate. Services in other languages generate their types
+//! from the same schema.
+//!
+//! Decoding a `Matrix` or `FilterSnapshot` checks its dimensions and returns
+//! `Error::DimensionMismatch` if they don't add up.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::matrix::DenseMatrix;
+//! use lqe::multivariate::DynamicKalmanFilter;
+//! use lqe::proto::FilterSnapshot;
+//! use prost::Message;
+//!
+//! let filter: DynamicKalmanFilter<DenseMatrix> = DynamicKalmanFilter::new(2, 1);
+//! let bytes = FilterSnapshot::from(&filter).encode_to_vec();
+//! let decoded: DynamicKalmanFilter<DenseMatrix> = FilterSnapshot::decode(&bytes[..]).unwrap().to_filter().unwrap();
+//! decoded == filter;
+//! // => true
+//! ```
+
+use crate::error::Error;
+use crate::matrix;
+use crate::measurement::SensorId;
+use crate::multivariate::DynamicKalmanFilter;
+use crate::LQE;
+
+/// `Measurement` is a `measurement::Measurement` with a timestamp in seconds.
+#[derive(Clone, Copy, PartialEq, prost::Message)]
+pub struct Measurement {
+    #[prost(double, tag = "1")]
+    pub timestamp: f64,
+    #[prost(double, tag = "2")]
+    pub value: f64,
+    #[prost(double, tag = "3")]
+    pub variance: f64,
+    #[prost(uint32, tag = "4")]
+    pub sensor_id: u32
+}
+
+impl From<&crate::measurement::Measurement<f64>> for Measurement {
+    fn from(measurement: &crate::measurement::Measurement<f64>) -> Self {
+        Measurement {
+            timestamp: measurement.timestamp,
+            value: measurement.value,
+            variance: measurement.variance,
+            sensor_id: measurement.sensor_id.0
+        }
+    }
+}
+
+impl From<&Measurement> for crate::measurement::Measurement<f64> {
+    fn from(measurement: &Measurement) -> Self {
+        crate::measurement::Measurement {
+            timestamp: measurement.timestamp,
+            value: measurement.value,
+            variance: measurement.variance,
+            sensor_id: SensorId(measurement.sensor_id)
+        }
+    }
+}
+
+/// `Estimate` is the estimate of an `LQE`.
+#[derive(Clone, Copy, PartialEq, prost::Message)]
+pub struct Estimate {
+    #[prost(double, tag = "1")]
+    pub measurement: f64,
+    #[prost(double, tag = "2")]
+    pub variance: f64
+}
+
+impl From<&LQE> for Estimate {
+    fn from(lqe: &LQE) -> Self {
+        Estimate {
+            measurement: lqe.measurement,
+            variance: lqe.variance
+        }
+    }
+}
+
+impl From<&Estimate> for LQE {
+    fn from(estimate: &Estimate) -> Self {
+        LQE {
+            measurement: estimate.measurement,
+            variance: estimate.variance
+        }
+    }
+}
+
+/// `Matrix` is a matrix of any `matrix::Matrix` backend, with its elements
+/// in row-major order.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Matrix {
+    #[prost(uint32, tag = "1")]
+    pub rows: u32,
+    #[prost(uint32, tag = "2")]
+    pub columns: u32,
+    #[prost(double, repeated, tag = "3")]
+    pub values: Vec<f64>
+}
+
+impl Matrix {
+    /// `from_matrix` copies the elements of `matrix`.
+    pub fn from_matrix<T: matrix::Matrix>(matrix: &T) -> Self {
+        let (rows, columns) = matrix.shape();
+        let mut values = Vec::with_capacity(rows * columns);
+        for row in 0..rows {
+            for column in 0..columns {
+                values.push(matrix.get(row, column));
+            }
+        }
+        Matrix {
+            rows: rows as u32,
+            columns: columns as u32,
+            values
+        }
+    }
+
+    /// `to_matrix` copies the elements into a matrix of backend `T`, failing
+    /// if their number isn't `rows` times `columns`.
+    pub fn to_matrix<T: matrix::Matrix>(&self) -> Result<T, Error> {
+        let (rows, columns) = (self.rows as usize, self.columns as usize);
+        if self.values.len() != rows * columns {
+            return Err(Error::DimensionMismatch);
+        }
+        let mut matrix = T::zeros(rows, columns);
+        for (i, value) in self.values.iter().enumerate() {
+            matrix.set(i / columns, i % columns, *value);
+        }
+        Ok(matrix)
+    }
+}
+
+/// `FilterSnapshot` is the state and model of a `DynamicKalmanFilter`, from
+/// which the filter can be restored to carry on where it left off.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FilterSnapshot {
+    #[prost(message, optional, tag = "1")]
+    pub state: Option<Matrix>,
+    #[prost(message, optional, tag = "2")]
+    pub covariance: Option<Matrix>,
+    #[prost(message, optional, tag = "3")]
+    pub transition: Option<Matrix>,
+    #[prost(message, optional, tag = "4")]
+    pub process_noise: Option<Matrix>,
+    #[prost(message, optional, tag = "5")]
+    pub observation: Option<Matrix>,
+    #[prost(message, optional, tag = "6")]
+    pub measurement_noise: Option<Matrix>
+}
+
+impl<T: matrix::Matrix> From<&DynamicKalmanFilter<T>> for FilterSnapshot {
+    fn from(filter: &DynamicKalmanFilter<T>) -> Self {
+        FilterSnapshot {
+            state: Some(Matrix::from_matrix(&filter.state)),
+            covariance: Some(Matrix::from_matrix(&filter.covariance)),
+            transition: Some(Matrix::from_matrix(&filter.transition)),
+            process_noise: Some(Matrix::from_matrix(&filter.process_noise)),
+            observation: Some(Matrix::from_matrix(&filter.observation)),
+            measurement_noise: Some(Matrix::from_matrix(&filter.measurement_noise))
+        }
+    }
+}
+
+impl FilterSnapshot {
+    /// `to_filter` restores the filter, failing if a matrix is missing or
+    /// the shapes don't fit together.
+    pub fn to_filter<T: matrix::Matrix>(&self) -> Result<DynamicKalmanFilter<T>, Error> {
+        fn field<T: matrix::Matrix>(matrix: &Option<Matrix>) -> Result<T, Error> {
+            matrix.as_ref().ok_or(Error::DimensionMismatch)?.to_matrix()
+        }
+        let filter = DynamicKalmanFilter {
+            state: field(&self.state)?,
+            covariance: field(&self.covariance)?,
+            transition: field(&self.transition)?,
+            process_noise: field(&self.process_noise)?,
+            observation: field(&self.observation)?,
+            measurement_noise: field(&self.measurement_noise)?
+        };
+        filter.validate()?;
+        Ok(filter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::{DenseMatrix, Matrix as _};
+    use prost::Message;
+
+    #[test]
+    fn round_trips_through_bytes() {
+        let measurement = crate::measurement::Measurement {
+            timestamp: 1.5,
+            value: 10.0,
+            variance: 2.0,
+            sensor_id: SensorId(3)
+        };
+        let bytes = Measurement::from(&measurement).encode_to_vec();
+        let decoded = Measurement::decode(&bytes[..]).unwrap();
+        assert_eq!(crate::measurement::Measurement::from(&decoded), measurement);
+
+        let lqe = LQE {
+            measurement: 7.0,
+            variance: 2.0
+        };
+        let decoded = Estimate::decode(&Estimate::from(&lqe).encode_to_vec()[..]).unwrap();
+        assert_eq!(LQE::from(&decoded), lqe);
+
+        let mut filter = DynamicKalmanFilter::<DenseMatrix>::new(2, 1);
+        filter.transition = DenseMatrix::from_row_slices(&[&[1.0, 1.0], &[0.0, 1.0]]);
+        filter.next(&DenseMatrix::vector(&[2.0])).unwrap();
+        let decoded = FilterSnapshot::decode(&FilterSnapshot::from(&filter).encode_to_vec()[..]).unwrap();
+        assert_eq!(decoded.to_filter::<DenseMatrix>().unwrap(), filter);
+    }
+
+    #[test]
+    fn rejects_inconsistent_dimensions() {
+        let matrix = Matrix {
+            rows: 2,
+            columns: 2,
+            values: vec![1.0, 2.0, 3.0]
+        };
+        assert_eq!(matrix.to_matrix::<DenseMatrix>(), Err(Error::DimensionMismatch));
+
+        let filter = DynamicKalmanFilter::<DenseMatrix>::new(2, 1);
+        let mut snapshot = FilterSnapshot::from(&filter);
+        snapshot.measurement_noise = None;
+        assert_eq!(snapshot.to_filter::<DenseMatrix>(), Err(Error::DimensionMismatch));
+        snapshot.measurement_noise = Some(Matrix::from_matrix(&DenseMatrix::identity(2)));
+        assert_eq!(snapshot.to_filter::<DenseMatrix>(), Err(Error::DimensionMismatch));
+    }
+}
@@ -0,0 +1,158 @@
+//! The predict - update protocol in the type system.
+//!
+//! A filter step is a prediction followed by at most one update, and the
+//! estimate is only meaningful once the step is complete. `Updated` and
+//! `Predicted` wrap an estimate, the `State`, in either phase and only offer
+//! the calls valid in it: an `Updated` state can be predicted and read, a
+//! `Predicted` one updated or coasted through a step without a measurement.
+//! Updating twice or reading the result of a half-done step doesn't compile.
+//!
+//! Both the scalar `LQE` and `DynamicKalmanFilter` can be wrapped.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::protocol::Updated;
+//! use lqe::LQE;
+//!
+//! let estimate = Updated::new(LQE { measurement: 7.0, variance: 1.5 });
+//! let estimate = estimate.predict(0.0, 0.5).update(10.0, 2.0);
+//! estimate.result();
+//! // => (8.5, 1.0)
+//! ```
+//!
+//! ```compile_fail
+//! use lqe::protocol::Updated;
+//! use lqe::LQE;
+//!
+//! let estimate = Updated::new(LQE { measurement: 7.0, variance: 1.5 });
+//! estimate.predict(0.0, 0.5).result();
+//! ```
+
+use crate::error::Error;
+use crate::matrix::Matrix;
+use crate::multivariate::DynamicKalmanFilter;
+use crate::step;
+use crate::LQE;
+
+/// `Updated` is a `State` whose latest step is complete.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Updated<State>(State);
+
+/// `Predicted` is a `State` that has been predicted forward and awaits the
+/// measurement of the step.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Predicted<State>(State);
+
+impl<State> Updated<State> {
+    /// `new` wraps a `state` at the end of a step, e.g. the initial
+    /// estimate.
+    pub fn new(state: State) -> Self {
+        Updated(state)
+    }
+
+    /// `get` returns the wrapped state.
+    pub fn get(&self) -> &State {
+        &self.0
+    }
+
+    /// `into_inner` unwraps the state.
+    pub fn into_inner(self) -> State {
+        self.0
+    }
+}
+
+impl<State> Predicted<State> {
+    /// `coast` completes a step without a measurement, keeping the
+    /// prediction as the estimate.
+    pub fn coast(self) -> Updated<State> {
+        Updated(self.0)
+    }
+}
+
+impl Updated<LQE> {
+    /// `predict` starts the next step, as `step::predict`.
+    pub fn predict(self, control: f64, process_noise: f64) -> Predicted<LQE> {
+        Predicted(step::predict(self.0, control, process_noise))
+    }
+
+    /// `result` returns the estimate as a tuple value.
+    pub fn result(&self) -> (f64, f64) {
+        self.0.result()
+    }
+}
+
+impl Predicted<LQE> {
+    /// `update` completes the step with `measurement`, as `step::update`.
+    pub fn update(self, measurement: f64, measurement_noise: f64) -> Updated<LQE> {
+        Updated(step::update(self.0, measurement, measurement_noise).posterior)
+    }
+}
+
+impl<T: Matrix> Updated<DynamicKalmanFilter<T>> {
+    /// `predict` starts the next step, as `DynamicKalmanFilter::predict`.
+    pub fn predict(mut self) -> Predicted<DynamicKalmanFilter<T>> {
+        self.0.predict();
+        Predicted(self.0)
+    }
+
+    /// `result` returns the state estimate and its covariance.
+    pub fn result(&self) -> (T, T) {
+        self.0.result()
+    }
+}
+
+impl<T: Matrix> Predicted<DynamicKalmanFilter<T>> {
+    /// `update` completes the step with `measurement`, as
+    /// `DynamicKalmanFilter::update`. If the measurement can't be applied
+    /// the filter is handed back unchanged, still awaiting it, with the
+    /// error.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use lqe::matrix::{DenseMatrix, Matrix};
+    /// use lqe::multivariate::DynamicKalmanFilter;
+    /// use lqe::protocol::Updated;
+    /// let filter = Updated::new(DynamicKalmanFilter::<DenseMatrix>::new(1, 1));
+    /// let filter = filter.predict().update(&DenseMatrix::vector(&[2.0])).unwrap();
+    /// filter.result().0.get(0, 0);
+    /// // => 1.0
+    /// ```
+    pub fn update(mut self, measurement: &T) -> Result<Updated<DynamicKalmanFilter<T>>, (Self, Error)> {
+        match self.0.update(measurement) {
+            Ok(()) => Ok(Updated(self.0)),
+            Err(error) => Err((self, error))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::DenseMatrix;
+
+    #[test]
+    fn steps_scalar_estimate() {
+        let estimate = Updated::new(LQE {
+            measurement: 7.0,
+            variance: 1.5
+        });
+
+        let estimate = estimate.predict(0.0, 0.5).update(10.0, 2.0);
+        assert_eq!(estimate.result(), (8.5, 1.0));
+        assert_eq!(estimate.predict(1.0, 1.0).coast().result(), (9.5, 2.0));
+    }
+
+    #[test]
+    fn hands_back_rejected_filter() {
+        let mut filter = DynamicKalmanFilter::<DenseMatrix>::new(1, 1);
+        filter.measurement_noise = DenseMatrix::vector(&[-1.0]);
+        filter.covariance = DenseMatrix::zeros(1, 1);
+
+        let predicted = Updated::new(filter.clone()).predict();
+        let (predicted, error) = predicted.update(&DenseMatrix::vector(&[1.0])).unwrap_err();
+        assert_eq!(error, Error::SingularInnovation);
+        assert_eq!(predicted.coast().into_inner(), filter);
+    }
+}
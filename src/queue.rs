@@ -0,0 +1,157 @@
+//! Reordering of measurements from several sensors.
+//!
+//! Sensors on different links deliver their measurements with different
+//! latencies, so they rarely arrive in the order they were taken. Filters
+//! on the other hand can only move forward in time. A `MeasurementQueue`
+//! holds every measurement back for a reordering `window` after it was
+//! taken and releases them in time order, so anything arriving up to
+//! `window` seconds late still falls into place.
+//!
+//! Measurements arriving after a later one has been released are dropped
+//! and counted, see `MeasurementQueue::dropped`.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::measurement::{Measurement, SensorId};
+//! use lqe::queue::MeasurementQueue;
+//!
+//! let mut queue = MeasurementQueue::new(0.5);
+//! let reading = |timestamp, sensor| Measurement { timestamp, value: 1.0, variance: 1.0, sensor_id: SensorId(sensor) };
+//! queue.push(1.2, reading(1.2, 1));
+//! queue.push(1.0, reading(1.0, 2));
+//! queue.push(1.4, reading(1.4, 1));
+//! queue.ready(1.7).iter().map(|(_, m)| m.sensor_id.0).collect::<Vec<_>>();
+//! // => [2, 1]
+//! ```
+
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use crate::timed::Timestamp;
+
+/// `MeasurementQueue` buffers measurements `M` taken at timestamps `T` and
+/// releases them in time order once they are `window` seconds old.
+///
+/// Measurements with the same timestamp are released in the order they
+/// were pushed.
+#[derive(Clone, Debug)]
+pub struct MeasurementQueue<M, T = Instant> {
+    window: f64,
+    pending: VecDeque<(T, M)>,
+    released: Option<T>,
+    dropped: usize
+}
+
+impl<M, T: Timestamp> MeasurementQueue<M, T> {
+    /// `new` creates an empty queue with a reordering `window` in seconds.
+    pub fn new(window: f64) -> Self {
+        MeasurementQueue {
+            window,
+            pending: VecDeque::new(),
+            released: None,
+            dropped: 0
+        }
+    }
+
+    /// `push` buffers `measurement` taken at `timestamp`, returning `false`
+    /// and dropping it if a later measurement has already been released.
+    pub fn push(&mut self, timestamp: T, measurement: M) -> bool {
+        if let Some(released) = &self.released {
+            if released.seconds_since(&timestamp) > 0.0 {
+                self.dropped += 1;
+                return false;
+            }
+        }
+        // Timestamps only report elapsed time, so `a` is no later than `b`
+        // when no time has passed from `b` to `a`.
+        let index = self
+            .pending
+            .partition_point(|(pending, _)| pending.seconds_since(&timestamp) <= 0.0);
+        self.pending.insert(index, (timestamp, measurement));
+        true
+    }
+
+    /// `pop` releases the earliest measurement if it is at least `window`
+    /// seconds old at `now`.
+    pub fn pop(&mut self, now: T) -> Option<(T, M)> {
+        let (timestamp, _) = self.pending.front()?;
+        if now.seconds_since(timestamp) < self.window {
+            return None;
+        }
+        self.release()
+    }
+
+    /// `ready` releases all measurements at least `window` seconds old at
+    /// `now`, in time order.
+    pub fn ready(&mut self, now: T) -> Vec<(T, M)> {
+        let mut ready = Vec::new();
+        while let Some(entry) = self.pop(now) {
+            ready.push(entry);
+        }
+        ready
+    }
+
+    /// `flush` releases all buffered measurements regardless of their age,
+    /// e.g. at the end of a log.
+    pub fn flush(&mut self) -> Vec<(T, M)> {
+        let mut flushed = Vec::with_capacity(self.pending.len());
+        while let Some(entry) = self.release() {
+            flushed.push(entry);
+        }
+        flushed
+    }
+
+    /// `release` removes the earliest measurement from the queue.
+    fn release(&mut self) -> Option<(T, M)> {
+        let entry = self.pending.pop_front()?;
+        self.released = Some(entry.0);
+        Some(entry)
+    }
+
+    /// `len` returns the number of buffered measurements.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// `is_empty` tells whether no measurements are buffered.
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// `dropped` returns the number of measurements that arrived too late.
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// `window` returns the reordering window in seconds.
+    pub fn window(&self) -> f64 {
+        self.window
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timed::Ticks;
+
+    #[test]
+    fn releases_in_time_order() {
+        let mut queue = MeasurementQueue::new(0.1);
+        for (timestamp, sensor) in &[(30, 'a'), (10, 'b'), (20, 'c'), (10, 'd')] {
+            assert!(queue.push(Ticks::<100>(*timestamp), *sensor));
+        }
+        assert_eq!(queue.pop(Ticks(19)), None);
+        let released: Vec<_> = queue.ready(Ticks(30)).into_iter().map(|(_, sensor)| sensor).collect();
+        assert_eq!(released, vec!['b', 'd', 'c']);
+        assert_eq!(queue.len(), 1);
+
+        // Too late for the filter, which has already moved past 20.
+        assert!(!queue.push(Ticks(15), 'e'));
+        assert!(queue.push(Ticks(20), 'f'));
+        assert_eq!(queue.dropped(), 1);
+        let flushed: Vec<_> = queue.flush().into_iter().map(|(_, sensor)| sensor).collect();
+        assert_eq!(flushed, vec!['f', 'a']);
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,387 @@
+//! A Redis streams connector.
+//!
+//! With the `redis` feature enabled a `StreamConnector` reads measurement
+//! records from a Redis stream as a member of a consumer group, folds them
+//! into a `TimedFilter` and appends the estimates to an output stream.
+//!
+//! Input records have the fields `timestamp` in seconds, `value`, `variance`
+//! and optionally `sensor_id`; records missing one are acknowledged and
+//! skipped. Output records have the fields `timestamp`, `measurement`,
+//! `variance` and `source`, the id of the input record.
+//!
+//! Every batch is committed in one transaction: the estimates are appended,
+//! the filter state is checkpointed to a hash along with the id of the last
+//! record folded in, and the records are acknowledged. If the connector dies
+//! before the transaction, its successor restores the checkpoint and gets
+//! the unacknowledged records again, so every record is processed at least
+//! once and, thanks to the checkpointed id, folded into the estimate exactly
+//! once.
+//!
+//! # Example:
+//!
+//! ```no_run
+//! use lqe::LQE;
+//! use lqe::redis::StreamConnector;
+//! use lqe::timed::TimedFilter;
+//!
+//! let client = redis::Client::open("redis://127.0.0.1/").unwrap();
+//! let mut connection = client.get_connection().unwrap();
+//! let filter = TimedFilter::new(LQE { measurement: 20.0, variance: 100.0 }, 0.01);
+//! let mut connector = StreamConnector::new(filter, "temperature", "temperature:estimates", "lqe", "worker-1");
+//! loop {
+//!     connector.poll(&mut connection).unwrap();
+//! }
+//! ```
+
+use std::collections::HashMap;
+
+use ::redis::streams::{StreamId, StreamReadOptions, StreamReadReply};
+use ::redis::{Commands, ConnectionLike, RedisResult};
+
+use crate::measurement::{Measurement, SensorId};
+use crate::timed::TimedFilter;
+use crate::LQE;
+
+/// `StreamConnector` filters the records of stream `input` into stream
+/// `output`, reading them as `consumer` of consumer group `group` at most
+/// `batch_size` at a time and checkpointing to the hash `checkpoint`.
+///
+/// `block_ms` is how long a poll waits for new records.
+pub struct StreamConnector {
+    pub filter: TimedFilter<f64>,
+    pub input: String,
+    pub output: String,
+    pub group: String,
+    pub consumer: String,
+    pub checkpoint: String,
+    pub batch_size: usize,
+    pub block_ms: usize,
+    last_id: Option<String>,
+    restored: bool,
+    pending: bool
+}
+
+impl StreamConnector {
+    /// `new` creates a connector checkpointing to `<input>:<group>:checkpoint`.
+    /// The filter is used as it is unless a checkpoint is found.
+    pub fn new(
+        filter: TimedFilter<f64>,
+        input: impl Into<String>,
+        output: impl Into<String>,
+        group: impl Into<String>,
+        consumer: impl Into<String>
+    ) -> Self {
+        let (input, group) = (input.into(), group.into());
+        StreamConnector {
+            filter,
+            checkpoint: format!("{}:{}:checkpoint", input, group),
+            input,
+            output: output.into(),
+            group,
+            consumer: consumer.into(),
+            batch_size: 100,
+            block_ms: 1000,
+            last_id: None,
+            restored: false,
+            pending: false
+        }
+    }
+
+    /// `poll` processes one batch of records and returns its size. The first
+    /// poll creates the consumer group if need be and restores the
+    /// checkpoint, and the polls after it work through the records delivered
+    /// to `consumer` but never acknowledged before reading new ones.
+    ///
+    /// If committing the batch fails, the next poll starts over from the
+    /// checkpoint.
+    pub fn poll<C: ConnectionLike>(&mut self, connection: &mut C) -> RedisResult<usize> {
+        if !self.restored {
+            self.restore(connection)?;
+        }
+        let mut options = StreamReadOptions::default()
+            .group(&self.group, &self.consumer)
+            .count(self.batch_size);
+        if !self.pending {
+            options = options.block(self.block_ms);
+        }
+        let start = if self.pending { "0" } else { ">" };
+        let reply: StreamReadReply = connection.xread_options(&[&self.input], &[start], &options)?;
+        let records: Vec<StreamId> = reply.keys.into_iter().flat_map(|key| key.ids).collect();
+        if records.len() < self.batch_size {
+            self.pending = false;
+        }
+        if records.is_empty() {
+            return Ok(0);
+        }
+
+        let (measurement, variance) = self.filter.result();
+        let snapshot = (
+            LQE {
+                measurement,
+                variance
+            },
+            self.filter.last_timestamp(),
+            self.last_id.clone()
+        );
+        let estimates = self.fold(&records);
+        let mut pipeline = ::redis::pipe();
+        pipeline.atomic();
+        for (source, timestamp, estimate) in &estimates {
+            pipeline
+                .xadd(
+                    &self.output,
+                    "*",
+                    &[
+                        ("timestamp", timestamp.to_string()),
+                        ("measurement", estimate.measurement.to_string()),
+                        ("variance", estimate.variance.to_string()),
+                        ("source", source.clone())
+                    ]
+                )
+                .ignore();
+        }
+        pipeline.hset_multiple(&self.checkpoint, &self.checkpoint_fields()).ignore();
+        let ids: Vec<&str> = records.iter().map(|record| record.id.as_str()).collect();
+        pipeline.xack(&self.input, &self.group, &ids).ignore();
+        if let Err(error) = pipeline.query::<()>(connection) {
+            // Take the batch back out of the estimate, so that it is folded
+            // in again when redelivered even if there is no checkpoint yet.
+            let (estimate, timestamp, last_id) = snapshot;
+            self.filter.resume(estimate, timestamp);
+            self.last_id = last_id;
+            self.restored = false;
+            return Err(error);
+        }
+        Ok(records.len())
+    }
+
+    /// `restore` creates the consumer group, reading the stream from its
+    /// start, and resumes the filter from the checkpoint if there is one.
+    fn restore<C: ConnectionLike>(&mut self, connection: &mut C) -> RedisResult<()> {
+        let created: RedisResult<()> = connection.xgroup_create_mkstream(&self.input, &self.group, "0");
+        if let Err(error) = created {
+            if error.code() != Some("BUSYGROUP") {
+                return Err(error);
+            }
+        }
+        let checkpoint: HashMap<String, String> = connection.hgetall(&self.checkpoint)?;
+        self.resume_from(&checkpoint);
+        self.restored = true;
+        self.pending = true;
+        Ok(())
+    }
+
+    /// `fold` folds the records not yet in the estimate into it, returning
+    /// the id, timestamp and estimate after each.
+    fn fold(&mut self, records: &[StreamId]) -> Vec<(String, f64, LQE)> {
+        let mut estimates = Vec::new();
+        for record in records {
+            if let Some(last_id) = &self.last_id {
+                if parse_id(&record.id) <= parse_id(last_id) {
+                    continue;
+                }
+            }
+            if let Some(measurement) = measurement(record) {
+                let estimate = self.filter.apply(&measurement);
+                estimates.push((record.id.clone(), measurement.timestamp, estimate));
+            }
+            self.last_id = Some(record.id.clone());
+        }
+        estimates
+    }
+
+    /// `checkpoint_fields` returns the fields of the checkpoint hash.
+    fn checkpoint_fields(&self) -> Vec<(&'static str, String)> {
+        let (measurement, variance) = self.filter.result();
+        let mut fields = vec![
+            ("id", self.last_id.clone().unwrap_or_default()),
+            ("measurement", measurement.to_string()),
+            ("variance", variance.to_string())
+        ];
+        if let Some(timestamp) = self.filter.last_timestamp() {
+            fields.push(("timestamp", timestamp.to_string()));
+        }
+        fields
+    }
+
+    /// `resume_from` resumes the filter from the fields of a checkpoint,
+    /// leaving it as it is if they are incomplete.
+    fn resume_from(&mut self, checkpoint: &HashMap<String, String>) {
+        let field = |name: &str| checkpoint.get(name).and_then(|value| value.parse::<f64>().ok());
+        if let (Some(id), Some(measurement), Some(variance)) =
+            (checkpoint.get("id"), field("measurement"), field("variance"))
+        {
+            let estimate = LQE {
+                measurement,
+                variance
+            };
+            self.filter.resume(estimate, field("timestamp"));
+            self.last_id = Some(id.clone()).filter(|id| !id.is_empty());
+        }
+    }
+}
+
+/// `parse_id` splits a stream id `<milliseconds>-<sequence>` into its parts,
+/// which order the ids.
+fn parse_id(id: &str) -> (u64, u64) {
+    let mut parts = id.splitn(2, '-');
+    let milliseconds = parts.next().and_then(|part| part.parse().ok()).unwrap_or(0);
+    let sequence = parts.next().and_then(|part| part.parse().ok()).unwrap_or(0);
+    (milliseconds, sequence)
+}
+
+/// `measurement` reads the measurement of a record, if it has one.
+fn measurement(record: &StreamId) -> Option<Measurement<f64>> {
+    let field = |name: &str| record.get::<String>(name).and_then(|value| value.parse().ok());
+    Some(Measurement {
+        timestamp: field("timestamp")?,
+        value: field("value")?,
+        variance: field("variance")?,
+        sensor_id: SensorId(record.get::<String>("sensor_id").and_then(|id| id.parse().ok()).unwrap_or(0))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::redis::{ErrorKind, RedisError, Value};
+
+    /// `Connection` answers the commands of a poll with `records`, failing
+    /// the first `failures` commits.
+    struct Connection {
+        records: Vec<StreamId>,
+        failures: usize,
+        commits: Vec<String>
+    }
+
+    fn bulk(value: &str) -> Value {
+        Value::BulkString(value.as_bytes().to_vec())
+    }
+
+    impl ConnectionLike for Connection {
+        fn req_packed_command(&mut self, cmd: &[u8]) -> RedisResult<Value> {
+            let command = String::from_utf8_lossy(cmd);
+            if command.contains("XREADGROUP") {
+                let records = self
+                    .records
+                    .iter()
+                    .map(|record| {
+                        let mut fields = Vec::new();
+                        for (name, value) in &record.map {
+                            fields.push(bulk(name));
+                            fields.push(value.clone());
+                        }
+                        Value::Array(vec![bulk(&record.id), Value::Array(fields)])
+                    })
+                    .collect();
+                Ok(Value::Array(vec![Value::Array(vec![bulk("in"), Value::Array(records)])]))
+            } else if command.contains("HGETALL") {
+                Ok(Value::Array(Vec::new()))
+            } else {
+                Ok(Value::Okay)
+            }
+        }
+
+        fn req_packed_commands(&mut self, cmd: &[u8], offset: usize, _count: usize) -> RedisResult<Vec<Value>> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(RedisError::from((ErrorKind::IoError, "connection lost")));
+            }
+            self.commits.push(String::from_utf8_lossy(cmd).into_owned());
+            Ok(vec![Value::Array(vec![Value::Okay; offset - 1])])
+        }
+
+        fn get_db(&self) -> i64 {
+            0
+        }
+
+        fn check_connection(&mut self) -> bool {
+            true
+        }
+
+        fn is_open(&self) -> bool {
+            true
+        }
+    }
+
+    fn record(id: &str, fields: &[(&str, &str)]) -> StreamId {
+        StreamId {
+            id: id.to_string(),
+            map: fields
+                .iter()
+                .map(|(name, value)| (name.to_string(), Value::BulkString(value.as_bytes().to_vec())))
+                .collect()
+        }
+    }
+
+    fn connector() -> StreamConnector {
+        let filter = TimedFilter::new(
+            LQE {
+                measurement: 7.0,
+                variance: 2.0
+            },
+            0.5
+        );
+        StreamConnector::new(filter, "in", "out", "lqe", "worker")
+    }
+
+    #[test]
+    fn folds_each_record_once() {
+        let mut connector = connector();
+        assert_eq!(connector.checkpoint, "in:lqe:checkpoint");
+        let records = [
+            record("1-0", &[("timestamp", "0"), ("value", "10"), ("variance", "2")]),
+            record("1-1", &[("timestamp", "1"), ("value", "oops"), ("variance", "2")]),
+            record("2-0", &[("timestamp", "2"), ("value", "8.5"), ("variance", "2"), ("sensor_id", "3")])
+        ];
+        let estimates = connector.fold(&records);
+        assert_eq!(estimates.len(), 2);
+        assert_eq!(estimates[0], ("1-0".to_string(), 0.0, LQE { measurement: 8.5, variance: 1.0 }));
+        assert_eq!(estimates[1].2.result(), (8.5, 1.0));
+        assert_eq!(connector.last_id.as_deref(), Some("2-0"));
+        // Redelivered records are acknowledged without being folded in again.
+        assert!(connector.fold(&records[1..]).is_empty());
+        assert!(parse_id("10-0") > parse_id("9-5"));
+    }
+
+    #[test]
+    fn resumes_from_checkpoint() {
+        let mut connector = connector();
+        connector.fold(&[record("5-2", &[("timestamp", "3"), ("value", "10"), ("variance", "2")])]);
+        let checkpoint: HashMap<String, String> = connector
+            .checkpoint_fields()
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect();
+
+        let mut restored = self::connector();
+        restored.resume_from(&checkpoint);
+        assert_eq!(restored.last_id.as_deref(), Some("5-2"));
+        assert_eq!(restored.filter.result(), (8.5, 1.0));
+        assert_eq!(restored.filter.last_timestamp(), Some(3.0));
+
+        // An empty checkpoint leaves the filter alone.
+        let mut fresh = self::connector();
+        fresh.resume_from(&HashMap::new());
+        assert_eq!((fresh.last_id, fresh.filter.result()), (None, (7.0, 2.0)));
+    }
+
+    #[test]
+    fn refolds_batch_after_failed_commit() {
+        let mut connector = connector();
+        let mut connection = Connection {
+            records: vec![record("1-0", &[("timestamp", "0"), ("value", "10"), ("variance", "2")])],
+            failures: 1,
+            commits: Vec::new()
+        };
+        // The first commit fails before any checkpoint was written.
+        assert!(connector.poll(&mut connection).is_err());
+        assert_eq!((connector.last_id.as_deref(), connector.filter.result()), (None, (7.0, 2.0)));
+
+        // The redelivered record is folded in and its estimate emitted.
+        assert_eq!(connector.poll(&mut connection), Ok(1));
+        assert_eq!(connector.filter.result(), (8.5, 1.0));
+        assert_eq!(connection.commits.len(), 1);
+        assert!(connection.commits[0].contains("XADD") && connection.commits[0].contains("1-0"));
+    }
+}
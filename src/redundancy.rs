@@ -0,0 +1,189 @@
+//! Fault detection and isolation across redundant sensors.
+//!
+//! When several sensors measure the same quantity, a faulty one shows up as
+//! disagreeing with the consensus of the others. A `RedundantFilter` fuses
+//! the sensors into a single estimate of a randomly walking value, checks
+//! each sensor against the estimate built without it, and stops using a
+//! sensor once a `Cusum` on its residuals detects a shift.
+//!
+//! Isolating a fault takes at least three working sensors, or two and a
+//! prediction confident enough to break the tie, and assumes one sensor
+//! fails at a time.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::LQE;
+//! use lqe::diagnostics::Cusum;
+//! use lqe::redundancy::RedundantFilter;
+//!
+//! let mut filter = RedundantFilter::new(LQE { measurement: 20.0, variance: 1.0 }, 0.01, &[0.25; 3], Cusum::new(0.5, 5.0));
+//! for _ in 0..10 {
+//!     // The third sensor is stuck at its maximum reading.
+//!     filter.next(&[20.1, 19.9, 85.0]);
+//! }
+//! filter.excluded().collect::<Vec<_>>();
+//! // => [2]
+//! ```
+
+use crate::diagnostics::Cusum;
+use crate::LQE;
+
+/// `Sensor` is the configuration and health of one of the redundant
+/// sensors.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct Sensor {
+    variance: f64,
+    detector: Cusum,
+    excluded: bool
+}
+
+/// `RedundantFilter` estimates a value measured by several redundant
+/// sensors, excluding the ones it detects as faulty.
+///
+/// Each sensor has its own measurement `variance` and a copy of the
+/// `detector`, fed with the residual of the sensor against the estimate
+/// from the prediction and the other working sensors, normalized by its
+/// standard deviation. When detectors fire, the sensor with the largest
+/// residual is excluded and the other detectors restart, since the faulty
+/// sensor skewed their residuals too.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RedundantFilter {
+    pub estimate: LQE,
+    pub process_noise: f64,
+    sensors: Vec<Sensor>
+}
+
+impl RedundantFilter {
+    /// `new` creates a filter starting from `estimate`, with a sensor for
+    /// each of the measurement `variances`.
+    pub fn new(estimate: LQE, process_noise: f64, variances: &[f64], detector: Cusum) -> Self {
+        RedundantFilter {
+            estimate,
+            process_noise,
+            sensors: variances
+                .iter()
+                .map(|variance| Sensor {
+                    variance: *variance,
+                    detector,
+                    excluded: false
+                })
+                .collect()
+        }
+    }
+
+    /// `next` performs the entire predict - update cycle with one
+    /// measurement per sensor, in the order the sensors were given, and
+    /// returns the refined estimate. Measurements of excluded sensors are
+    /// ignored.
+    ///
+    /// # Panics:
+    ///
+    /// Panics if the number of measurements differs from the number of
+    /// sensors.
+    pub fn next(&mut self, measurements: &[f64]) -> LQE {
+        assert_eq!(measurements.len(), self.sensors.len(), "expected one measurement per sensor");
+        let prediction = LQE {
+            measurement: self.estimate.measurement,
+            variance: self.estimate.variance + self.process_noise
+        };
+
+        let mut faulty: Option<(usize, f64)> = None;
+        for i in 0..self.sensors.len() {
+            if self.sensors[i].excluded {
+                continue;
+            }
+            let others = self.fuse(prediction, measurements, Some(i));
+            let sensor = &mut self.sensors[i];
+            let residual = (measurements[i] - others.measurement) / (others.variance + sensor.variance).sqrt();
+            let detected = sensor.detector.push(residual).is_some();
+            if detected && faulty.is_none_or(|(_, largest)| residual.abs() > largest) {
+                faulty = Some((i, residual.abs()));
+            }
+        }
+        if let Some((i, _)) = faulty {
+            for (j, sensor) in self.sensors.iter_mut().enumerate() {
+                sensor.excluded |= i == j;
+                sensor.detector.reset();
+            }
+        }
+
+        self.estimate = self.fuse(prediction, measurements, None);
+        self.estimate
+    }
+
+    /// `fuse` corrects `prediction` with the measurements of the working
+    /// sensors, leaving out `skip`.
+    fn fuse(&self, prediction: LQE, measurements: &[f64], skip: Option<usize>) -> LQE {
+        self.sensors
+            .iter()
+            .zip(measurements)
+            .enumerate()
+            .filter(|(i, (sensor, _))| !sensor.excluded && Some(*i) != skip)
+            .fold(prediction, |estimate, (_, (sensor, measurement))| {
+                estimate.correct(*measurement, sensor.variance).posterior
+            })
+    }
+
+    /// `is_excluded` returns whether the sensor at `index` has been excluded as
+    /// faulty.
+    pub fn is_excluded(&self, index: usize) -> bool {
+        self.sensors[index].excluded
+    }
+
+    /// `excluded` returns the indices of the sensors excluded as faulty.
+    pub fn excluded(&self) -> impl Iterator<Item = usize> + '_ {
+        self.sensors.iter().enumerate().filter(|(_, sensor)| sensor.excluded).map(|(i, _)| i)
+    }
+
+    /// `readmit` resumes using the sensor at `index`, e.g. after it has been
+    /// repaired, with a fresh detector.
+    pub fn readmit(&mut self, index: usize) {
+        let sensor = &mut self.sensors[index];
+        sensor.excluded = false;
+        sensor.detector.reset();
+    }
+
+    /// `result` returns the current estimate as a tuple value.
+    pub fn result(&self) -> (f64, f64) {
+        self.estimate.result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particle::Random;
+
+    #[test]
+    fn isolates_biased_sensor() {
+        let mut random = Random::new(5);
+        let mut filter = RedundantFilter::new(LQE { measurement: 0.0, variance: 100.0 }, 0.01, &[1.0; 4], Cusum::new(0.5, 8.0));
+
+        for _ in 0..200 {
+            let readings = (0..4).map(|_| 10.0 + random.normal()).collect::<Vec<_>>();
+            filter.next(&readings);
+        }
+        assert_eq!(filter.excluded().count(), 0);
+        for _ in 0..50 {
+            let mut readings = (0..4).map(|_| 10.0 + random.normal()).collect::<Vec<_>>();
+            readings[1] += 3.0;
+            filter.next(&readings);
+        }
+        assert_eq!(filter.excluded().collect::<Vec<_>>(), [1]);
+        assert!((filter.estimate.measurement - 10.0).abs() < 0.5);
+
+        filter.readmit(1);
+        assert!(!filter.is_excluded(1));
+    }
+
+    #[test]
+    fn ignores_excluded_measurements() {
+        let mut filter = RedundantFilter::new(LQE { measurement: 5.0, variance: 1.0 }, 0.0, &[1.0; 3], Cusum::new(0.5, 3.0));
+
+        filter.next(&[5.0, 5.0, 50.0]);
+        assert!(filter.is_excluded(2));
+        assert_eq!(filter.next(&[5.0, 5.0, -50.0]).measurement, 5.0);
+    }
+}
@@ -0,0 +1,225 @@
+//! Multi-sensor fusion by sensor ID.
+//!
+//! Each sensor observing a system has its own observation model `H` and
+//! measurement noise `R`. A `SensorRegistry` keeps them by `SensorId`, so a
+//! single `DynamicKalmanFilter` can take measurements from any number of
+//! sensors, each applied with the model of the sensor it came from.
+//!
+//! Sensors like gyroscopes and magnetometers read with a bias that drifts
+//! from one power-up to the next. During a calibration phase, with the
+//! system held at a known reference state, e.g. at rest, the registry
+//! averages the difference between what each sensor reads and what it
+//! should read, and subtracts that bias from its later measurements.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::matrix::{DenseMatrix, Matrix};
+//! use lqe::measurement::SensorId;
+//! use lqe::multivariate::DynamicKalmanFilter;
+//! use lqe::registry::SensorRegistry;
+//!
+//! // Position and velocity, measured by a GPS and a wheel encoder.
+//! let mut filter = DynamicKalmanFilter::<DenseMatrix>::new(2, 1);
+//! let mut sensors = SensorRegistry::new();
+//! sensors.register(SensorId(1), DenseMatrix::from_row_slices(&[&[1.0, 0.0]]), DenseMatrix::vector(&[4.0]));
+//! sensors.register(SensorId(2), DenseMatrix::from_row_slices(&[&[0.0, 1.0]]), DenseMatrix::vector(&[1.0]));
+//! sensors.update(&mut filter, SensorId(1), &DenseMatrix::vector(&[10.0])).unwrap();
+//! sensors.update(&mut filter, SensorId(2), &DenseMatrix::vector(&[2.0])).unwrap();
+//! (filter.state.get(0, 0), filter.state.get(1, 0));
+//! // => (2.0, 1.0)
+//! ```
+
+use std::collections::BTreeMap;
+use std::mem;
+
+use crate::error::Error;
+use crate::matrix::Matrix;
+use crate::measurement::SensorId;
+use crate::multivariate::DynamicKalmanFilter;
+
+/// `Sensor` is the `observation` model, `measurement_noise` and `bias` of a
+/// sensor, its measurements being `z = H x + b + v`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sensor<T> {
+    pub observation: T,
+    pub measurement_noise: T,
+    pub bias: T
+}
+
+/// `Calibration` is a calibration phase at the `reference` state, with the
+/// sum of the deviations from it and their number by sensor.
+#[derive(Clone, Debug, PartialEq)]
+struct Calibration<T> {
+    reference: T,
+    deviations: BTreeMap<SensorId, (T, usize)>
+}
+
+/// `SensorRegistry` holds the model of every sensor by its ID.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SensorRegistry<T> {
+    sensors: BTreeMap<SensorId, Sensor<T>>,
+    calibration: Option<Calibration<T>>
+}
+
+impl<T> Default for SensorRegistry<T> {
+    fn default() -> Self {
+        SensorRegistry {
+            sensors: BTreeMap::new(),
+            calibration: None
+        }
+    }
+}
+
+impl<T: Matrix> SensorRegistry<T> {
+    /// `new` creates a registry without any sensors.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `register` adds `sensor_id` with its `observation` model and
+    /// `measurement_noise`, without bias, replacing any previous model of
+    /// the sensor.
+    pub fn register(&mut self, sensor_id: SensorId, observation: T, measurement_noise: T) {
+        let bias = T::zeros(observation.shape().0, 1);
+        self.sensors.insert(
+            sensor_id,
+            Sensor {
+                observation,
+                measurement_noise,
+                bias
+            }
+        );
+    }
+
+    /// `unregister` removes `sensor_id`, returning its model if it was
+    /// registered.
+    pub fn unregister(&mut self, sensor_id: SensorId) -> Option<Sensor<T>> {
+        self.sensors.remove(&sensor_id)
+    }
+
+    /// `get` returns the model of `sensor_id`.
+    pub fn get(&self, sensor_id: SensorId) -> Option<&Sensor<T>> {
+        self.sensors.get(&sensor_id)
+    }
+
+    /// `get_mut` returns the model of `sensor_id` for changes, e.g. to
+    /// recalibrate its noise.
+    pub fn get_mut(&mut self, sensor_id: SensorId) -> Option<&mut Sensor<T>> {
+        self.sensors.get_mut(&sensor_id)
+    }
+
+    /// `sensors` returns the IDs of the registered sensors in order.
+    pub fn sensors(&self) -> impl Iterator<Item = SensorId> + '_ {
+        self.sensors.keys().copied()
+    }
+
+    /// `start_calibration` starts a calibration phase with the system held
+    /// at the `reference` state, discarding any unfinished one.
+    pub fn start_calibration(&mut self, reference: T) {
+        self.calibration = Some(Calibration {
+            reference,
+            deviations: BTreeMap::new()
+        });
+    }
+
+    /// `is_calibrating` tells whether a calibration phase is running.
+    pub fn is_calibrating(&self) -> bool {
+        self.calibration.is_some()
+    }
+
+    /// `finish_calibration` ends the calibration phase, setting the bias of
+    /// every sensor that reported during it to its mean deviation from the
+    /// reference. It returns the sensors that were calibrated.
+    pub fn finish_calibration(&mut self) -> Vec<SensorId> {
+        let calibration = match self.calibration.take() {
+            Some(calibration) => calibration,
+            None => return Vec::new()
+        };
+        let mut calibrated = Vec::new();
+        for (sensor_id, (sum, count)) in calibration.deviations {
+            if let Some(sensor) = self.sensors.get_mut(&sensor_id) {
+                sensor.bias = sum.scale(1.0 / count as f64);
+                calibrated.push(sensor_id);
+            }
+        }
+        calibrated
+    }
+
+    /// `update` refines the estimate of `filter` with `measurement` from
+    /// `sensor_id`, using the model of the sensor and removing its bias.
+    ///
+    /// During a calibration phase the measurement is recorded for the bias
+    /// of the sensor instead and `filter` is left alone. The observation
+    /// model of `filter` is left as it was. Measurements from unregistered
+    /// sensors fail with `Error::UnknownSensor`.
+    pub fn update(&mut self, filter: &mut DynamicKalmanFilter<T>, sensor_id: SensorId, measurement: &T) -> Result<(), Error> {
+        let sensor = self.sensors.get(&sensor_id).ok_or(Error::UnknownSensor)?;
+        if let Some(calibration) = &mut self.calibration {
+            let deviation = measurement.sub(&sensor.observation.mul(&calibration.reference));
+            let (sum, count) = calibration
+                .deviations
+                .entry(sensor_id)
+                .or_insert_with(|| (T::zeros(deviation.shape().0, 1), 0));
+            *sum = sum.add(&deviation);
+            *count += 1;
+            return Ok(());
+        }
+        let observation = mem::replace(&mut filter.observation, sensor.observation.clone());
+        let measurement_noise = mem::replace(&mut filter.measurement_noise, sensor.measurement_noise.clone());
+        let result = filter.update(&measurement.sub(&sensor.bias));
+        filter.observation = observation;
+        filter.measurement_noise = measurement_noise;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::DenseMatrix;
+
+    #[test]
+    fn dispatches_by_sensor() {
+        let model = DynamicKalmanFilter::<DenseMatrix>::new(2, 2);
+        let mut sensors = SensorRegistry::new();
+        sensors.register(SensorId(1), DenseMatrix::identity(2), DenseMatrix::identity(2));
+        sensors.register(SensorId(2), DenseMatrix::from_row_slices(&[&[1.0, 1.0]]), DenseMatrix::vector(&[0.5]));
+
+        let mut filter = model.clone();
+        let mut expected = model.clone();
+        sensors.update(&mut filter, SensorId(2), &DenseMatrix::vector(&[3.0])).unwrap();
+        expected.observation = DenseMatrix::from_row_slices(&[&[1.0, 1.0]]);
+        expected.measurement_noise = DenseMatrix::vector(&[0.5]);
+        expected.update(&DenseMatrix::vector(&[3.0])).unwrap();
+        assert_eq!(filter.state, expected.state);
+        assert_eq!(filter.covariance, expected.covariance);
+        assert_eq!(filter.observation, model.observation);
+
+        assert_eq!(
+            sensors.update(&mut filter, SensorId(3), &DenseMatrix::vector(&[3.0])),
+            Err(Error::UnknownSensor)
+        );
+        sensors.unregister(SensorId(2));
+        assert_eq!(sensors.sensors().collect::<Vec<_>>(), vec![SensorId(1)]);
+    }
+
+    #[test]
+    fn calibrates_bias_at_rest() {
+        // A gyroscope reading 0.3 rad/s too high, calibrated at rest.
+        let mut filter = DynamicKalmanFilter::<DenseMatrix>::new(1, 1);
+        let mut sensors = SensorRegistry::new();
+        sensors.register(SensorId(1), DenseMatrix::identity(1), DenseMatrix::vector(&[0.01]));
+        sensors.start_calibration(DenseMatrix::vector(&[0.0]));
+        for z in &[0.29, 0.31, 0.30] {
+            sensors.update(&mut filter, SensorId(1), &DenseMatrix::vector(&[*z])).unwrap();
+        }
+        assert_eq!(filter.state.get(0, 0), 0.0);
+        assert_eq!(sensors.finish_calibration(), vec![SensorId(1)]);
+        assert!(!sensors.is_calibrating());
+        assert!((sensors.get(SensorId(1)).unwrap().bias.get(0, 0) - 0.3).abs() < 1e-12);
+
+        sensors.update(&mut filter, SensorId(1), &DenseMatrix::vector(&[1.3])).unwrap();
+        assert!((filter.state.get(0, 0) - 1.0).abs() < 0.02);
+    }
+}
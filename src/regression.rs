@@ -0,0 +1,206 @@
+//! Online linear regression with drifting coefficients.
+//!
+//! `DynamicRegression` fits `y = β₁x₁ + … + βₖxₖ` one observation at a time
+//! while letting the coefficients `β` wander, e.g. a sensor calibration that
+//! ages or a market beta that shifts. It is a Kalman filter whose state is the
+//! coefficients, random walking by `drift` per step, observed through the
+//! regressors.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::regression::DynamicRegression;
+//!
+//! // y = 2 + 3x, with an intercept regressor of 1.0.
+//! let mut regression = DynamicRegression::new(2, 1e-6, 0.01);
+//! for x in &[0.0, 1.0, 2.0, 3.0] {
+//!     regression.update(&[1.0, *x], 2.0 + 3.0 * x).unwrap();
+//! }
+//! regression.forecast(&[1.0, 4.0]).measurement;
+//! // => 14.0
+//! ```
+
+use crate::error::Error;
+use crate::matrix::{DenseMatrix, Matrix};
+use crate::multivariate::DynamicKalmanFilter;
+use crate::LQE;
+
+/// `DynamicRegression` estimates the coefficients of a linear regression that
+/// change over time.
+///
+/// `drift` is the variance each coefficient gains per observation, `0.0` for
+/// ordinary recursive least squares. `noise` is the variance of the targets
+/// around the regression line. `filter` holds the coefficients as its state,
+/// starting from zero with a variance of `1000.0` each.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DynamicRegression {
+    pub filter: DynamicKalmanFilter<DenseMatrix>,
+    pub drift: f64,
+    pub noise: f64
+}
+
+impl DynamicRegression {
+    /// `new` creates a regression on `coefficients` regressors.
+    pub fn new(coefficients: usize, drift: f64, noise: f64) -> Self {
+        let mut filter = DynamicKalmanFilter::<DenseMatrix>::new(coefficients, 1);
+        filter.covariance = DenseMatrix::identity(coefficients).scale(1000.0);
+        DynamicRegression { filter, drift, noise }
+    }
+
+    /// `forecast` returns the distribution of the target for `regressors`
+    /// under the current coefficients, including the target noise.
+    ///
+    /// # Panics:
+    ///
+    /// Panics if the number of regressors doesn't match the regression.
+    pub fn forecast(&self, regressors: &[f64]) -> LQE {
+        let coefficients = self.filter.state.shape().0;
+        let covariance = self
+            .filter
+            .covariance
+            .add(&DenseMatrix::identity(coefficients).scale(self.drift));
+        let observation = DenseMatrix::from_row_slices(&[regressors]);
+        LQE {
+            measurement: observation.mul(&self.filter.state).get(0, 0),
+            variance: observation.mul(&covariance).mul(&observation.transpose()).get(0, 0) + self.noise
+        }
+    }
+
+    /// `update` lets the coefficients drift by one step, then refines them
+    /// with the observation that `regressors` produced `target`.
+    ///
+    /// # Panics:
+    ///
+    /// Panics if the number of regressors doesn't match the regression.
+    pub fn update(&mut self, regressors: &[f64], target: f64) -> Result<(), Error> {
+        let coefficients = self.filter.state.shape().0;
+        self.filter.process_noise = DenseMatrix::identity(coefficients).scale(self.drift);
+        self.filter.predict();
+        self.filter.observation = DenseMatrix::from_row_slices(&[regressors]);
+        self.filter.measurement_noise = DenseMatrix::from_row_slices(&[&[self.noise]]);
+        self.filter.update(&DenseMatrix::vector(&[target]))
+    }
+
+    /// `coefficients` returns the current estimate of the coefficients.
+    pub fn coefficients(&self) -> Vec<f64> {
+        (0..self.filter.state.shape().0).map(|i| self.filter.state.get(i, 0)).collect()
+    }
+}
+
+/// `Spread` is the deviation of a price from the one its pair predicts, with
+/// the variance expected of it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Spread {
+    pub value: f64,
+    pub variance: f64
+}
+
+impl Spread {
+    /// `z_score` returns the spread in standard deviations, the usual signal
+    /// to enter or exit a position on.
+    pub fn z_score(&self) -> f64 {
+        self.value / self.variance.sqrt()
+    }
+}
+
+/// `HedgeRatio` estimates the time-varying hedge ratio `β` and intercept `α`
+/// in `y = βx + α` between the prices `x` and `y` of a pair.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::regression::HedgeRatio;
+///
+/// let mut pair = HedgeRatio::new(1e-5, 1e-3);
+/// for (x, y) in &[(10.0, 21.0), (10.5, 22.0), (10.2, 21.4), (10.8, 22.6)] {
+///     pair.update(*x, *y).unwrap();
+/// }
+/// let spread = pair.update(11.0, 24.0).unwrap();
+/// spread.z_score() > 2.0;
+/// // => true
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct HedgeRatio {
+    pub regression: DynamicRegression
+}
+
+impl HedgeRatio {
+    /// `new` creates an estimator whose coefficients drift by `drift` per
+    /// observation, with `noise` the variance of the spread.
+    pub fn new(drift: f64, noise: f64) -> Self {
+        HedgeRatio {
+            regression: DynamicRegression::new(2, drift, noise)
+        }
+    }
+
+    /// `update` refines the estimate with the prices `x` and `y`, returning
+    /// the spread of `y` against the prediction made before seeing it.
+    pub fn update(&mut self, x: f64, y: f64) -> Result<Spread, Error> {
+        let forecast = self.regression.forecast(&[x, 1.0]);
+        self.regression.update(&[x, 1.0], y)?;
+        Ok(Spread {
+            value: y - forecast.measurement,
+            variance: forecast.variance
+        })
+    }
+
+    /// `hedge_ratio` returns the current estimate of `β`.
+    pub fn hedge_ratio(&self) -> f64 {
+        self.regression.filter.state.get(0, 0)
+    }
+
+    /// `intercept` returns the current estimate of `α`.
+    pub fn intercept(&self) -> f64 {
+        self.regression.filter.state.get(1, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particle::Random;
+
+    #[test]
+    fn fits_static_regression() {
+        let mut regression = DynamicRegression::new(2, 0.0, 0.01);
+
+        for x in &[0.0, 1.0, 2.0, 3.0, 4.0] {
+            regression.update(&[1.0, *x], 2.0 + 3.0 * x).unwrap();
+        }
+        let coefficients = regression.coefficients();
+        assert!((coefficients[0] - 2.0).abs() < 1e-3 && (coefficients[1] - 3.0).abs() < 1e-3);
+        let forecast = regression.forecast(&[1.0, 10.0]);
+        assert!((forecast.measurement - 32.0).abs() < 1e-2);
+        assert!(forecast.variance > 0.01);
+    }
+
+    #[test]
+    fn follows_drifting_coefficient() {
+        let mut random = Random::new(9);
+        let mut regression = DynamicRegression::new(1, 1e-3, 0.01);
+
+        for step in 0..1000 {
+            let slope = if step < 500 { 1.0 } else { 2.0 };
+            let x = random.normal();
+            regression.update(&[x], slope * x + 0.1 * random.normal()).unwrap();
+        }
+        assert!((regression.coefficients()[0] - 2.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn tracks_hedge_ratio() {
+        let mut random = Random::new(21);
+        let mut pair = HedgeRatio::new(1e-6, 0.01);
+        let mut x = 50.0;
+
+        for _ in 0..500 {
+            x += random.normal();
+            pair.update(x, 1.5 * x + 4.0 + 0.1 * random.normal()).unwrap();
+        }
+        assert!((pair.hedge_ratio() - 1.5).abs() < 0.01);
+        assert!((pair.intercept() - 4.0).abs() < 0.5);
+        let spread = pair.update(x, 1.5 * x + 4.0 + 1.0).unwrap();
+        assert!(spread.z_score() > 5.0);
+    }
+}
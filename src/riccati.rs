@@ -0,0 +1,340 @@
+//! The algebraic Riccati equations.
+//!
+//! `solve_dare` returns the stabilizing solution `X` of the discrete
+//! algebraic Riccati equation
+//!
+//! `X = Aᵀ X A - Aᵀ X B (R + Bᵀ X B)⁻¹ Bᵀ X A + Q`,
+//!
+//! the equation behind both the steady-state Kalman filter and the infinite
+//! horizon linear quadratic regulator. `lqr_gain` designs the regulator for
+//! a system `x = A x + B u` with state cost `Q` and input cost `R`, and
+//! `filter_covariance` the steady-state filter for a `DynamicKalmanFilter`
+//! model, which is the same equation for `A = Fᵀ`, `B = Hᵀ`.
+//!
+//! The equation is solved with the structure-preserving doubling algorithm
+//! (Chu, Fan and Lin, 2005), which converges quadratically.
+//!
+//! `solve_care` solves the continuous algebraic Riccati equation
+//!
+//! `Aᵀ X + X A - X B R⁻¹ Bᵀ X + Q = 0`
+//!
+//! for the continuous-time counterparts: `continuous_lqr_gain` for the
+//! regulator of `dx/dt = A x + B u` and `kalman_bucy_gain` for the observer
+//! of `dx/dt = A x + w`, `y = C x + v`. It is solved through the matrix sign
+//! function of the Hamiltonian matrix (Roberts, 1980).
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::matrix::{DenseMatrix, Matrix};
+//! use lqe::riccati;
+//!
+//! // A double integrator, driven by its acceleration.
+//! let a = DenseMatrix::from_row_slices(&[&[1.0, 1.0], &[0.0, 1.0]]);
+//! let b = DenseMatrix::vector(&[0.5, 1.0]);
+//! let gain = riccati::lqr_gain(&a, &b, &DenseMatrix::identity(2), &DenseMatrix::identity(1)).unwrap();
+//! gain.shape();
+//! // => (1, 2)
+//! ```
+
+use crate::matrix::Matrix;
+use crate::multivariate::DynamicKalmanFilter;
+
+/// The number of doubling or sign function steps a solution is given to
+/// converge.
+const ITERATIONS: usize = 100;
+
+/// `solve` returns `matrix⁻¹ rhs` for a square `matrix` by Gaussian
+/// elimination with partial pivoting, or `None` if it is singular.
+fn solve<T: Matrix>(matrix: &T, rhs: &T) -> Option<T> {
+    let (size, columns) = (matrix.shape().0, rhs.shape().1);
+    let mut matrix = matrix.clone();
+    let mut solution = rhs.clone();
+    for k in 0..size {
+        let pivot = (k..size).max_by(|i, j| matrix.get(*i, k).abs().total_cmp(&matrix.get(*j, k).abs()))?;
+        if matrix.get(pivot, k) == 0.0 || matrix.get(pivot, k).is_nan() {
+            return None;
+        }
+        for j in 0..size {
+            let (upper, lower) = (matrix.get(k, j), matrix.get(pivot, j));
+            matrix.set(k, j, lower);
+            matrix.set(pivot, j, upper);
+        }
+        for j in 0..columns {
+            let (upper, lower) = (solution.get(k, j), solution.get(pivot, j));
+            solution.set(k, j, lower);
+            solution.set(pivot, j, upper);
+        }
+        for i in k + 1..size {
+            let factor = matrix.get(i, k) / matrix.get(k, k);
+            for j in k..size {
+                matrix.set(i, j, matrix.get(i, j) - factor * matrix.get(k, j));
+            }
+            for j in 0..columns {
+                solution.set(i, j, solution.get(i, j) - factor * solution.get(k, j));
+            }
+        }
+    }
+    for k in (0..size).rev() {
+        for j in 0..columns {
+            let value = solution.get(k, j) - (k + 1..size).map(|i| matrix.get(k, i) * solution.get(i, j)).sum::<f64>();
+            solution.set(k, j, value / matrix.get(k, k));
+        }
+    }
+    Some(solution)
+}
+
+/// `solve_dare` returns the stabilizing solution `X` of the discrete
+/// algebraic Riccati equation for the `n` x `n` `a`, `n` x `m` `b`, positive
+/// semidefinite `q` and positive definite `r`.
+///
+/// It returns `None` if `r` isn't positive definite or there is no
+/// stabilizing solution, e.g. because `(a, b)` isn't stabilizable.
+///
+/// # Panics:
+///
+/// Panics if the shapes of the matrices don't fit together.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::matrix::{DenseMatrix, Matrix};
+/// use lqe::riccati;
+/// let one = DenseMatrix::identity(1);
+/// riccati::solve_dare(&one, &one, &one, &one).unwrap().get(0, 0);
+/// // => 1.618033988749895
+/// ```
+pub fn solve_dare<T: Matrix>(a: &T, b: &T, q: &T, r: &T) -> Option<T> {
+    let states = a.shape().0;
+    assert_eq!(a.shape(), (states, states), "matrix must be square");
+    assert_eq!(q.shape(), (states, states), "matrix shapes must be compatible");
+    assert_eq!(b.shape(), (states, r.shape().0), "matrix shapes must be compatible");
+
+    let mut a = a.clone();
+    let mut g = b.mul(&r.cholesky_solve(&b.transpose())?);
+    let mut h = q.clone();
+    let identity = T::identity(states);
+    for _ in 0..ITERATIONS {
+        let w = identity.add(&g.mul(&h));
+        let transformed = solve(&w, &a)?;
+        let next_g = g.add(&a.mul(&solve(&w, &g)?).mul(&a.transpose()));
+        let next_h = h.add(&a.transpose().mul(&h).mul(&transformed));
+        a = a.mul(&transformed);
+        g = next_g;
+
+        let mut change = 0.0_f64;
+        let mut size = 0.0_f64;
+        for i in 0..states {
+            for j in 0..states {
+                change = change.max((next_h.get(i, j) - h.get(i, j)).abs());
+                size = size.max(next_h.get(i, j).abs());
+            }
+        }
+        h = next_h;
+        if change.is_nan() || size.is_infinite() {
+            return None;
+        }
+        if change <= 1e-12 * (1.0 + size) {
+            return Some(h.symmetrize());
+        }
+    }
+    None
+}
+
+/// `lqr_gain` returns the gain `K` of the regulator `u = -K x` minimizing
+/// the sum of `xᵀ Q x + uᵀ R u` over all steps of `x = A x + B u`, or `None`
+/// as `solve_dare`.
+pub fn lqr_gain<T: Matrix>(a: &T, b: &T, q: &T, r: &T) -> Option<T> {
+    let x = solve_dare(a, b, q, r)?;
+    let bx = b.transpose().mul(&x);
+    bx.mul(b).add(r).cholesky_solve(&bx.mul(a))
+}
+
+/// `filter_covariance` returns the steady-state prediction covariance of
+/// `model`, or `None` if it has none, e.g. because an unstable state is
+/// unobservable.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::matrix::{DenseMatrix, Matrix};
+/// use lqe::multivariate::DynamicKalmanFilter;
+/// use lqe::riccati;
+/// let mut model = DynamicKalmanFilter::<DenseMatrix>::new(1, 1);
+/// model.process_noise = DenseMatrix::identity(1);
+/// model.measurement_noise = DenseMatrix::vector(&[2.0]);
+/// riccati::filter_covariance(&model).unwrap().get(0, 0);
+/// // => 2.0
+/// ```
+pub fn filter_covariance<T: Matrix>(model: &DynamicKalmanFilter<T>) -> Option<T> {
+    solve_dare(
+        &model.transition.transpose(),
+        &model.observation.transpose(),
+        &model.process_noise,
+        &model.measurement_noise
+    )
+}
+
+/// `solve_care` returns the stabilizing solution `X` of the continuous
+/// algebraic Riccati equation for the `n` x `n` `a`, `n` x `m` `b`, positive
+/// semidefinite `q` and positive definite `r`.
+///
+/// It returns `None` if `r` isn't positive definite or there is no
+/// stabilizing solution, e.g. because `(a, b)` isn't stabilizable.
+///
+/// # Panics:
+///
+/// Panics if the shapes of the matrices don't fit together.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::matrix::{DenseMatrix, Matrix};
+/// use lqe::riccati;
+/// let one = DenseMatrix::identity(1);
+/// riccati::solve_care(&one, &one, &one, &one).unwrap().get(0, 0);
+/// // => 2.414213562373095
+/// ```
+pub fn solve_care<T: Matrix>(a: &T, b: &T, q: &T, r: &T) -> Option<T> {
+    let states = a.shape().0;
+    assert_eq!(a.shape(), (states, states), "matrix must be square");
+    assert_eq!(q.shape(), (states, states), "matrix shapes must be compatible");
+    assert_eq!(b.shape(), (states, r.shape().0), "matrix shapes must be compatible");
+
+    // The Hamiltonian [[A, -G], [-Q, -Aᵀ]] with G = B R⁻¹ Bᵀ.
+    let g = b.mul(&r.cholesky_solve(&b.transpose())?);
+    let mut sign = T::zeros(2 * states, 2 * states);
+    for i in 0..states {
+        for j in 0..states {
+            sign.set(i, j, a.get(i, j));
+            sign.set(i, states + j, -g.get(i, j));
+            sign.set(states + i, j, -q.get(i, j));
+            sign.set(states + i, states + j, -a.get(j, i));
+        }
+    }
+    let identity = T::identity(2 * states);
+    let mut converged = false;
+    for _ in 0..ITERATIONS {
+        let next = sign.add(&solve(&sign, &identity)?).scale(0.5);
+        let change = next.sub(&sign);
+        let (mut difference, mut size) = (0.0_f64, 0.0_f64);
+        for i in 0..2 * states {
+            for j in 0..2 * states {
+                difference = difference.max(change.get(i, j).abs());
+                size = size.max(next.get(i, j).abs());
+            }
+        }
+        sign = next;
+        if difference.is_nan() {
+            return None;
+        }
+        if difference <= 1e-12 * (1.0 + size) {
+            converged = true;
+            break;
+        }
+    }
+    if !converged {
+        return None;
+    }
+
+    // The stable invariant subspace [I; X] is the null space of sign + I:
+    // [W₁₂; W₂₂ + I] X = -[W₁₁ + I; W₂₁], solved in the least squares sense.
+    let (mut left, mut right) = (T::zeros(2 * states, states), T::zeros(2 * states, states));
+    for i in 0..2 * states {
+        for j in 0..states {
+            left.set(i, j, sign.get(i, states + j) + if i == states + j { 1.0 } else { 0.0 });
+            right.set(i, j, -sign.get(i, j) - if i == j { 1.0 } else { 0.0 });
+        }
+    }
+    let normal = left.transpose().mul(&left);
+    Some(normal.cholesky_solve(&left.transpose().mul(&right))?.symmetrize())
+}
+
+/// `continuous_lqr_gain` returns the gain `K` of the regulator `u = -K x`
+/// minimizing the integral of `xᵀ Q x + uᵀ R u` for `dx/dt = A x + B u`, or
+/// `None` as `solve_care`.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::matrix::{DenseMatrix, Matrix};
+/// use lqe::riccati;
+/// // A double integrator.
+/// let a = DenseMatrix::from_row_slices(&[&[0.0, 1.0], &[0.0, 0.0]]);
+/// let b = DenseMatrix::vector(&[0.0, 1.0]);
+/// riccati::continuous_lqr_gain(&a, &b, &DenseMatrix::identity(2), &DenseMatrix::identity(1)).unwrap();
+/// // => [[1.0, 1.7320508075688772]]
+/// ```
+pub fn continuous_lqr_gain<T: Matrix>(a: &T, b: &T, q: &T, r: &T) -> Option<T> {
+    let x = solve_care(a, b, q, r)?;
+    r.cholesky_solve(&b.transpose().mul(&x))
+}
+
+/// `kalman_bucy_gain` returns the gain `L` of the steady-state observer
+/// `dx/dt = A x + L (y - C x)` for `dx/dt = A x + w`, `y = C x + v` with
+/// noise spectral densities `q` of `w` and `r` of `v`, or `None` as
+/// `solve_care`.
+pub fn kalman_bucy_gain<T: Matrix>(a: &T, c: &T, q: &T, r: &T) -> Option<T> {
+    let covariance = solve_care(&a.transpose(), &c.transpose(), q, r)?;
+    Some(r.cholesky_solve(&c.mul(&covariance))?.transpose())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intermittent;
+    use crate::matrix::DenseMatrix;
+    use crate::stability;
+
+    #[test]
+    fn matches_riccati_recursion() {
+        let mut model = DynamicKalmanFilter::<DenseMatrix>::new(3, 2);
+        model.transition = DenseMatrix::from_row_slices(&[&[1.0, 1.0, 0.5], &[0.0, 1.0, 1.0], &[0.0, 0.0, 0.9]]);
+        model.process_noise = DenseMatrix::from_row_slices(&[&[0.1, 0.0, 0.0], &[0.0, 0.0, 0.0], &[0.0, 0.0, 0.3]]);
+        model.observation = DenseMatrix::from_row_slices(&[&[1.0, 0.0, 0.0], &[0.0, 0.0, 1.0]]);
+        model.measurement_noise = DenseMatrix::from_row_slices(&[&[2.0, 0.5], &[0.5, 1.0]]);
+
+        let covariance = filter_covariance(&model).unwrap();
+        let expected = intermittent::expected_covariance(&model, 1.0).unwrap();
+        for i in 0..3 {
+            for j in 0..3 {
+                assert!((covariance.get(i, j) - expected.get(i, j)).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn stabilizes_with_lqr() {
+        let a = DenseMatrix::from_row_slices(&[&[1.1, 1.0], &[0.0, 1.2]]);
+        let b = DenseMatrix::vector(&[0.0, 1.0]);
+        let gain = lqr_gain(&a, &b, &DenseMatrix::identity(2), &DenseMatrix::identity(1)).unwrap();
+        let closed_loop = a.sub(&b.mul(&gain));
+        let eigenvalues = stability::eigenvalues(&closed_loop).unwrap();
+        assert!(eigenvalues.iter().all(|eigenvalue| eigenvalue.modulus() < 1.0));
+
+        // Without an input the unstable states can't be brought back.
+        let b = DenseMatrix::vector(&[0.0, 0.0]);
+        assert!(solve_dare(&a, &b, &DenseMatrix::identity(2), &DenseMatrix::identity(1)).is_none());
+    }
+
+    #[test]
+    fn solves_care() {
+        let a = DenseMatrix::from_row_slices(&[&[0.0, 1.0], &[0.0, 0.0]]);
+        let b = DenseMatrix::vector(&[0.0, 1.0]);
+        let q = DenseMatrix::identity(2);
+        let r = DenseMatrix::identity(1);
+        let x = solve_care(&a, &b, &q, &r).unwrap();
+        let root = 3.0_f64.sqrt();
+        let expected = DenseMatrix::from_row_slices(&[&[root, 1.0], &[1.0, root]]);
+        for i in 0..2 {
+            for j in 0..2 {
+                assert!((x.get(i, j) - expected.get(i, j)).abs() < 1e-9);
+            }
+        }
+
+        let gain = kalman_bucy_gain(&a.transpose(), &b.transpose(), &q, &r).unwrap();
+        assert!((gain.get(0, 0) - 1.0).abs() < 1e-9);
+        assert!((gain.get(1, 0) - root).abs() < 1e-9);
+        assert!(solve_care(&a, &DenseMatrix::vector(&[0.0, 0.0]), &q, &r).is_none());
+    }
+}
@@ -0,0 +1,185 @@
+//! Conversions to and from ROS 2 message layouts.
+//!
+//! ROS 2 messages carry their covariances as flat row-major arrays: 36
+//! elements over `x, y, z, roll, pitch, yaw` for `PoseWithCovariance` and
+//! `TwistWithCovariance` (and so `Odometry`), and 9 elements over `x, y, z`
+//! for each of the vectors of `Imu`, whose first element is `-1` when the
+//! quantity isn't provided. The functions here convert between those fields
+//! and filter measurements and estimates, so they work the same with the
+//! message types generated by `r2r` and by `rclrs`, e.g.
+//! `ros::measurement(&[msg.pose.position.x, msg.pose.position.y], ...)`.
+//!
+//! There are no conversions from or to the `Odometry`, `PoseWithCovariance`
+//! and `Imu` types themselves: both bindings generate them from a sourced
+//! ROS 2 installation at build time, so depending on either would break
+//! every build without one. Copying the positions, orientations and vectors
+//! of a message is left to the caller; only the covariance layouts, where the
+//! mistakes happen, are handled here.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::matrix::{DenseMatrix, Matrix};
+//! use lqe::ros::{self, POSE_X, POSE_Y};
+//!
+//! // The x and y of a PoseWithCovariance.
+//! let mut covariance = [0.0; 36];
+//! covariance[0] = 0.25;
+//! covariance[7] = 0.5;
+//! let (measurement, noise): (DenseMatrix, DenseMatrix) =
+//!     ros::measurement(&[1.0, 2.0], &ros::select(&covariance, &[POSE_X, POSE_Y]).unwrap()).unwrap();
+//! noise.get(1, 1);
+//! // => 0.5
+//! ```
+
+use crate::error::Error;
+use crate::matrix::Matrix;
+
+/// The index of `x` among the six pose or twist dimensions.
+pub const POSE_X: usize = 0;
+/// The index of `y` among the six pose or twist dimensions.
+pub const POSE_Y: usize = 1;
+/// The index of `z` among the six pose or twist dimensions.
+pub const POSE_Z: usize = 2;
+/// The index of `roll` among the six pose or twist dimensions.
+pub const POSE_ROLL: usize = 3;
+/// The index of `pitch` among the six pose or twist dimensions.
+pub const POSE_PITCH: usize = 4;
+/// The index of `yaw` among the six pose or twist dimensions.
+pub const POSE_YAW: usize = 5;
+
+/// The variance reported for dimensions a filter doesn't estimate, large
+/// enough for consumers such as `robot_localization` to ignore them.
+pub const UNKNOWN_VARIANCE: f64 = 1e9;
+
+/// `measurement` returns `values` as a measurement vector with the
+/// row-major `covariance` as its noise, e.g. for `DynamicKalmanFilter::update`.
+///
+/// Returns `Error::DimensionMismatch` if `covariance` doesn't have the square
+/// of the number of values.
+pub fn measurement<T: Matrix>(values: &[f64], covariance: &[f64]) -> Result<(T, T), Error> {
+    let size = values.len();
+    if covariance.len() != size * size {
+        return Err(Error::DimensionMismatch);
+    }
+    let mut noise = T::zeros(size, size);
+    for i in 0..size {
+        for j in 0..size {
+            noise.set(i, j, covariance[i * size + j]);
+        }
+    }
+    Ok((T::vector(values), noise))
+}
+
+/// `select` returns the row-major covariance of the given `dimensions` of a
+/// square row-major `covariance`, e.g. the position block of a pose.
+///
+/// Returns `Error::DimensionMismatch` if `covariance` isn't square or a
+/// dimension is out of its range.
+pub fn select(covariance: &[f64], dimensions: &[usize]) -> Result<Vec<f64>, Error> {
+    let size = (covariance.len() as f64).sqrt().round() as usize;
+    if size * size != covariance.len() || dimensions.iter().any(|dimension| *dimension >= size) {
+        return Err(Error::DimensionMismatch);
+    }
+    Ok(dimensions
+        .iter()
+        .flat_map(|i| dimensions.iter().map(move |j| covariance[i * size + j]))
+        .collect())
+}
+
+/// `is_provided` returns whether an `Imu` covariance describes data, rather
+/// than starting with the `-1` that marks a missing quantity.
+pub fn is_provided(covariance: &[f64; 9]) -> bool {
+    covariance[0] != -1.0
+}
+
+/// `pose_covariance` returns the 36-element covariance of a pose or twist
+/// message from an estimate `covariance`, given the state index holding each
+/// of `x, y, z, roll, pitch, yaw`, or `None` for those not estimated, which
+/// get `UNKNOWN_VARIANCE`.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::matrix::{DenseMatrix, Matrix};
+/// use lqe::ros;
+/// // A planar estimate of x, y and yaw.
+/// let covariance = DenseMatrix::from_row_slices(&[&[1.0, 0.1, 0.0], &[0.1, 2.0, 0.0], &[0.0, 0.0, 0.3]]);
+/// let message = ros::pose_covariance(&covariance, [Some(0), Some(1), None, None, None, Some(2)]);
+/// (message[1], message[14], message[35]);
+/// // => (0.1, 1e9, 0.3)
+/// ```
+pub fn pose_covariance<T: Matrix>(covariance: &T, states: [Option<usize>; 6]) -> [f64; 36] {
+    let mut message = [0.0; 36];
+    for (i, row) in states.iter().enumerate() {
+        for (j, column) in states.iter().enumerate() {
+            message[i * 6 + j] = match (row, column) {
+                (Some(row), Some(column)) => covariance.get(*row, *column),
+                _ if i == j => UNKNOWN_VARIANCE,
+                _ => 0.0
+            };
+        }
+    }
+    message
+}
+
+/// `vector_covariance` returns the 9-element covariance of a vector of an
+/// `Imu` message from an estimate `covariance`, given the state indices of
+/// its `x, y, z`.
+pub fn vector_covariance<T: Matrix>(covariance: &T, states: [usize; 3]) -> [f64; 9] {
+    let mut message = [0.0; 9];
+    for (i, row) in states.iter().enumerate() {
+        for (j, column) in states.iter().enumerate() {
+            message[i * 3 + j] = covariance.get(*row, *column);
+        }
+    }
+    message
+}
+
+/// `values` returns the elements at the given state indices of an estimate
+/// `state`, e.g. to fill in the position of a pose message.
+pub fn values<T: Matrix>(state: &T, states: &[usize]) -> Vec<f64> {
+    states.iter().map(|i| state.get(*i, 0)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::DenseMatrix;
+
+    #[test]
+    fn converts_message_covariances() {
+        let mut covariance = [0.0; 36];
+        for (i, element) in covariance.iter_mut().enumerate() {
+            *element = i as f64;
+        }
+
+        let block = select(&covariance, &[POSE_X, POSE_YAW]).unwrap();
+        assert_eq!(block, vec![0.0, 5.0, 30.0, 35.0]);
+        let (measurement, noise): (DenseMatrix, DenseMatrix) = measurement(&[1.0, 2.0], &block).unwrap();
+        assert_eq!(measurement, DenseMatrix::vector(&[1.0, 2.0]));
+        assert_eq!(noise.get(1, 0), 30.0);
+    }
+
+    #[test]
+    fn rejects_misshapen_covariances() {
+        assert_eq!(select(&[0.0; 35], &[POSE_X]), Err(Error::DimensionMismatch));
+        assert_eq!(select(&[0.0; 9], &[POSE_YAW]), Err(Error::DimensionMismatch));
+        assert_eq!(select(&[], &[]), Ok(vec![]));
+        let result: Result<(DenseMatrix, DenseMatrix), Error> = measurement(&[1.0, 2.0], &[1.0; 3]);
+        assert_eq!(result, Err(Error::DimensionMismatch));
+    }
+
+    #[test]
+    fn fills_message_from_estimate() {
+        let state = DenseMatrix::vector(&[1.0, 2.0, 3.0]);
+        let covariance = DenseMatrix::from_row_slices(&[&[1.0, 0.1, 0.2], &[0.1, 2.0, 0.3], &[0.2, 0.3, 3.0]]);
+
+        let pose = pose_covariance(&covariance, [Some(2), None, None, None, None, Some(0)]);
+        assert_eq!((pose[0], pose[5], pose[30], pose[35]), (3.0, 0.2, 0.2, 1.0));
+        assert_eq!((pose[7], pose[8]), (UNKNOWN_VARIANCE, 0.0));
+        assert_eq!(vector_covariance(&covariance, [0, 1, 2])[5], 0.3);
+        assert_eq!(values(&state, &[2, 0]), vec![3.0, 1.0]);
+        assert!(!is_provided(&[-1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]));
+    }
+}
@@ -0,0 +1,168 @@
+//! Distance estimation from received signal strength.
+//!
+//! The received signal strength (RSSI) of a BLE beacon or Wi-Fi access point
+//! falls off with the logarithm of the distance to it, by the log-distance
+//! path-loss model
+//!
+//! ```text
+//! RSSI = P₀ - 10 n log₁₀(d)
+//! ```
+//!
+//! with `P₀` the strength at 1 m and `n` the path-loss exponent, 2 in free
+//! space and 2.5 to 4 indoors. Reflections and bodies in the way make single
+//! readings scatter by several dB, i.e. tens of percent of the distance. An
+//! `RssiRanger` smooths the distance as a random walk, linearizing the
+//! path-loss model at each update.
+//!
+//! The defaults follow common practice: a shadowing standard deviation of
+//! 5 dB and a walking pace of about 1 m/s.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::rssi::{PathLoss, RssiRanger};
+//!
+//! let mut ranger = RssiRanger::new(PathLoss::ble(), 1.0);
+//! for rssi in &[-71.0, -77.0, -73.0, -79.0, -75.0] {
+//!     ranger.next(0.2, *rssi);
+//! }
+//! ranger.distance() > 2.0;
+//! // => true
+//! ```
+
+use crate::{step, LQE};
+
+/// The shortest distance the ranger estimates, in meters, below which the
+/// path-loss model breaks down.
+const MINIMUM_DISTANCE: f64 = 0.1;
+
+/// `PathLoss` is the log-distance path-loss model with `reference_power`
+/// `P₀` in dBm and `exponent` `n`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PathLoss {
+    pub reference_power: f64,
+    pub exponent: f64
+}
+
+impl PathLoss {
+    /// `ble` returns the model of a typical BLE beacon indoors, advertising
+    /// -59 dBm at 1 m.
+    pub fn ble() -> Self {
+        PathLoss {
+            reference_power: -59.0,
+            exponent: 2.5
+        }
+    }
+
+    /// `wifi` returns the model of a typical Wi-Fi access point in an
+    /// office, through walls.
+    pub fn wifi() -> Self {
+        PathLoss {
+            reference_power: -40.0,
+            exponent: 3.0
+        }
+    }
+
+    /// `rssi` returns the expected signal strength at `distance`.
+    pub fn rssi(&self, distance: f64) -> f64 {
+        self.reference_power - 10.0 * self.exponent * distance.log10()
+    }
+
+    /// `distance` returns the distance at which `rssi` is expected, e.g. for
+    /// a first guess.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use lqe::rssi::PathLoss;
+    /// PathLoss::ble().distance(-84.0);
+    /// // => 10.0
+    /// ```
+    pub fn distance(&self, rssi: f64) -> f64 {
+        10f64.powf((self.reference_power - rssi) / (10.0 * self.exponent))
+    }
+}
+
+/// `RssiRanger` estimates the distance to a transmitter following
+/// `path_loss`.
+///
+/// `process_noise` is the variance the distance gains per second, in m²/s,
+/// and `measurement_noise` the variance of an RSSI reading in dB².
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RssiRanger {
+    pub estimate: LQE,
+    pub path_loss: PathLoss,
+    pub process_noise: f64,
+    pub measurement_noise: f64
+}
+
+impl RssiRanger {
+    /// `new` creates a ranger starting from a guess of the `distance`, as
+    /// uncertain as the distance itself.
+    pub fn new(path_loss: PathLoss, distance: f64) -> Self {
+        RssiRanger {
+            estimate: LQE {
+                measurement: distance,
+                variance: distance * distance
+            },
+            path_loss,
+            process_noise: 1.0,
+            measurement_noise: 25.0
+        }
+    }
+
+    /// `predict` moves the estimate `dt` seconds forward.
+    pub fn predict(&mut self, dt: f64) -> LQE {
+        self.estimate = step::predict(self.estimate, 0.0, self.process_noise * dt);
+        self.estimate
+    }
+
+    /// `update` refines the estimate with a reading of `rssi`.
+    pub fn update(&mut self, rssi: f64) -> LQE {
+        let distance = self.estimate.measurement.max(MINIMUM_DISTANCE);
+        // The slope of the path-loss model turns the reading into a distance
+        // measurement with the same effect as the linearized update.
+        let slope = -10.0 * self.path_loss.exponent / (distance * std::f64::consts::LN_10);
+        let measurement = distance + (rssi - self.path_loss.rssi(distance)) / slope;
+        let mut posterior = step::update(self.estimate, measurement, self.measurement_noise / (slope * slope)).posterior;
+        posterior.measurement = posterior.measurement.max(MINIMUM_DISTANCE);
+        self.estimate = posterior;
+        self.estimate
+    }
+
+    /// `next` predicts `dt` seconds forward and updates with `rssi`.
+    pub fn next(&mut self, dt: f64, rssi: f64) -> LQE {
+        self.predict(dt);
+        self.update(rssi)
+    }
+
+    /// `distance` returns the estimated distance in meters.
+    pub fn distance(&self) -> f64 {
+        self.estimate.measurement
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particle::Random;
+
+    #[test]
+    fn smooths_noisy_readings() {
+        let path_loss = PathLoss::ble();
+        let mut ranger = RssiRanger::new(path_loss, 1.0);
+        ranger.process_noise = 0.01;
+        let mut random = Random::new(5);
+        let mut worst = 0.0_f64;
+        for step in 0..200 {
+            let rssi = path_loss.rssi(5.0) + 5.0 * random.normal();
+            ranger.next(0.1, rssi);
+            if step >= 100 {
+                worst = worst.max((ranger.distance() - 5.0).abs());
+            }
+        }
+        // Single readings are off by up to a factor of two.
+        assert!(worst < 1.0);
+    }
+}
@@ -0,0 +1,176 @@
+//! State scaling.
+//!
+//! States of wildly different magnitudes, e.g. a position in metres next to
+//! an attitude in microradians, give covariances whose entries span many
+//! orders of magnitude. Their condition numbers are huge and their Cholesky
+//! factors lose precision. A `ScaledFilter` runs its filter in normalized
+//! units instead: every state is divided by a scale factor, its typical
+//! magnitude or uncertainty, so that all of them are of order one. States,
+//! covariances and model go in and come out in their physical units.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::matrix::{DenseMatrix, Matrix};
+//! use lqe::multivariate::DynamicKalmanFilter;
+//! use lqe::scaling::ScaledFilter;
+//!
+//! // A range in metres and a bearing in radians, known to about 1 mm and
+//! // 1 microradian.
+//! let mut model = DynamicKalmanFilter::<DenseMatrix>::new(2, 2);
+//! model.covariance = DenseMatrix::from_row_slices(&[&[1e-6, 0.0], &[0.0, 1e-12]]);
+//! model.measurement_noise = model.covariance.clone();
+//! let mut filter = ScaledFilter::new(model, &[1e-3, 1e-6]);
+//! filter.filter.condition();
+//! // => Some(1.0)
+//! filter.update(&DenseMatrix::vector(&[2e-3, 2e-6])).unwrap();
+//! filter.state();
+//! // => [[1e-3], [1e-6]]
+//! ```
+
+use crate::error::Error;
+use crate::matrix::Matrix;
+use crate::multivariate::DynamicKalmanFilter;
+
+/// `rescale` returns `matrix` with row `i` multiplied by `rows(i)` and column
+/// `j` by `columns(j)`.
+fn rescale<T: Matrix>(matrix: &T, rows: impl Fn(usize) -> f64, columns: impl Fn(usize) -> f64) -> T {
+    let (row_count, column_count) = matrix.shape();
+    let mut scaled = matrix.clone();
+    for i in 0..row_count {
+        for j in 0..column_count {
+            scaled.set(i, j, matrix.get(i, j) * rows(i) * columns(j));
+        }
+    }
+    scaled
+}
+
+/// `ScaledFilter` runs `filter` in normalized units, in which state `i` is
+/// its physical value divided by `scale[i]`.
+///
+/// `filter` can be inspected and adjusted directly, e.g. to check its
+/// `condition`, but its matrices are in normalized units. Measurements and
+/// their noise are left in their own units.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScaledFilter<T> {
+    pub filter: DynamicKalmanFilter<T>,
+    scale: Vec<f64>
+}
+
+impl<T: Matrix> ScaledFilter<T> {
+    /// `new` normalizes `model`, given in physical units, by the factors
+    /// `scale`, one per state.
+    ///
+    /// # Panics:
+    ///
+    /// Panics if there isn't one factor per state, or one is not positive.
+    pub fn new(model: DynamicKalmanFilter<T>, scale: &[f64]) -> Self {
+        assert_eq!(scale.len(), model.dimensions().0, "need one scale factor per state");
+        assert!(scale.iter().all(|factor| *factor > 0.0), "scale factors must be positive");
+        let inverse = |i: usize| scale[i].recip();
+        let unit = |_| 1.0;
+        let filter = DynamicKalmanFilter {
+            state: rescale(&model.state, inverse, unit),
+            covariance: rescale(&model.covariance, inverse, inverse),
+            transition: rescale(&model.transition, inverse, |j| scale[j]),
+            process_noise: rescale(&model.process_noise, inverse, inverse),
+            observation: rescale(&model.observation, unit, |j| scale[j]),
+            measurement_noise: model.measurement_noise
+        };
+        ScaledFilter {
+            filter,
+            scale: scale.to_vec()
+        }
+    }
+
+    /// `scale` returns the scale factors of the states.
+    pub fn scale(&self) -> &[f64] {
+        &self.scale
+    }
+
+    /// `predict` moves the estimate one step forward.
+    pub fn predict(&mut self) {
+        self.filter.predict();
+    }
+
+    /// `update` refines the estimate with `measurement`, in its own units.
+    pub fn update(&mut self, measurement: &T) -> Result<(), Error> {
+        self.filter.update(measurement)
+    }
+
+    /// `next` performs the entire predict - update cycle for a measurement.
+    pub fn next(&mut self, measurement: &T) -> Result<(), Error> {
+        self.filter.next(measurement)
+    }
+
+    /// `state` returns the state estimate in physical units.
+    pub fn state(&self) -> T {
+        rescale(&self.filter.state, |i| self.scale[i], |_| 1.0)
+    }
+
+    /// `covariance` returns the covariance of the estimate in physical units.
+    pub fn covariance(&self) -> T {
+        rescale(&self.filter.covariance, |i| self.scale[i], |j| self.scale[j])
+    }
+
+    /// `result` returns the state estimate and its covariance in physical
+    /// units.
+    pub fn result(&self) -> (T, T) {
+        (self.state(), self.covariance())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::DenseMatrix;
+
+    /// `model` tracks a position in metres and an angle in microradians, each
+    /// with its rate, measuring both.
+    fn model() -> DynamicKalmanFilter<DenseMatrix> {
+        let mut model = DynamicKalmanFilter::new(4, 2);
+        model.state = DenseMatrix::vector(&[1e3, 1.0, 2e-6, 1e-7]);
+        model.covariance = DenseMatrix::from_row_slices(&[
+            &[1e2, 0.0, 0.0, 0.0],
+            &[0.0, 1.0, 0.0, 0.0],
+            &[0.0, 0.0, 1e-12, 0.0],
+            &[0.0, 0.0, 0.0, 1e-14]
+        ]);
+        model.transition = DenseMatrix::from_row_slices(&[
+            &[1.0, 1.0, 0.0, 0.0],
+            &[0.0, 1.0, 0.0, 0.0],
+            &[0.0, 0.0, 1.0, 1.0],
+            &[0.0, 0.0, 0.0, 1.0]
+        ]);
+        model.process_noise = model.covariance.scale(1e-3);
+        model.observation = DenseMatrix::from_row_slices(&[&[1.0, 0.0, 0.0, 0.0], &[0.0, 0.0, 1.0, 0.0]]);
+        model.measurement_noise = DenseMatrix::from_row_slices(&[&[4.0, 0.0], &[0.0, 4e-12]]);
+        model
+    }
+
+    #[test]
+    fn matches_unscaled_filter() {
+        let mut plain = model();
+        let mut scaled = ScaledFilter::new(model(), &[10.0, 1.0, 1e-6, 1e-7]);
+        assert_eq!(scaled.state(), plain.state);
+
+        for step in 0..20 {
+            let measurement = DenseMatrix::vector(&[1e3 + f64::from(step), 2e-6 + 1e-7 * f64::from(step)]);
+            plain.next(&measurement).unwrap();
+            scaled.next(&measurement).unwrap();
+        }
+        let (state, covariance) = scaled.result();
+        for i in 0..4 {
+            let tolerance = 1e-9 * plain.covariance.get(i, i).sqrt();
+            assert!((state.get(i, 0) - plain.state.get(i, 0)).abs() < tolerance);
+            assert!((covariance.get(i, i) / plain.covariance.get(i, i) - 1.0).abs() < 1e-9);
+        }
+        assert!(scaled.filter.condition().unwrap() * 1e10 < plain.condition().unwrap());
+    }
+
+    #[test]
+    #[should_panic(expected = "need one scale factor per state")]
+    fn checks_scale_length() {
+        ScaledFilter::new(model(), &[1.0]);
+    }
+}
@@ -0,0 +1,167 @@
+//! A filter shared between an interrupt handler and the main loop.
+//!
+//! `SharedFilter` lets an interrupt service routine push measurements into an
+//! `Estimator` while the main loop, or another thread, reads the latest
+//! estimate. Neither side ever waits on the other:
+//!
+//! - The estimate is published through a sequence lock over 32-bit atomics,
+//!   so a reader interrupted by a push simply reads again, and no 64-bit
+//!   atomics are needed.
+//! - Pushes are claimed with an atomic flag. A push that interrupts another
+//!   push, e.g. from a handler of higher priority, is refused rather than
+//!   corrupting the estimator.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::baseline::ExponentialMovingAverage;
+//! use lqe::shared::SharedFilter;
+//!
+//! static PRESSURE: SharedFilter<ExponentialMovingAverage> = SharedFilter::new(ExponentialMovingAverage::new(0.5));
+//!
+//! // In the interrupt handler of the sensor:
+//! PRESSURE.push(1013.0);
+//! PRESSURE.push(1015.0);
+//!
+//! // In the main loop:
+//! PRESSURE.estimate().map(|estimate| estimate.measurement);
+//! // => Some(1014.0)
+//! ```
+
+use std::cell::UnsafeCell;
+use std::sync::atomic::{fence, AtomicBool, AtomicU32, Ordering};
+
+use crate::baseline::Estimator;
+use crate::LQE;
+
+/// `SharedFilter` runs `E` on the measurements pushed to it and publishes
+/// every estimate.
+pub struct SharedFilter<E> {
+    estimator: UnsafeCell<E>,
+    pushing: AtomicBool,
+    // Even while the estimate is stable, odd while it is being written, and
+    // zero until the first push.
+    sequence: AtomicU32,
+    // The halves of the bits of the measurement and variance.
+    estimate: [AtomicU32; 4]
+}
+
+// Safety: the estimator is only touched by the push holding `pushing`, and
+// the estimate only through atomics.
+unsafe impl<E: Send> Sync for SharedFilter<E> {}
+
+impl<E: Estimator> SharedFilter<E> {
+    /// `new` shares `estimator`, in `const` contexts too so that it can be
+    /// kept in a `static`.
+    pub const fn new(estimator: E) -> Self {
+        SharedFilter {
+            estimator: UnsafeCell::new(estimator),
+            pushing: AtomicBool::new(false),
+            sequence: AtomicU32::new(0),
+            estimate: [AtomicU32::new(0), AtomicU32::new(0), AtomicU32::new(0), AtomicU32::new(0)]
+        }
+    }
+
+    /// `push` folds in `measurement` and publishes the new estimate,
+    /// returning whether it could. It fails only if it interrupted another
+    /// push.
+    pub fn push(&self, measurement: f64) -> bool {
+        if self.pushing.swap(true, Ordering::Acquire) {
+            return false;
+        }
+        // Safety: the flag gives this push exclusive access until released.
+        let estimate = unsafe { (*self.estimator.get()).next(measurement) };
+        let sequence = self.sequence.load(Ordering::Relaxed);
+        self.sequence.store(sequence.wrapping_add(1), Ordering::Relaxed);
+        fence(Ordering::Release);
+        let (measurement, variance) = (estimate.measurement.to_bits(), estimate.variance.to_bits());
+        let halves = [measurement as u32, (measurement >> 32) as u32, variance as u32, (variance >> 32) as u32];
+        for (word, half) in self.estimate.iter().zip(halves.iter()) {
+            word.store(*half, Ordering::Relaxed);
+        }
+        // Skip zero on wrapping around, which would read as no estimate.
+        let published = sequence.wrapping_add(2).max(2);
+        self.sequence.store(published, Ordering::Release);
+        self.pushing.store(false, Ordering::Release);
+        true
+    }
+
+    /// `estimate` returns the latest estimate, or `None` before the first
+    /// push.
+    pub fn estimate(&self) -> Option<LQE> {
+        loop {
+            let before = self.sequence.load(Ordering::Acquire);
+            if before == 0 {
+                return None;
+            }
+            let mut halves = [0u64; 4];
+            for (half, word) in halves.iter_mut().zip(self.estimate.iter()) {
+                *half = u64::from(word.load(Ordering::Relaxed));
+            }
+            fence(Ordering::Acquire);
+            if before & 1 == 0 && self.sequence.load(Ordering::Relaxed) == before {
+                return Some(LQE {
+                    measurement: f64::from_bits(halves[0] | halves[1] << 32),
+                    variance: f64::from_bits(halves[2] | halves[3] << 32)
+                });
+            }
+            std::hint::spin_loop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::baseline::ExponentialMovingAverage;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn publishes_estimates() {
+        let filter = SharedFilter::new(ExponentialMovingAverage::new(0.5));
+        assert_eq!(filter.estimate(), None);
+
+        let mut reference = ExponentialMovingAverage::new(0.5);
+        for measurement in &[1.0, 3.0, 2.0] {
+            assert!(filter.push(*measurement));
+            assert_eq!(filter.estimate(), Some(reference.next(*measurement)));
+        }
+
+        // A push interrupting another is refused.
+        filter.pushing.store(true, Ordering::Relaxed);
+        assert!(!filter.push(4.0));
+    }
+
+    /// `Echo` estimates every measurement as itself with a variance equal to
+    /// it, so that a torn read shows as a mismatch.
+    struct Echo;
+
+    impl Estimator for Echo {
+        fn next(&mut self, measurement: f64) -> LQE {
+            LQE {
+                measurement,
+                variance: measurement
+            }
+        }
+    }
+
+    #[test]
+    fn reads_consistent_estimates_concurrently() {
+        let filter = Arc::new(SharedFilter::new(Echo));
+        let pusher = {
+            let filter = Arc::clone(&filter);
+            thread::spawn(move || {
+                for i in 0..100_000 {
+                    filter.push(f64::from(i) * 1.000_001);
+                }
+            })
+        };
+        for _ in 0..100_000 {
+            if let Some(estimate) = filter.estimate() {
+                assert_eq!(estimate.measurement, estimate.variance);
+            }
+        }
+        pusher.join().unwrap();
+    }
+}
@@ -0,0 +1,315 @@
+//! Sparse models for large structured states.
+//!
+//! For states with hundreds of components, e.g. values on a spatial grid, the
+//! transition `F` and observation `H` matrices are usually banded or otherwise
+//! sparse. `SparseKalmanFilter` stores them as `CsrMatrix` so that every
+//! product involving them costs `O(nonzeros * N)` instead of `O(N³)`. The
+//! covariance itself fills in and stays in a dense `Matrix` backend.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::matrix::{DenseMatrix, Matrix};
+//! use lqe::sparse::{CsrMatrix, SparseKalmanFilter};
+//!
+//! // Three cells diffusing into their neighbours, with the middle one measured.
+//! let mut filter: SparseKalmanFilter<DenseMatrix> = SparseKalmanFilter {
+//!   state: DenseMatrix::zeros(3, 1),
+//!   covariance: DenseMatrix::identity(3),
+//!   transition: CsrMatrix::from_triplets(3, 3, &[
+//!     (0, 0, 0.8), (0, 1, 0.2),
+//!     (1, 0, 0.1), (1, 1, 0.8), (1, 2, 0.1),
+//!     (2, 1, 0.2), (2, 2, 0.8)
+//!   ]),
+//!   process_noise: DenseMatrix::identity(3).scale(0.01),
+//!   observation: CsrMatrix::from_triplets(1, 3, &[(0, 1, 1.0)]),
+//!   measurement_noise: DenseMatrix::identity(1)
+//! };
+//!
+//! filter.next(&DenseMatrix::vector(&[1.0])).unwrap();
+//! ```
+
+use crate::error::Error;
+use crate::matrix::{DenseMatrix, Matrix};
+
+/// `CsrMatrix` is a sparse matrix in compressed sparse row format.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::sparse::CsrMatrix;
+/// let matrix = CsrMatrix::from_triplets(2, 3, &[(0, 0, 1.0), (1, 2, 2.0)]);
+/// matrix.nonzeros();
+/// // => 2
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct CsrMatrix {
+    rows: usize,
+    columns: usize,
+    row_offsets: Vec<usize>,
+    column_indices: Vec<usize>,
+    values: Vec<f64>
+}
+
+impl CsrMatrix {
+    /// `from_triplets` creates a `rows` x `columns` matrix from
+    /// `(row, column, value)` entries. Duplicate entries are summed.
+    ///
+    /// # Panics:
+    ///
+    /// Panics if an entry lies outside the matrix.
+    pub fn from_triplets(rows: usize, columns: usize, triplets: &[(usize, usize, f64)]) -> Self {
+        let mut sorted = triplets.to_vec();
+        sorted.sort_by_key(|&(row, column, _)| (row, column));
+        let mut row_offsets = vec![0; rows + 1];
+        let mut column_indices: Vec<usize> = Vec::with_capacity(sorted.len());
+        let mut values: Vec<f64> = Vec::with_capacity(sorted.len());
+        let mut last = None;
+        for (row, column, value) in sorted {
+            assert!(row < rows && column < columns, "matrix index out of bounds");
+            if last == Some((row, column)) {
+                *values.last_mut().unwrap() += value;
+            } else {
+                column_indices.push(column);
+                values.push(value);
+                row_offsets[row + 1] += 1;
+                last = Some((row, column));
+            }
+        }
+        for row in 0..rows {
+            row_offsets[row + 1] += row_offsets[row];
+        }
+        CsrMatrix {
+            rows,
+            columns,
+            row_offsets,
+            column_indices,
+            values
+        }
+    }
+
+    /// `identity` creates a `size` x `size` identity matrix.
+    pub fn identity(size: usize) -> Self {
+        let triplets: Vec<_> = (0..size).map(|i| (i, i, 1.0)).collect();
+        CsrMatrix::from_triplets(size, size, &triplets)
+    }
+
+    /// `shape` returns the number of rows and columns.
+    pub fn shape(&self) -> (usize, usize) {
+        (self.rows, self.columns)
+    }
+
+    /// `nonzeros` returns the number of stored entries.
+    pub fn nonzeros(&self) -> usize {
+        self.values.len()
+    }
+
+    /// `row_entries` iterates over the `(column, value)` entries of `row`.
+    fn row_entries(&self, row: usize) -> impl Iterator<Item = (usize, f64)> + '_ {
+        let range = self.row_offsets[row]..self.row_offsets[row + 1];
+        self.column_indices[range.clone()].iter().copied().zip(self.values[range].iter().copied())
+    }
+
+    /// `mul_dense` returns the product `self * dense`.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use lqe::matrix::{DenseMatrix, Matrix};
+    /// use lqe::sparse::CsrMatrix;
+    /// let matrix = CsrMatrix::from_triplets(2, 2, &[(0, 1, 2.0), (1, 0, 1.0)]);
+    /// matrix.mul_dense(&DenseMatrix::vector(&[3.0, 4.0]));
+    /// // => [8.0, 3.0]
+    /// ```
+    pub fn mul_dense<T: Matrix>(&self, dense: &T) -> T {
+        let (inner, columns) = dense.shape();
+        assert_eq!(self.columns, inner, "matrix shapes must be compatible");
+        let mut product = T::zeros(self.rows, columns);
+        for row in 0..self.rows {
+            for (k, value) in self.row_entries(row) {
+                for column in 0..columns {
+                    product.set(row, column, product.get(row, column) + value * dense.get(k, column));
+                }
+            }
+        }
+        product
+    }
+
+    /// `dense_mul_transpose` returns the product `dense * selfᵀ`.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use lqe::matrix::{DenseMatrix, Matrix};
+    /// use lqe::sparse::CsrMatrix;
+    /// let matrix = CsrMatrix::from_triplets(1, 2, &[(0, 1, 2.0)]);
+    /// matrix.dense_mul_transpose(&DenseMatrix::from_row_slices(&[&[3.0, 4.0]]));
+    /// // => [[8.0]]
+    /// ```
+    pub fn dense_mul_transpose<T: Matrix>(&self, dense: &T) -> T {
+        let (rows, inner) = dense.shape();
+        assert_eq!(self.columns, inner, "matrix shapes must be compatible");
+        let mut product = T::zeros(rows, self.rows);
+        for column in 0..self.rows {
+            for (k, value) in self.row_entries(column) {
+                for row in 0..rows {
+                    product.set(row, column, product.get(row, column) + dense.get(row, k) * value);
+                }
+            }
+        }
+        product
+    }
+}
+
+/// `SparseKalmanFilter` is a multivariate filter whose `transition` (`F`) and
+/// `observation` (`H`) matrices are sparse. The other fields are as in
+/// `DynamicKalmanFilter`.
+///
+/// The covariance update uses `P - K H P` rather than the Joseph form, which
+/// would cost a dense `O(N³)` product; the result is re-symmetrized instead.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SparseKalmanFilter<T = DenseMatrix> {
+    pub state: T,
+    pub covariance: T,
+    pub transition: CsrMatrix,
+    pub process_noise: T,
+    pub observation: CsrMatrix,
+    pub measurement_noise: T
+}
+
+impl<T: Matrix> SparseKalmanFilter<T> {
+    /// `predict` moves the estimate one step forward, `x = F x` and
+    /// `P = F P Fᵀ + Q`.
+    pub fn predict(&mut self) {
+        self.state = self.transition.mul_dense(&self.state);
+        self.covariance = self
+            .transition
+            .dense_mul_transpose(&self.transition.mul_dense(&self.covariance))
+            .add(&self.process_noise);
+    }
+
+    /// `update` refines the estimate with `measurement`, an `M` x 1 matrix.
+    pub fn update(&mut self, measurement: &T) -> Result<(), Error> {
+        let innovation = measurement.sub(&self.observation.mul_dense(&self.state));
+        // H P is M x N; P is symmetric so P Hᵀ = (H P)ᵀ.
+        let observed_covariance = self.observation.mul_dense(&self.covariance);
+        let innovation_covariance = self
+            .observation
+            .dense_mul_transpose(&observed_covariance)
+            .add(&self.measurement_noise);
+        let gain = innovation_covariance
+            .cholesky_solve(&observed_covariance)
+            .ok_or(Error::SingularInnovation)?
+            .transpose();
+        self.state = self.state.add(&gain.mul(&innovation));
+        let covariance = self.covariance.sub(&gain.mul(&observed_covariance));
+        self.covariance = covariance.add(&covariance.transpose()).scale(0.5);
+        Ok(())
+    }
+
+    /// `next` performs the entire predict - update cycle for a measurement.
+    pub fn next(&mut self, measurement: &T) -> Result<(), Error> {
+        self.predict();
+        self.update(measurement)
+    }
+
+    /// `result` returns the current state estimate and its covariance.
+    pub fn result(&self) -> (T, T) {
+        (self.state.clone(), self.covariance.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multivariate::DynamicKalmanFilter;
+
+    fn to_dense(sparse: &CsrMatrix) -> DenseMatrix {
+        sparse.mul_dense(&DenseMatrix::identity(sparse.shape().1))
+    }
+
+    fn assert_close(actual: &DenseMatrix, expected: &DenseMatrix) {
+        let difference = actual.sub(expected);
+        let (rows, columns) = difference.shape();
+        for i in 0..rows {
+            for j in 0..columns {
+                assert!(difference.get(i, j).abs() < 1e-9, "element ({}, {}) differs", i, j);
+            }
+        }
+    }
+
+    /// A 1D grid of `size` cells diffusing into their neighbours, every
+    /// `stride`th cell measured.
+    fn diffusion(size: usize, stride: usize) -> SparseKalmanFilter {
+        let mut transition = Vec::new();
+        for i in 0..size {
+            transition.push((i, i, 0.8));
+            if i > 0 {
+                transition.push((i, i - 1, 0.1));
+            }
+            if i + 1 < size {
+                transition.push((i, i + 1, 0.1));
+            }
+        }
+        let measured: Vec<_> = (0..size).step_by(stride).collect();
+        let observation: Vec<_> = measured.iter().enumerate().map(|(row, &cell)| (row, cell, 1.0)).collect();
+        SparseKalmanFilter {
+            state: DenseMatrix::zeros(size, 1),
+            covariance: DenseMatrix::identity(size),
+            transition: CsrMatrix::from_triplets(size, size, &transition),
+            process_noise: DenseMatrix::identity(size).scale(0.01),
+            observation: CsrMatrix::from_triplets(measured.len(), size, &observation),
+            measurement_noise: DenseMatrix::identity(measured.len()).scale(0.5)
+        }
+    }
+
+    #[test]
+    fn builds_from_triplets() {
+        let matrix = CsrMatrix::from_triplets(2, 3, &[(1, 2, 2.0), (0, 0, 1.0), (1, 2, 0.5)]);
+
+        assert_eq!(matrix.nonzeros(), 2);
+        assert_eq!(to_dense(&matrix), DenseMatrix::from_row_slices(&[&[1.0, 0.0, 0.0], &[0.0, 0.0, 2.5]]));
+        assert_eq!(to_dense(&CsrMatrix::identity(2)), DenseMatrix::identity(2));
+    }
+
+    #[test]
+    fn multiplies_with_dense_matrices() {
+        let sparse = CsrMatrix::from_triplets(2, 3, &[(0, 0, 1.0), (0, 2, 2.0), (1, 1, 3.0)]);
+        let dense = DenseMatrix::from_row_slices(&[&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0], &[7.0, 8.0, 9.0]]);
+
+        assert_eq!(sparse.mul_dense(&dense), to_dense(&sparse).mul(&dense));
+        assert_eq!(sparse.dense_mul_transpose(&dense), dense.mul(&to_dense(&sparse).transpose()));
+    }
+
+    #[test]
+    fn matches_dense_filter() {
+        let mut sparse = diffusion(12, 4);
+        let mut dense = DynamicKalmanFilter {
+            state: sparse.state.clone(),
+            covariance: sparse.covariance.clone(),
+            transition: to_dense(&sparse.transition),
+            process_noise: sparse.process_noise.clone(),
+            observation: to_dense(&sparse.observation),
+            measurement_noise: sparse.measurement_noise.clone()
+        };
+
+        for step in 0..5 {
+            let measurement = DenseMatrix::vector(&[1.0, f64::from(step), -1.0]);
+            sparse.next(&measurement).unwrap();
+            dense.next(&measurement).unwrap();
+        }
+        assert_close(&sparse.state, &dense.state);
+        assert_close(&sparse.covariance, &dense.covariance);
+    }
+
+    #[test]
+    fn rejects_singular_innovation() {
+        let mut filter = SparseKalmanFilter {
+            covariance: DenseMatrix::zeros(12, 12),
+            measurement_noise: DenseMatrix::zeros(3, 3),
+            ..diffusion(12, 4)
+        };
+
+        assert_eq!(filter.update(&DenseMatrix::vector(&[1.0, 1.0, 1.0])), Err(Error::SingularInnovation));
+    }
+}
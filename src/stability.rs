@@ -0,0 +1,396 @@
+//! Stability of the steady-state filter.
+//!
+//! With a fixed model the covariance, and with it the gain `K`, of a filter
+//! settle to steady-state values. The estimation error then evolves as
+//! `e = (I - K H) F e` plus noise, so the eigenvalues of the closed-loop
+//! matrix `(I - K H) F` tell how fast the filter forgets a wrong estimate:
+//! an error shrinks by their modulus each step. Moduli close to one make a
+//! filter that trusts its model so much it barely reacts to measurements;
+//! raising `Q` or lowering `R` pulls them towards zero.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::matrix::{DenseMatrix, Matrix};
+//! use lqe::multivariate::DynamicKalmanFilter;
+//! use lqe::stability;
+//!
+//! // A random walk with process noise 1 measured with variance 2.
+//! let mut model = DynamicKalmanFilter::<DenseMatrix>::new(1, 1);
+//! model.process_noise = DenseMatrix::identity(1);
+//! model.measurement_noise = DenseMatrix::vector(&[2.0]);
+//! let steady_state = stability::steady_state(&model).unwrap();
+//! steady_state.gain.get(0, 0);
+//! // => 0.5
+//! steady_state.spectral_radius();
+//! // => 0.5
+//! steady_state.is_stable();
+//! // => true
+//! ```
+
+use crate::matrix::Matrix;
+use crate::multivariate::DynamicKalmanFilter;
+use crate::riccati;
+
+/// The number of QR iterations an eigenvalue is given to converge.
+const ITERATIONS: usize = 60;
+
+/// `Eigenvalue` is a complex eigenvalue `re + im i`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Eigenvalue {
+    pub re: f64,
+    pub im: f64
+}
+
+impl Eigenvalue {
+    /// `modulus` returns the absolute value of the eigenvalue.
+    pub fn modulus(&self) -> f64 {
+        self.re.hypot(self.im)
+    }
+}
+
+/// `eigenvalues` returns the eigenvalues of the square `matrix`, complex
+/// conjugate pairs next to each other, or `None` if the QR algorithm fails
+/// to converge.
+///
+/// The matrix is reduced to Hessenberg form and its eigenvalues found by
+/// the shifted QR algorithm (Press et al., Numerical Recipes, 11.5 - 11.6).
+pub fn eigenvalues<T: Matrix>(matrix: &T) -> Option<Vec<Eigenvalue>> {
+    let (n, _) = matrix.shape();
+    // 1-based, as in the reference.
+    let mut a = vec![vec![0.0; n + 1]; n + 1];
+    for (i, row) in a.iter_mut().enumerate().skip(1) {
+        for (j, value) in row.iter_mut().enumerate().skip(1) {
+            *value = matrix.get(i - 1, j - 1);
+        }
+    }
+    hessenberg(&mut a, n);
+    let mut real = vec![0.0; n + 1];
+    let mut imaginary = vec![0.0; n + 1];
+    if !hqr(&mut a, n, &mut real, &mut imaginary) {
+        return None;
+    }
+    Some(
+        real.into_iter()
+            .zip(imaginary)
+            .skip(1)
+            .map(|(re, im)| Eigenvalue { re, im })
+            .collect()
+    )
+}
+
+/// `hessenberg` reduces the 1-based `a` to upper Hessenberg form by
+/// elimination with pivoting, keeping its eigenvalues.
+#[allow(clippy::needless_range_loop)]
+fn hessenberg(a: &mut [Vec<f64>], n: usize) {
+    for m in 2..n {
+        let (mut x, mut i) = (0.0_f64, m);
+        for j in m..=n {
+            if a[j][m - 1].abs() > x.abs() {
+                x = a[j][m - 1];
+                i = j;
+            }
+        }
+        if i != m {
+            a.swap(i, m);
+            for row in a.iter_mut() {
+                row.swap(i, m);
+            }
+        }
+        if x != 0.0 {
+            for i in m + 1..=n {
+                let y = a[i][m - 1] / x;
+                if y != 0.0 {
+                    a[i][m - 1] = 0.0;
+                    for j in m..=n {
+                        a[i][j] -= y * a[m][j];
+                    }
+                    for row in a.iter_mut().skip(1) {
+                        row[m] += y * row[i];
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// `hqr` finds the eigenvalues of the 1-based upper Hessenberg matrix `a`,
+/// destroying it, returning `false` if they don't converge.
+#[allow(clippy::many_single_char_names, clippy::needless_range_loop)]
+fn hqr(a: &mut [Vec<f64>], n: usize, real: &mut [f64], imaginary: &mut [f64]) -> bool {
+    let n = n as isize;
+    macro_rules! a {
+        ($i:expr, $j:expr) => {
+            a[($i) as usize][($j) as usize]
+        };
+    }
+    let mut norm = 0.0;
+    for i in 1..=n {
+        for j in (i - 1).max(1)..=n {
+            norm += a!(i, j).abs();
+        }
+    }
+    let (mut p, mut q, mut r) = (0.0_f64, 0.0_f64, 0.0_f64);
+    let (mut x, mut y, mut z, mut w);
+    let mut nn = n;
+    let mut t = 0.0;
+    while nn >= 1 {
+        let mut its = 0;
+        let mut l;
+        loop {
+            // Look for a single small subdiagonal element to split at.
+            l = nn;
+            while l >= 2 {
+                let mut s = a!(l - 1, l - 1).abs() + a!(l, l).abs();
+                if s == 0.0 {
+                    s = norm;
+                }
+                if a!(l, l - 1).abs() + s == s {
+                    a!(l, l - 1) = 0.0;
+                    break;
+                }
+                l -= 1;
+            }
+            x = a!(nn, nn);
+            if l == nn {
+                // One root found.
+                real[nn as usize] = x + t;
+                imaginary[nn as usize] = 0.0;
+                nn -= 1;
+            } else {
+                y = a!(nn - 1, nn - 1);
+                w = a!(nn, nn - 1) * a!(nn - 1, nn);
+                if l == nn - 1 {
+                    // Two roots found.
+                    p = 0.5 * (y - x);
+                    q = p * p + w;
+                    z = q.abs().sqrt();
+                    x += t;
+                    let (upper, lower) = ((nn - 1) as usize, nn as usize);
+                    if q >= 0.0 {
+                        z = p + z.copysign(p);
+                        real[upper] = x + z;
+                        real[lower] = if z != 0.0 { x - w / z } else { x + z };
+                        imaginary[upper] = 0.0;
+                        imaginary[lower] = 0.0;
+                    } else {
+                        real[upper] = x + p;
+                        real[lower] = x + p;
+                        imaginary[upper] = -z;
+                        imaginary[lower] = z;
+                    }
+                    nn -= 2;
+                } else {
+                    if its == ITERATIONS {
+                        return false;
+                    }
+                    if its == 10 || its == 20 {
+                        // Exceptional shift.
+                        t += x;
+                        for i in 1..=nn {
+                            a!(i, i) -= x;
+                        }
+                        let s = a!(nn, nn - 1).abs() + a!(nn - 1, nn - 2).abs();
+                        x = 0.75 * s;
+                        y = x;
+                        w = -0.4375 * s * s;
+                    }
+                    its += 1;
+                    // Look for two consecutive small subdiagonal elements.
+                    let mut m = nn - 2;
+                    while m >= l {
+                        z = a!(m, m);
+                        r = x - z;
+                        let s = y - z;
+                        p = (r * s - w) / a!(m + 1, m) + a!(m, m + 1);
+                        q = a!(m + 1, m + 1) - z - r - s;
+                        r = a!(m + 2, m + 1);
+                        let s = p.abs() + q.abs() + r.abs();
+                        p /= s;
+                        q /= s;
+                        r /= s;
+                        if m == l {
+                            break;
+                        }
+                        let u = a!(m, m - 1).abs() * (q.abs() + r.abs());
+                        let v = p.abs() * (a!(m - 1, m - 1).abs() + z.abs() + a!(m + 1, m + 1).abs());
+                        if u + v == v {
+                            break;
+                        }
+                        m -= 1;
+                    }
+                    for i in m + 2..=nn {
+                        a!(i, i - 2) = 0.0;
+                        if i != m + 2 {
+                            a!(i, i - 3) = 0.0;
+                        }
+                    }
+                    // Double QR step on rows l to nn and columns m to nn.
+                    for k in m..nn {
+                        if k != m {
+                            p = a!(k, k - 1);
+                            q = a!(k + 1, k - 1);
+                            r = if k != nn - 1 { a!(k + 2, k - 1) } else { 0.0 };
+                            x = p.abs() + q.abs() + r.abs();
+                            if x != 0.0 {
+                                p /= x;
+                                q /= x;
+                                r /= x;
+                            }
+                        }
+                        let s = (p * p + q * q + r * r).sqrt().copysign(p);
+                        if s != 0.0 {
+                            if k == m {
+                                if l != m {
+                                    a!(k, k - 1) = -a!(k, k - 1);
+                                }
+                            } else {
+                                a!(k, k - 1) = -s * x;
+                            }
+                            p += s;
+                            x = p / s;
+                            y = q / s;
+                            z = r / s;
+                            q /= p;
+                            r /= p;
+                            for j in k..=nn {
+                                p = a!(k, j) + q * a!(k + 1, j);
+                                if k != nn - 1 {
+                                    p += r * a!(k + 2, j);
+                                    a!(k + 2, j) -= p * z;
+                                }
+                                a!(k + 1, j) -= p * y;
+                                a!(k, j) -= p * x;
+                            }
+                            for i in l..=nn.min(k + 3) {
+                                p = x * a!(i, k) + y * a!(i, k + 1);
+                                if k != nn - 1 {
+                                    p += z * a!(i, k + 2);
+                                    a!(i, k + 2) -= p * r;
+                                }
+                                a!(i, k + 1) -= p * q;
+                                a!(i, k) -= p;
+                            }
+                        }
+                    }
+                }
+            }
+            if l >= nn - 1 {
+                break;
+            }
+        }
+    }
+    true
+}
+
+/// `SteadyState` is the filter a model settles to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SteadyState<T> {
+    /// `covariance` is the steady-state prediction covariance.
+    pub covariance: T,
+    /// `gain` is the steady-state Kalman gain `K`.
+    pub gain: T,
+    /// `closed_loop` is `(I - K H) F`, propagating the estimation error.
+    pub closed_loop: T,
+    /// `eigenvalues` are the eigenvalues of `closed_loop`.
+    pub eigenvalues: Vec<Eigenvalue>
+}
+
+impl<T> SteadyState<T> {
+    /// `spectral_radius` returns the largest modulus of the eigenvalues, the
+    /// factor by which the slowest error shrinks each step.
+    pub fn spectral_radius(&self) -> f64 {
+        self.eigenvalues.iter().map(Eigenvalue::modulus).fold(0.0, f64::max)
+    }
+
+    /// `is_stable` checks that every error decays, i.e. the spectral radius
+    /// is below one.
+    pub fn is_stable(&self) -> bool {
+        self.spectral_radius() < 1.0
+    }
+
+    /// `is_marginal` checks whether the spectral radius is within `margin`
+    /// of one or beyond, so some error barely decays, if at all.
+    pub fn is_marginal(&self, margin: f64) -> bool {
+        self.spectral_radius() >= 1.0 - margin
+    }
+
+    /// `time_constant` returns the number of steps in which the slowest
+    /// error shrinks by a factor `e`, infinite unless the filter is stable.
+    pub fn time_constant(&self) -> f64 {
+        let radius = self.spectral_radius();
+        if radius < 1.0 {
+            -1.0 / radius.ln()
+        } else {
+            f64::INFINITY
+        }
+    }
+}
+
+/// `steady_state` returns the filter `model` settles to, or `None` if its
+/// covariance diverges, e.g. because an unstable state is unobservable.
+pub fn steady_state<T: Matrix>(model: &DynamicKalmanFilter<T>) -> Option<SteadyState<T>> {
+    let covariance = riccati::filter_covariance(model)?;
+    let observation = &model.observation;
+    let observed = observation.mul(&covariance);
+    let innovation = observed.mul(&observation.transpose()).add(&model.measurement_noise);
+    let gain = innovation.cholesky_solve(&observed)?.transpose();
+    let states = covariance.shape().0;
+    let closed_loop = T::identity(states).sub(&gain.mul(observation)).mul(&model.transition);
+    let eigenvalues = eigenvalues(&closed_loop)?;
+    Some(SteadyState {
+        covariance,
+        gain,
+        closed_loop,
+        eigenvalues
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::DenseMatrix;
+
+    fn sorted(mut eigenvalues: Vec<Eigenvalue>) -> Vec<(f64, f64)> {
+        eigenvalues.sort_by(|a, b| (a.re, a.im).partial_cmp(&(b.re, b.im)).unwrap());
+        eigenvalues.iter().map(|eigenvalue| (eigenvalue.re, eigenvalue.im)).collect()
+    }
+
+    #[test]
+    fn finds_eigenvalues() {
+        let rotation = DenseMatrix::from_row_slices(&[&[0.0, -2.0], &[2.0, 0.0]]);
+        assert_eq!(sorted(eigenvalues(&rotation).unwrap()), vec![(0.0, -2.0), (0.0, 2.0)]);
+
+        // The companion matrix of (x - 1)(x - 2)(x - 3)(x - 4).
+        let companion = DenseMatrix::from_row_slices(&[
+            &[10.0, -35.0, 50.0, -24.0],
+            &[1.0, 0.0, 0.0, 0.0],
+            &[0.0, 1.0, 0.0, 0.0],
+            &[0.0, 0.0, 1.0, 0.0]
+        ]);
+        let roots = sorted(eigenvalues(&companion).unwrap());
+        for (root, expected) in roots.iter().zip(1..) {
+            assert!((root.0 - f64::from(expected)).abs() < 1e-9);
+            assert_eq!(root.1, 0.0);
+        }
+    }
+
+    #[test]
+    fn analyzes_random_walk() {
+        let (q, r) = (0.5, 3.0);
+        let mut model = DynamicKalmanFilter::<DenseMatrix>::new(1, 1);
+        model.process_noise = DenseMatrix::vector(&[q]);
+        model.measurement_noise = DenseMatrix::vector(&[r]);
+        let analysis = steady_state(&model).unwrap();
+
+        let covariance = (q + (q * q + 4.0 * q * r).sqrt()) / 2.0;
+        let gain = covariance / (covariance + r);
+        assert!((analysis.covariance.get(0, 0) - covariance).abs() < 1e-6);
+        assert!((analysis.spectral_radius() - (1.0 - gain)).abs() < 1e-6);
+        assert!(analysis.is_stable());
+        assert!(!analysis.is_marginal(0.1));
+
+        model.process_noise = DenseMatrix::vector(&[1e-8]);
+        assert!(steady_state(&model).unwrap().is_marginal(0.1));
+    }
+}
@@ -0,0 +1,105 @@
+//! Scalar predict and update steps, and per-step diagnostics.
+//!
+//! `predict` and `update` are the two phases of a scalar Kalman filter:
+//! `predict` moves an estimate forward by a known control input and widens
+//! it by the process noise, `update` folds in a measurement with its
+//! variance. They replace `LQE::next`, which passes the same two arguments
+//! to both phases.
+//!
+//! `StepInfo` describes a single update and is handed to observers
+//! registered with `TimedFilter::set_observer`, e.g. for logging, plotting
+//! or fault detection.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::{step, LQE};
+//!
+//! // A value drifting by 0.5 per step, measured with a variance of 2.0.
+//! let estimate = LQE { measurement: 7.0, variance: 1.5 };
+//! let prior = step::predict(estimate, 0.0, 0.5);
+//! step::update(prior, 10.0, 2.0).posterior.result();
+//! // => (8.5, 1.0)
+//! ```
+
+use crate::LQE;
+
+/// `predict` moves `estimate` one step forward: `control` is the known
+/// change of the value over the step, e.g. a commanded move, and
+/// `process_noise` the variance it gains from unknown ones.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::{step, LQE};
+/// step::predict(LQE { measurement: 7.0, variance: 2.0 }, 1.0, 0.5).result();
+/// // => (8.0, 2.5)
+/// ```
+pub fn predict(estimate: LQE, control: f64, process_noise: f64) -> LQE {
+    let prediction = LQE {
+        measurement: estimate.measurement + control,
+        variance: estimate.variance + process_noise
+    };
+    step_event!(
+        control,
+        process_noise,
+        mean = prediction.measurement,
+        variance = prediction.variance,
+        "predict"
+    );
+    prediction
+}
+
+/// `update` refines `estimate` with `measurement`, whose variance is
+/// `measurement_noise`, returning the whole step. Its `posterior` is the
+/// refined estimate.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::{step, LQE};
+/// step::update(LQE { measurement: 7.0, variance: 2.0 }, 10.0, 2.0).posterior.result();
+/// // => (8.5, 1.0)
+/// ```
+pub fn update(estimate: LQE, measurement: f64, measurement_noise: f64) -> StepInfo {
+    estimate.correct(measurement, measurement_noise)
+}
+
+/// `StepInfo` records how a measurement changed the estimate.
+///
+/// `innovation` is the difference between the measurement and the prediction,
+/// `innovation_variance` its expected variance and `gain` the weight given to
+/// the measurement.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct StepInfo {
+    pub prediction: LQE,
+    pub measurement: f64,
+    pub innovation: f64,
+    pub innovation_variance: f64,
+    pub gain: f64,
+    pub posterior: LQE
+}
+
+/// `Observer` is a boxed callback receiving the `StepInfo` of each step.
+pub type Observer = Box<dyn FnMut(&StepInfo) + Send>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn separates_predict_and_update() {
+        let estimate = LQE {
+            measurement: 7.0,
+            variance: 1.5
+        };
+
+        let prior = predict(estimate, 1.0, 0.5);
+        assert_eq!(prior.result(), (8.0, 2.0));
+        let step = update(prior, 10.0, 2.0);
+        assert_eq!(step.prediction, prior);
+        assert_eq!((step.innovation, step.gain), (2.0, 0.5));
+        assert_eq!(step.posterior.result(), (9.0, 1.0));
+    }
+}
@@ -0,0 +1,558 @@
+//! Structural time-series models.
+//!
+//! A structural model decomposes a series into components with a direct
+//! interpretation, each a random walk of its own: a level, and optionally a
+//! slope it moves by, and a seasonal pattern repeating over a fixed period.
+//! `StructuralModel` casts the classic local level, local linear trend and
+//! damped trend models into state-space form, with an optional `Seasonal`
+//! component composed onto them, and filters, smooths and forecasts a series
+//! with them.
+//!
+//! `HoltWinters` expresses exponential smoothing in the same framework, as a
+//! single source of error model whose gain is fixed by the smoothing
+//! parameters. It gives the classic Holt-Winters forecasts together with
+//! prediction intervals.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::structural::StructuralModel;
+//!
+//! let model = StructuralModel::local_linear_trend(0.1, 0.01, 1.0);
+//! let sales = [12.0, 13.5, 14.8, 16.1, 17.2, 18.9];
+//! let forecast = model.forecast(&sales, 3).unwrap();
+//! forecast[2].measurement;
+//! // => 22.8
+//! ```
+
+use crate::batch;
+use crate::error::Error;
+use crate::matrix::{DenseMatrix, Matrix};
+use crate::multivariate::DynamicKalmanFilter;
+use crate::LQE;
+
+/// `Trend` is how the level of a structural model evolves.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Trend {
+    /// The level is a random walk.
+    Level,
+    /// The level moves by a slope, which is a random walk itself.
+    Linear,
+    /// Like `Linear`, but the slope decays towards zero by the given damping
+    /// factor in `(0, 1)` per step, so that long forecasts flatten out.
+    Damped(f64)
+}
+
+/// `Seasonal` is a pattern that repeats every `period` steps, e.g. 7 for the
+/// weekly pattern of a daily series, and that may change slowly by gaining
+/// the variance `noise` per step.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Seasonal {
+    /// One effect per step of the period, constrained to sum to zero over
+    /// it, with `period - 1` states.
+    Dummy { period: usize, noise: f64 },
+    /// A sum of the first `harmonics` sinusoids of the period, with up to
+    /// `2 * harmonics` states. Fewer harmonics than `period / 2` give a
+    /// smoother pattern with fewer states, which suits long periods.
+    Trigonometric { period: usize, harmonics: usize, noise: f64 }
+}
+
+impl Seasonal {
+    /// `state_space` returns the transition, process noise and observation of
+    /// the seasonal states.
+    fn state_space(&self) -> (DenseMatrix, DenseMatrix, DenseMatrix) {
+        match *self {
+            Seasonal::Dummy { period, noise } => {
+                let states = period.saturating_sub(1);
+                let mut transition = DenseMatrix::zeros(states, states);
+                for j in 0..states {
+                    transition.set(0, j, -1.0);
+                    if j + 1 < states {
+                        transition.set(j + 1, j, 1.0);
+                    }
+                }
+                let mut process_noise = DenseMatrix::zeros(states, states);
+                let mut observation = DenseMatrix::zeros(1, states);
+                if states > 0 {
+                    process_noise.set(0, 0, noise);
+                    observation.set(0, 0, 1.0);
+                }
+                (transition, process_noise, observation)
+            }
+            Seasonal::Trigonometric { period, harmonics, noise } => {
+                // The harmonic at half the period is a single alternating
+                // state; the others rotate a pair.
+                let sizes = (1..=harmonics.min(period / 2))
+                    .map(|j| if 2 * j == period { 1 } else { 2 })
+                    .collect::<Vec<_>>();
+                let states = sizes.iter().sum();
+                let mut transition = DenseMatrix::zeros(states, states);
+                let mut observation = DenseMatrix::zeros(1, states);
+                let mut start = 0;
+                for (j, size) in sizes.iter().enumerate() {
+                    let frequency = 2.0 * std::f64::consts::PI * (j + 1) as f64 / period as f64;
+                    let (sin, cos) = frequency.sin_cos();
+                    transition.set(start, start, cos);
+                    if *size == 2 {
+                        transition.set(start, start + 1, sin);
+                        transition.set(start + 1, start, -sin);
+                        transition.set(start + 1, start + 1, cos);
+                    }
+                    observation.set(0, start, 1.0);
+                    start += size;
+                }
+                (transition, DenseMatrix::identity(states).scale(noise), observation)
+            }
+        }
+    }
+}
+
+/// `StructuralModel` is a level observed with noise `observation_noise`,
+/// moving with `trend`, plus an optional `seasonal` pattern.
+///
+/// `level_noise` and `slope_noise` are the variances the level and slope
+/// gain per step, the slope noise being unused for `Trend::Level`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct StructuralModel {
+    pub trend: Trend,
+    pub level_noise: f64,
+    pub slope_noise: f64,
+    pub observation_noise: f64,
+    pub seasonal: Option<Seasonal>
+}
+
+impl StructuralModel {
+    /// `local_level` creates a model whose level is a random walk.
+    pub fn local_level(level_noise: f64, observation_noise: f64) -> Self {
+        StructuralModel {
+            trend: Trend::Level,
+            level_noise,
+            slope_noise: 0.0,
+            observation_noise,
+            seasonal: None
+        }
+    }
+
+    /// `local_linear_trend` creates a model whose level moves by a randomly
+    /// walking slope.
+    pub fn local_linear_trend(level_noise: f64, slope_noise: f64, observation_noise: f64) -> Self {
+        StructuralModel {
+            trend: Trend::Linear,
+            level_noise,
+            slope_noise,
+            observation_noise,
+            seasonal: None
+        }
+    }
+
+    /// `damped_trend` creates a model whose level moves by a randomly walking
+    /// slope that decays by `damping` per step.
+    pub fn damped_trend(damping: f64, level_noise: f64, slope_noise: f64, observation_noise: f64) -> Self {
+        StructuralModel {
+            trend: Trend::Damped(damping),
+            level_noise,
+            slope_noise,
+            observation_noise,
+            seasonal: None
+        }
+    }
+
+    /// `with_seasonal` returns the model with the seasonal component
+    /// `seasonal` added.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use lqe::structural::{Seasonal, StructuralModel};
+    ///
+    /// let model = StructuralModel::local_level(0.1, 1.0)
+    ///     .with_seasonal(Seasonal::Dummy { period: 7, noise: 0.01 });
+    /// model.state_space().dimensions();
+    /// // => (7, 1)
+    /// ```
+    pub fn with_seasonal(self, seasonal: Seasonal) -> Self {
+        StructuralModel {
+            seasonal: Some(seasonal),
+            ..self
+        }
+    }
+
+    /// `state_space` returns the model as a filter over the state `[level]`
+    /// or `[level, slope]`, followed by the seasonal states, starting from a
+    /// diffuse prior so that the first observations decide the initial
+    /// values.
+    pub fn state_space(&self) -> DynamicKalmanFilter<DenseMatrix> {
+        let (transition, process_noise) = match self.trend {
+            Trend::Level => (
+                DenseMatrix::from_row_slices(&[&[1.0]]),
+                DenseMatrix::from_row_slices(&[&[self.level_noise]])
+            ),
+            Trend::Linear | Trend::Damped(_) => {
+                let damping = if let Trend::Damped(damping) = self.trend { damping } else { 1.0 };
+                (
+                    DenseMatrix::from_row_slices(&[&[1.0, damping], &[0.0, damping]]),
+                    DenseMatrix::from_row_slices(&[&[self.level_noise, 0.0], &[0.0, self.slope_noise]])
+                )
+            }
+        };
+        let mut observation = DenseMatrix::zeros(1, transition.shape().0);
+        observation.set(0, 0, 1.0);
+        let mut components = vec![(transition, process_noise, observation)];
+        components.extend(self.seasonal.map(|seasonal| seasonal.state_space()));
+
+        // Compose the components block-diagonally, observing their sum.
+        let states = components.iter().map(|(transition, _, _)| transition.shape().0).sum();
+        let mut filter = DynamicKalmanFilter::<DenseMatrix>::new(states, 1);
+        filter.covariance = DenseMatrix::identity(states).scale(1e6);
+        let mut start = 0;
+        for (transition, process_noise, observation) in &components {
+            let size = transition.shape().0;
+            for i in 0..size {
+                for j in 0..size {
+                    filter.transition.set(start + i, start + j, transition.get(i, j));
+                    filter.process_noise.set(start + i, start + j, process_noise.get(i, j));
+                }
+                filter.observation.set(0, start + i, observation.get(0, i));
+            }
+            start += size;
+        }
+        filter.measurement_noise.set(0, 0, self.observation_noise);
+        filter
+    }
+
+    /// `filter` returns the estimate of the level after each of
+    /// `observations`, given the observations up to it. The level excludes
+    /// the seasonal component, i.e. it is the seasonally adjusted series.
+    pub fn filter(&self, observations: &[f64]) -> Result<Vec<LQE>, Error> {
+        let mut filter = self.state_space();
+        let mut levels = Vec::with_capacity(observations.len());
+        for observation in observations {
+            filter.next(&DenseMatrix::vector(&[*observation]))?;
+            levels.push(level(&filter.state, &filter.covariance));
+        }
+        Ok(levels)
+    }
+
+    /// `smooth` returns the estimate of the level at each of `observations`,
+    /// given all of them.
+    pub fn smooth(&self, observations: &[f64]) -> Result<Vec<LQE>, Error> {
+        let measurements = observations.iter().map(|z| DenseMatrix::vector(&[*z])).collect::<Vec<_>>();
+        let smoothed = batch::smooth(&self.state_space(), &measurements)?;
+        Ok(smoothed.iter().map(|(state, covariance)| level(state, covariance)).collect())
+    }
+
+    /// `impute` returns the smoothed estimate of the series without its
+    /// observation noise at each step of `observations`, filling in the
+    /// steps that are `None` from the observations around them.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use lqe::structural::StructuralModel;
+    ///
+    /// let model = StructuralModel::local_linear_trend(0.01, 0.01, 0.1);
+    /// let imputed = model.impute(&[Some(1.0), Some(2.0), None, Some(4.0)]).unwrap();
+    /// imputed[2].measurement;
+    /// // => 3.0
+    /// ```
+    pub fn impute(&self, observations: &[Option<f64>]) -> Result<Vec<LQE>, Error> {
+        let measurements = observations
+            .iter()
+            .map(|z| z.map(|z| DenseMatrix::vector(&[z])))
+            .collect::<Vec<_>>();
+        let model = self.state_space();
+        let smoothed = batch::smooth_missing(&model, &measurements)?;
+        let observation = &model.observation;
+        Ok(smoothed
+            .iter()
+            .map(|(state, covariance)| LQE {
+                measurement: observation.mul(state).get(0, 0),
+                variance: observation.mul(covariance).mul(&observation.transpose()).get(0, 0)
+            })
+            .collect())
+    }
+
+    /// `forecast` returns the distribution of the next `horizon`
+    /// observations after `observations`, including the observation noise.
+    pub fn forecast(&self, observations: &[f64], horizon: usize) -> Result<Vec<LQE>, Error> {
+        let mut filter = self.state_space();
+        for observation in observations {
+            filter.next(&DenseMatrix::vector(&[*observation]))?;
+        }
+        let mut forecasts = Vec::with_capacity(horizon);
+        for _ in 0..horizon {
+            filter.predict();
+            let observation = &filter.observation;
+            forecasts.push(LQE {
+                measurement: observation.mul(&filter.state).get(0, 0),
+                variance: observation.mul(&filter.covariance).mul(&observation.transpose()).get(0, 0)
+                    + self.observation_noise
+            });
+        }
+        Ok(forecasts)
+    }
+}
+
+/// `level` returns the level component of a state and its covariance.
+fn level(state: &DenseMatrix, covariance: &DenseMatrix) -> LQE {
+    LQE {
+        measurement: state.get(0, 0),
+        variance: covariance.get(0, 0)
+    }
+}
+
+/// `HoltWinters` is additive Holt-Winters exponential smoothing with level,
+/// trend and seasonal smoothing parameters `alpha`, `beta` and `gamma` (each
+/// in `[0, 1]`), and a season of `period` steps; a period below 2 disables
+/// the seasonal component.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::structural::HoltWinters;
+///
+/// let model = HoltWinters { alpha: 0.5, beta: 0.1, gamma: 0.1, period: 4 };
+/// let sales = [10.0, 14.0, 8.0, 12.0, 11.0, 15.0, 9.0, 13.0, 12.0, 16.0];
+/// let forecast = model.forecast(&sales, 4).unwrap();
+/// forecast[0].measurement;
+/// // => 10.27
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct HoltWinters {
+    pub alpha: f64,
+    pub beta: f64,
+    pub gamma: f64,
+    pub period: usize
+}
+
+impl HoltWinters {
+    /// `innovations` returns the transition `F`, gain `g` and observation `w`
+    /// of the equivalent state-space model over `[level, slope, season...]`,
+    /// the most recent seasonal effect first:
+    /// `yₜ = w xₜ₋₁ + eₜ` and `xₜ = F xₜ₋₁ + g eₜ`.
+    fn innovations(&self) -> (DenseMatrix, DenseMatrix, DenseMatrix) {
+        let seasons = if self.period >= 2 { self.period } else { 0 };
+        let states = 2 + seasons;
+        let mut transition = DenseMatrix::zeros(states, states);
+        transition.set(0, 0, 1.0);
+        transition.set(0, 1, 1.0);
+        transition.set(1, 1, 1.0);
+        // The classic recursions in error correction form.
+        let mut gain = DenseMatrix::vector(&[self.alpha, self.alpha * self.beta]);
+        let mut observation = DenseMatrix::from_row_slices(&[&[1.0, 1.0]]);
+        if seasons > 0 {
+            gain = resize(&gain, states, 1);
+            gain.set(2, 0, (1.0 - self.alpha) * self.gamma);
+            observation = resize(&observation, 1, states);
+            observation.set(0, states - 1, 1.0);
+            // The new effect is the one from a period ago; the others age.
+            transition.set(2, states - 1, 1.0);
+            for i in 3..states {
+                transition.set(i, i - 1, 1.0);
+            }
+        }
+        (transition, gain, observation)
+    }
+
+    /// `forecast` returns the distribution of the next `horizon` values after
+    /// `series`.
+    ///
+    /// The initial state is set from the first two periods of `series` (or
+    /// first two values without a season), and the variance of the errors
+    /// from the one-step errors over the rest.
+    ///
+    /// # Errors:
+    ///
+    /// Returns `Error::DimensionMismatch` if `series` is shorter than that.
+    pub fn forecast(&self, series: &[f64], horizon: usize) -> Result<Vec<LQE>, Error> {
+        let (transition, gain, observation) = self.innovations();
+        let (mut state, start) = self.initial_state(series)?;
+        let mut squares = 0.0;
+        for value in &series[start..] {
+            let error = value - observation.mul(&state).get(0, 0);
+            squares += error * error;
+            state = transition.mul(&state).add(&gain.scale(error));
+        }
+        let variance = squares / (series.len() - start).max(1) as f64;
+
+        let mut forecasts = Vec::with_capacity(horizon);
+        let mut spread = 1.0;
+        let mut propagated = DenseMatrix::identity(transition.shape().0);
+        for _ in 0..horizon {
+            forecasts.push(LQE {
+                measurement: observation.mul(&propagated).mul(&state).get(0, 0),
+                variance: variance * spread
+            });
+            spread += observation.mul(&propagated).mul(&gain).get(0, 0).powi(2);
+            propagated = transition.mul(&propagated);
+        }
+        Ok(forecasts)
+    }
+
+    /// `initial_state` returns the state after the first values of `series`
+    /// and the index to continue from.
+    fn initial_state(&self, series: &[f64]) -> Result<(DenseMatrix, usize), Error> {
+        if self.period < 2 {
+            if series.len() < 2 {
+                return Err(Error::DimensionMismatch);
+            }
+            return Ok((DenseMatrix::vector(&[series[0], series[1] - series[0]]), 1));
+        }
+        let period = self.period;
+        if series.len() < 2 * period {
+            return Err(Error::DimensionMismatch);
+        }
+        let mean = |values: &[f64]| values.iter().sum::<f64>() / values.len() as f64;
+        let level = mean(&series[..period]);
+        let slope = (mean(&series[period..2 * period]) - level) / period as f64;
+        let mut state = DenseMatrix::zeros(2 + period, 1);
+        state.set(0, 0, level);
+        state.set(1, 0, slope);
+        for (i, value) in series[..period].iter().rev().enumerate() {
+            state.set(2 + i, 0, value - level);
+        }
+        Ok((state, period))
+    }
+}
+
+/// `resize` returns the top-left `rows` x `columns` block of `matrix`, padded
+/// with zeros.
+fn resize(matrix: &DenseMatrix, rows: usize, columns: usize) -> DenseMatrix {
+    let mut resized = DenseMatrix::zeros(rows, columns);
+    let (old_rows, old_columns) = matrix.shape();
+    for i in 0..rows.min(old_rows) {
+        for j in 0..columns.min(old_columns) {
+            resized.set(i, j, matrix.get(i, j));
+        }
+    }
+    resized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_scalar_filter_for_local_level() {
+        let model = StructuralModel::local_level(0.5, 1.0);
+
+        let levels = model.filter(&[3.0, 5.0]).unwrap();
+        let first = LQE { measurement: 0.0, variance: 1e6 + 0.5 }.correct(3.0, 1.0).posterior;
+        let second = LQE { variance: first.variance + 0.5, ..first }.correct(5.0, 1.0).posterior;
+        assert!((levels[1].measurement - second.measurement).abs() < 1e-9);
+        assert!((levels[1].variance - second.variance).abs() < 1e-9);
+    }
+
+    #[test]
+    fn forecasts_linear_trend() {
+        let model = StructuralModel::local_linear_trend(1e-6, 1e-6, 1e-4);
+        let series = (0..10).map(|t| 2.0 + 0.5 * f64::from(t)).collect::<Vec<_>>();
+
+        let forecast = model.forecast(&series, 4).unwrap();
+        assert!((forecast[3].measurement - 8.5).abs() < 1e-3);
+        assert!(forecast[0].variance < forecast[3].variance);
+    }
+
+    #[test]
+    fn flattens_damped_forecasts() {
+        let series = (0..10).map(|t| 2.0 + 0.5 * f64::from(t)).collect::<Vec<_>>();
+        let damped = StructuralModel::damped_trend(0.5, 1e-6, 1e-2, 1e-4);
+
+        let forecast = damped.forecast(&series, 50).unwrap();
+        assert!((forecast[49].measurement - forecast[48].measurement).abs() < 1e-9);
+        assert!(forecast[49].measurement < 8.5);
+    }
+
+    #[test]
+    fn smooths_with_later_observations() {
+        let model = StructuralModel::local_level(1.0, 1.0);
+
+        let filtered = model.filter(&[0.0, 0.0, 6.0]).unwrap();
+        let smoothed = model.smooth(&[0.0, 0.0, 6.0]).unwrap();
+        assert!(smoothed[1].measurement > filtered[1].measurement);
+        assert!(smoothed[1].variance < filtered[1].variance);
+        assert_eq!(smoothed[2], filtered[2]);
+    }
+
+    #[test]
+    fn imputes_missing_observations() {
+        let model = StructuralModel::local_level(1e-4, 1e-4).with_seasonal(Seasonal::Dummy { period: 7, noise: 1e-6 });
+        let series = (0..28).map(|t| if t % 5 == 3 { None } else { Some(weekly(t)) }).collect::<Vec<_>>();
+
+        let imputed = model.impute(&series).unwrap();
+        for (t, estimate) in imputed.iter().enumerate().skip(7) {
+            assert!((estimate.measurement - weekly(t)).abs() < 0.05, "step {}", t);
+        }
+        assert!(imputed[13].variance > imputed[12].variance);
+    }
+
+    fn weekly(t: usize) -> f64 {
+        10.0 + [3.0, 1.0, 0.0, -1.0, -2.0, -2.0, 1.0][t % 7]
+    }
+
+    #[test]
+    fn forecasts_dummy_seasonal() {
+        let model = StructuralModel::local_level(1e-6, 1e-4).with_seasonal(Seasonal::Dummy { period: 7, noise: 1e-6 });
+        let series = (0..28).map(weekly).collect::<Vec<_>>();
+
+        let forecast = model.forecast(&series, 7).unwrap();
+        for (h, estimate) in forecast.iter().enumerate() {
+            assert!((estimate.measurement - weekly(28 + h)).abs() < 1e-2);
+        }
+        let levels = model.filter(&series).unwrap();
+        assert!((levels[27].measurement - 10.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn forecasts_trigonometric_seasonal() {
+        let model = StructuralModel::local_level(1e-6, 1e-4).with_seasonal(Seasonal::Trigonometric {
+            period: 7,
+            harmonics: 3,
+            noise: 1e-6
+        });
+        let series = (0..28).map(weekly).collect::<Vec<_>>();
+
+        assert_eq!(model.state_space().dimensions(), (7, 1));
+        let forecast = model.forecast(&series, 7).unwrap();
+        for (h, estimate) in forecast.iter().enumerate() {
+            assert!((estimate.measurement - weekly(28 + h)).abs() < 1e-2);
+        }
+        let even = Seasonal::Trigonometric { period: 4, harmonics: 2, noise: 0.0 };
+        assert_eq!(even.state_space().0.shape(), (3, 3));
+    }
+
+    #[test]
+    fn matches_holt_recursions() {
+        let model = HoltWinters { alpha: 0.5, beta: 0.2, gamma: 0.0, period: 0 };
+        let series = [1.0, 3.0, 4.0, 7.0];
+
+        // Holt's recursions from l = 1, b = 2.
+        let (mut level, mut slope) = (1.0f64, 2.0f64);
+        for value in &series[1..] {
+            let previous = level;
+            level = 0.5 * value + 0.5 * (level + slope);
+            slope = 0.2 * 0.5 * (value - previous - slope) + slope;
+        }
+        let forecast = model.forecast(&series, 3).unwrap();
+        assert!((forecast[2].measurement - (level + 3.0 * slope)).abs() < 1e-12);
+        // The h-step variance is σ² (1 + Σ (α + αβ j)²) for j < h.
+        let ratio = forecast[2].variance / forecast[0].variance;
+        assert!((ratio - (1.0 + 0.6f64.powi(2) + 0.7f64.powi(2))).abs() < 1e-12);
+    }
+
+    #[test]
+    fn forecasts_holt_winters_season() {
+        let model = HoltWinters { alpha: 0.3, beta: 0.1, gamma: 0.2, period: 7 };
+        let series = (0..35).map(weekly).collect::<Vec<_>>();
+
+        let forecast = model.forecast(&series, 7).unwrap();
+        for (h, estimate) in forecast.iter().enumerate() {
+            assert!((estimate.measurement - weekly(35 + h)).abs() < 1e-9);
+        }
+        assert_eq!(model.forecast(&series[..10], 1), Err(Error::DimensionMismatch));
+    }
+}
@@ -0,0 +1,462 @@
+//! Time-aware filtering.
+//!
+//! A `TimedFilter` keeps track of when it last saw a measurement and grows the
+//! variance of its estimate by `process_noise * dt` before folding in the next
+//! one, so irregularly sampled sensors don't need their noise rescaled by hand.
+//!
+//! Filters left running for hundreds of millions of steps can opt into
+//! compensated summation with `set_compensated`, so that corrections and
+//! variance growth too small to register against the estimate still add up
+//! instead of being rounded away.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::LQE;
+//! use lqe::timed::TimedFilter;
+//!
+//! let mut filter = TimedFilter::new(LQE { measurement: 7.0, variance: 2.0 }, 0.5);
+//! filter.next(0.0, 7.5, 1.0);
+//! filter.next(2.0, 8.0, 1.0).result();
+//! // => (7.75, 0.625)
+//! ```
+
+use std::time::Instant;
+
+use crate::measurement::Measurement;
+#[cfg(feature = "metrics")]
+use crate::metrics::Metrics;
+use crate::step::{self, Observer, StepInfo};
+use crate::LQE;
+
+/// `Timestamp` is anything that can report the seconds elapsed since an earlier
+/// value of itself.
+///
+/// It is implemented for `std::time::Instant`, for plain `f64` seconds and for
+/// `Ticks` read from a monotonic counter. Timestamps that run backwards are
+/// treated as simultaneous.
+pub trait Timestamp: Copy {
+    /// `seconds_since` returns the seconds elapsed from `earlier` to `self`.
+    fn seconds_since(&self, earlier: &Self) -> f64;
+}
+
+impl Timestamp for Instant {
+    fn seconds_since(&self, earlier: &Self) -> f64 {
+        self.saturating_duration_since(*earlier).as_secs_f64()
+    }
+}
+
+impl Timestamp for f64 {
+    fn seconds_since(&self, earlier: &Self) -> f64 {
+        (self - earlier).max(0.0)
+    }
+}
+
+/// `Ticks` is a reading from a monotonic counter running at `HZ` ticks per
+/// second, e.g. a hardware timer on a microcontroller.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::timed::{Ticks, Timestamp};
+/// Ticks::<1_000>(2_500).seconds_since(&Ticks(1_000));
+/// // => 1.5
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Ticks<const HZ: u32>(pub u64);
+
+impl<const HZ: u32> Timestamp for Ticks<HZ> {
+    fn seconds_since(&self, earlier: &Self) -> f64 {
+        self.0.saturating_sub(earlier.0) as f64 / f64::from(HZ)
+    }
+}
+
+/// `TimedFilter` wraps an `LQE` estimate of a randomly walking value.
+///
+/// `process_noise` is the variance the value gains per second. Each call to
+/// `next` first predicts the estimate forward by the time elapsed since the
+/// previous call, then updates it with the new measurement.
+pub struct TimedFilter<T = Instant> {
+    estimate: LQE,
+    process_noise: f64,
+    last_timestamp: Option<T>,
+    // The rounding errors carried by the mean and variance, when compensated.
+    compensation: Option<LQE>,
+    observer: Option<Observer>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Metrics>
+}
+
+/// `compensated_add` returns `sum + addend` by Kahan summation, carrying the
+/// rounding error over to the next addition in `compensation`.
+fn compensated_add(sum: f64, addend: f64, compensation: &mut f64) -> f64 {
+    let corrected = addend - *compensation;
+    let total = sum + corrected;
+    *compensation = (total - sum) - corrected;
+    total
+}
+
+impl<T: Timestamp> TimedFilter<T> {
+    /// `new` creates a filter starting from `estimate`. The first measurement
+    /// is applied without any prediction.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use std::time::Instant;
+    /// use lqe::LQE;
+    /// use lqe::timed::TimedFilter;
+    /// let filter: TimedFilter<Instant> = TimedFilter::new(LQE { measurement: 7.0, variance: 2.0 }, 0.5);
+    /// ```
+    pub fn new(estimate: LQE, process_noise: f64) -> Self {
+        TimedFilter {
+            estimate,
+            process_noise,
+            last_timestamp: None,
+            compensation: None,
+            observer: None,
+            #[cfg(feature = "metrics")]
+            metrics: None
+        }
+    }
+
+    /// `predict` moves the estimate forward to `timestamp` without a
+    /// measurement, growing its variance by `process_noise * dt`. A
+    /// timestamp before the last one leaves the estimate as it is.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use lqe::LQE;
+    /// use lqe::timed::TimedFilter;
+    /// let mut filter = TimedFilter::new(LQE { measurement: 7.0, variance: 2.0 }, 0.5);
+    /// filter.next(0.0, 7.0, 2.0);
+    /// filter.predict(4.0).result();
+    /// // => (7.0, 3.0)
+    /// ```
+    pub fn predict(&mut self, timestamp: T) -> LQE {
+        match self.last_timestamp {
+            Some(last_timestamp) => {
+                let dt = timestamp.seconds_since(&last_timestamp);
+                // An earlier timestamp is treated as simultaneous and leaves
+                // the clock where it is, so the time it skipped back over
+                // isn't counted again by the next prediction.
+                if dt > 0.0 {
+                    let mut prediction = step::predict(self.estimate, 0.0, self.process_noise * dt);
+                    if let Some(compensation) = self.compensation.as_mut() {
+                        prediction.variance =
+                            compensated_add(self.estimate.variance, self.process_noise * dt, &mut compensation.variance);
+                    }
+                    self.estimate = prediction;
+                    self.last_timestamp = Some(timestamp);
+                }
+            }
+            None => self.last_timestamp = Some(timestamp)
+        }
+        self.estimate
+    }
+
+    /// `next` performs the entire predict - update cycle for a measurement
+    /// taken at `timestamp`.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use lqe::LQE;
+    /// use lqe::timed::TimedFilter;
+    /// let mut filter = TimedFilter::new(LQE { measurement: 7.0, variance: 2.0 }, 0.5);
+    /// filter.next(0.0, 10.0, 2.0).result();
+    /// // => (8.5, 1.0)
+    /// ```
+    pub fn next(&mut self, timestamp: T, measurement: f64, variance: f64) -> LQE {
+        let prior = self.predict(timestamp);
+        let mut step = step::update(prior, measurement, variance);
+        if let Some(compensation) = self.compensation.as_mut() {
+            step.posterior.measurement =
+                compensated_add(prior.measurement, step.gain * step.innovation, &mut compensation.measurement);
+            // The variance is scaled rather than summed, and its error with it.
+            compensation.variance *= 1.0 - step.gain;
+        }
+        if let Some(observer) = self.observer.as_mut() {
+            observer(&step);
+        }
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.record(&step);
+        }
+        self.estimate = step.posterior;
+        self.estimate
+    }
+
+    /// `set_compensated` turns compensated summation of the corrections to
+    /// the mean and the growth of the variance on or off. It costs a few
+    /// extra operations per step and keeps very long runs from drifting.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use lqe::LQE;
+    /// use lqe::timed::TimedFilter;
+    /// let mut filter = TimedFilter::new(LQE { measurement: 7.0, variance: 1.0 }, 1e-17);
+    /// filter.set_compensated(true);
+    /// for second in 0..1000 {
+    ///     filter.predict(f64::from(second));
+    /// }
+    /// filter.result();
+    /// // => (7.0, 1.00000000000001)
+    /// ```
+    pub fn set_compensated(&mut self, compensated: bool) {
+        self.compensation = if compensated {
+            Some(LQE::new(0.0, 0.0))
+        } else {
+            None
+        };
+    }
+
+    /// `set_observer` registers a callback invoked with the `StepInfo` of every
+    /// subsequent call to `next`, replacing any previous observer.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use lqe::LQE;
+    /// use lqe::timed::TimedFilter;
+    /// let mut filter = TimedFilter::new(LQE { measurement: 7.0, variance: 2.0 }, 0.5);
+    /// filter.set_observer(|step| println!("innovation: {}", step.innovation));
+    /// filter.next(0.0, 10.0, 2.0);
+    /// // innovation: 3
+    /// ```
+    pub fn set_observer(&mut self, observer: impl FnMut(&StepInfo) + Send + 'static) {
+        self.observer = Some(Box::new(observer));
+    }
+
+    /// `set_metrics` publishes the health of the filter after every
+    /// subsequent call to `next` and counts its resets.
+    #[cfg(feature = "metrics")]
+    pub fn set_metrics(&mut self, metrics: Metrics) {
+        self.metrics = Some(metrics);
+    }
+
+    /// `reset` restarts the filter from `estimate`, forgetting the timestamp of
+    /// the previous measurement.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use lqe::LQE;
+    /// use lqe::timed::TimedFilter;
+    /// let mut filter = TimedFilter::new(LQE { measurement: 7.0, variance: 2.0 }, 0.5);
+    /// filter.next(0.0, 10.0, 2.0);
+    /// filter.reset(LQE { measurement: 3.0, variance: 2.0 });
+    /// filter.next(9.0, 3.0, 2.0).result();
+    /// // => (3.0, 1.0)
+    /// ```
+    pub fn reset(&mut self, estimate: LQE) {
+        self.estimate = estimate;
+        self.last_timestamp = None;
+        self.clear_compensation();
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.record_reset();
+        }
+    }
+
+    /// `resume` restarts the filter from `estimate` as of `last_timestamp`,
+    /// e.g. restored from a checkpoint, so the next measurement is predicted
+    /// forward from then.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use lqe::LQE;
+    /// use lqe::timed::TimedFilter;
+    /// let mut filter = TimedFilter::new(LQE { measurement: 7.0, variance: 2.0 }, 0.5);
+    /// filter.resume(LQE { measurement: 3.0, variance: 1.0 }, Some(0.0));
+    /// filter.next(2.0, 3.0, 2.0).result();
+    /// // => (3.0, 1.0)
+    /// ```
+    pub fn resume(&mut self, estimate: LQE, last_timestamp: Option<T>) {
+        self.estimate = estimate;
+        self.last_timestamp = last_timestamp;
+        self.clear_compensation();
+    }
+
+    /// `clear_compensation` forgets the rounding errors carried over from
+    /// the estimate that was replaced.
+    fn clear_compensation(&mut self) {
+        if let Some(compensation) = self.compensation.as_mut() {
+            *compensation = LQE::new(0.0, 0.0);
+        }
+    }
+
+    /// `last_timestamp` returns the timestamp of the previous measurement or
+    /// prediction, if there was one since the last reset.
+    pub fn last_timestamp(&self) -> Option<T> {
+        self.last_timestamp
+    }
+
+    /// `apply` runs `next` with the timestamp, value and variance of a
+    /// `Measurement`.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use lqe::LQE;
+    /// use lqe::measurement::{Measurement, SensorId};
+    /// use lqe::timed::TimedFilter;
+    /// let mut filter = TimedFilter::new(LQE { measurement: 7.0, variance: 2.0 }, 0.5);
+    /// filter.apply(&Measurement { timestamp: 0.0, value: 10.0, variance: 2.0, sensor_id: SensorId(1) }).result();
+    /// // => (8.5, 1.0)
+    /// ```
+    pub fn apply(&mut self, measurement: &Measurement<T>) -> LQE {
+        self.next(measurement.timestamp, measurement.value, measurement.variance)
+    }
+
+    /// `result` returns the current state of the filter as a tuple value.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use lqe::LQE;
+    /// use lqe::timed::TimedFilter;
+    /// let filter = TimedFilter::<f64>::new(LQE { measurement: 7.0, variance: 2.0 }, 0.5);
+    /// filter.result();
+    /// // => (7.0, 2.0)
+    /// ```
+    pub fn result(&self) -> (f64, f64) {
+        self.estimate.result()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<T: defmt::Format> defmt::Format for TimedFilter<T> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "TimedFilter {{ estimate: {}, process_noise: {}, last_timestamp: {} }}",
+            self.estimate,
+            self.process_noise,
+            self.last_timestamp
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::measurement::SensorId;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn measures_elapsed_time() {
+        let start = Instant::now();
+
+        assert_eq!((start + Duration::from_millis(250)).seconds_since(&start), 0.25);
+        assert_eq!(3.5.seconds_since(&1.0), 2.5);
+        assert_eq!(Ticks::<1_000>(2_500).seconds_since(&Ticks(1_000)), 1.5);
+    }
+
+    #[test]
+    fn treats_backwards_time_as_simultaneous() {
+        assert_eq!(1.0.seconds_since(&3.5), 0.0);
+        assert_eq!(Ticks::<1_000>(1_000).seconds_since(&Ticks(2_500)), 0.0);
+    }
+
+    #[test]
+    fn scales_process_noise_by_elapsed_time() {
+        let mut filter = TimedFilter::new(LQE { measurement: 7.0, variance: 2.0 }, 0.5);
+
+        filter.next(0.0, 7.0, 2.0);
+        assert_eq!(filter.predict(4.0).result(), (7.0, 3.0));
+        assert_eq!(filter.predict(4.0).result(), (7.0, 3.0));
+    }
+
+    #[test]
+    fn keeps_clock_on_backwards_timestamps() {
+        let mut filter = TimedFilter::new(LQE { measurement: 7.0, variance: 2.0 }, 0.5);
+
+        filter.next(0.0, 7.0, 2.0);
+        assert_eq!(filter.predict(4.0).result(), (7.0, 3.0));
+        assert_eq!(filter.predict(1.0).result(), (7.0, 3.0));
+        assert_eq!(filter.last_timestamp(), Some(4.0));
+        // Only the two seconds past 4.0 add noise.
+        assert_eq!(filter.predict(6.0).result(), (7.0, 4.0));
+    }
+
+    #[test]
+    fn runs_filter_correctly() {
+        let mut filter = TimedFilter::new(LQE { measurement: 7.0, variance: 2.0 }, 0.5);
+
+        assert_eq!(filter.next(0.0, 7.5, 1.0).result(), (7.333333333333333, 0.6666666666666667));
+        assert_eq!(filter.next(2.0, 8.0, 1.0).result(), (7.75, 0.625));
+    }
+
+    #[test]
+    fn notifies_observer_after_each_step() {
+        let (sender, receiver) = mpsc::channel();
+        let mut filter = TimedFilter::new(LQE { measurement: 7.0, variance: 2.0 }, 0.5);
+
+        filter.set_observer(move |step| sender.send(*step).unwrap());
+        filter.next(0.0, 10.0, 2.0);
+        filter.predict(4.0);
+        filter.next(4.0, 8.5, 1.0);
+
+        let steps: Vec<StepInfo> = receiver.try_iter().collect();
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[0].prediction, LQE { measurement: 7.0, variance: 2.0 });
+        assert_eq!((steps[0].innovation, steps[0].gain), (3.0, 0.5));
+        assert_eq!(steps[0].posterior, LQE { measurement: 8.5, variance: 1.0 });
+        assert_eq!(steps[1].prediction, LQE { measurement: 8.5, variance: 3.0 });
+        assert_eq!(steps[1].posterior, LQE { measurement: 8.5, variance: 0.75 });
+    }
+
+    #[test]
+    fn restarts_after_reset() {
+        let mut filter = TimedFilter::new(LQE { measurement: 7.0, variance: 2.0 }, 0.5);
+
+        filter.next(0.0, 10.0, 2.0);
+        filter.reset(LQE { measurement: 3.0, variance: 2.0 });
+        assert_eq!(filter.result(), (3.0, 2.0));
+        assert_eq!(filter.next(9.0, 3.0, 2.0).result(), (3.0, 1.0));
+    }
+
+    #[test]
+    fn compensates_long_runs() {
+        let mut plain = TimedFilter::new(LQE { measurement: 7.0, variance: 1.0 }, 1e-17);
+        let mut compensated = TimedFilter::new(LQE { measurement: 7.0, variance: 1.0 }, 1e-17);
+        compensated.set_compensated(true);
+
+        for second in 0..1000 {
+            plain.predict(f64::from(second));
+            compensated.predict(f64::from(second));
+        }
+        assert_eq!(plain.result(), (7.0, 1.0));
+        assert!((compensated.result().1 - (1.0 + 999e-17)).abs() < 1e-16);
+
+        // Corrections too small to move the mean on their own still add up.
+        let mut plain = TimedFilter::new(LQE { measurement: 1.0, variance: 1e-16 }, 0.0);
+        let mut compensated = TimedFilter::new(LQE { measurement: 1.0, variance: 1e-16 }, 0.0);
+        compensated.set_compensated(true);
+        for second in 0..1000 {
+            plain.next(f64::from(second), 1.5, 1.0);
+            compensated.next(f64::from(second), 1.5, 1.0);
+        }
+        assert_eq!(plain.result().0, 1.0);
+        assert!(compensated.result().0 > 1.0 + 1e-14);
+    }
+
+    #[test]
+    fn applies_measurements() {
+        let mut filter = TimedFilter::new(LQE { measurement: 7.0, variance: 2.0 }, 0.5);
+        let measurement = Measurement {
+            timestamp: 0.0,
+            value: 10.0,
+            variance: 2.0,
+            sensor_id: SensorId(1)
+        };
+
+        assert_eq!(filter.apply(&measurement).result(), (8.5, 1.0));
+    }
+}
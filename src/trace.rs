@@ -0,0 +1,16 @@
+//! Instrumentation hooks.
+//!
+//! With the `tracing` feature enabled `step_event!` emits a `tracing` event at
+//! trace level, otherwise it expands to nothing.
+
+#[cfg(feature = "tracing")]
+macro_rules! step_event {
+    ($($field:tt)*) => {
+        tracing::trace!($($field)*)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! step_event {
+    ($($field:tt)*) => {};
+}
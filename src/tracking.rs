@@ -0,0 +1,540 @@
+//! Ready-made models for tracking moving objects.
+//!
+//! `Tracker2D` follows an object in a plane, e.g. a blob in a camera image or
+//! a vehicle on a map, from noisy position fixes. It models the velocity as
+//! constant up to random accelerations, the usual choice when nothing more is
+//! known about how the object moves.
+//!
+//! `Tracker3D` does the same in space, for drones or radar targets, with a
+//! choice of constant velocity or constant acceleration `Motion` and of
+//! sensors measuring position only or position and velocity.
+//!
+//! `BoxTracker` follows an object detected in video frames by its bounding
+//! box, with the model of the SORT tracker.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::tracking::Tracker2D;
+//!
+//! let mut tracker = Tracker2D::new((0.0, 0.0), 1.0);
+//! for (x, y) in &[(1.0, 0.5), (2.1, 1.0), (2.9, 1.4)] {
+//!     tracker.next(1.0, (*x, *y)).unwrap();
+//! }
+//! tracker.velocity();
+//! // => (0.94, 0.45)
+//! ```
+
+use crate::error::Error;
+use crate::matrix::{DenseMatrix, Matrix};
+use crate::multivariate::DynamicKalmanFilter;
+
+/// `factorial` returns `n!` as a float, for the small `n` of a motion model.
+fn factorial(n: usize) -> f64 {
+    (1..=n).map(|i| i as f64).product()
+}
+
+/// `kinematics` returns the transition and process noise over `dt` seconds
+/// for `axes` independent axes, each with `order` derivatives (2 for
+/// constant velocity, 3 for constant acceleration) driven by white noise of
+/// spectral density `noise` in the highest one.
+///
+/// The state holds every axis of a derivative together: the positions of all
+/// axes, then their velocities, and so on.
+fn kinematics(axes: usize, order: usize, dt: f64, noise: f64) -> (DenseMatrix, DenseMatrix) {
+    let size = axes * order;
+    let mut transition = DenseMatrix::zeros(size, size);
+    let mut process_noise = DenseMatrix::zeros(size, size);
+    for i in 0..order {
+        for j in 0..order {
+            let variance = {
+                let power = 2 * order - 1 - i - j;
+                noise * dt.powi(power as i32)
+                    / (factorial(order - 1 - i) * factorial(order - 1 - j) * power as f64)
+            };
+            for axis in 0..axes {
+                if j >= i {
+                    transition.set(i * axes + axis, j * axes + axis, dt.powi((j - i) as i32) / factorial(j - i));
+                }
+                process_noise.set(i * axes + axis, j * axes + axis, variance);
+            }
+        }
+    }
+    (transition, process_noise)
+}
+
+/// `Tracker2D` estimates the position and velocity of an object in a plane
+/// from position measurements.
+///
+/// `acceleration_noise` is the spectral density of the random accelerations
+/// of the object, a larger value letting the tracker turn faster at the cost
+/// of more noise. `measurement_noise` is the variance of a position fix in
+/// each axis. `filter` exposes the underlying state `[x, y, vx, vy]`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tracker2D {
+    pub filter: DynamicKalmanFilter<DenseMatrix>,
+    pub acceleration_noise: f64,
+    pub measurement_noise: f64
+}
+
+impl Tracker2D {
+    /// `new` creates a tracker at `position`, standing still but with a large
+    /// velocity uncertainty so that the first few fixes set it quickly.
+    pub fn new(position: (f64, f64), measurement_noise: f64) -> Self {
+        let mut filter = DynamicKalmanFilter::new(4, 2);
+        filter.state = DenseMatrix::vector(&[position.0, position.1, 0.0, 0.0]);
+        for i in 0..4 {
+            filter.covariance.set(i, i, if i < 2 { measurement_noise } else { 100.0 });
+        }
+        Tracker2D {
+            filter,
+            acceleration_noise: 1.0,
+            measurement_noise
+        }
+    }
+
+    /// `predict` moves the estimate `dt` seconds forward.
+    pub fn predict(&mut self, dt: f64) {
+        let (transition, process_noise) = kinematics(2, 2, dt, self.acceleration_noise);
+        self.filter.transition = transition;
+        self.filter.process_noise = process_noise;
+        self.filter.predict();
+    }
+
+    /// `update` refines the estimate with a measured `position`.
+    pub fn update(&mut self, position: (f64, f64)) -> Result<(), Error> {
+        self.filter.measurement_noise = DenseMatrix::identity(2).scale(self.measurement_noise);
+        self.filter.update(&DenseMatrix::vector(&[position.0, position.1]))
+    }
+
+    /// `next` predicts `dt` seconds forward and updates with `position`.
+    pub fn next(&mut self, dt: f64, position: (f64, f64)) -> Result<(), Error> {
+        self.predict(dt);
+        self.update(position)
+    }
+
+    /// `position` returns the estimated position.
+    pub fn position(&self) -> (f64, f64) {
+        (self.filter.state.get(0, 0), self.filter.state.get(1, 0))
+    }
+
+    /// `velocity` returns the estimated velocity.
+    pub fn velocity(&self) -> (f64, f64) {
+        (self.filter.state.get(2, 0), self.filter.state.get(3, 0))
+    }
+}
+
+/// `Motion` is the assumption a tracker makes about how its target moves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Motion {
+    /// The velocity is constant up to random accelerations.
+    ConstantVelocity,
+    /// The acceleration is constant up to random jerks, for manoeuvring
+    /// targets.
+    ConstantAcceleration
+}
+
+impl Motion {
+    /// `order` returns the number of derivatives of the position modelled,
+    /// including the position itself.
+    fn order(self) -> usize {
+        match self {
+            Motion::ConstantVelocity => 2,
+            Motion::ConstantAcceleration => 3
+        }
+    }
+}
+
+/// `Tracker3D` estimates the position, velocity and, with
+/// `Motion::ConstantAcceleration`, acceleration of a point target in space.
+///
+/// `process_noise` is the spectral density of the random accelerations
+/// (constant velocity) or jerks (constant acceleration) of the target.
+/// `position_noise`, `velocity_noise` and `acceleration_noise` are the
+/// variances of a measurement in each axis. `filter` exposes the underlying
+/// state `[x, y, z, vx, vy, vz]`, followed by `[ax, ay, az]` with constant
+/// acceleration.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::tracking::{Motion, Tracker3D};
+///
+/// let mut tracker = Tracker3D::new((0.0, 0.0, 10.0), Motion::ConstantAcceleration, 1.0);
+/// tracker.predict(0.1);
+/// tracker.update_position((0.2, 0.1, 10.1)).unwrap();
+/// tracker.predict(0.1);
+/// tracker.update_position_velocity((0.4, 0.2, 10.2), (2.0, 1.0, 1.0)).unwrap();
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tracker3D {
+    pub filter: DynamicKalmanFilter<DenseMatrix>,
+    pub motion: Motion,
+    pub process_noise: f64,
+    pub position_noise: f64,
+    pub velocity_noise: f64,
+    pub acceleration_noise: f64
+}
+
+impl Tracker3D {
+    /// `new` creates a tracker at `position`, standing still but with a large
+    /// uncertainty in the other derivatives so that the first few
+    /// measurements set them quickly. The velocity and acceleration noise
+    /// start out equal to `position_noise`.
+    pub fn new(position: (f64, f64, f64), motion: Motion, position_noise: f64) -> Self {
+        let size = 3 * motion.order();
+        let mut filter = DynamicKalmanFilter::<DenseMatrix>::new(size, 3);
+        filter.state.set(0, 0, position.0);
+        filter.state.set(1, 0, position.1);
+        filter.state.set(2, 0, position.2);
+        for i in 0..size {
+            filter.covariance.set(i, i, if i < 3 { position_noise } else { 100.0 });
+        }
+        Tracker3D {
+            filter,
+            motion,
+            process_noise: 1.0,
+            position_noise,
+            velocity_noise: position_noise,
+            acceleration_noise: position_noise
+        }
+    }
+
+    /// `predict` moves the estimate `dt` seconds forward.
+    pub fn predict(&mut self, dt: f64) {
+        let (transition, process_noise) = kinematics(3, self.motion.order(), dt, self.process_noise);
+        self.filter.transition = transition;
+        self.filter.process_noise = process_noise;
+        self.filter.predict();
+    }
+
+    /// `update_position` refines the estimate with a measured `position`.
+    pub fn update_position(&mut self, position: (f64, f64, f64)) -> Result<(), Error> {
+        self.observe(3);
+        self.filter.update(&DenseMatrix::vector(&[position.0, position.1, position.2]))
+    }
+
+    /// `update_position_velocity` refines the estimate with a measured
+    /// `position` and `velocity`, e.g. from a GNSS receiver or a Doppler
+    /// radar.
+    pub fn update_position_velocity(
+        &mut self,
+        position: (f64, f64, f64),
+        velocity: (f64, f64, f64)
+    ) -> Result<(), Error> {
+        self.observe(6);
+        let measurement = [position.0, position.1, position.2, velocity.0, velocity.1, velocity.2];
+        self.filter.update(&DenseMatrix::vector(&measurement))
+    }
+
+    /// `update_velocity` refines the estimate with a `velocity` measured in
+    /// the first axes, e.g. only `[north, east]` from a speed over ground.
+    ///
+    /// More than three axes fail with `Error::DimensionMismatch`.
+    pub fn update_velocity(&mut self, velocity: &[f64]) -> Result<(), Error> {
+        self.update_derivative(1, velocity, self.velocity_noise)
+    }
+
+    /// `update_acceleration` refines the estimate with an `acceleration`
+    /// measured in the first axes, e.g. by an accelerometer rotated into the
+    /// frame of the tracker.
+    ///
+    /// More than three axes, or a tracker without
+    /// `Motion::ConstantAcceleration`, fail with `Error::DimensionMismatch`.
+    pub fn update_acceleration(&mut self, acceleration: &[f64]) -> Result<(), Error> {
+        self.update_derivative(2, acceleration, self.acceleration_noise)
+    }
+
+    /// `position` returns the estimated position.
+    pub fn position(&self) -> (f64, f64, f64) {
+        self.axes(0)
+    }
+
+    /// `velocity` returns the estimated velocity.
+    pub fn velocity(&self) -> (f64, f64, f64) {
+        self.axes(1)
+    }
+
+    /// `acceleration` returns the estimated acceleration, which is only
+    /// modelled with `Motion::ConstantAcceleration`.
+    pub fn acceleration(&self) -> Option<(f64, f64, f64)> {
+        match self.motion {
+            Motion::ConstantVelocity => None,
+            Motion::ConstantAcceleration => Some(self.axes(2))
+        }
+    }
+
+    /// `observe` sets up the filter to measure the first `measurements`
+    /// elements of the state.
+    fn observe(&mut self, measurements: usize) {
+        let size = 3 * self.motion.order();
+        let mut observation = DenseMatrix::zeros(measurements, size);
+        let mut measurement_noise = DenseMatrix::zeros(measurements, measurements);
+        for i in 0..measurements {
+            observation.set(i, i, 1.0);
+            measurement_noise.set(i, i, if i < 3 { self.position_noise } else { self.velocity_noise });
+        }
+        self.filter.observation = observation;
+        self.filter.measurement_noise = measurement_noise;
+    }
+
+    /// `update_derivative` refines the estimate with `values` of the first
+    /// axes of a `derivative` of the position, each measured with variance
+    /// `noise`.
+    fn update_derivative(&mut self, derivative: usize, values: &[f64], noise: f64) -> Result<(), Error> {
+        if values.len() > 3 || derivative >= self.motion.order() {
+            return Err(Error::DimensionMismatch);
+        }
+        let mut observation = DenseMatrix::zeros(values.len(), 3 * self.motion.order());
+        for i in 0..values.len() {
+            observation.set(i, 3 * derivative + i, 1.0);
+        }
+        self.filter.observation = observation;
+        self.filter.measurement_noise = DenseMatrix::identity(values.len()).scale(noise);
+        self.filter.update(&DenseMatrix::vector(values))
+    }
+
+    fn axes(&self, derivative: usize) -> (f64, f64, f64) {
+        let state = &self.filter.state;
+        let start = 3 * derivative;
+        (state.get(start, 0), state.get(start + 1, 0), state.get(start + 2, 0))
+    }
+}
+
+/// `BoundingBox` is an axis-aligned box in image coordinates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BoundingBox {
+    pub left: f64,
+    pub top: f64,
+    pub right: f64,
+    pub bottom: f64
+}
+
+impl BoundingBox {
+    /// `measurement` returns the box as its centre, area and aspect ratio.
+    /// A box without a positive, finite width and height has no aspect ratio
+    /// and fails with `Error::ImpossibleMeasurement`.
+    fn measurement(&self) -> Result<DenseMatrix, Error> {
+        let (width, height) = (self.right - self.left, self.bottom - self.top);
+        let valid = |size: f64| size.is_finite() && size > 0.0;
+        if !valid(width) || !valid(height) {
+            return Err(Error::ImpossibleMeasurement);
+        }
+        Ok(DenseMatrix::vector(&[
+            (self.left + self.right) / 2.0,
+            (self.top + self.bottom) / 2.0,
+            width * height,
+            width / height
+        ]))
+    }
+}
+
+/// `BoxTracker` estimates the bounding box of an object across video frames,
+/// with the constant velocity model of SORT: the state is the centre `(u,
+/// v)`, area `s` and aspect ratio `r` of the box, and the velocities of all
+/// but the aspect ratio, which is assumed constant.
+///
+/// The noise levels are those of the reference SORT implementation, in
+/// pixels per frame, and can be changed through `filter`.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::tracking::{BoundingBox, BoxTracker};
+///
+/// let detection = |left: f64| BoundingBox { left, top: 10.0, right: left + 20.0, bottom: 50.0 };
+/// let mut tracker = BoxTracker::new(detection(0.0)).unwrap();
+/// for frame in 1..10 {
+///     tracker.predict();
+///     tracker.update(detection(f64::from(frame) * 2.0)).unwrap();
+/// }
+/// tracker.predict();
+/// tracker.bounding_box().left;
+/// // => 20.0
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct BoxTracker {
+    pub filter: DynamicKalmanFilter<DenseMatrix>
+}
+
+impl BoxTracker {
+    /// `new` starts a track at the first detection `bounding_box`, which
+    /// fails with `Error::ImpossibleMeasurement` if the box is empty.
+    pub fn new(bounding_box: BoundingBox) -> Result<Self, Error> {
+        let mut filter = DynamicKalmanFilter::<DenseMatrix>::new(7, 4);
+        let measurement = bounding_box.measurement()?;
+        for i in 0..4 {
+            filter.state.set(i, 0, measurement.get(i, 0));
+        }
+        for i in 0..3 {
+            filter.transition.set(i, i + 4, 1.0);
+        }
+        for i in 0..7 {
+            let (covariance, process_noise) = match i {
+                0..=3 => (10.0, 1.0),
+                4 | 5 => (10_000.0, 0.01),
+                _ => (10_000.0, 0.0001)
+            };
+            filter.covariance.set(i, i, covariance);
+            filter.process_noise.set(i, i, process_noise);
+        }
+        filter.measurement_noise = DenseMatrix::from_row_slices(&[
+            &[1.0, 0.0, 0.0, 0.0],
+            &[0.0, 1.0, 0.0, 0.0],
+            &[0.0, 0.0, 10.0, 0.0],
+            &[0.0, 0.0, 0.0, 10.0]
+        ]);
+        Ok(BoxTracker { filter })
+    }
+
+    /// `predict` moves the estimate one frame forward, never letting the
+    /// area shrink below zero.
+    pub fn predict(&mut self) {
+        let state = &mut self.filter.state;
+        if state.get(2, 0) + state.get(6, 0) <= 0.0 {
+            state.set(6, 0, 0.0);
+        }
+        self.filter.predict();
+    }
+
+    /// `update` refines the estimate with the detection `bounding_box`. An
+    /// empty box fails with `Error::ImpossibleMeasurement`, leaving the
+    /// estimate unchanged.
+    pub fn update(&mut self, bounding_box: BoundingBox) -> Result<(), Error> {
+        self.filter.update(&bounding_box.measurement()?)
+    }
+
+    /// `bounding_box` returns the estimated bounding box.
+    pub fn bounding_box(&self) -> BoundingBox {
+        let state = &self.filter.state;
+        let (u, v) = (state.get(0, 0), state.get(1, 0));
+        let area = state.get(2, 0).max(0.0);
+        let width = (area * state.get(3, 0)).max(0.0).sqrt();
+        let height = if width > 0.0 { area / width } else { 0.0 };
+        BoundingBox {
+            left: u - width / 2.0,
+            top: v - height / 2.0,
+            right: u + width / 2.0,
+            bottom: v + height / 2.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_kinematic_models() {
+        let (transition, process_noise) = kinematics(1, 3, 2.0, 1.0);
+
+        assert_eq!(
+            transition,
+            DenseMatrix::from_row_slices(&[&[1.0, 2.0, 2.0], &[0.0, 1.0, 2.0], &[0.0, 0.0, 1.0]])
+        );
+        assert_eq!(process_noise.get(0, 0), 32.0 / 20.0);
+        assert_eq!(process_noise.get(1, 2), 4.0 / 2.0);
+        assert_eq!(process_noise.get(2, 2), 2.0);
+        let (transition, _) = kinematics(2, 2, 0.5, 1.0);
+        assert_eq!((transition.get(0, 2), transition.get(1, 3), transition.get(0, 3)), (0.5, 0.5, 0.0));
+    }
+
+    #[test]
+    fn tracks_moving_object() {
+        let mut tracker = Tracker2D::new((0.0, 0.0), 0.01);
+
+        for t in 1..=20 {
+            let t = f64::from(t);
+            tracker.next(0.5, (t * 1.5, 3.0 - t)).unwrap();
+        }
+        let (vx, vy) = tracker.velocity();
+        assert!((vx - 3.0).abs() < 0.05 && (vy + 2.0).abs() < 0.05);
+        let (x, y) = tracker.position();
+        assert!((x - 30.0).abs() < 0.05 && (y + 17.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn tracks_accelerating_target() {
+        let mut tracker = Tracker3D::new((0.0, 0.0, 0.0), Motion::ConstantAcceleration, 0.01);
+        tracker.process_noise = 0.01;
+
+        for step in 1..=40 {
+            let t = f64::from(step) * 0.25;
+            tracker.predict(0.25);
+            tracker.update_position((t, 0.5 * t * t, 100.0)).unwrap();
+        }
+        let (ax, ay, az) = tracker.acceleration().unwrap();
+        assert!(ax.abs() < 0.1 && (ay - 1.0).abs() < 0.1 && az.abs() < 0.1);
+        let (vx, vy, _) = tracker.velocity();
+        assert!((vx - 1.0).abs() < 0.1 && (vy - 10.0).abs() < 0.2);
+    }
+
+    #[test]
+    fn fuses_velocity_measurements() {
+        let mut tracker = Tracker3D::new((0.0, 0.0, 0.0), Motion::ConstantVelocity, 1.0);
+        tracker.velocity_noise = 0.01;
+
+        tracker.predict(1.0);
+        tracker.update_position_velocity((0.0, 0.0, 0.0), (3.0, -1.0, 0.5)).unwrap();
+        let (vx, vy, vz) = tracker.velocity();
+        assert!((vx - 3.0).abs() < 0.1 && (vy + 1.0).abs() < 0.1 && (vz - 0.5).abs() < 0.1);
+        assert_eq!(tracker.acceleration(), None);
+        tracker.update_position((1.0, 1.0, 1.0)).unwrap();
+        assert_eq!(tracker.filter.dimensions(), (6, 3));
+
+        // A ground speed leaves the vertical alone.
+        tracker.predict(1.0);
+        let (_, _, before) = tracker.velocity();
+        tracker.update_velocity(&[2.0, 0.0]).unwrap();
+        let (vx, _, vz) = tracker.velocity();
+        assert!((vx - 2.0).abs() < 0.1 && vz == before);
+        assert_eq!(tracker.update_velocity(&[0.0; 4]), Err(Error::DimensionMismatch));
+        assert_eq!(tracker.update_acceleration(&[0.0; 3]), Err(Error::DimensionMismatch));
+    }
+
+    #[test]
+    fn tracks_bounding_box() {
+        let detection = |frame: f64| BoundingBox {
+            left: 100.0 + 3.0 * frame,
+            top: 50.0 - frame,
+            right: 140.0 + 3.0 * frame,
+            bottom: 130.0 - frame
+        };
+        let mut tracker = BoxTracker::new(detection(0.0)).unwrap();
+
+        for frame in 1..30 {
+            tracker.predict();
+            tracker.update(detection(f64::from(frame))).unwrap();
+        }
+        tracker.predict();
+        let predicted = tracker.bounding_box();
+        let expected = detection(30.0);
+        assert!((predicted.left - expected.left).abs() < 0.5);
+        assert!((predicted.top - expected.top).abs() < 0.5);
+        assert!((predicted.right - expected.right).abs() < 0.5);
+        assert!((predicted.bottom - expected.bottom).abs() < 0.5);
+    }
+
+    #[test]
+    fn keeps_area_nonnegative() {
+        let mut tracker = BoxTracker::new(BoundingBox { left: 0.0, top: 0.0, right: 2.0, bottom: 2.0 }).unwrap();
+        tracker.filter.state.set(6, 0, -10.0);
+
+        tracker.predict();
+        assert_eq!(tracker.filter.state.get(2, 0), 4.0);
+    }
+
+    #[test]
+    fn rejects_empty_boxes() {
+        let flat = BoundingBox { left: 0.0, top: 1.0, right: 2.0, bottom: 1.0 };
+        assert_eq!(BoxTracker::new(flat), Err(Error::ImpossibleMeasurement));
+        let mut tracker = BoxTracker::new(BoundingBox { left: 0.0, top: 0.0, right: 2.0, bottom: 2.0 }).unwrap();
+        let before = tracker.clone();
+        assert_eq!(tracker.update(flat), Err(Error::ImpossibleMeasurement));
+        let inverted = BoundingBox { left: 2.0, top: 0.0, right: 0.0, bottom: 2.0 };
+        assert_eq!(tracker.update(inverted), Err(Error::ImpossibleMeasurement));
+        assert_eq!(tracker, before);
+    }
+}
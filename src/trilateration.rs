@@ -0,0 +1,196 @@
+//! Positioning from ranges to fixed anchors.
+//!
+//! Indoor positioning systems measure the distance from a tag to anchors at
+//! known positions, by time of flight of UWB pulses or from the signal
+//! strength of BLE beacons, see `rssi`. A `RangeLocator` fuses the ranges to
+//! any number of anchors into a position estimate in 2D or 3D, linearizing
+//! the range to each anchor at the estimate. Anchors are identified by the
+//! `SensorId` their ranges come with, so they can be added, moved and
+//! removed while the locator runs.
+//!
+//! Ranges only fix the position when the anchors surround it: from anchors
+//! on a line, a position and its mirror image can't be told apart. Starting
+//! from the centroid of the anchors avoids converging to the mirror image.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::measurement::SensorId;
+//! use lqe::trilateration::RangeLocator;
+//!
+//! let mut locator = RangeLocator::new(&[5.0, 5.0]);
+//! locator.set_anchor(SensorId(1), &[0.0, 0.0]);
+//! locator.set_anchor(SensorId(2), &[10.0, 0.0]);
+//! locator.set_anchor(SensorId(3), &[0.0, 10.0]);
+//! for _ in 0..5 {
+//!     locator.next(1.0, &[(SensorId(1), 5.0), (SensorId(2), 8.06), (SensorId(3), 6.71)]).unwrap();
+//! }
+//! locator.position();
+//! // => [3.0, 4.0]
+//! ```
+
+use std::collections::BTreeMap;
+
+use crate::error::Error;
+use crate::matrix::{DenseMatrix, Matrix};
+use crate::measurement::SensorId;
+use crate::multivariate::DynamicKalmanFilter;
+
+/// `RangeLocator` estimates a position from ranges to anchors.
+///
+/// `process_noise` is the variance each coordinate of the position gains per
+/// second and `range_noise` the variance of a range measurement. `filter`
+/// exposes the underlying state, the position.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RangeLocator {
+    pub filter: DynamicKalmanFilter<DenseMatrix>,
+    pub process_noise: f64,
+    pub range_noise: f64,
+    anchors: BTreeMap<SensorId, Vec<f64>>
+}
+
+impl RangeLocator {
+    /// `new` creates a locator in as many dimensions as `position` has, 2 or
+    /// 3, starting there with an uncertainty of 10 m in each coordinate.
+    pub fn new(position: &[f64]) -> Self {
+        let dimensions = position.len();
+        let mut filter = DynamicKalmanFilter::new(dimensions, 1);
+        filter.state = DenseMatrix::vector(position);
+        filter.covariance = DenseMatrix::identity(dimensions).scale(100.0);
+        RangeLocator {
+            filter,
+            process_noise: 0.1,
+            range_noise: 0.01,
+            anchors: BTreeMap::new()
+        }
+    }
+
+    /// `set_anchor` places the anchor `sensor_id` at `position`.
+    ///
+    /// # Panics:
+    ///
+    /// Panics if `position` doesn't have the dimensions of the locator.
+    pub fn set_anchor(&mut self, sensor_id: SensorId, position: &[f64]) {
+        assert_eq!(position.len(), self.dimensions(), "anchor dimensions must match the locator");
+        self.anchors.insert(sensor_id, position.to_vec());
+    }
+
+    /// `remove_anchor` removes the anchor `sensor_id`, returning its
+    /// position if it was placed.
+    pub fn remove_anchor(&mut self, sensor_id: SensorId) -> Option<Vec<f64>> {
+        self.anchors.remove(&sensor_id)
+    }
+
+    /// `anchors` returns the anchors and their positions in order.
+    pub fn anchors(&self) -> impl Iterator<Item = (SensorId, &[f64])> {
+        self.anchors.iter().map(|(sensor_id, position)| (*sensor_id, position.as_slice()))
+    }
+
+    /// `centroid` returns the mean position of the anchors, a good start for
+    /// a tag of unknown position, or `None` without anchors.
+    pub fn centroid(&self) -> Option<Vec<f64>> {
+        if self.anchors.is_empty() {
+            return None;
+        }
+        let mut centroid = vec![0.0; self.dimensions()];
+        for position in self.anchors.values() {
+            for (sum, coordinate) in centroid.iter_mut().zip(position) {
+                *sum += coordinate / self.anchors.len() as f64;
+            }
+        }
+        Some(centroid)
+    }
+
+    /// `predict` moves the estimate `dt` seconds forward.
+    pub fn predict(&mut self, dt: f64) {
+        let dimensions = self.dimensions();
+        self.filter.process_noise = DenseMatrix::identity(dimensions).scale(self.process_noise * dt);
+        self.filter.predict();
+    }
+
+    /// `update` refines the estimate with `ranges` to anchors, all taken at
+    /// the same time.
+    ///
+    /// Ranges to anchors that haven't been placed fail with
+    /// `Error::UnknownSensor`, leaving the estimate unchanged.
+    pub fn update(&mut self, ranges: &[(SensorId, f64)]) -> Result<(), Error> {
+        let dimensions = self.dimensions();
+        let mut observation = DenseMatrix::zeros(ranges.len(), dimensions);
+        let mut measurement = DenseMatrix::zeros(ranges.len(), 1);
+        let mut expected_ranges = DenseMatrix::zeros(ranges.len(), 1);
+        for (row, (sensor_id, range)) in ranges.iter().enumerate() {
+            let anchor = self.anchors.get(sensor_id).ok_or(Error::UnknownSensor)?;
+            let offset: Vec<f64> = (0..dimensions)
+                .map(|i| self.filter.state.get(i, 0) - anchor[i])
+                .collect();
+            let expected = offset.iter().map(|x| x * x).sum::<f64>().sqrt();
+            if expected == 0.0 {
+                // On top of the anchor the direction is undefined.
+                continue;
+            }
+            for (i, x) in offset.iter().enumerate() {
+                observation.set(row, i, x / expected);
+            }
+            measurement.set(row, 0, *range);
+            expected_ranges.set(row, 0, expected);
+        }
+        self.filter.update_linearized(
+            &measurement,
+            &expected_ranges,
+            observation,
+            DenseMatrix::identity(ranges.len()).scale(self.range_noise)
+        )
+    }
+
+    /// `next` predicts `dt` seconds forward and updates with `ranges`.
+    pub fn next(&mut self, dt: f64, ranges: &[(SensorId, f64)]) -> Result<(), Error> {
+        self.predict(dt);
+        self.update(ranges)
+    }
+
+    /// `position` returns the estimated position.
+    pub fn position(&self) -> Vec<f64> {
+        (0..self.dimensions()).map(|i| self.filter.state.get(i, 0)).collect()
+    }
+
+    /// `dimensions` returns the number of coordinates of a position.
+    pub fn dimensions(&self) -> usize {
+        self.filter.state.shape().0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particle::Random;
+
+    #[test]
+    fn locates_tag_in_room() {
+        let anchors = [[0.0, 0.0, 2.5], [8.0, 0.0, 0.5], [8.0, 6.0, 2.5], [0.0, 6.0, 0.5]];
+        let tag = [3.0, 4.5, 1.0];
+        let mut locator = RangeLocator::new(&[0.0; 3]);
+        for (id, anchor) in anchors.iter().enumerate() {
+            locator.set_anchor(SensorId(id as u32), anchor);
+        }
+        locator.filter.state = DenseMatrix::vector(&locator.centroid().unwrap());
+
+        let mut random = Random::new(9);
+        for _ in 0..50 {
+            let ranges: Vec<_> = anchors
+                .iter()
+                .enumerate()
+                .map(|(id, anchor)| {
+                    let range = anchor.iter().zip(&tag).map(|(a, x)| (a - x).powi(2)).sum::<f64>().sqrt();
+                    (SensorId(id as u32), range + 0.1 * random.normal())
+                })
+                .collect();
+            locator.next(0.1, &ranges).unwrap();
+        }
+        let error = locator.position().iter().zip(&tag).map(|(x, t)| (x - t).powi(2)).sum::<f64>().sqrt();
+        assert!(error < 0.2);
+
+        let before = locator.position();
+        assert_eq!(locator.update(&[(SensorId(7), 1.0)]), Err(Error::UnknownSensor));
+        assert_eq!(locator.position(), before);
+    }
+}
@@ -0,0 +1,327 @@
+//! Choosing noise variances from logged data.
+//!
+//! `grid_search` runs the scalar random-walk filter over a recorded series of
+//! measurements for every combination of candidate process and measurement
+//! noise variances, and returns the pair that scores best. Without ground
+//! truth the score is the likelihood the filter assigns to the measurements;
+//! with it, the error of the estimates. `cross_validate` scores each pair on
+//! data held out from the run it was filtered on instead.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::LQE;
+//! use lqe::tuning::{self, Objective};
+//!
+//! let measurements = [5.1, 4.7, 5.4, 5.0, 4.8, 5.3, 4.9];
+//! let candidates = tuning::logspace(1e-4, 10.0, 11);
+//! let best = tuning::grid_search(
+//!     LQE { measurement: 5.0, variance: 1.0 },
+//!     &measurements,
+//!     &candidates,
+//!     &candidates,
+//!     Objective::LogLikelihood
+//! );
+//! best.unwrap().unwrap().measurement_noise;
+//! // => 0.1
+//! ```
+
+use crate::error::Error;
+use crate::LQE;
+
+/// `Objective` is what a set of noise variances is scored by.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Objective<'a> {
+    /// The log-likelihood of the measurements under the filter, for when the
+    /// true values are unknown.
+    LogLikelihood,
+    /// The root mean square error of the estimates against the true values,
+    /// one per measurement.
+    Rmse(&'a [f64])
+}
+
+impl<'a> Objective<'a> {
+    /// `check` fails with `Error::DimensionMismatch` if there are fewer true
+    /// values than `measurements`.
+    fn check(self, measurements: &[f64]) -> Result<(), Error> {
+        match self {
+            Objective::Rmse(truth) if truth.len() < measurements.len() => Err(Error::DimensionMismatch),
+            _ => Ok(())
+        }
+    }
+
+    /// `slice` returns the objective for the measurements `start..end`.
+    fn slice(self, start: usize, end: usize) -> Objective<'a> {
+        match self {
+            Objective::LogLikelihood => Objective::LogLikelihood,
+            Objective::Rmse(truth) => Objective::Rmse(&truth[start..end])
+        }
+    }
+}
+
+/// `Tuning` is a pair of noise variances and the cost they scored, lower
+/// being better: the negative log-likelihood or the RMSE.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Tuning {
+    pub process_noise: f64,
+    pub measurement_noise: f64,
+    pub cost: f64
+}
+
+/// `logspace` returns `count` values spaced evenly on a logarithmic scale
+/// from `low` to `high`, the usual way to sweep a variance.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::tuning;
+/// tuning::logspace(0.01, 1.0, 3);
+/// // => [0.01, 0.1, 1.0]
+/// ```
+pub fn logspace(low: f64, high: f64, count: usize) -> Vec<f64> {
+    if count < 2 {
+        return vec![low; count];
+    }
+    let (low, high) = (low.ln(), high.ln());
+    (0..count)
+        .map(|i| (low + (high - low) * i as f64 / (count - 1) as f64).exp())
+        .collect()
+}
+
+/// `evaluate` returns the cost of filtering `measurements` from `initial`
+/// with `process_noise` and `measurement_noise` under `objective`.
+///
+/// An `Rmse` objective with fewer true values than measurements fails with
+/// `Error::DimensionMismatch`.
+pub fn evaluate(
+    initial: LQE,
+    measurements: &[f64],
+    process_noise: f64,
+    measurement_noise: f64,
+    objective: Objective
+) -> Result<f64, Error> {
+    objective.check(measurements)?;
+    Ok(run(initial, measurements, process_noise, measurement_noise, objective).1)
+}
+
+/// `run` filters `measurements` from `initial`, returning the final estimate
+/// and the cost under `objective`.
+fn run(
+    initial: LQE,
+    measurements: &[f64],
+    process_noise: f64,
+    measurement_noise: f64,
+    objective: Objective
+) -> (LQE, f64) {
+    let mut estimate = initial;
+    let mut cost = 0.0;
+    for (i, measurement) in measurements.iter().enumerate() {
+        let prediction = LQE {
+            variance: estimate.variance + process_noise,
+            ..estimate
+        };
+        let step = prediction.correct(*measurement, measurement_noise);
+        estimate = step.posterior;
+        cost += match objective {
+            Objective::LogLikelihood => {
+                let variance = step.innovation_variance;
+                0.5 * ((2.0 * std::f64::consts::PI * variance).ln() + step.innovation.powi(2) / variance)
+            }
+            Objective::Rmse(truth) => (estimate.measurement - truth[i]).powi(2)
+        };
+    }
+    let cost = match objective {
+        Objective::LogLikelihood => cost,
+        Objective::Rmse(_) => (cost / measurements.len().max(1) as f64).sqrt()
+    };
+    (estimate, cost)
+}
+
+/// `grid_search` returns the combination of `process_noises` and
+/// `measurement_noises` with the lowest cost for filtering `measurements`
+/// from `initial`, or `None` if either list is empty or every cost is NaN.
+///
+/// An `Rmse` objective with fewer true values than measurements fails with
+/// `Error::DimensionMismatch`.
+pub fn grid_search(
+    initial: LQE,
+    measurements: &[f64],
+    process_noises: &[f64],
+    measurement_noises: &[f64],
+    objective: Objective
+) -> Result<Option<Tuning>, Error> {
+    objective.check(measurements)?;
+    let mut best: Option<Tuning> = None;
+    for &process_noise in process_noises {
+        for &measurement_noise in measurement_noises {
+            let cost = run(initial, measurements, process_noise, measurement_noise, objective).1;
+            if best.map_or(!cost.is_nan(), |best| cost < best.cost) {
+                best = Some(Tuning {
+                    process_noise,
+                    measurement_noise,
+                    cost
+                });
+            }
+        }
+    }
+    Ok(best)
+}
+
+/// `cross_validate` is `grid_search` scored on data the filter hasn't been
+/// tuned on, so that the chosen variances aren't overfitted to one run.
+///
+/// The measurements are split into `folds + 1` consecutive blocks. For each
+/// fold the filter runs over the blocks before it, then is scored on the
+/// next one only (rolling-origin validation, which keeps the time order that
+/// k-fold shuffling would break). The returned cost is the mean over folds.
+/// Returns `None` if there are no folds or fewer measurements than blocks,
+/// and fails with `Error::DimensionMismatch` for an `Rmse` objective with
+/// fewer true values than measurements.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::LQE;
+/// use lqe::tuning::{self, Objective};
+///
+/// let measurements = [5.1, 4.7, 5.4, 5.0, 4.8, 5.3, 4.9, 5.2, 5.0];
+/// let candidates = tuning::logspace(1e-4, 10.0, 11);
+/// let best = tuning::cross_validate(
+///     LQE { measurement: 5.0, variance: 1.0 },
+///     &measurements,
+///     &candidates,
+///     &candidates,
+///     2,
+///     Objective::LogLikelihood
+/// );
+/// ```
+pub fn cross_validate(
+    initial: LQE,
+    measurements: &[f64],
+    process_noises: &[f64],
+    measurement_noises: &[f64],
+    folds: usize,
+    objective: Objective
+) -> Result<Option<Tuning>, Error> {
+    objective.check(measurements)?;
+    let size = match folds.checked_add(1) {
+        Some(blocks) if folds > 0 => measurements.len() / blocks,
+        _ => 0
+    };
+    if size == 0 {
+        return Ok(None);
+    }
+    let mut best: Option<Tuning> = None;
+    for &process_noise in process_noises {
+        for &measurement_noise in measurement_noises {
+            let mut cost = 0.0;
+            for fold in 1..=folds {
+                let (start, end) = (fold * size, (fold + 1) * size);
+                let history = objective.slice(0, start);
+                let (trained, _) = run(initial, &measurements[..start], process_noise, measurement_noise, history);
+                let validation = objective.slice(start, end);
+                cost += run(trained, &measurements[start..end], process_noise, measurement_noise, validation).1;
+            }
+            let cost = cost / folds as f64;
+            if best.map_or(!cost.is_nan(), |best| cost < best.cost) {
+                best = Some(Tuning {
+                    process_noise,
+                    measurement_noise,
+                    cost
+                });
+            }
+        }
+    }
+    Ok(best)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particle::Random;
+
+    fn random_walk(seed: u64, steps: usize) -> (Vec<f64>, Vec<f64>) {
+        let mut random = Random::new(seed);
+        let mut value = 0.0;
+        let mut truth = Vec::with_capacity(steps);
+        let mut measurements = Vec::with_capacity(steps);
+        for _ in 0..steps {
+            value += 0.1f64.sqrt() * random.normal();
+            truth.push(value);
+            measurements.push(value + random.normal());
+        }
+        (truth, measurements)
+    }
+
+    #[test]
+    fn spaces_logarithmically() {
+        let values = logspace(0.01, 100.0, 5);
+
+        assert_eq!(values.len(), 5);
+        for (value, expected) in values.iter().zip(&[0.01, 0.1, 1.0, 10.0, 100.0]) {
+            assert!((value / expected - 1.0).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn finds_noise_by_likelihood() {
+        let (_, measurements) = random_walk(1, 2000);
+        let candidates = logspace(1e-3, 10.0, 13);
+
+        let best = grid_search(LQE { measurement: 0.0, variance: 1.0 }, &measurements, &candidates, &candidates, Objective::LogLikelihood)
+            .unwrap()
+            .unwrap();
+        assert!(best.process_noise > 0.03 && best.process_noise < 0.3);
+        assert!(best.measurement_noise > 0.5 && best.measurement_noise < 2.0);
+    }
+
+    #[test]
+    fn finds_noise_by_error() {
+        let (truth, measurements) = random_walk(2, 2000);
+        let candidates = logspace(1e-3, 10.0, 13);
+
+        let best = grid_search(
+            LQE { measurement: 0.0, variance: 1.0 },
+            &measurements,
+            &candidates,
+            &[1.0],
+            Objective::Rmse(&truth)
+        )
+        .unwrap()
+        .unwrap();
+        assert!(best.process_noise > 0.03 && best.process_noise < 0.3);
+        assert_eq!(grid_search(LQE { measurement: 0.0, variance: 1.0 }, &measurements, &[], &[1.0], Objective::Rmse(&truth)), Ok(None));
+        assert_eq!(
+            grid_search(LQE { measurement: 0.0, variance: 1.0 }, &measurements, &candidates, &[1.0], Objective::Rmse(&truth[1..])),
+            Err(Error::DimensionMismatch)
+        );
+    }
+
+    #[test]
+    fn cross_validates_on_held_out_data() {
+        let (truth, measurements) = random_walk(4, 3000);
+        let candidates = logspace(1e-3, 10.0, 13);
+        let initial = LQE { measurement: 0.0, variance: 1.0 };
+
+        let best = cross_validate(initial, &measurements, &candidates, &candidates, 4, Objective::LogLikelihood)
+            .unwrap()
+            .unwrap();
+        assert!(best.process_noise > 0.03 && best.process_noise < 0.3);
+        assert!(best.measurement_noise > 0.5 && best.measurement_noise < 2.0);
+        let best = cross_validate(initial, &measurements, &candidates, &[1.0], 3, Objective::Rmse(&truth))
+            .unwrap()
+            .unwrap();
+        assert!(best.process_noise > 0.03 && best.process_noise < 0.3);
+        assert_eq!(cross_validate(initial, &measurements[..2], &candidates, &candidates, 2, Objective::LogLikelihood), Ok(None));
+        assert_eq!(cross_validate(initial, &measurements, &candidates, &candidates, 0, Objective::LogLikelihood), Ok(None));
+        assert_eq!(
+            cross_validate(initial, &measurements, &candidates, &candidates, usize::MAX, Objective::LogLikelihood),
+            Ok(None)
+        );
+        assert_eq!(
+            cross_validate(initial, &measurements, &candidates, &[1.0], 3, Objective::Rmse(&truth[..10])),
+            Err(Error::DimensionMismatch)
+        );
+    }
+}
@@ -0,0 +1,261 @@
+//! Sensor dropout detection.
+//!
+//! A sensor that stops reporting leaves a filter with nothing to update
+//! from, and one that reports late hands it data that no longer describes
+//! the present. A `WatchedFilter` keeps a `Watchdog` per sensor, coasts on
+//! predictions while its sensors are silent and drops measurements that
+//! arrive too late to be trusted.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::LQE;
+//! use lqe::measurement::{Measurement, SensorId};
+//! use lqe::timed::TimedFilter;
+//! use lqe::watchdog::{Status, WatchedFilter};
+//!
+//! let mut filter = WatchedFilter::new(TimedFilter::new(LQE { measurement: 7.0, variance: 2.0 }, 0.5));
+//! filter.watch(SensorId(1), 0.5);
+//! filter.apply(&Measurement { timestamp: 0.0, value: 10.0, variance: 2.0, sensor_id: SensorId(1) });
+//! filter.tick(2.0).result();
+//! // => (8.5, 2.0)
+//! filter.status(SensorId(1));
+//! // => Some(Status::Stale)
+//! ```
+
+use std::collections::BTreeMap;
+
+use crate::measurement::{Measurement, SensorId};
+use crate::timed::{TimedFilter, Timestamp};
+use crate::LQE;
+
+/// `Status` is the health of a watched sensor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Status {
+    /// The sensor hasn't reported yet.
+    Waiting,
+    /// The latest measurement is within the timeout.
+    Fresh,
+    /// The sensor has been silent for longer than the timeout.
+    Stale
+}
+
+/// `Watchdog` tracks when a sensor last reported and flags it stale once it
+/// has been silent for more than `timeout` seconds.
+///
+/// # Example:
+///
+/// ```
+/// use lqe::watchdog::{Status, Watchdog};
+/// let mut watchdog = Watchdog::new(0.5);
+/// watchdog.feed(1.0);
+/// (watchdog.status(1.25), watchdog.status(2.0));
+/// // => (Status::Fresh, Status::Stale)
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Watchdog<T> {
+    pub timeout: f64,
+    last_seen: Option<T>
+}
+
+impl<T: Timestamp> Watchdog<T> {
+    /// `new` creates a watchdog for a sensor that hasn't reported yet.
+    pub fn new(timeout: f64) -> Self {
+        Watchdog {
+            timeout,
+            last_seen: None
+        }
+    }
+
+    /// `feed` records a report from the sensor at `timestamp`. Reports older
+    /// than the latest one are ignored.
+    pub fn feed(&mut self, timestamp: T) {
+        match self.last_seen {
+            Some(last_seen) if last_seen.seconds_since(&timestamp) > 0.0 => {}
+            _ => self.last_seen = Some(timestamp)
+        }
+    }
+
+    /// `status` returns the health of the sensor at `now`.
+    pub fn status(&self, now: T) -> Status {
+        match self.last_seen {
+            None => Status::Waiting,
+            Some(last_seen) if now.seconds_since(&last_seen) > self.timeout => Status::Stale,
+            Some(_) => Status::Fresh
+        }
+    }
+}
+
+/// `WatchedFilter` wraps a `TimedFilter` with a `Watchdog` per sensor.
+///
+/// `tick` moves the filter forward to the present, predicting only, so it
+/// keeps producing estimates while sensors are silent. A measurement from a
+/// watched sensor that is older than the present by more than the timeout of
+/// the sensor is dropped as stale; one within the timeout but older than the
+/// filter's last step is applied as of that step, since the filter can't go
+/// back in time. Measurements from unwatched sensors are dropped if they are
+/// older than the filter's last step and applied otherwise.
+pub struct WatchedFilter<T: Timestamp> {
+    pub filter: TimedFilter<T>,
+    watchdogs: BTreeMap<SensorId, Watchdog<T>>,
+    now: Option<T>
+}
+
+impl<T: Timestamp> WatchedFilter<T> {
+    /// `new` wraps `filter` without watching any sensor yet.
+    pub fn new(filter: TimedFilter<T>) -> Self {
+        WatchedFilter {
+            filter,
+            watchdogs: BTreeMap::new(),
+            now: None
+        }
+    }
+
+    /// `watch` starts watching `sensor_id` with a `timeout` in seconds,
+    /// replacing any previous watchdog of the sensor.
+    pub fn watch(&mut self, sensor_id: SensorId, timeout: f64) {
+        self.watchdogs.insert(sensor_id, Watchdog::new(timeout));
+    }
+
+    /// `apply` feeds the watchdog of the sensor and runs the entire predict -
+    /// update cycle for `measurement`, returning the refined estimate, or
+    /// `None` if the measurement is stale and was dropped.
+    pub fn apply(&mut self, measurement: &Measurement<T>) -> Option<LQE> {
+        // How far the measurement lags behind the filter, which can't go
+        // back in time to apply it where it belongs.
+        let lag = self
+            .filter
+            .last_timestamp()
+            .map_or(0.0, |last_timestamp| last_timestamp.seconds_since(&measurement.timestamp));
+        match self.watchdogs.get(&measurement.sensor_id) {
+            Some(watchdog) => {
+                if let Some(now) = self.now {
+                    if now.seconds_since(&measurement.timestamp) > watchdog.timeout {
+                        return None;
+                    }
+                }
+            }
+            None if lag > 0.0 => return None,
+            None => {}
+        }
+        if let Some(watchdog) = self.watchdogs.get_mut(&measurement.sensor_id) {
+            watchdog.feed(measurement.timestamp);
+        }
+        self.advance(measurement.timestamp);
+        match self.filter.last_timestamp() {
+            // Still fresh enough to use, so apply it at the filter's time.
+            Some(last_timestamp) if lag > 0.0 => {
+                Some(self.filter.next(last_timestamp, measurement.value, measurement.variance))
+            }
+            _ => Some(self.filter.apply(measurement))
+        }
+    }
+
+    /// `tick` predicts the estimate forward to `now` without a measurement.
+    pub fn tick(&mut self, now: T) -> LQE {
+        self.advance(now);
+        self.filter.predict(now)
+    }
+
+    /// `advance` moves the present forward to `timestamp`, if it is later.
+    fn advance(&mut self, timestamp: T) {
+        match self.now {
+            Some(now) if now.seconds_since(&timestamp) > 0.0 => {}
+            _ => self.now = Some(timestamp)
+        }
+    }
+
+    /// `status` returns the health of `sensor_id` at the present, or `None`
+    /// if it isn't watched.
+    pub fn status(&self, sensor_id: SensorId) -> Option<Status> {
+        let watchdog = self.watchdogs.get(&sensor_id)?;
+        Some(self.now.map_or(Status::Waiting, |now| watchdog.status(now)))
+    }
+
+    /// `stale` returns the watched sensors that are stale at the present.
+    pub fn stale(&self) -> impl Iterator<Item = SensorId> + '_ {
+        self.watchdogs
+            .keys()
+            .copied()
+            .filter(move |sensor_id| self.status(*sensor_id) == Some(Status::Stale))
+    }
+
+    /// `predict_only` returns whether none of the watched sensors is fresh,
+    /// so that the estimate rests on predictions alone.
+    pub fn predict_only(&self) -> bool {
+        self.watchdogs
+            .keys()
+            .all(|sensor_id| self.status(*sensor_id) != Some(Status::Fresh))
+    }
+
+    /// `result` returns the current state of the filter as a tuple value.
+    pub fn result(&self) -> (f64, f64) {
+        self.filter.result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measurement(timestamp: f64, value: f64, sensor: u32) -> Measurement<f64> {
+        Measurement {
+            timestamp,
+            value,
+            variance: 1.0,
+            sensor_id: SensorId(sensor)
+        }
+    }
+
+    #[test]
+    fn flags_silent_sensors() {
+        let mut watchdog = Watchdog::new(0.5);
+
+        assert_eq!(watchdog.status(0.0), Status::Waiting);
+        watchdog.feed(1.0);
+        watchdog.feed(0.2);
+        assert_eq!(watchdog.status(1.5), Status::Fresh);
+        assert_eq!(watchdog.status(1.75), Status::Stale);
+    }
+
+    #[test]
+    fn coasts_on_predictions_during_dropout() {
+        let mut filter = WatchedFilter::new(TimedFilter::new(LQE { measurement: 0.0, variance: 1.0 }, 0.1));
+        filter.watch(SensorId(1), 0.5);
+        filter.watch(SensorId(2), 0.5);
+
+        assert!(filter.predict_only());
+        filter.apply(&measurement(0.0, 1.0, 1));
+        filter.apply(&measurement(0.1, 1.0, 2));
+        assert!(!filter.predict_only());
+        filter.tick(0.55);
+        assert_eq!(filter.stale().collect::<Vec<_>>(), [SensorId(1)]);
+        assert!(!filter.predict_only());
+        let estimate = filter.tick(1.0);
+        assert!(filter.predict_only());
+        assert_eq!(estimate.measurement, filter.filter.result().0);
+        assert_eq!(filter.status(SensorId(3)), None);
+    }
+
+    #[test]
+    fn drops_stale_measurements() {
+        let mut filter = WatchedFilter::new(TimedFilter::new(LQE { measurement: 0.0, variance: 1.0 }, 0.1));
+        filter.watch(SensorId(1), 0.5);
+
+        filter.tick(2.0);
+        assert_eq!(filter.apply(&measurement(1.0, 5.0, 1)), None);
+        assert_eq!(filter.status(SensorId(1)), Some(Status::Waiting));
+        // Late but within the timeout: applied at 2.0, without rewinding.
+        let late = filter.apply(&measurement(1.8, 5.0, 1)).unwrap();
+        assert_eq!(late.result(), (2.5, 0.5));
+        assert_eq!(filter.filter.last_timestamp(), Some(2.0));
+        assert_eq!(filter.apply(&measurement(1.0, 5.0, 2)), None);
+        assert_eq!(filter.result(), late.result());
+        assert_eq!(filter.status(SensorId(1)), Some(Status::Fresh));
+        let next = filter.apply(&measurement(3.0, 5.0, 2)).unwrap();
+        let prior = late.variance + 0.1;
+        assert!((next.variance - prior / (prior + 1.0)).abs() < 1e-12);
+    }
+}
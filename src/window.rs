@@ -0,0 +1,182 @@
+//! Sliding-window estimation with marginalization.
+//!
+//! `SlidingWindow` keeps the last few measurements and re-estimates the states
+//! they belong to in one batch, like `batch::estimate`, so that each of them
+//! benefits from the ones that follow. Measurements leaving the window are
+//! marginalized into a Gaussian prior on the oldest remaining state, which
+//! keeps the cost per step bounded while losing nothing for linear models.
+//!
+//! With `estimate_within` the window becomes a moving horizon estimator,
+//! constraining the states over the horizon to given bounds.
+//!
+//! # Example:
+//!
+//! ```
+//! use lqe::matrix::{DenseMatrix, Matrix};
+//! use lqe::multivariate::DynamicKalmanFilter;
+//! use lqe::window::SlidingWindow;
+//!
+//! let mut model = DynamicKalmanFilter::<DenseMatrix>::new(1, 1);
+//! model.process_noise = DenseMatrix::identity(1).scale(0.1);
+//! let mut window = SlidingWindow::new(model, 3);
+//! for z in &[1.0, 1.2, 0.9, 1.1] {
+//!     window.push(DenseMatrix::vector(&[*z])).unwrap();
+//! }
+//! window.estimate().unwrap().len();
+//! // => 3
+//! ```
+
+use std::collections::VecDeque;
+
+use crate::batch;
+use crate::error::Error;
+use crate::matrix::Matrix;
+use crate::multivariate::DynamicKalmanFilter;
+
+/// `SlidingWindow` estimates the states of the last `size` measurements with
+/// the system model of `model`.
+///
+/// The `state` and `covariance` of `model` are the prior marginalized from
+/// every measurement that has left the window.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SlidingWindow<T> {
+    pub model: DynamicKalmanFilter<T>,
+    size: usize,
+    measurements: VecDeque<T>
+}
+
+impl<T: Matrix> SlidingWindow<T> {
+    /// `new` creates an empty window holding up to `size` measurements, with
+    /// the estimate of `model` as the prior before the first one.
+    ///
+    /// # Panics:
+    ///
+    /// Panics if `size` is zero.
+    pub fn new(model: DynamicKalmanFilter<T>, size: usize) -> Self {
+        assert!(size > 0, "window size must be positive");
+        SlidingWindow {
+            model,
+            size,
+            measurements: VecDeque::with_capacity(size)
+        }
+    }
+
+    /// `push` adds `measurement` to the window, marginalizing the oldest one
+    /// into the prior if the window is full.
+    ///
+    /// Marginalizing a state out of the normal equations leaves a Gaussian
+    /// prior on the next one, which for a linear model is exactly the
+    /// filtered estimate, so this is a predict - update step of `model`. If
+    /// it fails, the window and its prior are left as they were.
+    pub fn push(&mut self, measurement: T) -> Result<(), Error> {
+        if self.measurements.len() == self.size {
+            if let Some(oldest) = self.measurements.front() {
+                let mut model = self.model.clone();
+                model.next(oldest)?;
+                self.model = model;
+            }
+            self.measurements.pop_front();
+        }
+        self.measurements.push_back(measurement);
+        Ok(())
+    }
+
+    /// `estimate` returns the maximum a posteriori state after each
+    /// measurement in the window, oldest first.
+    pub fn estimate(&self) -> Result<Vec<T>, Error> {
+        let measurements = self.measurements.iter().cloned().collect::<Vec<_>>();
+        batch::estimate(&self.model, &measurements)
+    }
+
+    /// `estimate_within` is `estimate` with every state in the window
+    /// constrained to lie between `lower` and `upper` element-wise, as in
+    /// `batch::estimate_within`.
+    ///
+    /// The prior marginalized from older measurements is not constrained, so
+    /// it only approximates the arrival cost of the constrained problem.
+    pub fn estimate_within(&self, lower: &T, upper: &T) -> Result<Vec<T>, Error> {
+        let measurements = self.measurements.iter().cloned().collect::<Vec<_>>();
+        batch::estimate_within(&self.model, &measurements, lower, upper)
+    }
+
+    /// `len` returns the number of measurements in the window.
+    pub fn len(&self) -> usize {
+        self.measurements.len()
+    }
+
+    /// `is_empty` returns whether the window holds no measurements yet.
+    pub fn is_empty(&self) -> bool {
+        self.measurements.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::DenseMatrix;
+
+    fn constant_velocity() -> DynamicKalmanFilter<DenseMatrix> {
+        DynamicKalmanFilter {
+            state: DenseMatrix::vector(&[0.0, 1.0]),
+            covariance: DenseMatrix::identity(2),
+            transition: DenseMatrix::from_row_slices(&[&[1.0, 1.0], &[0.0, 1.0]]),
+            process_noise: DenseMatrix::identity(2).scale(0.1),
+            observation: DenseMatrix::from_row_slices(&[&[1.0, 0.0]]),
+            measurement_noise: DenseMatrix::identity(1)
+        }
+    }
+
+    #[test]
+    fn matches_full_batch() {
+        let measurements = [1.2, 1.9, 3.3, 3.8, 5.1, 5.7]
+            .iter()
+            .map(|z| DenseMatrix::vector(&[*z]))
+            .collect::<Vec<_>>();
+        let mut window = SlidingWindow::new(constant_velocity(), 3);
+        for measurement in &measurements {
+            window.push(measurement.clone()).unwrap();
+        }
+
+        let full = batch::estimate(&constant_velocity(), &measurements).unwrap();
+        let windowed = window.estimate().unwrap();
+        assert_eq!(window.len(), 3);
+        for (actual, expected) in windowed.iter().zip(&full[3..]) {
+            let difference = actual.sub(expected);
+            assert!(difference.get(0, 0).abs() < 1e-9 && difference.get(1, 0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn constrains_horizon() {
+        let mut window = SlidingWindow::new(constant_velocity(), 3);
+        for z in &[0.5, -0.2, -1.4, -2.1] {
+            window.push(DenseMatrix::vector(&[*z])).unwrap();
+        }
+
+        let lower = DenseMatrix::vector(&[0.0, f64::NEG_INFINITY]);
+        let upper = DenseMatrix::vector(&[f64::INFINITY, f64::INFINITY]);
+        let constrained = window.estimate_within(&lower, &upper).unwrap();
+        assert!(window.estimate().unwrap().iter().any(|state| state.get(0, 0) < 0.0));
+        assert!(constrained.iter().all(|state| state.get(0, 0) >= 0.0));
+    }
+
+    #[test]
+    fn keeps_window_on_failed_marginalization() {
+        let mut window = SlidingWindow::new(constant_velocity(), 2);
+        window.push(DenseMatrix::vector(&[1.0])).unwrap();
+        window.push(DenseMatrix::vector(&[2.0])).unwrap();
+        window.model.measurement_noise = DenseMatrix::identity(1).scale(-100.0);
+        let before = window.clone();
+
+        assert_eq!(window.push(DenseMatrix::vector(&[3.0])), Err(Error::SingularInnovation));
+        assert_eq!(window, before);
+    }
+
+    #[test]
+    fn starts_empty() {
+        let window = SlidingWindow::new(constant_velocity(), 2);
+
+        assert!(window.is_empty());
+        assert_eq!(window.estimate(), Ok(vec![]));
+    }
+}